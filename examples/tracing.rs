@@ -0,0 +1,29 @@
+//! Shows the events emitted with the `tracing` feature when initialization is contended
+//!
+//! Run with `cargo run --example tracing --features tracing`.
+
+use linux_once::NamedOnce;
+use std::time::Duration;
+
+static INIT: NamedOnce = NamedOnce::new("example");
+
+fn main() {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing_subscriber::filter::LevelFilter::DEBUG)
+        .with_thread_names(true)
+        .init();
+
+    let threads = (0..4)
+        .map(|i| {
+            std::thread::Builder::new()
+                .name(format!("worker-{}", i))
+                .spawn(|| INIT.call_once(|| std::thread::sleep(Duration::from_millis(100))))
+                .expect("failed to spawn thread")
+        })
+        // required for true concurrency
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().expect("Failed to join");
+    }
+}
@@ -0,0 +1,296 @@
+// Tests of the API shared by all platforms
+//
+// This file is included into modules that import `Once` and `RetryOnce` of one of the
+// implementations so it must only use the portable API.
+
+use crate::{CallOnceOutcome, OnceStatus, PoisonedError, TryCallOnce};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[test]
+fn outcomes() {
+    let once = Once::new();
+    assert_eq!(once.state(), OnceStatus::Incomplete);
+    assert_eq!(once.try_call_once(|| ()), TryCallOnce::Ran);
+    assert_eq!(once.try_call_once(|| panic!("ran after completion")), TryCallOnce::AlreadyComplete);
+    assert_eq!(once.call_once_timeout(|| panic!("ran after completion"), Duration::from_secs(0)), CallOnceOutcome::AlreadyComplete);
+    assert!(!once.call_once_and_report(|| panic!("ran after completion")));
+    assert!(once.wait_timeout(Duration::from_secs(0)));
+    assert_eq!(format!("{:?}", once), "Once { state: Complete }");
+
+    let mut once = Once::default();
+    let mut calls = 0;
+    once.call_once_mut(|| calls += 1);
+    once.call_once_dyn(&mut || calls += 1);
+    once.call_once_relaxed_check(|| calls += 1);
+    assert_eq!(calls, 1);
+    assert!(once.is_completed_relaxed());
+}
+
+#[test]
+fn poisoning() {
+    let once = Once::new();
+    std::panic::catch_unwind(|| once.call_once(|| panic!())).expect_err("the closure didn't panic");
+    assert!(once.is_poisoned());
+    assert_eq!(once.try_call_once(|| ()), TryCallOnce::Poisoned);
+    std::panic::catch_unwind(|| once.wait()).expect_err("wait didn't panic");
+    once.wait_force();
+
+    once.call_once_force(|state| {
+        assert!(state.is_poisoned());
+        state.poison();
+    });
+    assert!(once.is_poisoned());
+    once.clear_poison();
+    assert_eq!(once.state(), OnceStatus::Incomplete);
+    assert_eq!(once.call_once_try(|| Err::<(), _>("failed")), Err("failed"));
+    once.call_once_catch(|| panic!("caught")).expect_err("the panic wasn't caught");
+    assert!(!once.is_poisoned());
+    // SAFETY: there's nothing to initialize
+    unsafe { once.mark_completed(); }
+    assert!(once.is_completed());
+
+    let once = Once::new();
+    once.poison();
+    assert!(once.is_poisoned());
+}
+
+#[test]
+fn call_once_checked() {
+    let once = Once::new();
+    assert_eq!(once.call_once_checked(|| ()), Ok(()));
+    assert_eq!(once.call_once_checked(|| panic!("ran after completion")), Ok(()));
+
+    let once = Once::new();
+    std::panic::catch_unwind(|| once.call_once_checked(|| panic!())).expect_err("the closure didn't panic");
+    let error: PoisonedError = once.call_once_checked(|| panic!("ran while poisoned")).expect_err("poisoning not reported");
+    assert!(error.to_string().starts_with("Once instance has previously been poisoned"));
+}
+
+#[test]
+fn call_once_checked_poisoned_while_waiting() {
+    let once = Arc::new(Once::new());
+    let guard = once.begin().expect("fresh Once already initialized");
+    let cloned = Arc::clone(&once);
+    let waiter = std::thread::spawn(move || cloned.call_once_checked(|| panic!("ran while poisoned")));
+    let start = Instant::now();
+    while once.waiters() == 0 {
+        assert!(start.elapsed() < Duration::from_secs(10), "waiter didn't block");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    // dropping the guard poisons the Once just like a panicking closure
+    drop(guard);
+    waiter.join().expect("Failed to join").expect_err("poisoning not reported");
+}
+
+#[test]
+fn contended_waiting() {
+    let once = Arc::new(Once::new());
+    let guard = once.begin().expect("fresh Once already initialized");
+    assert!(once.is_running());
+    assert_eq!(once.try_call_once(|| panic!("ran while another initialization is running")), TryCallOnce::Busy);
+    assert!(!once.wait_deadline(Instant::now() + Duration::from_millis(10)));
+    assert_eq!(once.call_once_timeout(|| (), Duration::from_millis(10)), CallOnceOutcome::TimedOut);
+
+    let waiters = (0..2)
+        .map(|i| {
+            let cloned = Arc::clone(&once);
+            std::thread::spawn(move || if i == 0 {
+                cloned.call_once(|| panic!("the initialization should've completed"))
+            } else {
+                cloned.wait()
+            })
+        })
+        // required for true concurrency
+        .collect::<Vec<_>>();
+    let start = Instant::now();
+    while once.waiters() < 2 {
+        assert!(start.elapsed() < Duration::from_secs(10), "waiters didn't block");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    let mut reports = 0;
+    let cloned = Arc::clone(&once);
+    let watched = std::thread::spawn(move || {
+        cloned.call_once_watched(|| panic!("the initialization should've completed"), Duration::from_millis(10), |_| reports += 1);
+        reports
+    });
+    std::thread::sleep(Duration::from_millis(50));
+    guard.complete();
+    for waiter in waiters {
+        waiter.join().expect("Failed to join");
+    }
+    assert_eq!(watched.join().expect("Failed to join"), 1);
+    assert_eq!(once.waiters(), 0);
+
+    // aborting lets the next caller run its closure
+    let once = Once::new();
+    once.begin().expect("fresh Once already initialized").abort();
+    let mut ran = false;
+    once.call_once_abort_on_panic(|| ran = true);
+    assert!(ran);
+}
+
+#[test]
+fn call_once_from_signal() {
+    let once = Once::new();
+    let guard = once.begin().expect("fresh Once already initialized");
+    assert!(!once.call_once_from_signal(|| panic!("ran while another initialization is running")));
+    guard.complete();
+    assert!(once.call_once_from_signal(|| panic!("ran after completion")));
+
+    let once = Once::new();
+    let mut ran = false;
+    assert!(once.call_once_from_signal(|| ran = true));
+    assert!(ran && once.is_completed());
+
+    let once = Once::new();
+    once.poison();
+    assert!(!once.call_once_from_signal(|| panic!("ran while poisoned")));
+}
+
+#[test]
+fn call_once_from_signal_wakes_waiters() {
+    let once = Arc::new(Once::new());
+    let guard = once.begin().expect("fresh Once already initialized");
+    let cloned = Arc::clone(&once);
+    let waiter = std::thread::spawn(move || cloned.wait());
+    let start = Instant::now();
+    while once.waiters() == 0 {
+        assert!(start.elapsed() < Duration::from_secs(10), "waiter didn't block");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    // the waiting thread keeps blocking after the abort
+    guard.abort();
+    assert!(once.call_once_from_signal(|| ()));
+    waiter.join().expect("Failed to join");
+}
+
+#[test]
+fn call_once_spin() {
+    let once = Arc::new(Once::new());
+    let guard = once.begin().expect("fresh Once already initialized");
+    let cloned = Arc::clone(&once);
+    let spinner = std::thread::spawn(move || cloned.call_once_spin(|| panic!("the initialization should've completed")));
+    std::thread::sleep(Duration::from_millis(10));
+    guard.complete();
+    spinner.join().expect("Failed to join");
+
+    let once = Once::new();
+    let mut ran = false;
+    once.call_once_spin(|| ran = true);
+    assert!(ran && once.is_completed());
+    std::panic::catch_unwind(|| {
+        let once = Once::new();
+        once.poison();
+        once.call_once_spin(|| ());
+    }).expect_err("call_once_spin didn't panic on poisoned Once");
+}
+
+#[test]
+fn retry_once() {
+    let once = RetryOnce::new();
+    std::panic::catch_unwind(|| once.call_once(|| panic!())).expect_err("the closure didn't panic");
+    assert!(!once.is_completed() && !once.is_running());
+    let mut ran = false;
+    once.call_once(|| ran = true);
+    assert!(ran && once.is_completed());
+    assert_eq!(format!("{:?}", once), "RetryOnce { state: Complete }");
+}
+
+#[test]
+fn retry_budget_exhaustion() {
+    let once = RetryOnce::with_retry_budget(2);
+    for _ in 0..3 {
+        assert!(!once.is_poisoned());
+        std::panic::catch_unwind(|| once.call_once(|| panic!())).expect_err("the closure didn't panic");
+    }
+    assert!(once.is_poisoned());
+    std::panic::catch_unwind(|| once.call_once(|| panic!("ran while poisoned"))).expect_err("call_once didn't panic");
+    assert_eq!(format!("{:?}", once), "RetryOnce { state: Poisoned }");
+}
+
+#[test]
+fn retry_budget_success_on_last_retry() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let once = Arc::new(RetryOnce::with_retry_budget(2));
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let threads = (0..4)
+        .map(|_| {
+            let once = Arc::clone(&once);
+            let attempts = Arc::clone(&attempts);
+            std::thread::spawn(move || std::panic::catch_unwind(|| once.call_once(|| {
+                // give the other threads time to block
+                std::thread::sleep(Duration::from_millis(20));
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    panic!("transient failure");
+                }
+            })).is_err())
+        })
+        // required for true concurrency
+        .collect::<Vec<_>>();
+    let panicked = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).filter(|panicked| *panicked).count();
+    assert_eq!(panicked, 2);
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    assert!(once.is_completed() && !once.is_poisoned());
+}
+
+#[test]
+fn call_once_init() {
+    use core::cell::UnsafeCell;
+    use core::mem::MaybeUninit;
+
+    let once = Once::new();
+    let slot = UnsafeCell::new(MaybeUninit::<String>::uninit());
+    // SAFETY: the slot is only accessed through the Once and dropped below
+    let value = unsafe { once.call_once_init(&slot, || String::from("initialized")) };
+    assert_eq!(value, "initialized");
+    // SAFETY: same as above
+    let value = unsafe { once.call_once_init(&slot, || panic!("ran after completion")) };
+    assert_eq!(value, "initialized");
+    assert!(once.is_completed());
+    // SAFETY: the Once is complete and no references are alive
+    unsafe { slot.into_inner().assume_init_drop(); }
+
+    let once = Once::new();
+    let slot = UnsafeCell::new(MaybeUninit::<String>::uninit());
+    // SAFETY: the slot is only accessed through the Once
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe { once.call_once_init(&slot, || panic!()) })).expect_err("the closure didn't panic");
+    assert!(once.is_poisoned());
+    // SAFETY: same as above
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe { once.call_once_init(&slot, String::new) })).expect_err("call_once_init didn't panic");
+}
+
+#[test]
+fn call_once_init_concurrent() {
+    use core::cell::UnsafeCell;
+    use core::mem::MaybeUninit;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Slot {
+        once: Once,
+        value: UnsafeCell<MaybeUninit<usize>>,
+    }
+
+    // SAFETY: the value is only accessed through the Once
+    unsafe impl Sync for Slot {}
+
+    let slot = Arc::new(Slot { once: Once::new(), value: UnsafeCell::new(MaybeUninit::uninit()) });
+    let runs = Arc::new(AtomicUsize::new(0));
+    let threads = (0..4)
+        .map(|i| {
+            let slot = Arc::clone(&slot);
+            let runs = Arc::clone(&runs);
+            // SAFETY: the value is only accessed through the Once
+            std::thread::spawn(move || *unsafe { slot.once.call_once_init(&slot.value, || {
+                runs.fetch_add(1, Ordering::Relaxed);
+                i
+            }) })
+        })
+        // required for true concurrency
+        .collect::<Vec<_>>();
+    let values = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+    assert_eq!(runs.load(Ordering::Relaxed), 1);
+    assert!(values.iter().all(|value| *value == values[0]));
+}
@@ -0,0 +1,82 @@
+//! `Once` protocol running on a word owned by someone else
+//!
+//! `Once` is `repr(transparent)` over the futex word, so a reference to a suitable `AtomicI32` can
+//! be reinterpreted as a reference to `Once`. The borrowed view never drops the `Once`, which is
+//! the only thing ownership adds, so all methods are simply reached through `Deref`.
+
+use core::fmt;
+use core::ops::Deref;
+use core::sync::atomic::AtomicI32;
+use crate::Once;
+
+/// A [`Once`] operating on an `AtomicI32` it doesn't own.
+///
+/// This is useful when the storage of the flag is dictated by someone else, e.g. a word reserved
+/// for it in a memory segment mapped by foreign code, where an owned [`Once`] can't be placed.
+/// The view runs exactly the same protocol as [`Once`] on the word, see
+/// [the layout section](Once#layout) for the encoding. All methods of [`Once`] are available
+/// through [`Deref`].
+///
+/// ```
+/// use linux_once::BorrowedOnce;
+/// use std::sync::atomic::AtomicI32;
+///
+/// static FLAG: AtomicI32 = AtomicI32::new(0);
+///
+/// // SAFETY: FLAG is initialized to 0 and only ever accessed through BorrowedOnce
+/// let once = unsafe { BorrowedOnce::from_atomic(&FLAG) };
+/// once.call_once(|| println!("initializing"));
+/// assert!(once.is_completed());
+/// ```
+///
+/// The futex operations are private to the process, so if the word is shared with other
+/// processes their threads waiting for the initialization are not woken up. Diagnostics recorded
+/// when the word gets poisoned are only released once the poison is cleared, since there's no
+/// owner that would drop them.
+#[derive(Clone, Copy)]
+pub struct BorrowedOnce<'a> {
+    once: &'a Once,
+}
+
+impl<'a> BorrowedOnce<'a> {
+    /// Creates a view of `word` as a [`Once`].
+    ///
+    /// # Safety
+    ///
+    /// For the whole lifetime `'a`:
+    ///
+    /// * `word` must hold one of the states described in [the layout section](Once#layout),
+    ///   usually it's initialized to [`Once::INCOMPLETE`] before it's shared.
+    /// * `word` must only be modified through `Once` methods, be it this view, other views of the
+    ///   same word or [`Once::as_ptr()`] used according to its documentation. Code unaware of the
+    ///   protocol may only load it and compare with [`Once::COMPLETE`].
+    /// * If `word` holds [`Once::COMPLETE`] the initialization it guards must have been performed,
+    ///   with the same requirements as [`Once::mark_completed()`].
+    ///
+    /// Code relying on the `Once` (possibly including unsafe code) would observe uninitialized
+    /// state otherwise.
+    pub const unsafe fn from_atomic(word: &'a AtomicI32) -> Self {
+        // SAFETY: Once is repr(transparent) over AtomicI32 and the caller guarantees the value
+        // follows the protocol
+        BorrowedOnce { once: unsafe { &*(word as *const AtomicI32 as *const Once) } }
+    }
+
+    /// Returns the underlying [`Once`] with the lifetime of the borrowed word.
+    pub fn as_once(&self) -> &'a Once {
+        self.once
+    }
+}
+
+impl<'a> Deref for BorrowedOnce<'a> {
+    type Target = Once;
+
+    fn deref(&self) -> &Self::Target {
+        self.once
+    }
+}
+
+impl<'a> fmt::Debug for BorrowedOnce<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BorrowedOnce").field("state", &self.once.state()).finish()
+    }
+}
@@ -0,0 +1,63 @@
+//! Closure stored next to its `Once`
+//!
+//! This is a `LazyLock` producing `()`: it already stores the closure until the first access,
+//! consumes it in the initialization and knows what to drop in each state.
+
+use core::fmt;
+use crate::LazyLock;
+
+/// A closure which runs at most once, no matter how many times it's called.
+///
+/// With [`Once`](crate::Once) each call site passes its own closure, so two call sites with
+/// different closures compile fine but only one of them ever runs. `CallOnce` stores the closure
+/// when it's created and [`call()`](Self::call) takes no arguments, like `sync.OnceFunc` in Go.
+///
+/// The default type of the closure is a function pointer so that it can be used in statics:
+///
+/// ```
+/// use linux_once::CallOnce;
+///
+/// static INIT_LOGGING: CallOnce = CallOnce::new(|| println!("initializing logging"));
+///
+/// INIT_LOGGING.call();
+/// INIT_LOGGING.call();
+/// assert!(INIT_LOGGING.is_completed());
+/// ```
+///
+/// If the closure panics the `CallOnce` is poisoned, just like [`Once`](crate::Once), and all
+/// further calls panic.
+pub struct CallOnce<F = fn()> {
+    lazy: LazyLock<(), F>,
+}
+
+impl<F: FnOnce()> CallOnce<F> {
+    /// Creates a new `CallOnce` which runs `f` on the first call.
+    pub const fn new(f: F) -> Self {
+        CallOnce { lazy: LazyLock::new(f) }
+    }
+
+    /// Runs the closure if it's the first call, otherwise does nothing.
+    ///
+    /// If another thread is running the closure this blocks until it finishes.
+    ///
+    /// # Panics
+    ///
+    /// If the closure panics the panic is propagated and the `CallOnce` is poisoned. If the
+    /// `CallOnce` has been poisoned this method panics too.
+    pub fn call(&self) {
+        LazyLock::force(&self.lazy);
+    }
+}
+
+impl<F> CallOnce<F> {
+    /// Returns `true` if the closure has completed successfully.
+    pub fn is_completed(&self) -> bool {
+        LazyLock::get(&self.lazy).is_some()
+    }
+}
+
+impl<F> fmt::Debug for CallOnce<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallOnce").field("completed", &self.is_completed()).finish()
+    }
+}
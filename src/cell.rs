@@ -0,0 +1,447 @@
+//! Value initialized at most once, built on `Once`
+//!
+//! The value lives next to the `Once` in an `UnsafeCell<MaybeUninit<T>>`. It's written only by
+//! the thread running the initialization, inside `call_once_try()` or between `begin()` and
+//! `complete()` depending on the poisoning policy, and read only after the `Once` is observed
+//! complete, so `Once` provides all the synchronization. The value is initialized if
+//! and only if the `Once` is complete, which is what `Drop` and `into_inner()` rely on.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::time::{Duration, Instant};
+use crate::{Once, OnceStatus};
+use crate::poison_policy::{call_once_with_policy, PoisonPolicy};
+
+/// A thread-safe cell which can be written to only once.
+///
+/// This is like [`std::sync::OnceLock`] except the threads waiting for the initialization block
+/// using the same mechanism as [`Once`]. If the initialization closure panics the cell is
+/// poisoned, just like [`Once`], and accessing it with
+/// [`get_or_init()`](Self::get_or_init) panics. Cells created using
+/// [`with_poison_policy()`](Self::with_poison_policy) can stay empty instead.
+///
+/// ```
+/// use linux_once::OnceCell;
+///
+/// static CONFIG: OnceCell<String> = OnceCell::new();
+///
+/// assert_eq!(CONFIG.get(), None);
+/// assert_eq!(CONFIG.get_or_init(|| String::from("verbose")), "verbose");
+/// assert_eq!(CONFIG.get_or_init(|| unreachable!()), "verbose");
+/// assert_eq!(CONFIG.get().map(String::as_str), Some("verbose"));
+/// ```
+///
+/// # Dropping
+///
+/// The cell holds a value only after an initialization returned successfully and the value is
+/// dropped exactly once: when the cell is dropped, unless it was moved out by
+/// [`take()`](Self::take) or [`into_inner()`](Self::into_inner) first. Values rejected because
+/// the cell was initialized already are returned to or dropped by their callers. Whatever the
+/// initialization closure owns when it panics or returns an error is dropped by the unwinding or
+/// by the closure itself, nothing of it ends up in the cell, so dropping an empty or poisoned
+/// cell drops nothing.
+pub struct OnceCell<T> {
+    once: Once,
+    policy: PoisonPolicy,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> OnceCell<T> {
+    /// Creates a new empty cell.
+    pub const fn new() -> Self {
+        OnceCell::with_poison_policy(PoisonPolicy::Poison)
+    }
+
+    /// Creates a new empty cell handling panicking initializers according to `policy`.
+    ///
+    /// With [`PoisonPolicy::Retry`] a panic in the closure passed to
+    /// [`get_or_init()`](Self::get_or_init) unwinds out of it leaving the cell empty and the
+    /// threads waiting for the initialization run their own closures. The cell is never
+    /// poisoned.
+    ///
+    /// ```
+    /// use linux_once::{OnceCell, PoisonPolicy};
+    ///
+    /// static CONFIG: OnceCell<String> = OnceCell::with_poison_policy(PoisonPolicy::Retry);
+    ///
+    /// std::panic::catch_unwind(|| CONFIG.get_or_init(|| panic!("config not ready"))).unwrap_err();
+    /// assert_eq!(CONFIG.get_or_init(|| String::from("verbose")), "verbose");
+    /// ```
+    pub const fn with_poison_policy(policy: PoisonPolicy) -> Self {
+        OnceCell { once: Once::new(), policy, value: UnsafeCell::new(MaybeUninit::uninit()) }
+    }
+
+    /// Returns the value if the cell was initialized.
+    ///
+    /// This never blocks: if the initialization is running on another thread `None` is returned.
+    pub fn get(&self) -> Option<&T> {
+        if self.once.is_completed() {
+            // SAFETY: the Once is complete
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// Initializes the cell with `value` if it's empty.
+    ///
+    /// If another thread is initializing the cell this blocks until it finishes. Returns the value
+    /// back in `Err` if the cell was initialized already, see [`try_insert()`](Self::try_insert)
+    /// for a variant returning the reference to the stored value as well.
+    ///
+    /// # Panics
+    ///
+    /// If the cell has been poisoned this method panics.
+    #[track_caller]
+    pub fn set(&self, value: T) -> Result<(), T> {
+        self.try_insert(value).map(drop).map_err(|(_, value)| value)
+    }
+
+    /// Initializes the cell with `value` if it's empty, returning the reference to the stored
+    /// value.
+    ///
+    /// If the cell was initialized already the reference to the current value is returned along
+    /// with the rejected `value`. Exactly one value wins when this races with other
+    /// initializations, the others are returned to their callers. If another thread is
+    /// initializing the cell this blocks until it finishes.
+    ///
+    /// # Panics
+    ///
+    /// If the cell has been poisoned this method panics.
+    #[track_caller]
+    pub fn try_insert(&self, value: T) -> Result<&T, (&T, T)> {
+        let mut value = Some(value);
+        let current = self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(current),
+            Some(value) => Err((current, value)),
+        }
+    }
+
+    /// Blocks the current thread until the cell is initialized by another thread.
+    ///
+    /// This is useful for threads that must not run the initialization themselves. The threads
+    /// initializing the cell using [`set()`](Self::set) or [`get_or_init()`](Self::get_or_init)
+    /// wake up the waiting threads just like [`Once::wait()`] does.
+    ///
+    /// # Panics
+    ///
+    /// If the cell has been poisoned this method panics.
+    pub fn wait(&self) -> &T {
+        self.once.wait();
+        // SAFETY: wait returns only after the Once completed
+        unsafe { self.get_unchecked() }
+    }
+
+    /// Blocks the current thread until the cell is initialized or the timeout expires.
+    ///
+    /// Returns `None` if the timeout expired first. Zero timeout just checks whether the value is
+    /// available. See [`Once::wait_timeout()`] for how the timeout is measured.
+    ///
+    /// # Panics
+    ///
+    /// If the cell has been poisoned this method panics.
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<&T> {
+        if self.once.wait_timeout(timeout) {
+            // SAFETY: wait_timeout returns true only after the Once completed
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// Blocks the current thread until the cell is initialized or the deadline passes.
+    ///
+    /// Returns `None` if the deadline passed first. A deadline in the past just checks whether
+    /// the value is available.
+    ///
+    /// # Panics
+    ///
+    /// If the cell has been poisoned this method panics.
+    pub fn wait_deadline(&self, deadline: Instant) -> Option<&T> {
+        if self.once.wait_deadline(deadline) {
+            // SAFETY: wait_deadline returns true only after the Once completed
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value, initializing it with `f` if the cell is empty.
+    ///
+    /// If another thread is initializing the cell this blocks until it finishes. Only one closure
+    /// is ever executed successfully.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics the panic is propagated and the cell is poisoned, unless it was created
+    /// with [`PoisonPolicy::Retry`]. If the cell has been poisoned this method panics too.
+    #[track_caller]
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        match self.get_or_try_init(|| Ok::<_, core::convert::Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Returns the value, initializing it with `f` if the cell is empty, without blocking while
+    /// `f` runs.
+    ///
+    /// Unlike [`get_or_init()`](Self::get_or_init) the cell is not marked as being initialized
+    /// while `f` runs, so several threads may run their closures concurrently. The first value
+    /// published wins and is returned to all of them, the other values are dropped. This is
+    /// cheaper than blocking when `f` is quick, such as parsing an environment variable.
+    ///
+    /// The value is published the same way as in [`try_insert()`](Self::try_insert), so this
+    /// can be freely mixed with the blocking methods on the same cell. If a blocking
+    /// initialization is running when `f` returns this waits for it and returns its value.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics the panic is propagated and the cell is left untouched. If the cell has been
+    /// poisoned this method panics.
+    #[track_caller]
+    pub fn get_or_init_racy<F: FnOnce() -> T>(&self, f: F) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        match self.try_insert(f()) {
+            Ok(value) => value,
+            Err((current, _)) => current,
+        }
+    }
+
+    /// Returns the value, initializing it with the fallible `f` if the cell is empty.
+    ///
+    /// If `f` returns an error the cell stays empty and the error is returned to the calling
+    /// thread only. The threads that were waiting for this attempt are woken up and one of them
+    /// runs its own closure, so a later call may still initialize the cell.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics the panic is propagated and the cell is poisoned, unless it was created
+    /// with [`PoisonPolicy::Retry`]. If the cell has been poisoned this method panics too.
+    #[track_caller]
+    pub fn get_or_try_init<E, F: FnOnce() -> Result<T, E>>(&self, f: F) -> Result<&T, E> {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+
+        call_once_with_policy(&self.once, self.policy, || {
+            let value = f()?;
+            // SAFETY: only the thread running the initialization writes the value and nobody
+            // reads it before the Once completes, which doesn't happen if this isn't reached
+            unsafe { (*self.value.get()).write(value); }
+            Ok(())
+        })?;
+        // SAFETY: call_once_with_policy returns Ok only after the Once completed
+        Ok(unsafe { self.get_unchecked() })
+    }
+
+    /// Returns the value, initializing it with `f` if the cell is empty and `f` returns `Some`.
+    ///
+    /// If `f` returns `None` the cell stays empty and `None` is returned, the same way as an error
+    /// returned from [`get_or_try_init()`](Self::get_or_try_init): the threads that were waiting
+    /// for this attempt are woken up and one of them runs its own closure.
+    ///
+    /// ```
+    /// use linux_once::OnceCell;
+    ///
+    /// let cell = OnceCell::new();
+    /// assert_eq!(cell.get_or_maybe_init(|| None), None);
+    /// assert_eq!(cell.get_or_maybe_init(|| Some(42)), Some(&42));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Same as [`get_or_init()`](Self::get_or_init).
+    #[track_caller]
+    pub fn get_or_maybe_init<F: FnOnce() -> Option<T>>(&self, f: F) -> Option<&T> {
+        self.get_or_try_init(|| f().ok_or(())).ok()
+    }
+
+    /// Returns the mutable reference to the value if the cell was initialized.
+    ///
+    /// Since this borrows the cell mutably no synchronization is needed.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.once.is_completed() {
+            // SAFETY: the Once is complete so the value is initialized
+            Some(unsafe { self.value.get_mut().assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the mutable reference to the value, initializing it with `f` if the cell is empty.
+    ///
+    /// Since this borrows the cell mutably no other thread can be waiting for it, so the value is
+    /// stored without any synchronization or waking. For the same reason a panic in `f` leaves
+    /// the cell empty instead of poisoning it, nobody can observe the cell in the meantime.
+    ///
+    /// ```
+    /// use linux_once::OnceCell;
+    ///
+    /// let mut cell = OnceCell::new();
+    /// cell.get_mut_or_init(Vec::new).push(42);
+    /// assert_eq!(cell.get(), Some(&vec![42]));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the cell has been poisoned this method panics.
+    #[track_caller]
+    pub fn get_mut_or_init<F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
+        match self.get_mut_or_try_init(|| Ok::<_, core::convert::Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Returns the mutable reference to the value, initializing it with the fallible `f` if the
+    /// cell is empty.
+    ///
+    /// If `f` returns an error the cell stays empty and the error is returned. Otherwise this
+    /// behaves like [`get_mut_or_init()`](Self::get_mut_or_init).
+    ///
+    /// # Panics
+    ///
+    /// If the cell has been poisoned this method panics.
+    #[track_caller]
+    pub fn get_mut_or_try_init<E, F: FnOnce() -> Result<T, E>>(&mut self, f: F) -> Result<&mut T, E> {
+        if !self.once.is_completed() {
+            if self.once.is_poisoned() {
+                self.once.panic_poisoned();
+            }
+            let value = f()?;
+            self.value.get_mut().write(value);
+            // a plain store, there are no waiters to wake up
+            self.once = Once::new_completed();
+        }
+        debug_assert_eq!(self.once.state(), OnceStatus::Complete);
+        // SAFETY: the Once is complete so the value is initialized
+        Ok(unsafe { self.value.get_mut().assume_init_mut() })
+    }
+
+    /// Moves the value out of the cell, leaving it empty.
+    ///
+    /// The cell can be initialized again afterwards. If the cell was poisoned the poisoning is
+    /// cleared and `None` is returned: the value was never written so there's nothing broken to
+    /// protect from and the exclusive access proves no other thread is observing the cell.
+    pub fn take(&mut self) -> Option<T> {
+        let completed = self.once.is_completed();
+        // resetting the Once makes sure the value is not read or dropped again
+        self.once = Once::new();
+        if completed {
+            // SAFETY: the Once was complete so the value is initialized
+            Some(unsafe { self.value.get_mut().assume_init_read() })
+        } else {
+            None
+        }
+    }
+
+    /// Consumes the cell returning the value if it was initialized.
+    ///
+    /// A poisoned cell returns `None`, same as [`take()`](Self::take).
+    pub fn into_inner(mut self) -> Option<T> {
+        // Drop doesn't see the value anymore
+        self.take()
+    }
+
+    /// Returns the value without checking whether the cell was initialized.
+    ///
+    /// This is a plain reference computation without any atomic access, so it's useful on hot
+    /// paths that run after the initialization is known to be finished. Debug builds check the
+    /// state anyway.
+    ///
+    /// ```
+    /// use linux_once::OnceCell;
+    ///
+    /// static CONFIG: OnceCell<String> = OnceCell::new();
+    ///
+    /// CONFIG.set(String::from("verbose")).unwrap();
+    /// // SAFETY: the cell was observed initialized on this thread above
+    /// assert_eq!(unsafe { CONFIG.get_unchecked() }, "verbose");
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The cell must have been initialized and the initialization must happen-before this call.
+    /// That's the case if this thread previously observed the cell initialized (e.g. `get()`
+    /// returned `Some` or `get_or_init()` returned) or received the information from a thread
+    /// that did through a synchronizing operation such as joining it or a channel.
+    pub unsafe fn get_unchecked(&self) -> &T {
+        debug_assert!(self.once.is_completed(), "get_unchecked called on an uninitialized OnceCell");
+        (*self.value.get()).assume_init_ref()
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            // SAFETY: the Once is complete so the value is initialized and it's never accessed
+            // again
+            unsafe { self.value.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        OnceCell::new()
+    }
+}
+
+impl<T> From<T> for OnceCell<T> {
+    /// Creates an initialized cell.
+    fn from(value: T) -> Self {
+        OnceCell { once: Once::new_completed(), policy: PoisonPolicy::Poison, value: UnsafeCell::new(MaybeUninit::new(value)) }
+    }
+}
+
+impl<T: Clone> Clone for OnceCell<T> {
+    /// Clones the value if the cell was initialized.
+    ///
+    /// If the initialization is running on another thread it's not waited for and the clone is
+    /// empty. The clone has the same poisoning policy.
+    fn clone(&self) -> Self {
+        match self.get() {
+            Some(value) => {
+                let mut clone = OnceCell::from(value.clone());
+                clone.policy = self.policy;
+                clone
+            },
+            None => OnceCell::with_poison_policy(self.policy),
+        }
+    }
+}
+
+impl<T: PartialEq> PartialEq for OnceCell<T> {
+    /// Compares the values, empty cells are equal to each other.
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl<T: Eq> Eq for OnceCell<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceCell").field(value).finish(),
+            None => f.debug_struct("OnceCell").field("state", &self.once.state()).finish(),
+        }
+    }
+}
+
+// SAFETY: the value is shared between threads and may be initialized by any of them, same as in
+// std::sync::OnceLock
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+// SAFETY: sending the cell sends the value
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+// Same as std::sync::OnceLock, the poisoning protects from observing a broken value
+impl<T: RefUnwindSafe + UnwindSafe> RefUnwindSafe for OnceCell<T> {}
+impl<T: UnwindSafe> UnwindSafe for OnceCell<T> {}
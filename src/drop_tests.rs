@@ -0,0 +1,236 @@
+// Destruction of the values stored in the cells in all states
+//
+// Every value is instrumented so that the tests can check nothing leaked and nothing was dropped
+// twice. These are meant to run under Miri as well, which catches reads of uninitialized storage:
+// `cargo +nightly miri test --lib drop_tests`
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use crate::{LazyLock, OnceCell, OnceLock, PoisonPolicy};
+
+#[derive(Default)]
+struct Counters {
+    created: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+impl Counters {
+    fn new() -> Arc<Self> {
+        Arc::new(Counters::default())
+    }
+
+    fn create(self: &Arc<Self>) -> Instrumented {
+        self.created.fetch_add(1, Relaxed);
+        Instrumented(Arc::clone(self))
+    }
+
+    /// Returns the number of values that are alive
+    fn alive(&self) -> usize {
+        let dropped = self.dropped.load(Relaxed);
+        let created = self.created.load(Relaxed);
+        assert!(dropped <= created, "dropped {} values but created only {}", dropped, created);
+        created - dropped
+    }
+}
+
+struct Instrumented(Arc<Counters>);
+
+impl Clone for Instrumented {
+    fn clone(&self) -> Self {
+        self.0.create()
+    }
+}
+
+impl Drop for Instrumented {
+    fn drop(&mut self) {
+        self.0.dropped.fetch_add(1, Relaxed);
+    }
+}
+
+impl core::fmt::Debug for Instrumented {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("Instrumented")
+    }
+}
+
+/// Creates a resource, moves it into the local state and panics
+fn panic_holding(counters: &Arc<Counters>) -> Instrumented {
+    let _local = (Box::new(counters.create()), counters.create());
+    panic!("panicking with resources on purpose");
+}
+
+#[test]
+fn once_cell_initializer_panics() {
+    for policy in [PoisonPolicy::Poison, PoisonPolicy::Retry].iter().copied() {
+        let counters = Counters::new();
+        let cell = OnceCell::with_poison_policy(policy);
+        catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic_holding(&counters)))).expect_err("the closure didn't panic");
+        catch_unwind(AssertUnwindSafe(|| cell.get_or_try_init(|| Ok::<_, ()>(panic_holding(&counters))))).ok();
+        assert!(cell.get().is_none());
+        assert_eq!(counters.alive(), 0);
+        // the cell holds nothing to drop, neither poisoned nor empty
+        drop(cell);
+        assert_eq!(counters.alive(), 0);
+    }
+}
+
+#[test]
+fn once_cell_error_drops_partial_value() {
+    let counters = Counters::new();
+    let cell = OnceCell::new();
+    let result = cell.get_or_try_init(|| {
+        let _partial = counters.create();
+        Err::<Instrumented, _>("failed")
+    });
+    assert_eq!(result.map(drop), Err("failed"));
+    assert_eq!(counters.alive(), 0);
+    assert_eq!(cell.get_or_maybe_init(|| None.map(|()| counters.create())).map(drop), None);
+    drop(cell);
+    assert_eq!(counters.created.load(Relaxed), 1);
+    assert_eq!(counters.alive(), 0);
+}
+
+#[test]
+fn once_cell_take_and_into_inner_in_all_states() {
+    let counters = Counters::new();
+
+    // empty
+    let mut cell = OnceCell::<Instrumented>::new();
+    assert!(cell.take().is_none());
+    assert!(cell.into_inner().is_none());
+
+    // initialized
+    let mut cell = OnceCell::new();
+    cell.get_or_init(|| counters.create());
+    let taken = cell.take().expect("cell not initialized");
+    assert_eq!(counters.alive(), 1);
+    cell.get_or_init(|| counters.create());
+    let inner = cell.into_inner().expect("cell not initialized");
+    assert_eq!(counters.alive(), 2);
+    drop((taken, inner));
+    assert_eq!(counters.alive(), 0);
+
+    // poisoned
+    let mut cell = OnceCell::new();
+    catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic_holding(&counters)))).expect_err("the closure didn't panic");
+    assert!(cell.take().is_none());
+    cell.get_or_init(|| counters.create());
+    drop(cell);
+    assert_eq!(counters.alive(), 0);
+}
+
+#[test]
+fn once_cell_drops_value_exactly_once() {
+    let counters = Counters::new();
+    let cell = OnceCell::new();
+    cell.get_or_init(|| counters.create());
+    // the rejected values are returned to the callers
+    assert!(cell.set(counters.create()).is_err());
+    assert!(cell.try_insert(counters.create()).is_err());
+    cell.get_or_init_racy(|| unreachable!("the cell is initialized"));
+    assert_eq!(counters.alive(), 1);
+    let clone = cell.clone();
+    assert_eq!(counters.alive(), 2);
+    drop(cell);
+    assert_eq!(counters.alive(), 1);
+    drop(clone);
+    assert_eq!(counters.alive(), 0);
+    assert_eq!(counters.created.load(Relaxed), 4);
+}
+
+#[test]
+fn once_lock_initializer_panics() {
+    for policy in [PoisonPolicy::Poison, PoisonPolicy::Retry].iter().copied() {
+        let counters = Counters::new();
+        let mut lock = OnceLock::with_poison_policy(policy);
+        catch_unwind(AssertUnwindSafe(|| lock.get_or_init(|| panic_holding(&counters)))).expect_err("the closure didn't panic");
+        catch_unwind(AssertUnwindSafe(|| {
+            lock.get_mut_or_init(|| panic_holding(&counters));
+        })).expect_err("the closure didn't panic");
+        assert!(lock.get().is_none());
+        assert_eq!(counters.alive(), 0);
+        assert!(lock.take().is_none());
+        lock.get_mut_or_init(|| counters.create());
+        assert!(lock.set(counters.create()).is_err());
+        assert_eq!(counters.alive(), 1);
+        drop(lock);
+        assert_eq!(counters.alive(), 0);
+    }
+}
+
+#[test]
+fn once_lock_take_and_into_inner() {
+    let counters = Counters::new();
+    let mut lock = OnceLock::new();
+    assert!(lock.take().is_none());
+    lock.get_or_init(|| counters.create());
+    let taken = lock.take().expect("lock not initialized");
+    lock.get_or_init(|| counters.create());
+    let inner = lock.into_inner().expect("lock not initialized");
+    assert_eq!(counters.alive(), 2);
+    drop((taken, inner));
+    assert_eq!(counters.alive(), 0);
+    assert!(OnceLock::<Instrumented>::new().into_inner().is_none());
+}
+
+#[test]
+fn lazy_lock_function_and_value_drops() {
+    let counters = Counters::new();
+
+    // never forced: the function and its captures are dropped
+    let captured = counters.create();
+    let lazy = LazyLock::new(move || captured);
+    drop(lazy);
+    assert_eq!(counters.alive(), 0);
+
+    // forced: the captures became the value which is dropped once
+    let captured = counters.create();
+    let lazy = LazyLock::new(move || captured);
+    LazyLock::force(&lazy);
+    assert_eq!(counters.alive(), 1);
+    drop(lazy);
+    assert_eq!(counters.alive(), 0);
+
+    // into_inner returns whatever is stored without dropping it
+    let captured = counters.create();
+    let f = LazyLock::into_inner(LazyLock::new(move || captured)).expect_err("value initialized");
+    assert_eq!(counters.alive(), 1);
+    let value = LazyLock::into_inner(LazyLock::new(f)).expect_err("value initialized")();
+    assert_eq!(counters.alive(), 1);
+    let mut lazy = LazyLock::new(move || value);
+    LazyLock::force_mut(&mut lazy);
+    let value = LazyLock::into_inner(lazy).unwrap_or_else(|_| panic!("value not initialized"));
+    assert_eq!(counters.alive(), 1);
+    drop(value);
+    assert_eq!(counters.alive(), 0);
+}
+
+#[test]
+fn lazy_lock_initializer_panics() {
+    let counters = Counters::new();
+
+    // the captures are moved into the panicking function and dropped by the unwinding
+    let captured = counters.create();
+    let lazy = LazyLock::new(|| {
+        let _moved = captured;
+        panic_holding(&counters)
+    });
+    catch_unwind(AssertUnwindSafe(|| LazyLock::force(&lazy))).expect_err("the closure didn't panic");
+    assert_eq!(counters.alive(), 0);
+    // the poisoned lazy holds neither the function nor the value
+    catch_unwind(AssertUnwindSafe(|| LazyLock::into_inner(lazy).map(drop).map_err(drop))).expect_err("poisoned lazy didn't panic");
+    assert_eq!(counters.alive(), 0);
+
+    let captured = counters.create();
+    let mut lazy = LazyLock::new(|| {
+        let _moved = captured;
+        panic_holding(&counters)
+    });
+    catch_unwind(AssertUnwindSafe(|| {
+        LazyLock::force_mut(&mut lazy);
+    })).expect_err("the closure didn't panic");
+    drop(lazy);
+    assert_eq!(counters.alive(), 0);
+    assert_eq!(counters.created.load(Relaxed), 6);
+}
@@ -0,0 +1,222 @@
+//! Events built on the same futex protocol as `Once`
+//!
+//! The word of the manual-reset `Event` has three states. Waiters announce themselves by moving `UNSET_NO_WAIT` to
+//! `UNSET_WAITING` before blocking, so `set()` only issues the wake syscall if the state it
+//! replaced says somebody may be blocked. There's no running state since setting is a single
+//! store.
+//!
+//! `AutoResetEvent` has to know whether any waiter is left after one consumed the signal, so its
+//! word counts the registered waiters instead and `set()` wakes a single thread. Each woken
+//! thread either consumes the signal or finds that another thread consumed it first and blocks
+//! again, so no signal is lost and none is consumed twice.
+
+use core::fmt;
+use core::sync::atomic::{AtomicI32, Ordering};
+use crate::futex;
+
+/// Not set and no thread is blocked
+const UNSET_NO_WAIT: i32 = 0;
+/// Not set and some threads may be blocked
+const UNSET_WAITING: i32 = 1;
+const SET: i32 = 2;
+
+/// A flag that is set once and releases all threads waiting for it.
+///
+/// This replaces the `Mutex<bool>` + `Condvar` combination: [`wait()`](Self::wait) blocks until
+/// some thread calls [`set()`](Self::set) and returns immediately from then on. Unlike
+/// [`Once`](crate::Once) there's no closure, so nothing can panic and the event can't be
+/// poisoned.
+///
+/// ```
+/// use linux_once::Event;
+///
+/// static READY: Event = Event::new();
+///
+/// let worker = std::thread::spawn(|| {
+///     READY.wait();
+///     assert!(READY.is_set());
+/// });
+/// READY.set();
+/// worker.join().unwrap();
+/// ```
+///
+/// Setting the event costs a single atomic operation unless some thread is blocked in which case
+/// all of them are woken up using a single syscall.
+pub struct Event {
+    state: AtomicI32,
+}
+
+impl Event {
+    /// Creates a new event that is not set.
+    pub const fn new() -> Self {
+        Event { state: AtomicI32::new(UNSET_NO_WAIT) }
+    }
+
+    /// Sets the event and wakes up all threads waiting for it.
+    ///
+    /// Memory operations performed before this call are visible to the threads that observe the
+    /// event set. Setting an event that is already set does nothing.
+    pub fn set(&self) {
+        // Only make expensive syscall if there are threads waiting
+        if self.state.swap(SET, Ordering::Release) == UNSET_WAITING {
+            futex::wake(&self.state, i32::MAX);
+        }
+    }
+
+    /// Returns `true` if the event has been set.
+    pub fn is_set(&self) -> bool {
+        self.state.load(Ordering::Acquire) == SET
+    }
+
+    /// Blocks the current thread until the event is set.
+    ///
+    /// Returns immediately if it's already set.
+    pub fn wait(&self) {
+        // Fast path, same as in Once
+        if self.state.load(Ordering::Acquire) != SET {
+            self.wait_slow();
+        }
+    }
+
+    #[cold]
+    fn wait_slow(&self) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            match state {
+                SET => break,
+                UNSET_NO_WAIT => match self.state.compare_exchange_weak(UNSET_NO_WAIT, UNSET_WAITING, Ordering::Acquire, Ordering::Acquire) {
+                    Ok(_) => state = UNSET_WAITING,
+                    Err(old) => state = old,
+                },
+                _waiting => {
+                    futex::wait(&self.state, UNSET_WAITING);
+                    state = self.state.load(Ordering::Acquire);
+                },
+            }
+        }
+    }
+}
+
+impl Default for Event {
+    fn default() -> Self {
+        Event::new()
+    }
+}
+
+impl fmt::Debug for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Event").field("is_set", &self.is_set()).finish()
+    }
+}
+
+/// Set on top of the waiter count while a signal is latched
+const SIGNALED: i32 = 1;
+/// Added to the state by each registered waiter
+const WAITER: i32 = 2;
+
+/// An event whose signal is consumed by the thread it releases.
+///
+/// Each [`set()`](Self::set) releases exactly one thread blocked in [`wait()`](Self::wait) and
+/// the event becomes unset again, like auto-reset events on Windows. If no thread is waiting the
+/// signal is latched and the next call to [`wait()`](Self::wait) consumes it without blocking.
+/// Signals don't accumulate: setting an event that is already set does nothing.
+///
+/// ```
+/// use linux_once::AutoResetEvent;
+///
+/// static STAGE_DONE: AutoResetEvent = AutoResetEvent::new();
+///
+/// let consumer = std::thread::spawn(|| {
+///     STAGE_DONE.wait();
+///     STAGE_DONE.wait();
+/// });
+/// STAGE_DONE.set();
+/// while STAGE_DONE.is_set() {
+///     std::thread::yield_now();
+/// }
+/// STAGE_DONE.set();
+/// consumer.join().unwrap();
+/// ```
+///
+/// Like [`Event`] it can't be poisoned and setting costs a single atomic operation unless some
+/// thread is blocked, in which case one of them is woken up.
+pub struct AutoResetEvent {
+    /// The `SIGNALED` bit plus `WAITER` times the number of registered waiters
+    state: AtomicI32,
+}
+
+impl AutoResetEvent {
+    /// Creates a new event that is not set.
+    pub const fn new() -> Self {
+        AutoResetEvent { state: AtomicI32::new(0) }
+    }
+
+    /// Sets the event, releasing one waiting thread or latching the signal if there's none.
+    ///
+    /// Memory operations performed before this call are visible to the thread that consumes the
+    /// signal.
+    pub fn set(&self) {
+        let previous = self.state.fetch_or(SIGNALED, Ordering::Release);
+        // If the signal was latched already the previous call woke a waiter up. A registered
+        // waiter that didn't block yet notices the change of the value so waking somebody else
+        // is harmless: one of them consumes the signal and the other one blocks again.
+        if previous & SIGNALED == 0 && previous >= WAITER {
+            futex::wake(&self.state, 1);
+        }
+    }
+
+    /// Returns `true` if a signal is latched, waiting for a thread to consume it.
+    pub fn is_set(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & SIGNALED != 0
+    }
+
+    /// Blocks the current thread until it consumes a signal.
+    ///
+    /// Returns immediately if a signal is latched, unsetting the event.
+    pub fn wait(&self) {
+        // Fast path: the signal is latched and nobody else waits
+        if self.state.compare_exchange(SIGNALED, 0, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            self.wait_slow();
+        }
+    }
+
+    #[cold]
+    fn wait_slow(&self) {
+        let mut registered = false;
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & SIGNALED != 0 {
+                let new = (state & !SIGNALED) - if registered { WAITER } else { 0 };
+                match self.state.compare_exchange_weak(state, new, Ordering::Acquire, Ordering::Relaxed) {
+                    Ok(_) => break,
+                    Err(old) => state = old,
+                }
+            } else if !registered {
+                // set() only wakes threads up if it observes a registered waiter
+                match self.state.compare_exchange_weak(state, state + WAITER, Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        registered = true;
+                        state += WAITER;
+                    },
+                    Err(old) => state = old,
+                }
+            } else {
+                // Returns immediately if the state changed since it was loaded
+                futex::wait(&self.state, state);
+                state = self.state.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl Default for AutoResetEvent {
+    fn default() -> Self {
+        AutoResetEvent::new()
+    }
+}
+
+impl fmt::Debug for AutoResetEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoResetEvent").field("is_set", &self.is_set()).finish()
+    }
+}
@@ -0,0 +1,275 @@
+//! Variant of `Once` releasing the waiters in the order they arrived
+//!
+//! Each waiter blocks on its own futex word which it registers in a queue. When the
+//! initialization finishes only the first waiter is woken up and each released waiter wakes the
+//! next one before returning, so they leave in arrival order. The queue is protected by a mutex
+//! which also serializes registering with finishing so that no waiter can be missed.
+//!
+//! A released waiter may find that another initialization started in the meantime (a forced one
+//! after poisoning) and has to wait again. Each waiter gets a ticket when it first queues and the
+//! queue is ordered by the tickets, so such a waiter gets back to its original place.
+
+use core::fmt;
+use core::sync::atomic::{AtomicI32, Ordering};
+use std::collections::VecDeque;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use crate::{OnceState, OnceStatus};
+use crate::futex;
+use crate::poison_hook;
+use crate::once_fn::OnceFn;
+
+const INCOMPLETE: i32 = 0;
+const COMPLETE: i32 = 1;
+const POISONED: i32 = 2;
+const RUNNING: i32 = 3;
+
+/// Value of the futex word of a waiter that is still queued
+const QUEUED: i32 = 0;
+/// Value of the futex word of a waiter whose turn came
+const RELEASED: i32 = 1;
+
+/// A variant of [`Once`](crate::Once) that releases the waiting threads in arrival order.
+///
+/// [`Once`](crate::Once) wakes all waiting threads with a single syscall and the scheduler then
+/// decides in which order they continue. This may be unfair: the thread that has been waiting the
+/// longest (and may be closest to some timeout) can end up continuing last. `FairOnce` queues
+/// the waiters and releases them one by one, each released thread waking up the next one.
+///
+/// This costs one wake syscall per waiter, issued sequentially, so the last waiter continues
+/// later than with [`Once`](crate::Once). `FairOnce` also needs a mutex and a queue so it's much
+/// larger and a waiter allocates. The fast path is still a single atomic load.
+///
+/// The API is a subset of [`Once`](crate::Once) and poisoning works the same way. If the closure
+/// recursively calls a method of the same `FairOnce` it deadlocks.
+pub struct FairOnce {
+    state: AtomicI32,
+    queue: Mutex<Queue>,
+}
+
+/// Threads blocked on a `FairOnce`
+struct Queue {
+    /// Ticket of the next thread that starts waiting
+    next_ticket: u64,
+    /// Tickets and futex words of the blocked threads in arrival order
+    waiters: VecDeque<(u64, Arc<AtomicI32>)>,
+}
+
+/// Place of a thread in the queue, kept while it waits repeatedly
+type Turn = (u64, Arc<AtomicI32>);
+
+impl FairOnce {
+    /// Creates a new `FairOnce` value.
+    pub const fn new() -> Self {
+        FairOnce {
+            state: AtomicI32::new(INCOMPLETE),
+            queue: Mutex::new(Queue { next_ticket: 0, waiters: VecDeque::new() }),
+        }
+    }
+
+    /// Performs an initialization routine once and only once.
+    ///
+    /// This behaves like [`Once::call_once()`](crate::Once::call_once) except the threads waiting
+    /// for the initialization are released in the order they started waiting.
+    ///
+    /// # Panics
+    ///
+    /// If this `FairOnce` has been poisoned because an initialization closure has panicked, this
+    /// method will also panic.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        // Fast path, same as in Once
+        if self.state.load(Ordering::Acquire) == COMPLETE {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_slow calls the closure at most once
+        self.call_once_slow(false, &mut |_| unsafe { f.take()() });
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) except ignores poisoning.
+    ///
+    /// See [`Once::call_once_force()`](crate::Once::call_once_force).
+    pub fn call_once_force<F: FnOnce(&OnceState)>(&self, f: F) {
+        if self.state.load(Ordering::Acquire) == COMPLETE {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_slow calls the closure at most once
+        self.call_once_slow(true, &mut |once_state| unsafe { f.take()(once_state) });
+    }
+
+    #[cold]
+    fn call_once_slow(&self, ignore_poisoning: bool, f: &mut dyn FnMut(&OnceState)) {
+        let mut state = self.state.load(Ordering::Acquire);
+        let mut turn = None;
+        loop {
+            match state {
+                COMPLETE => break,
+                POISONED if !ignore_poisoning => panic!("Once instance has previously been poisoned"),
+                INCOMPLETE | POISONED => match self.state.compare_exchange(state, RUNNING, Ordering::Acquire, Ordering::Acquire) {
+                    Ok(_) => {
+                        let mut panic_checker = PanicChecker { once: self, value_to_write: POISONED };
+                        let once_state = OnceState::new(state == POISONED);
+                        f(&once_state);
+                        panic_checker.value_to_write = if once_state.is_poison_requested() { POISONED } else { COMPLETE };
+                        break;
+                    },
+                    Err(old) => state = old,
+                },
+                _running => state = self.wait_turn(&mut turn),
+            }
+        }
+    }
+
+    /// Waits until the running initialization finishes and all threads that came earlier left
+    ///
+    /// `turn` is assigned when the thread first queues and reused if it has to wait again.
+    /// Returns the current state.
+    fn wait_turn(&self, turn: &mut Option<Turn>) -> i32 {
+        let word = {
+            let mut queue = self.lock();
+            // the finishing thread changes the state under the lock so it can't miss us
+            let state = self.state.load(Ordering::Acquire);
+            if state != RUNNING {
+                return state;
+            }
+            let (ticket, word) = turn.get_or_insert_with(|| {
+                let ticket = queue.next_ticket;
+                queue.next_ticket += 1;
+                (ticket, Arc::new(AtomicI32::new(QUEUED)))
+            });
+            word.store(QUEUED, Ordering::Relaxed);
+            // only differs from the back if we were released before
+            let position = queue.waiters.partition_point(|(other, _)| other < ticket);
+            queue.waiters.insert(position, (*ticket, Arc::clone(word)));
+            Arc::clone(word)
+        };
+
+        while word.load(Ordering::Acquire) == QUEUED {
+            futex::wait(&word, QUEUED);
+        }
+        #[cfg(test)]
+        test_hook::record_release();
+        let state = self.state.load(Ordering::Acquire);
+        // If another initialization started in the meantime (after poisoning) the remaining
+        // waiters have to wait for it instead, and so do we after returning to our place. Its
+        // initializer releases the first of us when it finishes.
+        if state != RUNNING {
+            self.release_next();
+        }
+        state
+    }
+
+    /// Wakes up the thread that has been waiting the longest, if any
+    fn release_next(&self) {
+        let next = self.lock().waiters.pop_front();
+        if let Some((_, next)) = next {
+            next.store(RELEASED, Ordering::Release);
+            futex::wake(&next, 1);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        // nothing panics while holding the lock
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns `true` if some initialization has completed successfully.
+    ///
+    /// See [`Once::is_completed()`](crate::Once::is_completed).
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Returns `true` if an initialization closure has panicked.
+    pub fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::Acquire) == POISONED
+    }
+
+    /// Returns the number of threads waiting in the queue.
+    ///
+    /// This is intended for diagnostics, the value may change right after it was observed.
+    pub fn waiters(&self) -> usize {
+        self.lock().waiters.len()
+    }
+
+    /// Returns a snapshot of the current state.
+    ///
+    /// The state may change right after it was observed so this is mainly useful for debugging.
+    pub fn state(&self) -> OnceStatus {
+        match self.state.load(Ordering::Acquire) {
+            COMPLETE => OnceStatus::Complete,
+            POISONED => OnceStatus::Poisoned,
+            RUNNING => OnceStatus::Running,
+            _ => OnceStatus::Incomplete,
+        }
+    }
+}
+
+impl Default for FairOnce {
+    fn default() -> Self {
+        FairOnce::new()
+    }
+}
+
+impl fmt::Debug for FairOnce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FairOnce").field("state", &self.state()).finish()
+    }
+}
+
+// Same as Once
+impl UnwindSafe for FairOnce {}
+impl RefUnwindSafe for FairOnce {}
+
+/// Writes the final state and starts releasing the waiters, poisoning unless told otherwise
+struct PanicChecker<'a> {
+    once: &'a FairOnce,
+    value_to_write: i32,
+}
+
+impl<'a> Drop for PanicChecker<'a> {
+    fn drop(&mut self) {
+        {
+            // threads registering after this see the final state and don't queue
+            let _queue = self.once.lock();
+            self.once.state.store(self.value_to_write, Ordering::Release);
+        }
+        self.once.release_next();
+        if self.value_to_write == POISONED {
+            poison_hook::notify_without_details();
+        }
+    }
+}
+
+/// Records the order in which the waiters were released, which tests can't observe reliably
+/// after the waiters return since the scheduler may reorder them
+#[cfg(test)]
+pub(crate) mod test_hook {
+    use core::cell::Cell;
+    use std::sync::{Mutex, PoisonError};
+
+    thread_local! {
+        static WAITER_ID: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+
+    static RELEASED: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+    /// Marks the current thread so that its release gets recorded
+    pub(crate) fn set_waiter_id(id: usize) {
+        WAITER_ID.with(|waiter_id| waiter_id.set(Some(id)));
+    }
+
+    pub(super) fn record_release() {
+        if let Some(id) = WAITER_ID.with(Cell::get) {
+            RELEASED.lock().unwrap_or_else(PoisonError::into_inner).push(id);
+        }
+    }
+
+    /// Returns the ids of the released threads in order
+    pub(crate) fn released() -> Vec<usize> {
+        RELEASED.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+}
@@ -0,0 +1,763 @@
+//! Portable implementation of `Once` used on systems other than Linux
+//!
+//! `std::sync::Once` can't wait with a timeout, can't be tried without blocking and doesn't tell
+//! whether it's running, so this is a small state machine protected by a `Mutex` instead, with a
+//! `Condvar` for waiting. The state is mirrored in an atomic so that the fast path is a single
+//! load, just like on Linux.
+//!
+//! The public API is the same as the one of the Linux implementation, except for the parts that
+//! are inherently tied to futexes. This module is compiled on Linux too when testing so that the
+//! shared API tests run against it.
+
+use core::any::Any;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use std::panic::{self, RefUnwindSafe, UnwindSafe};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::time::{Duration, Instant};
+use crate::{CallOnceOutcome, OnceState, OnceStatus, PoisonedError, SlowInit, TryCallOnce};
+use crate::once_fn::OnceFn;
+use crate::poison_hook;
+
+/// A synchronization primitive which can be used to run a one-time global initialization. Useful
+/// for one-time initialization for FFI or related functionality. This type can only be constructed
+/// with [`Once::new()`].
+pub struct Once {
+    /// Only modified while holding `waiters` so that no wake up is lost
+    state: AtomicU8,
+    /// Number of threads waiting on `condvar`
+    waiters: Mutex<usize>,
+    condvar: Condvar,
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Once::new()
+    }
+}
+
+impl fmt::Debug for Once {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Once").field("state", &self.state()).finish()
+    }
+}
+
+// The slow paths are instantiated only once, the generic methods only wrap the closures in
+// `OnceFn`. A function generic over the closure couldn't be coerced to these higher-ranked
+// pointers.
+#[allow(clippy::type_complexity)]
+const _: () = {
+    let _: fn(&Once, &mut dyn FnMut()) = Once::call_once_slow;
+    let _: fn(&Once, bool, Option<Instant>, &mut dyn FnMut(&OnceState) -> bool) -> CallOnceOutcome = Once::internal_call_once;
+};
+
+// Same as in std: a panicking closure poisons the Once so other threads can't observe broken
+// state without explicitly asking for it (`call_once_force()`).
+impl UnwindSafe for Once {}
+impl RefUnwindSafe for Once {}
+
+/// No closure started running yet
+const INCOMPLETE: u8 = 0;
+/// The closure finished without panicking
+const COMPLETE: u8 = 1;
+/// The closure panicked
+const POISONED: u8 = 2;
+/// The closure is running
+const RUNNING: u8 = 3;
+
+/// Writes the final state and wakes up the waiters, poisoning unless told otherwise
+struct PanicChecker<'a> {
+    once: &'a Once,
+    value_to_write: u8,
+}
+
+impl<'a> Drop for PanicChecker<'a> {
+    fn drop(&mut self) {
+        let waiters = self.once.lock();
+        self.once.state.store(self.value_to_write, Ordering::Release);
+        if *waiters > 0 {
+            self.once.condvar.notify_all();
+        }
+        // the hook may access the Once so it must not run under the lock
+        drop(waiters);
+        if self.value_to_write == POISONED {
+            poison_hook::notify_without_details();
+        }
+    }
+}
+
+/// Aborts the process if dropped, used to turn panics into aborts without `catch_unwind`
+struct AbortOnPanic;
+
+impl Drop for AbortOnPanic {
+    fn drop(&mut self) {
+        std::process::abort();
+    }
+}
+
+/// Guard representing running two-phase initialization started by [`Once::begin()`]
+///
+/// The initialization must be finished by calling [`complete()`](Self::complete) or
+/// [`abort()`](Self::abort). Dropping the guard poisons the [`Once`], just like panicking in
+/// [`Once::call_once()`] does. Either way, the waiting threads are woken up.
+#[must_use = "dropping the guard poisons the Once"]
+pub struct OnceInitGuard<'a>(PanicChecker<'a>);
+
+impl<'a> OnceInitGuard<'a> {
+    /// Marks the initialization as completed successfully.
+    pub fn complete(mut self) {
+        self.0.value_to_write = COMPLETE;
+    }
+
+    /// Gives up on the initialization leaving the [`Once`] incomplete.
+    ///
+    /// One of the waiting threads or a later caller gets to perform the initialization instead.
+    pub fn abort(mut self) {
+        self.0.value_to_write = INCOMPLETE;
+    }
+}
+
+impl Once {
+    /// Creates a new `Once` value.
+    pub const fn new() -> Self {
+        Once {
+            state: AtomicU8::new(INCOMPLETE),
+            waiters: Mutex::new(0),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Creates a new `Once` value that is already completed.
+    ///
+    /// No closure passed to this `Once` will ever run.
+    pub const fn new_completed() -> Self {
+        Once {
+            state: AtomicU8::new(COMPLETE),
+            waiters: Mutex::new(0),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Performs an initialization routine once and only once. The given closure will be executed if
+    /// this is the first time `call_once` has been called, and otherwise the routine will *not* be
+    /// invoked.
+    ///
+    /// See [`std::sync::Once::call_once()`] for details.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_slow calls the closure at most once
+        self.call_once_slow(&mut || unsafe { f.take()() });
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) using exclusive access.
+    pub fn call_once_mut<F: FnOnce()>(&mut self, f: F) {
+        self.call_once(f)
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) without being generic.
+    ///
+    /// `f` is called at most once even though it's `FnMut`.
+    #[inline]
+    pub fn call_once_dyn(&self, f: &mut dyn FnMut()) {
+        if self.is_completed() {
+            return;
+        }
+
+        self.call_once_slow(f);
+    }
+
+    #[cold]
+    fn call_once_slow(&self, f: &mut dyn FnMut()) {
+        if self.internal_call_once(false, None, &mut |_| {
+            f();
+            true
+        }) == CallOnceOutcome::Poisoned {
+            panic_poisoned();
+        }
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) except ignores poisoning.
+    ///
+    /// See [`std::sync::Once::call_once_force()`] for details.
+    pub fn call_once_force<F: FnOnce(&OnceState)>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: internal_call_once calls the closure at most once
+        self.internal_call_once(true, None, &mut |once_state| {
+            unsafe { f.take()(once_state) };
+            true
+        });
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) but aborts the process
+    /// instead of unwinding.
+    pub fn call_once_abort_on_panic<F: FnOnce()>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+
+        let abort_on_panic = AbortOnPanic;
+        self.call_once(f);
+        core::mem::forget(abort_on_panic);
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) but spins while another
+    /// thread is running the initialization.
+    ///
+    /// Unlike on Linux this is only best-effort: the state is protected by a `Mutex` which may
+    /// block in the kernel when contended. Beware of priority inversion, the spinning thread may
+    /// prevent the initializer from running if they share a CPU.
+    pub fn call_once_spin<F: FnOnce()>(&self, f: F) {
+        while self.is_running() {
+            core::hint::spin_loop();
+        }
+        self.call_once(f)
+    }
+
+    /// Attempts to perform an initialization routine from a signal handler.
+    ///
+    /// Returns `true` if the [`Once`] is complete and `false` without waiting if another
+    /// initialization is running or the [`Once`] is poisoned. Panics in the closure abort the
+    /// process.
+    ///
+    /// Unlike on Linux this is only best-effort since it needs a `Mutex`, which is not
+    /// async-signal-safe. The lock is only tried, if it's taken the call returns `false` instead
+    /// of waiting, and it's held until the state is published so it's never locked again. Waking
+    /// up the threads blocked on this [`Once`], if there are any, is not async-signal-safe either.
+    /// The closure runs under the lock, so other threads calling this [`Once`] block on it
+    /// meanwhile and the closure itself must not call it.
+    pub fn call_once_from_signal<F: FnOnce()>(&self, f: F) -> bool {
+        if self.is_completed() {
+            return true;
+        }
+
+        let waiters = match self.waiters.try_lock() {
+            Ok(waiters) => waiters,
+            Err(TryLockError::Poisoned(error)) => error.into_inner(),
+            Err(TryLockError::WouldBlock) => return false,
+        };
+        match self.state.load(Ordering::Acquire) {
+            COMPLETE => true,
+            INCOMPLETE => {
+                let abort_on_panic = AbortOnPanic;
+                // not using start() and PanicChecker since they would lock again
+                self.state.store(RUNNING, Ordering::Relaxed);
+                f();
+                self.state.store(COMPLETE, Ordering::Release);
+                if *waiters > 0 {
+                    self.condvar.notify_all();
+                }
+                drop(waiters);
+                core::mem::forget(abort_on_panic);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) and reports whether the
+    /// closure was executed.
+    pub fn call_once_and_report<F: FnOnce()>(&self, f: F) -> bool {
+        if self.is_completed() {
+            return false;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: internal_call_once calls the closure at most once
+        match self.internal_call_once(false, None, &mut |_| {
+            unsafe { f.take()() };
+            true
+        }) {
+            CallOnceOutcome::Ran => true,
+            CallOnceOutcome::Poisoned => panic_poisoned(),
+            _ => false,
+        }
+    }
+
+    /// Performs a fallible initialization routine once and only once.
+    ///
+    /// If the closure returns `Err` the [`Once`] stays incomplete and the error is returned.
+    pub fn call_once_try<E, F: FnOnce() -> Result<(), E>>(&self, f: F) -> Result<(), E> {
+        if self.is_completed() {
+            return Ok(());
+        }
+
+        let mut f = OnceFn::new(f);
+        let mut result = Ok(());
+        // SAFETY: internal_call_once calls the closure at most once
+        let outcome = self.internal_call_once(false, None, &mut |_| match unsafe { f.take()() } {
+            Ok(()) => true,
+            Err(error) => {
+                result = Err(error);
+                false
+            },
+        });
+        if outcome == CallOnceOutcome::Poisoned {
+            panic_poisoned();
+        }
+        result
+    }
+
+    /// Same as [`call_once_try()`](Self::call_once_try) except a panic in `f` leaves the `Once`
+    /// incomplete instead of poisoning it, used by the `Retry` poisoning policy
+    pub(crate) fn call_once_try_retry<E, F: FnOnce() -> Result<(), E>>(&self, f: F) -> Result<(), E> {
+        match self.begin() {
+            Some(OnceInitGuard(mut panic_checker)) => {
+                // resetting to incomplete wakes the waiters so one of them can retry
+                panic_checker.value_to_write = INCOMPLETE;
+                let result = f();
+                if result.is_ok() {
+                    panic_checker.value_to_write = COMPLETE;
+                }
+                result
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Performs an initialization routine once and only once, returning an error if the [`Once`]
+    /// is poisoned.
+    ///
+    /// This behaves exactly like [`call_once()`](Self::call_once) except it returns
+    /// [`PoisonedError`] instead of panicking when it finds the [`Once`] poisoned. Poisoning
+    /// locations are not recorded on this platform so the error never contains one.
+    pub fn call_once_checked<F: FnOnce()>(&self, f: F) -> Result<(), PoisonedError> {
+        if self.is_completed() {
+            return Ok(());
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: internal_call_once calls the closure at most once
+        let outcome = self.internal_call_once(false, None, &mut |_| {
+            unsafe { f.take()() };
+            true
+        });
+        match outcome {
+            CallOnceOutcome::Poisoned => Err(PoisonedError::new(None)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Attempts to perform an initialization routine without blocking.
+    pub fn try_call_once<F: FnOnce()>(&self, f: F) -> TryCallOnce {
+        if self.is_completed() {
+            return TryCallOnce::AlreadyComplete;
+        }
+
+        let mut f = OnceFn::new(f);
+        // a deadline in the past doesn't wait at all
+        // SAFETY: internal_call_once calls the closure at most once
+        match self.internal_call_once(false, Some(Instant::now()), &mut |_| {
+            unsafe { f.take()() };
+            true
+        }) {
+            CallOnceOutcome::Ran => TryCallOnce::Ran,
+            CallOnceOutcome::AlreadyComplete => TryCallOnce::AlreadyComplete,
+            CallOnceOutcome::TimedOut => TryCallOnce::Busy,
+            CallOnceOutcome::Poisoned => TryCallOnce::Poisoned,
+        }
+    }
+
+    /// Attempts to perform an initialization routine waiting at most `timeout` for other threads.
+    pub fn call_once_timeout<F: FnOnce()>(&self, f: F, timeout: Duration) -> CallOnceOutcome {
+        if self.is_completed() {
+            return CallOnceOutcome::AlreadyComplete;
+        }
+
+        let mut f = OnceFn::new(f);
+        // None is practically infinite
+        // SAFETY: internal_call_once calls the closure at most once
+        self.internal_call_once(false, Instant::now().checked_add(timeout), &mut |_| {
+            unsafe { f.take()() };
+            true
+        })
+    }
+
+    /// Performs an initialization routine once and only once, reporting stuck initializations.
+    ///
+    /// If another thread is running an initialization routine and it didn't finish within
+    /// `timeout`, `on_timeout` is called once and this keeps waiting.
+    pub fn call_once_watched<F: FnOnce(), H: FnOnce(&SlowInit)>(&self, f: F, timeout: Duration, on_timeout: H) {
+        if self.is_completed() {
+            return;
+        }
+
+        let start = Instant::now();
+        let mut f = OnceFn::new(f);
+        // SAFETY: internal_call_once calls the closure at most once and it doesn't call it if it
+        // times out, so the closure runs at most once across both calls below
+        let mut run = |_: &OnceState| {
+            unsafe { f.take()() };
+            true
+        };
+        let outcome = match self.internal_call_once(false, start.checked_add(timeout), &mut run) {
+            CallOnceOutcome::TimedOut => {
+                on_timeout(&SlowInit { waited: start.elapsed(), waiters: self.waiters(), name: None });
+                self.internal_call_once(false, None, &mut run)
+            },
+            outcome => outcome,
+        };
+        if outcome == CallOnceOutcome::Poisoned {
+            panic_poisoned();
+        }
+    }
+
+    /// Performs an initialization routine once and only once, catching panics.
+    ///
+    /// If the closure panics the payload is returned and the [`Once`] stays incomplete.
+    pub fn call_once_catch<F: FnOnce()>(&self, f: F) -> Result<(), Box<dyn Any + Send>> {
+        self.call_once_try(|| panic::catch_unwind(panic::AssertUnwindSafe(f)))
+    }
+
+    /// Runs `f` if no initialization has completed, waiting for running ones until `deadline`
+    ///
+    /// `f` returns `false` if the initialization failed and the `Once` should stay incomplete.
+    fn internal_call_once(&self, ignore_poisoning: bool, deadline: Option<Instant>, f: &mut dyn FnMut(&OnceState) -> bool) -> CallOnceOutcome {
+        let (waiters, state) = self.wait_while(deadline, |state| state == RUNNING);
+        match state {
+            COMPLETE => CallOnceOutcome::AlreadyComplete,
+            POISONED if !ignore_poisoning => CallOnceOutcome::Poisoned,
+            RUNNING => CallOnceOutcome::TimedOut,
+            _ => {
+                let mut panic_checker = self.start(waiters);
+                let once_state = OnceState::new(state == POISONED);
+                let succeeded = f(&once_state);
+                panic_checker.value_to_write = if once_state.is_poison_requested() {
+                    POISONED
+                } else if succeeded {
+                    COMPLETE
+                } else {
+                    INCOMPLETE
+                };
+                CallOnceOutcome::Ran
+            },
+        }
+    }
+
+    /// Starts a two-phase initialization.
+    ///
+    /// Returns `Some` if the calling thread won the right to perform the initialization and
+    /// `None` if some initialization has completed already.
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned, this method will also panic.
+    pub fn begin(&self) -> Option<OnceInitGuard<'_>> {
+        if self.is_completed() {
+            return None;
+        }
+
+        let (waiters, state) = self.wait_while(None, |state| state == RUNNING);
+        match state {
+            COMPLETE => None,
+            POISONED => panic_poisoned(),
+            _ => Some(OnceInitGuard(self.start(waiters))),
+        }
+    }
+
+    /// Blocks the current thread until initialization has completed.
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned, this method will also panic.
+    pub fn wait(&self) {
+        self.wait_deadline_opt(None);
+    }
+
+    /// Blocks the current thread until initialization has completed, ignoring poisoning.
+    pub fn wait_force(&self) {
+        let state = self.state.load(Ordering::Acquire);
+        if state == COMPLETE || state == POISONED {
+            return;
+        }
+
+        drop(self.wait_while(None, |state| state != COMPLETE && state != POISONED));
+    }
+
+    /// Blocks the current thread until initialization has completed or the timeout expires.
+    ///
+    /// Returns `true` if the initialization has completed.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        // None is practically infinite
+        self.wait_deadline_opt(Instant::now().checked_add(timeout))
+    }
+
+    /// Blocks the current thread until initialization has completed or the deadline passes.
+    ///
+    /// Returns `true` if the initialization has completed.
+    pub fn wait_deadline(&self, deadline: Instant) -> bool {
+        self.wait_deadline_opt(Some(deadline))
+    }
+
+    fn wait_deadline_opt(&self, deadline: Option<Instant>) -> bool {
+        if self.is_completed() {
+            return true;
+        }
+
+        match self.wait_while(deadline, |state| state != COMPLETE && state != POISONED).1 {
+            COMPLETE => true,
+            POISONED => panic_poisoned(),
+            _ => false,
+        }
+    }
+
+    /// Blocks while `condition` holds for the state, at most until the deadline passes
+    ///
+    /// Returns the lock along with the last observed state so that the caller can act on it.
+    fn wait_while(&self, deadline: Option<Instant>, mut condition: impl FnMut(u8) -> bool) -> (MutexGuard<'_, usize>, u8) {
+        let mut waiters = self.lock();
+        loop {
+            let state = self.state.load(Ordering::Acquire);
+            if !condition(state) {
+                break (waiters, state);
+            }
+            let timeout = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(timeout) if timeout > Duration::from_secs(0) => Some(timeout),
+                    _ => break (waiters, state),
+                },
+                None => None,
+            };
+            *waiters += 1;
+            waiters = match timeout {
+                Some(timeout) => self.condvar.wait_timeout(waiters, timeout).unwrap_or_else(PoisonError::into_inner).0,
+                None => self.condvar.wait(waiters).unwrap_or_else(PoisonError::into_inner),
+            };
+            *waiters -= 1;
+        }
+    }
+
+    /// Marks the `Once` as running, the state must've been observed incomplete or poisoned
+    fn start(&self, waiters: MutexGuard<'_, usize>) -> PanicChecker<'_> {
+        self.state.store(RUNNING, Ordering::Relaxed);
+        drop(waiters);
+        PanicChecker { once: self, value_to_write: POISONED }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        // nothing panics while holding the lock
+        self.waiters.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the number of threads blocked waiting for this `Once`.
+    pub fn waiters(&self) -> usize {
+        *self.lock()
+    }
+
+    /// Returns `true` if some [`call_once()`](Self::call_once) call has completed successfully.
+    ///
+    /// See [`std::sync::Once::is_completed()`] for details.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Returns `true` if some initialization has completed, without synchronizing with it.
+    pub fn is_completed_relaxed(&self) -> bool {
+        self.state.load(Ordering::Relaxed) == COMPLETE
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) except the check for
+    /// completion is `Relaxed`.
+    pub fn call_once_relaxed_check<F: FnOnce()>(&self, f: F) {
+        if self.is_completed_relaxed() {
+            return;
+        }
+
+        self.call_once(f)
+    }
+
+    /// Poisons an incomplete [`Once`] without running any closure.
+    ///
+    /// Only available with the `test-util` feature.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn poison(&self) {
+        let waiters = self.lock();
+        if self.state.load(Ordering::Relaxed) == INCOMPLETE {
+            self.state.store(POISONED, Ordering::Release);
+            if *waiters > 0 {
+                self.condvar.notify_all();
+            }
+            drop(waiters);
+            poison_hook::notify_without_details();
+        }
+    }
+
+    /// Clears the poisoned state so that the next call runs its closure again.
+    pub fn clear_poison(&self) {
+        let _waiters = self.lock();
+        if self.state.load(Ordering::Relaxed) == POISONED {
+            self.state.store(INCOMPLETE, Ordering::Release);
+        }
+    }
+
+    /// Marks the [`Once`] as completed without running any closure.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the initialization guarded by this `Once` has really been
+    /// performed and that all its memory effects happen-before this call.
+    pub unsafe fn mark_completed(&self) {
+        self.call_once_force(|_| ())
+    }
+
+    /// Initializes `slot` with the value returned by `init` once and only once, returning the
+    /// reference to the stored value.
+    ///
+    /// # Safety
+    ///
+    /// The `Once` must guard exactly this `slot`: `slot` must be initialized whenever the `Once`
+    /// is complete, nobody else may access it before that and afterwards it may only be read
+    /// until the lifetime `'a` ends.
+    pub unsafe fn call_once_init<'a, T, F: FnOnce() -> T>(&self, slot: &'a UnsafeCell<MaybeUninit<T>>, init: F) -> &'a T {
+        self.call_once(|| {
+            let value = init();
+            // SAFETY: only the thread running the initialization gets here and the caller
+            // guarantees nobody else accesses the slot before the Once completes
+            unsafe { (*slot.get()).write(value); }
+        });
+        // SAFETY: call_once returns only after the Once completed and the caller guarantees the
+        // slot is initialized in that case and not written anymore
+        unsafe { (*slot.get()).assume_init_ref() }
+    }
+
+    /// Returns `true` if a closure passed to [`call_once()`](Self::call_once) or
+    /// [`call_once_force()`](Self::call_once_force) panicked and no subsequent
+    /// [`call_once_force()`](Self::call_once_force) call has completed successfully.
+    ///
+    /// Just like with [`is_completed()`](Self::is_completed), the returned value may be stale by
+    /// the time it's observed.
+    pub fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::Acquire) == POISONED
+    }
+
+    /// Returns `true` if an initialization routine is running right now.
+    ///
+    /// The routine may finish right after this returns so the returned value is inherently racy.
+    pub fn is_running(&self) -> bool {
+        self.state.load(Ordering::Acquire) == RUNNING
+    }
+
+    /// Returns a snapshot of the current state.
+    ///
+    /// The state may change right after it was observed so this is mainly useful for debugging.
+    pub fn state(&self) -> OnceStatus {
+        match self.state.load(Ordering::Acquire) {
+            INCOMPLETE => OnceStatus::Incomplete,
+            COMPLETE => OnceStatus::Complete,
+            POISONED => OnceStatus::Poisoned,
+            _ => OnceStatus::Running,
+        }
+    }
+
+    /// Panics because the `Once` is poisoned, same as the Linux implementation
+    pub(crate) fn panic_poisoned(&self) -> ! {
+        panic_poisoned()
+    }
+}
+
+#[cold]
+fn panic_poisoned() -> ! {
+    panic!("Once instance has previously been poisoned")
+}
+
+/// A variant of [`Once`] that retries the initialization after panics.
+///
+/// If the initialization closure panics the `RetryOnce` is reset to incomplete instead and the
+/// threads waiting for it are woken up. A `RetryOnce` created by
+/// [`with_retry_budget()`](Self::with_retry_budget) only allows the given number of retries and
+/// gets poisoned after that.
+pub struct RetryOnce {
+    once: Once,
+    /// Number of remaining retries, only accessed by the thread running the initialization
+    budget: AtomicU32,
+}
+
+/// Retry budget of a `RetryOnce` that is never poisoned
+const UNLIMITED_RETRIES: u32 = u32::MAX;
+
+impl RetryOnce {
+    /// Creates a new `RetryOnce` value.
+    pub const fn new() -> Self {
+        RetryOnce::with_retry_budget(UNLIMITED_RETRIES)
+    }
+
+    /// Creates a new `RetryOnce` value that allows at most `retries` retries.
+    ///
+    /// After the closure panics `retries + 1` times in total the `RetryOnce` is permanently
+    /// poisoned and further calls panic. `u32::MAX` is treated as unlimited.
+    pub const fn with_retry_budget(retries: u32) -> Self {
+        RetryOnce { once: Once::new(), budget: AtomicU32::new(retries) }
+    }
+
+    /// Performs an initialization routine once and only once.
+    ///
+    /// A panic in the closure resets the `RetryOnce` to incomplete, unless the retry budget is
+    /// exhausted, and is propagated.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        if self.once.is_completed() {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        let mut payload = None;
+        // SAFETY: internal_call_once calls the closure at most once
+        let outcome = self.once.internal_call_once(false, None, &mut |once_state| {
+            match panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe { f.take()() })) {
+                Ok(()) => true,
+                Err(error) => {
+                    // ordered by the state transitions since only the initializing thread
+                    // accesses it
+                    match self.budget.load(Ordering::Relaxed) {
+                        0 => once_state.poison(),
+                        UNLIMITED_RETRIES => (),
+                        remaining => self.budget.store(remaining - 1, Ordering::Relaxed),
+                    }
+                    payload = Some(error);
+                    false
+                },
+            }
+        });
+        if outcome == CallOnceOutcome::Poisoned {
+            panic_poisoned();
+        }
+        if let Some(payload) = payload {
+            panic::resume_unwind(payload)
+        }
+    }
+
+    /// Returns `true` if some [`call_once()`](Self::call_once) call has completed successfully.
+    pub fn is_completed(&self) -> bool {
+        self.once.is_completed()
+    }
+
+    /// Returns `true` if an initialization routine is running right now.
+    pub fn is_running(&self) -> bool {
+        self.once.is_running()
+    }
+
+    /// Returns `true` if the retry budget was exhausted and the last closure panicked.
+    pub fn is_poisoned(&self) -> bool {
+        self.once.is_poisoned()
+    }
+}
+
+impl Default for RetryOnce {
+    fn default() -> Self {
+        RetryOnce::new()
+    }
+}
+
+impl fmt::Debug for RetryOnce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryOnce").field("state", &self.once.state()).finish()
+    }
+}
@@ -0,0 +1,438 @@
+//! Low-level futex operations
+//!
+//! These are the wrappers of the `futex` syscall all primitives of this crate are built on,
+//! exposed for building other small primitives. They operate on a 32-bit atomic and are private
+//! to the process (`FUTEX_PRIVATE_FLAG`). A minimal flag that threads can wait for looks like
+//! this:
+//!
+//! ```
+//! use linux_once::futex;
+//! use std::sync::atomic::{AtomicI32, Ordering};
+//!
+//! struct Flag(AtomicI32);
+//!
+//! impl Flag {
+//!     fn set(&self) {
+//!         self.0.store(1, Ordering::Release);
+//!         futex::wake_all(&self.0);
+//!     }
+//!
+//!     fn wait(&self) {
+//!         // the wait may return spuriously so the value has to be checked again
+//!         while self.0.load(Ordering::Acquire) == 0 {
+//!             futex::wait(&self.0, 0);
+//!         }
+//!     }
+//! }
+//!
+//! static FLAG: Flag = Flag(AtomicI32::new(0));
+//!
+//! let waiter = std::thread::spawn(|| FLAG.wait());
+//! FLAG.set();
+//! waiter.join().unwrap();
+//! ```
+//!
+//! The syscalls are made directly, not through `linux_futex`, because it panics on unexpected
+//! errors. That's a problem in sandboxes where seccomp denies the syscall with `EPERM` (or
+//! pretends it doesn't exist with `ENOSYS`). If a syscall is denied this module remembers it and
+//! from then on the waits poll the value instead. This preserves correctness, only the latency of
+//! waking up gets worse. Other errors are bugs and panic.
+//!
+//! Note that if the syscall is denied only to some threads, a thread that can't wake others up
+//! may leave threads that could block stuck in the kernel. Sandboxes usually apply the policy to
+//! the whole process so this is not handled.
+
+use core::convert::TryInto;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// Reason why a single attempt to wait returned without the initialization completing
+///
+/// Returned by [`Once::try_wait_raw()`](crate::Once::try_wait_raw).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitError {
+    /// The thread was interrupted by a signal (`EINTR`)
+    Interrupted,
+    /// The state changed before the thread could block (`EAGAIN`) or the thread was woken up
+    /// without the initialization completing
+    ValueChanged,
+    /// The deadline passed (`ETIMEDOUT`)
+    TimedOut,
+    /// The syscall failed with an unexpected error, such as `EPERM` when denied by seccomp
+    Os(i32),
+}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitError::Interrupted => f.write_str("waiting was interrupted by a signal"),
+            WaitError::ValueChanged => f.write_str("the state changed without the initialization completing"),
+            WaitError::TimedOut => f.write_str("the deadline passed before the initialization completed"),
+            WaitError::Os(errno) => write!(f, "the futex syscall failed: {}", std::io::Error::from_raw_os_error(*errno)),
+        }
+    }
+}
+
+impl std::error::Error for WaitError {}
+
+/// Set once a futex syscall was denied, after that we only poll
+static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Set once a PI futex syscall was denied or isn't supported, after that we spin
+///
+/// Separate from `UNAVAILABLE` since some architectures lack only the PI operations.
+static PI_UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Bitset matching all waiters, which makes the bitset operations behave like the plain ones
+const BITSET_MATCH_ANY: u32 = !0;
+
+/// Mask of the owner id in a PI futex, the high bits are flags set by the kernel
+const TID_MASK: u32 = 0x3fff_ffff;
+
+/// The reason why a wait returned
+///
+/// The value has to be checked again regardless of the reason: the threads may be woken up
+/// spuriously and the value may change again right after a wake up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitResult {
+    /// The thread was woken up, possibly spuriously or by a signal, or it noticed the value
+    /// changed while polling
+    Woken,
+    /// The value didn't match the expected one so the thread didn't block at all
+    ValueChanged,
+    /// The deadline passed
+    TimedOut,
+}
+
+/// Blocks the current thread until it's woken up, if `futex` contains `expected`.
+///
+/// The check and blocking are atomic with respect to [`wake()`]: if another thread changes the
+/// value and then calls [`wake()`] this thread either doesn't block or is woken up. The wait may
+/// return spuriously, the caller has to check the value again.
+pub fn wait(futex: &AtomicI32, expected: i32) -> WaitResult {
+    wait_until(futex, expected, None)
+}
+
+/// Same as [`wait()`] except it returns [`WaitResult::TimedOut`] after `timeout` passes.
+pub fn wait_timeout(futex: &AtomicI32, expected: i32, timeout: Duration) -> WaitResult {
+    // timeouts too large to represent never pass
+    wait_until(futex, expected, Instant::now().checked_add(timeout))
+}
+
+/// Same as [`wait()`] except it returns [`WaitResult::TimedOut`] once `deadline` passes.
+pub fn wait_deadline(futex: &AtomicI32, expected: i32, deadline: Instant) -> WaitResult {
+    wait_until(futex, expected, Some(deadline))
+}
+
+/// Blocks until `futex` is woken up if it contains `expected`.
+///
+/// May return spuriously, the caller has to check the value again. If `deadline` is `Some` the
+/// call returns once it passes.
+pub(crate) fn wait_until(futex: &AtomicI32, expected: i32, deadline: Option<Instant>) -> WaitResult {
+    wait_masked(futex, expected, deadline, BITSET_MATCH_ANY)
+}
+
+/// Same as [`wait()`] except only the wakes whose bitset intersects `bitset` wake the thread up
+///
+/// Used to share a single futex between waiters of independent events.
+pub(crate) fn wait_bitset(futex: &AtomicI32, expected: i32, bitset: u32) -> WaitResult {
+    wait_masked(futex, expected, None, bitset)
+}
+
+fn wait_masked(futex: &AtomicI32, expected: i32, deadline: Option<Instant>, bitset: u32) -> WaitResult {
+    if !UNAVAILABLE.load(Ordering::Relaxed) {
+        match sys_wait(futex, expected, deadline, bitset) {
+            // A signal is just a spurious wake up, the caller checks the value regardless
+            Ok(()) | Err(libc::EINTR) => return WaitResult::Woken,
+            Err(libc::EAGAIN) => return WaitResult::ValueChanged,
+            Err(libc::ETIMEDOUT) => return WaitResult::TimedOut,
+            Err(libc::EPERM) | Err(libc::ENOSYS) => UNAVAILABLE.store(true, Ordering::Relaxed),
+            Err(errno) => panic!("FUTEX_WAIT_BITSET failed with unexpected error {}", errno),
+        }
+    }
+    poll(futex, expected, deadline)
+}
+
+/// Performs a single wait syscall, reporting all errors
+pub(crate) fn wait_raw(futex: &AtomicI32, expected: i32, deadline: Option<Instant>) -> Result<(), WaitError> {
+    sys_wait(futex, expected, deadline, BITSET_MATCH_ANY).map_err(|errno| match errno {
+        libc::EAGAIN => WaitError::ValueChanged,
+        libc::EINTR => WaitError::Interrupted,
+        libc::ETIMEDOUT => WaitError::TimedOut,
+        errno => WaitError::Os(errno),
+    })
+}
+
+/// Wakes up at most `count` threads blocked in [`wait()`] on `futex`.
+///
+/// This always attempts the syscall since some threads could've blocked before the value
+/// changed. Threads polling because the syscalls are denied notice the change of the value on
+/// their own.
+pub fn wake(futex: &AtomicI32, count: i32) {
+    wake_bitset(futex, count, BITSET_MATCH_ANY)
+}
+
+/// Wakes up all threads blocked in [`wait()`] on `futex`.
+pub fn wake_all(futex: &AtomicI32) {
+    wake(futex, i32::MAX)
+}
+
+/// Same as [`wake()`] except it only wakes up the threads blocked in [`wait_bitset()`] with a
+/// bitset intersecting `bitset`
+pub(crate) fn wake_bitset(futex: &AtomicI32, count: i32, bitset: u32) {
+    match sys_wake(futex, count, bitset) {
+        Ok(()) => (),
+        Err(libc::EPERM) | Err(libc::ENOSYS) => UNAVAILABLE.store(true, Ordering::Relaxed),
+        Err(errno) => panic!("FUTEX_WAKE_BITSET failed with unexpected error {}", errno),
+    }
+}
+
+/// Same as [`wake()`] except it only performs async-signal-safe operations
+///
+/// Unexpected errors abort the process since panicking is not allowed in signal handlers.
+pub(crate) fn wake_from_signal(futex: &AtomicI32, count: i32) {
+    match sys_wake(futex, count, BITSET_MATCH_ANY) {
+        Ok(()) => (),
+        Err(libc::EPERM) | Err(libc::ENOSYS) => UNAVAILABLE.store(true, Ordering::Relaxed),
+        Err(_) => std::process::abort(),
+    }
+}
+
+/// Returns the id of the current thread as stored in PI futexes
+pub(crate) fn gettid() -> u32 {
+    // SAFETY: the syscall has no arguments and always succeeds
+    unsafe { libc::syscall(libc::SYS_gettid) as u32 }
+}
+
+/// Returns the id of the thread owning the PI futex, zero if it's unlocked
+pub(crate) fn pi_owner(futex: &AtomicU32) -> u32 {
+    futex.load(Ordering::Relaxed) & TID_MASK
+}
+
+/// Locks a PI futex, `tid` must be the id of the current thread
+///
+/// While the current thread is blocked the kernel boosts the priority of the owner. If the
+/// syscalls are denied the lock is acquired by spinning and there's no boosting.
+pub(crate) fn lock_pi(futex: &AtomicU32, tid: u32) {
+    loop {
+        if futex.compare_exchange_weak(0, tid, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+            return;
+        }
+        if PI_UNAVAILABLE.load(Ordering::Relaxed) {
+            std::thread::yield_now();
+            continue;
+        }
+        match sys_lock_pi(futex) {
+            Ok(()) => return,
+            // the owner is exiting or we were interrupted, the state has to be checked again
+            Err(libc::EAGAIN) | Err(libc::EINTR) => (),
+            Err(libc::EPERM) | Err(libc::ENOSYS) => PI_UNAVAILABLE.store(true, Ordering::Relaxed),
+            Err(errno) => panic!("FUTEX_LOCK_PI failed with unexpected error {}", errno),
+        }
+    }
+}
+
+/// Unlocks a PI futex locked by [`lock_pi()`] on the current thread
+///
+/// If some threads are blocked the kernel hands the lock over to the one with highest priority.
+pub(crate) fn unlock_pi(futex: &AtomicU32, tid: u32) {
+    // the kernel sets the waiters bit so this only fails if some thread is blocked
+    if futex.compare_exchange(tid, 0, Ordering::Release, Ordering::Relaxed).is_err() {
+        if let Err(errno) = sys_unlock_pi(futex) {
+            panic!("FUTEX_UNLOCK_PI failed with unexpected error {}", errno);
+        }
+    }
+}
+
+/// Replacement of the syscall for sandboxes, returns once the value has changed or the deadline
+/// passed
+fn poll(futex: &AtomicI32, expected: i32, deadline: Option<Instant>) -> WaitResult {
+    // the initializer might be just about to finish so yield first and then back off
+    const YIELDS: u32 = 10;
+    const MAX_SLEEP: Duration = Duration::from_millis(1);
+
+    let mut sleep = Duration::from_micros(10);
+    let mut iteration = 0;
+    if futex.load(Ordering::Acquire) != expected {
+        return WaitResult::ValueChanged;
+    }
+    while futex.load(Ordering::Acquire) == expected {
+        let remaining = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if remaining > Duration::from_secs(0) => Some(remaining),
+                _ => return WaitResult::TimedOut,
+            },
+            None => None,
+        };
+
+        if iteration < YIELDS {
+            iteration += 1;
+            std::thread::yield_now();
+        } else {
+            std::thread::sleep(remaining.map_or(sleep, |remaining| remaining.min(sleep)));
+            sleep = (sleep * 2).min(MAX_SLEEP);
+        }
+    }
+    WaitResult::Woken
+}
+
+fn sys_wait(futex: &AtomicI32, expected: i32, deadline: Option<Instant>, bitset: u32) -> Result<(), i32> {
+    #[cfg(test)]
+    test_hook::check()?;
+
+    let timeout = match deadline {
+        Some(deadline) => Some(monotonic_timespec(deadline)?),
+        None => None,
+    };
+    let timeout_ptr = timeout.as_ref().map_or(core::ptr::null(), |timeout| timeout as *const libc::timespec);
+    #[cfg(feature = "stats")]
+    crate::stats::record_blocked();
+    // SAFETY: the pointers are valid for the duration of the call
+    // FUTEX_WAIT_BITSET interprets the timeout as an absolute CLOCK_MONOTONIC time, same clock
+    // Instant uses
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            futex.as_ptr(),
+            libc::FUTEX_WAIT_BITSET | libc::FUTEX_PRIVATE_FLAG,
+            expected,
+            timeout_ptr,
+            core::ptr::null::<u32>(),
+            bitset,
+        )
+    };
+    if result == -1 {
+        Err(errno())
+    } else {
+        Ok(())
+    }
+}
+
+fn sys_wake(futex: &AtomicI32, count: i32, bitset: u32) -> Result<(), i32> {
+    #[cfg(test)]
+    test_hook::check()?;
+
+    #[cfg(feature = "stats")]
+    crate::stats::record_wake();
+    // SAFETY: the pointer is valid for the duration of the call, the timeout and the second
+    // futex are ignored by FUTEX_WAKE_BITSET
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            futex.as_ptr(),
+            libc::FUTEX_WAKE_BITSET | libc::FUTEX_PRIVATE_FLAG,
+            count,
+            core::ptr::null::<libc::timespec>(),
+            core::ptr::null::<u32>(),
+            bitset,
+        )
+    };
+    if result == -1 {
+        Err(errno())
+    } else {
+        Ok(())
+    }
+}
+
+fn sys_lock_pi(futex: &AtomicU32) -> Result<(), i32> {
+    #[cfg(test)]
+    test_hook::check()?;
+
+    // SAFETY: the pointer is valid for the duration of the call, null timeout means no timeout
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            futex.as_ptr(),
+            libc::FUTEX_LOCK_PI | libc::FUTEX_PRIVATE_FLAG,
+            0,
+            core::ptr::null::<libc::timespec>(),
+        )
+    };
+    if result == -1 {
+        Err(errno())
+    } else {
+        Ok(())
+    }
+}
+
+fn sys_unlock_pi(futex: &AtomicU32) -> Result<(), i32> {
+    // SAFETY: the pointer is valid for the duration of the call
+    let result = unsafe {
+        libc::syscall(libc::SYS_futex, futex.as_ptr(), libc::FUTEX_UNLOCK_PI | libc::FUTEX_PRIVATE_FLAG)
+    };
+    if result == -1 {
+        Err(errno())
+    } else {
+        Ok(())
+    }
+}
+
+/// Converts the deadline to an absolute `CLOCK_MONOTONIC` time
+fn monotonic_timespec(deadline: Instant) -> Result<libc::timespec, i32> {
+    let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: the pointer is valid
+    if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) } == -1 {
+        return Err(errno());
+    }
+    // Instant uses the same clock so the difference stays the same
+    Ok(add_timespec(now, deadline.saturating_duration_since(Instant::now())))
+}
+
+/// Adds `duration` to `time`, saturating instead of overflowing
+///
+/// Deadlines in the past result in zero `duration` so the syscall returns immediately.
+pub(crate) fn add_timespec(time: libc::timespec, duration: Duration) -> libc::timespec {
+    let mut nanos = time.tv_nsec + duration.subsec_nanos() as libc::c_long;
+    let mut secs = time.tv_sec.saturating_add(duration.as_secs().try_into().unwrap_or(libc::time_t::MAX));
+    if nanos >= 1_000_000_000 {
+        nanos -= 1_000_000_000;
+        secs = secs.saturating_add(1);
+    }
+    libc::timespec { tv_sec: secs, tv_nsec: nanos }
+}
+
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+/// Allows tests to simulate denied syscalls without a sandbox
+#[cfg(test)]
+pub(crate) mod test_hook {
+    use core::cell::Cell;
+    use core::sync::atomic::Ordering;
+
+    thread_local! {
+        static INJECTED_ERROR: Cell<Option<i32>> = const { Cell::new(None) };
+    }
+
+    /// Makes futex syscalls made by the current thread fail with `errno`
+    pub(crate) fn inject_error(errno: Option<i32>) {
+        INJECTED_ERROR.with(|error| error.set(errno));
+    }
+
+    /// Returns `true` if some syscall was denied
+    pub(crate) fn is_unavailable() -> bool {
+        super::UNAVAILABLE.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if some PI syscall was denied
+    pub(crate) fn is_pi_unavailable() -> bool {
+        super::PI_UNAVAILABLE.load(Ordering::Relaxed)
+    }
+
+    /// Makes the syscalls available again
+    ///
+    /// Other tests running in parallel may observe the fallback, which is fine since it's correct.
+    pub(crate) fn reset() {
+        super::UNAVAILABLE.store(false, Ordering::Relaxed);
+        super::PI_UNAVAILABLE.store(false, Ordering::Relaxed);
+    }
+
+    pub(super) fn check() -> Result<(), i32> {
+        match INJECTED_ERROR.with(Cell::get) {
+            Some(errno) => Err(errno),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Value initialized at most once and cleaned up at most once
+//!
+//! The value is guarded by `init` exactly like in `OnceCell`. Cleaning up first sets `cleaned`
+//! and then completes `init` without a value if it wasn't completed yet, which also waits for a
+//! running initialization. Thus whoever observes `init` complete has to check `cleaned` afterwards:
+//! if `init` was completed by the cleanup then `cleaned` is visible since it was set before.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use crate::Once;
+
+/// A cell initialized at most once whose value is consumed by a cleanup function at most once.
+///
+/// This is useful for handles returned by global initialization functions of C libraries, which
+/// have to be passed to the corresponding cleanup function exactly once, usually at exit.
+///
+/// After the cleanup started [`get()`](Self::get) returns `None` and
+/// [`get_or_init()`](Self::get_or_init) panics, the cell is never initialized again. If the cell
+/// is dropped without being cleaned up the value is dropped normally.
+///
+/// ```
+/// use linux_once::GuardedCell;
+///
+/// static HANDLE: GuardedCell<u32> = GuardedCell::new();
+///
+/// assert_eq!(*HANDLE.get_or_init(|| 42), 42);
+/// // SAFETY: HANDLE is not accessed anymore
+/// unsafe { HANDLE.cleanup(|handle| assert_eq!(handle, 42)); }
+/// assert_eq!(HANDLE.get(), None);
+/// ```
+pub struct GuardedCell<T> {
+    init: Once,
+    cleanup: Once,
+    cleaned: AtomicBool,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> GuardedCell<T> {
+    /// Creates a new empty cell.
+    pub const fn new() -> Self {
+        GuardedCell {
+            init: Once::new(),
+            cleanup: Once::new(),
+            cleaned: AtomicBool::new(false),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Returns the value if the cell was initialized and not cleaned up.
+    ///
+    /// This never blocks.
+    pub fn get(&self) -> Option<&T> {
+        // the order matters, see the module documentation
+        if self.init.is_completed() && !self.cleaned.load(Ordering::Acquire) {
+            // SAFETY: the Once was completed by the initialization
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value, initializing it with `f` if the cell is empty.
+    ///
+    /// If another thread is initializing the cell this blocks until it finishes.
+    ///
+    /// # Panics
+    ///
+    /// If the cell was cleaned up this method panics. If `f` panics the panic is propagated and
+    /// the cell is poisoned, accessing a poisoned cell panics too.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        self.init.call_once(|| {
+            let value = f();
+            // SAFETY: only the closure of the Once writes the value and nobody reads it before the
+            // Once completes
+            unsafe { (*self.value.get()).write(value); }
+        });
+        if self.cleaned.load(Ordering::Acquire) {
+            panic!("GuardedCell used after cleanup");
+        }
+        // SAFETY: the Once was completed by the initialization
+        unsafe { self.get_unchecked() }
+    }
+
+    /// Passes the value to `f` if the cell was initialized, ensuring it happens at most once.
+    ///
+    /// Only the first call does anything, the other ones block until it finishes. If an
+    /// initialization is running on another thread the first call waits for it. Returns `true`
+    /// if this call passed the value to `f`.
+    ///
+    /// The cell can't be initialized after this was called. A poisoned cell is cleaned up without
+    /// calling `f` since there's no value.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics the panic is propagated and the value is considered consumed. Further calls
+    /// panic as well.
+    ///
+    /// # Safety
+    ///
+    /// The references returned by this cell must not be used after this is called, in particular
+    /// no other thread may be using them concurrently.
+    pub unsafe fn cleanup<F: FnOnce(T)>(&self, f: F) -> bool {
+        let mut cleaned_up = false;
+        self.cleanup.call_once(|| {
+            self.cleaned.store(true, Ordering::Release);
+            let mut initialized = true;
+            self.init.call_once_force(|_| initialized = false);
+            if initialized {
+                // SAFETY: the Once was completed by the initialization and the value is never
+                // accessed again since cleaned is set
+                f(unsafe { (*self.value.get()).assume_init_read() });
+                cleaned_up = true;
+            }
+        });
+        cleaned_up
+    }
+
+    /// Returns `true` if the cleanup started.
+    pub fn is_cleaned_up(&self) -> bool {
+        self.cleaned.load(Ordering::Acquire)
+    }
+
+    /// # Safety
+    ///
+    /// The `init` Once must have been completed by the initialization.
+    unsafe fn get_unchecked(&self) -> &T {
+        (*self.value.get()).assume_init_ref()
+    }
+}
+
+impl<T> Drop for GuardedCell<T> {
+    fn drop(&mut self) {
+        if self.init.is_completed() && !*self.cleaned.get_mut() {
+            // SAFETY: the Once was completed by the initialization and it's never accessed again
+            unsafe { self.value.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+impl<T> Default for GuardedCell<T> {
+    fn default() -> Self {
+        GuardedCell::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for GuardedCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("GuardedCell").field(value).finish(),
+            None if self.is_cleaned_up() => f.write_str("GuardedCell(<cleaned up>)"),
+            None => f.debug_struct("GuardedCell").field("state", &self.init.state()).finish(),
+        }
+    }
+}
+
+// SAFETY: same as OnceCell, the value is additionally moved to the thread cleaning up
+unsafe impl<T: Send + Sync> Sync for GuardedCell<T> {}
+// SAFETY: sending the cell sends the value
+unsafe impl<T: Send> Send for GuardedCell<T> {}
+
+// Same as OnceCell
+impl<T: RefUnwindSafe + UnwindSafe> RefUnwindSafe for GuardedCell<T> {}
+impl<T: UnwindSafe> UnwindSafe for GuardedCell<T> {}
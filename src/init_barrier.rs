@@ -0,0 +1,111 @@
+//! Barrier whose last participant runs the initialization
+//!
+//! The arrivals are counted separately and the rest is a `Once`: the arrival that brings the
+//! count to `n` calls the closure through it and the others wait for it, so blocking, waking and
+//! poisoning behave exactly like in `Once`.
+
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+use crate::Once;
+
+/// Runs a closure once after a given number of participants arrived.
+///
+/// This is useful for a one-time initialization that has to wait until all worker threads
+/// checked in, e.g. because it takes a snapshot of their state. The first `n - 1` calls to
+/// [`arrive_and_call()`](Self::arrive_and_call) block, the `n`-th one runs its closure and then
+/// releases all of them. The closures passed by the other participants are never called.
+///
+/// ```
+/// use linux_once::InitBarrier;
+/// use std::sync::Arc;
+///
+/// let barrier = Arc::new(InitBarrier::new(3));
+/// let workers = (0..3)
+///     .map(|_| {
+///         let barrier = Arc::clone(&barrier);
+///         std::thread::spawn(move || barrier.arrive_and_call(|| println!("all workers arrived")))
+///     })
+///     .collect::<Vec<_>>();
+/// for worker in workers {
+///     worker.join().unwrap();
+/// }
+/// assert!(barrier.is_completed());
+/// ```
+///
+/// The arrivals are counted, not the threads: a thread arriving again after it was released is
+/// just a later arrival and returns immediately, and so do all arrivals after the `n`-th one once
+/// the closure finished. The closure must not arrive at the same barrier since the barrier waits
+/// for the closure: on Linux this panics like a recursive [`Once`] and elsewhere it deadlocks.
+pub struct InitBarrier {
+    arrived: AtomicU32,
+    participants: u32,
+    once: Once,
+}
+
+impl InitBarrier {
+    /// Creates a barrier for `n` participants.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is zero there would be no participant to run the closure so this panics.
+    pub const fn new(n: u32) -> Self {
+        assert!(n != 0, "InitBarrier needs at least one participant");
+        InitBarrier { arrived: AtomicU32::new(0), participants: n, once: Once::new() }
+    }
+
+    /// Registers the arrival of a participant, running `f` if it's the last one.
+    ///
+    /// Blocks until the closure of the last participant finished, unless it's finished already.
+    ///
+    /// # Panics
+    ///
+    /// If the closure of the last participant panics the panic is propagated and the barrier is
+    /// poisoned, which releases all waiting participants by panicking. All later arrivals panic
+    /// too.
+    pub fn arrive_and_call<F: FnOnce()>(&self, f: F) {
+        if self.once.is_completed() {
+            return;
+        }
+
+        // The count saturates so the later arrivals don't overflow it
+        let arrived = self.arrived.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |arrived| {
+            if arrived < self.participants {
+                Some(arrived + 1)
+            } else {
+                None
+            }
+        });
+        if arrived == Ok(self.participants - 1) {
+            self.once.call_once(f);
+        } else {
+            self.once.wait();
+        }
+    }
+
+    /// Returns the number of participants that arrived, at most `n`.
+    ///
+    /// The value may change right after it was observed so this is mainly useful for debugging.
+    pub fn arrived(&self) -> u32 {
+        self.arrived.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if the closure of the last participant has completed successfully.
+    pub fn is_completed(&self) -> bool {
+        self.once.is_completed()
+    }
+
+    /// Returns `true` if the closure of the last participant has panicked.
+    pub fn is_poisoned(&self) -> bool {
+        self.once.is_poisoned()
+    }
+}
+
+impl fmt::Debug for InitBarrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InitBarrier")
+            .field("arrived", &self.arrived())
+            .field("participants", &self.participants)
+            .field("state", &self.once.state())
+            .finish()
+    }
+}
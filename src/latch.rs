@@ -0,0 +1,129 @@
+//! Countdown latch on a single futex word
+//!
+//! The low 31 bits hold the remaining count and the high bit is set by waiters before blocking,
+//! just like `RUNNING_WAITING` in `Once`, so that the final `count_down()` only issues the wake
+//! syscall if somebody may be blocked. Reaching zero clears the bit, so once open the word stays
+//! zero.
+
+use core::fmt;
+use core::sync::atomic::{AtomicI32, Ordering};
+use crate::futex;
+
+/// Set while some threads may be blocked
+const WAITING: i32 = i32::MIN;
+const COUNT_MASK: i32 = i32::MAX;
+
+/// A countdown latch releasing the waiting threads once it was counted down to zero.
+///
+/// This is useful to block until a known number of one-time tasks finished, e.g. the
+/// initialization of all subsystems: each task calls [`count_down()`](Self::count_down) when
+/// it's done and [`wait()`](Self::wait) returns once all of them did.
+///
+/// ```
+/// use linux_once::Latch;
+/// use std::sync::Arc;
+///
+/// let subsystems = Arc::new(Latch::new(3));
+/// for _ in 0..3 {
+///     let subsystems = Arc::clone(&subsystems);
+///     std::thread::spawn(move || {
+///         // initialize the subsystem
+///         subsystems.count_down();
+///     });
+/// }
+/// subsystems.wait();
+/// assert!(subsystems.try_wait());
+/// ```
+///
+/// The latch can't be reset, once it reached zero it stays open.
+pub struct Latch {
+    state: AtomicI32,
+}
+
+impl Latch {
+    /// Creates a latch that opens after `count` calls to [`count_down()`](Self::count_down).
+    ///
+    /// A latch with zero count is open right away.
+    ///
+    /// # Panics
+    ///
+    /// The count is stored in 31 bits so this panics if `count` is larger than `i32::MAX`.
+    pub const fn new(count: u32) -> Self {
+        assert!(count <= COUNT_MASK as u32, "the count of Latch doesn't fit into 31 bits");
+        Latch { state: AtomicI32::new(count as i32) }
+    }
+
+    /// Decrements the count, waking up all waiting threads if it reaches zero.
+    ///
+    /// Memory operations performed before this call are visible to the threads that return from
+    /// [`wait()`](Self::wait) or observe the latch open using [`try_wait()`](Self::try_wait).
+    ///
+    /// # Panics
+    ///
+    /// Counting down more times than the latch was created with is a bug so this panics if the
+    /// count is already zero. The latch stays open.
+    pub fn count_down(&self) {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            let count = state & COUNT_MASK;
+            assert!(count != 0, "Latch counted down below zero");
+            // The last count down clears the waiting bit since nobody waits for an open latch
+            let new = if count == 1 { 0 } else { state - 1 };
+            match self.state.compare_exchange_weak(state, new, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => {
+                    // Only make expensive syscall if there are threads waiting
+                    if new == 0 && state & WAITING != 0 {
+                        futex::wake(&self.state, i32::MAX);
+                    }
+                    break;
+                },
+                Err(old) => state = old,
+            }
+        }
+    }
+
+    /// Blocks the current thread until the count reaches zero.
+    ///
+    /// Returns immediately if the latch is already open.
+    pub fn wait(&self) {
+        // Fast path, same as in Once
+        if self.state.load(Ordering::Acquire) != 0 {
+            self.wait_slow();
+        }
+    }
+
+    #[cold]
+    fn wait_slow(&self) {
+        let mut state = self.state.load(Ordering::Acquire);
+        while state != 0 {
+            if state & WAITING == 0 {
+                if let Err(old) = self.state.compare_exchange_weak(state, state | WAITING, Ordering::Acquire, Ordering::Acquire) {
+                    state = old;
+                    continue;
+                }
+                state |= WAITING;
+            }
+            // Returns immediately if another thread counted down in the meantime
+            futex::wait(&self.state, state);
+            state = self.state.load(Ordering::Acquire);
+        }
+    }
+
+    /// Returns `true` if the count reached zero, never blocks.
+    pub fn try_wait(&self) -> bool {
+        self.state.load(Ordering::Acquire) == 0
+    }
+
+    /// Returns the remaining count.
+    ///
+    /// The value may change right after it was observed so this is mainly useful for debugging.
+    pub fn count(&self) -> u32 {
+        (self.state.load(Ordering::Relaxed) & COUNT_MASK) as u32
+    }
+}
+
+impl fmt::Debug for Latch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Latch").field("count", &self.count()).finish()
+    }
+}
@@ -0,0 +1,264 @@
+//! Value initialized on the first access, built on `Once`
+//!
+//! The closure and the value share the same storage: the closure is moved out inside
+//! `call_once()` right before it's called and the value is written in its place. So the storage
+//! holds the closure while the `Once` is incomplete, the value once it's complete and nothing
+//! after it got poisoned, which is what `Drop` relies on.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use crate::{Once, OnceStatus};
+
+union Data<T, F> {
+    value: ManuallyDrop<T>,
+    f: ManuallyDrop<F>,
+}
+
+/// A value which is initialized on the first access.
+///
+/// This is like [`std::sync::LazyLock`] except the threads waiting for the initialization block
+/// using the same mechanism as [`Once`]. If the initialization closure panics the `LazyLock` is
+/// poisoned, just like [`Once`], and all further accesses panic.
+///
+/// The default type of the closure is a function pointer so that it can be used in statics:
+///
+/// ```
+/// use linux_once::LazyLock;
+/// use std::collections::HashMap;
+///
+/// static LEVELS: LazyLock<HashMap<&str, u8>> = LazyLock::new(|| {
+///     [("error", 1), ("warn", 2), ("info", 3)].iter().copied().collect()
+/// });
+///
+/// assert_eq!(LEVELS.get("warn"), Some(&2));
+/// ```
+///
+/// # Dropping
+///
+/// The `LazyLock` holds either the initializing function or the value, never both. Dropping it
+/// drops the function if it was never forced and the value if it was. The function is consumed
+/// by the initialization, so if it panics whatever it captured is dropped by the unwinding and
+/// dropping the poisoned `LazyLock` drops nothing. [`into_inner()`](Self::into_inner) moves out
+/// whichever is held without dropping it.
+pub struct LazyLock<T, F = fn() -> T> {
+    once: Once,
+    data: UnsafeCell<Data<T, F>>,
+}
+
+impl<T, F: FnOnce() -> T> LazyLock<T, F> {
+    /// Creates a new lazy value with the given initializing function.
+    pub const fn new(f: F) -> Self {
+        LazyLock { once: Once::new(), data: UnsafeCell::new(Data { f: ManuallyDrop::new(f) }) }
+    }
+
+    /// Forces the evaluation of the lazy value and returns the reference to the result.
+    ///
+    /// This is equivalent to the `Deref` impl but it's explicit. If another thread is
+    /// initializing the value this blocks until it finishes.
+    ///
+    /// # Panics
+    ///
+    /// If the initializing function panics the panic is propagated and the `LazyLock` is
+    /// poisoned. If the `LazyLock` has been poisoned this method panics too.
+    #[track_caller]
+    pub fn force(this: &LazyLock<T, F>) -> &T {
+        this.once.call_once(|| {
+            // SAFETY: the closure of the Once is the only one accessing the data until it
+            // completes and the data holds the function while the Once is incomplete. If the
+            // function panics the Once gets poisoned so the moved-out function is never touched
+            // again.
+            unsafe {
+                let data = &mut *this.data.get();
+                let f = ManuallyDrop::take(&mut data.f);
+                data.value = ManuallyDrop::new(f());
+            }
+        });
+        // SAFETY: call_once returns only after the Once completed
+        unsafe { this.get_unchecked() }
+    }
+
+    /// Forces the evaluation of the lazy value and returns the mutable reference to the result.
+    ///
+    /// Since this borrows the `LazyLock` mutably nobody can be waiting for the initialization so
+    /// this never blocks or wakes anyone up.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`force()`](Self::force).
+    #[track_caller]
+    pub fn force_mut(this: &mut LazyLock<T, F>) -> &mut T {
+        if !this.once.is_completed() {
+            // the guard takes care of poisoning if the function panics
+            if let Some(guard) = this.once.begin() {
+                let data = this.data.get_mut();
+                // SAFETY: the data holds the function while the Once is incomplete, see force
+                unsafe {
+                    let f = ManuallyDrop::take(&mut data.f);
+                    data.value = ManuallyDrop::new(f());
+                }
+                guard.complete();
+            }
+        }
+        // SAFETY: the Once is complete now
+        unsafe { &mut this.data.get_mut().value }
+    }
+}
+
+impl<T> LazyLock<T> {
+    /// Creates a new lazy value with the given function pointer as the initializing function.
+    ///
+    /// This is the same as [`new()`](Self::new) except it makes the type of the `LazyLock`
+    /// nameable even if a non-capturing closure is passed. That's useful in constants, where the
+    /// type has to be written out, and for building arrays of lazy values since a constant can
+    /// be repeated:
+    ///
+    /// ```
+    /// use linux_once::LazyLock;
+    ///
+    /// struct Table;
+    ///
+    /// impl Table {
+    ///     const EMPTY: LazyLock<Vec<u8>> = LazyLock::from_fn(|| vec![0; 256]);
+    /// }
+    ///
+    /// static TABLES: [LazyLock<Vec<u8>>; 4] = [Table::EMPTY; 4];
+    ///
+    /// assert_eq!(TABLES[2].len(), 256);
+    /// assert!(LazyLock::get(&TABLES[3]).is_none());
+    /// ```
+    pub const fn from_fn(f: fn() -> T) -> Self {
+        LazyLock::new(f)
+    }
+}
+
+impl<T, F> LazyLock<T, F> {
+    /// Creates a lazy value that is already initialized.
+    ///
+    /// The initializing function is never called so the type can be the same as the type of
+    /// other lazy values that compute their value at runtime:
+    ///
+    /// ```
+    /// use linux_once::LazyLock;
+    ///
+    /// static LIMITS: [LazyLock<usize>; 2] = [
+    ///     LazyLock::preinit(64),
+    ///     LazyLock::new(|| std::env::var("LIMIT").ok().and_then(|limit| limit.parse().ok()).unwrap_or(64)),
+    /// ];
+    ///
+    /// assert_eq!(LazyLock::get(&LIMITS[0]), Some(&64));
+    /// ```
+    pub const fn preinit(value: T) -> Self {
+        LazyLock { once: Once::new_completed(), data: UnsafeCell::new(Data { value: ManuallyDrop::new(value) }) }
+    }
+
+    /// Returns the value if it was initialized, without initializing it.
+    ///
+    /// This never runs the initializing function and never blocks, so it's suitable for `Drop`
+    /// impls and panic hooks. If the initialization is running on another thread or it panicked
+    /// `None` is returned.
+    ///
+    /// This is an associated function rather than a method so that it doesn't shadow the methods
+    /// of `T` accessed through `Deref`.
+    pub fn get(this: &LazyLock<T, F>) -> Option<&T> {
+        if this.once.is_completed() {
+            // SAFETY: the Once is complete
+            Some(unsafe { this.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// Consumes the `LazyLock` returning the value if it was initialized or the initializing
+    /// function if it wasn't.
+    ///
+    /// # Panics
+    ///
+    /// If the `LazyLock` has been poisoned this panics because it holds neither the value nor the
+    /// function anymore.
+    pub fn into_inner(this: LazyLock<T, F>) -> Result<T, F> {
+        let state = this.once.state();
+        // dropping this while panicking is fine, Drop knows the poisoned state holds nothing
+        if state == OnceStatus::Poisoned {
+            panic!("LazyLock instance has previously been poisoned");
+        }
+        let mut this = ManuallyDrop::new(this);
+        // SAFETY: we have the only access and the state tells which field is initialized, see the
+        // module documentation. The state can't be running since that requires a shared
+        // reference held by another thread. The data is not touched again since `this` is not
+        // dropped.
+        unsafe {
+            let data = &mut *this.data.get();
+            let result = match state {
+                OnceStatus::Complete => Ok(ManuallyDrop::take(&mut data.value)),
+                _ => Err(ManuallyDrop::take(&mut data.f)),
+            };
+            // the Once may own resources in the portable implementation
+            core::ptr::drop_in_place(&mut this.once);
+            result
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The `Once` must be complete.
+    unsafe fn get_unchecked(&self) -> &T {
+        &(*self.data.get()).value
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for LazyLock<T, F> {
+    type Target = T;
+
+    #[track_caller]
+    fn deref(&self) -> &T {
+        LazyLock::force(self)
+    }
+}
+
+impl<T, F: FnOnce() -> T> DerefMut for LazyLock<T, F> {
+    #[track_caller]
+    fn deref_mut(&mut self) -> &mut T {
+        LazyLock::force_mut(self)
+    }
+}
+
+impl<T, F> Drop for LazyLock<T, F> {
+    fn drop(&mut self) {
+        let data = self.data.get_mut();
+        // SAFETY: the state tells which field is initialized, see the module documentation.
+        // Nobody else can be initializing the value because we have a mutable reference.
+        match self.once.state() {
+            OnceStatus::Complete => unsafe { ManuallyDrop::drop(&mut data.value) },
+            OnceStatus::Incomplete => unsafe { ManuallyDrop::drop(&mut data.f) },
+            OnceStatus::Poisoned | OnceStatus::Running => (),
+        }
+    }
+}
+
+impl<T: Default> Default for LazyLock<T> {
+    /// Creates a new lazy value using `Default` as the initializing function.
+    fn default() -> Self {
+        LazyLock::new(T::default)
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for LazyLock<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.once.state() {
+            // SAFETY: the Once is complete
+            OnceStatus::Complete => f.debug_tuple("LazyLock").field(unsafe { self.get_unchecked() }).finish(),
+            state => f.debug_struct("LazyLock").field("state", &state).finish(),
+        }
+    }
+}
+
+// SAFETY: same bounds as std::sync::LazyLock, the function is called by one of the threads and
+// the value is shared between them
+unsafe impl<T: Send + Sync, F: Send> Sync for LazyLock<T, F> {}
+
+// Same as std::sync::LazyLock, the poisoning protects from observing a broken value
+impl<T: RefUnwindSafe + UnwindSafe, F: UnwindSafe> RefUnwindSafe for LazyLock<T, F> {}
+impl<T: UnwindSafe, F: UnwindSafe> UnwindSafe for LazyLock<T, F> {}
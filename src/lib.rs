@@ -1,16 +1,21 @@
 //! A Linux-optimized drop-in replacement for `std::sync::Once`
 //!
 //! This crate implements the same thing as `std::sync::Once` except it internally uses Linux `futex`
-//! instead of `CondVar`. This leads to ridiculously simple code (compared to `std`) with no
-//! `unsafe` and theoretically a bit better performance. (Sadly, in practice the performance is
-//! roughly same.)
+//! instead of `CondVar`. This leads to ridiculously simple code (compared to `std`) and
+//! theoretically a bit better performance. (Sadly, in practice the performance is roughly same.)
+//! The `unsafe` code is limited to the futex syscalls, the storage of the cells and the few methods
+//! that are `unsafe` themselves.
 //!
-//! On non-Linux systems this crate just reexports `Once` from `std` so that you can
-//! unconditionally import `Once` from this crate and it'll work just fine.
+//! On non-Linux systems this crate provides a portable implementation built on `Mutex` and
+//! `Condvar` from `std` so that you can unconditionally import `Once` from this crate and it'll work
+//! just fine. It has the same API, except for the parts inherently tied to futexes: the raw state
+//! layout, waiting policies, `try_wait_raw()`, `OncePi`, `NamedOnce`, `FairOnce`, `Event`,
+//! `AutoResetEvent`, `Latch`, `WaitGroup`, `OnceGroup`, `ResettableOnce`, `BorrowedOnce`, the
+//! `futex` module, `race::OnceNonZeroU32::wait()` and the `stats` feature.
 //!
-//! This crate can reach 1.0 very soon. Things to resolve before then:
-//!
-//! * wait for stabilization of force call?
+//! Note that `OnceState` is this crate's own type on all platforms. Older versions re-exported the
+//! one from `std` on non-Linux systems so code that passes it to functions expecting
+//! `std::sync::OnceState` has to be updated.
 //!
 //! ## Why this should have better performance, yet it doesn't?
 //!
@@ -32,192 +37,5123 @@
 //! originaly thought. These are my speculations. If you happen to have more information, please
 //! let me know.
 
-#![cfg_attr(all(test, feature = "bench"), feature(test))]
+#![cfg_attr(all(test, feature = "bench"), feature(test))]
+
+#[cfg(all(test, feature = "bench"))]
+extern crate test;
+
+/// Emits a `tracing` event if the feature is enabled, compiles to nothing otherwise
+///
+/// The events are emitted at debug level with `linux_once` target. Only the futex implementation
+/// emits them.
+#[cfg(target_os = "linux")]
+macro_rules! trace_event {
+    ($($args:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target: "linux_once", $($args)*);
+    }};
+}
+
+#[cfg(test)]
+mod tests;
+
+// The same tests run against both implementations so that their APIs can't diverge
+#[cfg(test)]
+mod api_tests {
+    use crate::{Once, RetryOnce};
+
+    include!("api_tests.rs");
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod fallback_api_tests {
+    use crate::fallback::{Once, RetryOnce};
+
+    include!("api_tests.rs");
+}
+
+// The same tests run against `std` and our `OnceLock` so that they can't diverge
+#[cfg(test)]
+mod std_once_lock_tests {
+    use std::sync::OnceLock;
+
+    include!("once_lock_tests.rs");
+}
+
+#[cfg(test)]
+mod once_lock_tests {
+    use crate::OnceLock;
+
+    include!("once_lock_tests.rs");
+}
+
+#[cfg(test)]
+mod drop_tests;
+
+#[cfg(target_os = "linux")]
+mod linux;
+
+// Compiled on Linux when testing so that the shared API tests run against it too
+#[cfg(any(not(target_os = "linux"), test))]
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+mod fallback;
+
+#[cfg(all(target_os = "linux", feature = "poison-message"))]
+mod poison_message;
+
+mod once_fn;
+
+mod poison_hook;
+
+mod cell;
+
+mod poison_policy;
+
+mod guarded;
+
+mod once_pin;
+
+mod static_once;
+
+pub mod unsync;
+
+pub mod race;
+
+#[cfg(feature = "serde")]
+mod serde_impls;
+
+mod once_lock;
+
+mod lazy;
+
+mod try_lazy;
+
+mod call_once;
+
+mod once_drop;
+
+mod init_barrier;
+
+mod once_array;
+
+mod padded;
+
+mod once_map;
+
+mod type_once;
+
+mod thread_local_once;
+
+#[cfg(target_os = "linux")]
+pub mod futex;
+
+#[cfg(target_os = "linux")]
+mod waiter_count;
+
+#[cfg(target_os = "linux")]
+mod poison_location;
+
+#[cfg(target_os = "linux")]
+mod pi;
+
+#[cfg(target_os = "linux")]
+mod named;
+
+#[cfg(target_os = "linux")]
+mod fair;
+
+#[cfg(target_os = "linux")]
+mod event;
+
+#[cfg(target_os = "linux")]
+mod latch;
+
+#[cfg(target_os = "linux")]
+mod wait_group;
+
+#[cfg(target_os = "linux")]
+mod once_group;
+
+#[cfg(target_os = "linux")]
+mod resettable;
+
+#[cfg(target_os = "linux")]
+mod borrowed;
+
+#[cfg(target_os = "linux")]
+pub mod policy;
+
+#[cfg(all(target_os = "linux", feature = "stats"))]
+pub mod stats;
+
+#[cfg(target_os = "linux")]
+pub use linux::{Once, OnceInitGuard, RetryOnce};
+
+#[cfg(target_os = "linux")]
+pub use futex::WaitError;
+
+#[cfg(target_os = "linux")]
+pub use pi::OncePi;
+
+#[cfg(target_os = "linux")]
+pub use named::NamedOnce;
+
+#[cfg(target_os = "linux")]
+pub use fair::FairOnce;
+
+#[cfg(target_os = "linux")]
+pub use event::{AutoResetEvent, Event};
+
+#[cfg(target_os = "linux")]
+pub use latch::Latch;
+
+#[cfg(target_os = "linux")]
+pub use wait_group::WaitGroup;
+
+#[cfg(target_os = "linux")]
+pub use once_group::OnceGroup;
+
+#[cfg(target_os = "linux")]
+pub use resettable::{ResetError, ResettableOnce};
+
+#[cfg(target_os = "linux")]
+pub use borrowed::BorrowedOnce;
+
+#[cfg(not(target_os = "linux"))]
+pub use fallback::{Once, OnceInitGuard, RetryOnce};
+
+pub use poison_hook::{set_poison_hook, PoisonInfo};
+
+pub use cell::OnceCell;
+
+pub use poison_policy::PoisonPolicy;
+
+pub use guarded::GuardedCell;
+
+pub use once_pin::OncePin;
+
+pub use static_once::StaticOnce;
+
+pub use once_lock::OnceLock;
+
+pub use lazy::LazyLock;
+
+pub use try_lazy::{RetryLazy, TryLazy};
+
+pub use call_once::CallOnce;
+
+pub use once_drop::OnceDrop;
+
+pub use init_barrier::InitBarrier;
+
+pub use once_array::OnceArray;
+
+pub use padded::{CachePadded, PaddedOnce};
+
+pub use once_map::OnceMap;
+
+pub use type_once::TypeOnce;
+
+pub use thread_local_once::ThreadLocalOnce;
+
+// Both implementations must implement the same traits so that switching platforms doesn't break
+// code. This is checked on every platform against the same list.
+const _: () = {
+    use std::panic::{RefUnwindSafe, UnwindSafe};
+
+    fn assert_once_traits<T: core::fmt::Debug + Default + Send + Sync + Unpin + UnwindSafe + RefUnwindSafe>() {}
+    // std's `OnceState` is not `Send` or `Sync` on some platforms
+    fn assert_once_state_traits<T: core::fmt::Debug>() {}
+
+    #[allow(dead_code)]
+    fn assert_all() {
+        assert_once_traits::<Once>();
+        assert_once_state_traits::<OnceState>();
+    }
+};
+
+/// Snapshot of the state of [`Once`] returned by [`Once::state()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnceStatus {
+    /// No initialization routine has run yet (or all of them failed without poisoning)
+    Incomplete,
+    /// An initialization routine is running right now
+    Running,
+    /// An initialization routine has completed successfully
+    Complete,
+    /// An initialization routine has panicked
+    Poisoned,
+}
+
+/// State yielded to [`Once::call_once_force()`]'s closure parameter. The state can be used to
+/// query the poison status of the [`Once`].
+#[derive(Debug)]
+pub struct OnceState {
+    poisoned: bool,
+    poison_requested: core::cell::Cell<bool>,
+}
+
+impl OnceState {
+    pub(crate) fn new(poisoned: bool) -> Self {
+        OnceState { poisoned, poison_requested: core::cell::Cell::new(false) }
+    }
+
+    /// Returns `true` if the associated [`Once`] was poisoned prior to the invocation of the
+    /// closure passed to [`Once::call_once_force()`].
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Poisons the associated [`Once`] even if the closure returns normally.
+    ///
+    /// This is useful when the closure passed to [`Once::call_once_force()`] finds out that the
+    /// recovery is impossible and wants to leave the [`Once`] poisoned without panicking.
+    pub fn poison(&self) {
+        self.poison_requested.set(true);
+    }
+
+    pub(crate) fn is_poison_requested(&self) -> bool {
+        self.poison_requested.get()
+    }
+}
+
+/// Outcome of [`Once::try_call_once()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryCallOnce {
+    /// The closure was executed by this call
+    Ran,
+    /// Some initialization routine has completed already so the closure was not executed
+    AlreadyComplete,
+    /// Another thread is running an initialization routine right now so the closure was not
+    /// executed
+    Busy,
+    /// The [`Once`] is poisoned so the closure was not executed
+    Poisoned,
+}
+
+/// Outcome of [`Once::call_once_timeout()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallOnceOutcome {
+    /// The closure was executed by this call
+    Ran,
+    /// Some initialization routine has completed already so the closure was not executed
+    AlreadyComplete,
+    /// Another thread was running an initialization routine and didn't finish in time
+    TimedOut,
+    /// The [`Once`] is poisoned so the closure was not executed
+    Poisoned,
+}
+
+/// Error returned by [`Once::call_once_checked()`] when the [`Once`] is poisoned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoisonedError {
+    location: Option<&'static core::panic::Location<'static>>,
+}
+
+impl PoisonedError {
+    pub(crate) fn new(location: Option<&'static core::panic::Location<'static>>) -> Self {
+        PoisonedError { location }
+    }
+
+    /// Returns the location of the call that poisoned the [`Once`], if it was recorded.
+    ///
+    /// Locations are only recorded on Linux, for at most 64 poisoned instances at a time. A
+    /// location is released when its [`Once`] is dropped or the poisoning is cleared, but a
+    /// poisoned [`Once`] that is leaked or moved by value keeps its location stored until the
+    /// process exits. So after 64 such instances the locations permanently stop being recorded
+    /// and this returns `None` for all later poisonings.
+    pub fn location(&self) -> Option<&'static core::panic::Location<'static>> {
+        self.location
+    }
+}
+
+impl core::fmt::Display for PoisonedError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.location {
+            Some(location) => write!(f, "Once instance has previously been poisoned by call at {}", location),
+            None => write!(f, "Once instance has previously been poisoned"),
+        }
+    }
+}
+
+impl std::error::Error for PoisonedError {}
+
+/// Diagnostic information passed to the handler of [`Once::call_once_watched()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SlowInit {
+    /// How long the current thread has been waiting for the initialization
+    pub waited: std::time::Duration,
+    /// Number of other threads blocked waiting for the same initialization at the moment
+    pub waiters: usize,
+    /// Name of the `Once` if it's a `NamedOnce`
+    pub name: Option<&'static str>,
+}
+
+#[cfg(test)]
+mod our_tests {
+    use super::{Once, OnceStatus};
+    use std::sync::{Arc, atomic::{AtomicUsize, Ordering::Relaxed}};
+    #[cfg(feature = "bench")]
+    use test::Bencher;
+
+    // Simulate 5 threads attempting to run `Once` at the same time
+    #[cfg(feature = "bench")]
+    const CONTENDED_THREADS: usize = 5;
+    // Simulate expensive operation that takes 1ms to complete
+    #[cfg(feature = "bench")]
+    const CONTENDED_WAIT: u64 = 1_000_000;
+    // Simulate cheap operation that takes 1us to complete
+    #[cfg(feature = "bench")]
+    const CONTENDED_SHORT_WAIT: u64 = 1_000;
+
+    // Sleeping would take much longer than the intended time so this busy-waits instead
+    #[cfg(feature = "bench")]
+    fn short_initializer() {
+        let start = std::time::Instant::now();
+        while start.elapsed() < std::time::Duration::from_nanos(CONTENDED_SHORT_WAIT) {
+            core::hint::spin_loop();
+        }
+    }
+
+    #[test]
+    fn basic() {
+        let mut ran = false;
+        let once = Once::new();
+        once.call_once(|| ran = true);
+        assert!(ran);
+        ran = false;
+        once.call_once(|| ran = true);
+        assert!(!ran);
+    }
+
+    #[test]
+    fn multithreaded() {
+        let once = Arc::new((Once::new(), AtomicUsize::new(0)));
+        let once_cloned = Arc::clone(&once);
+
+        let handle = std::thread::spawn(move || once_cloned.0.call_once(|| { once_cloned.1.fetch_add(1, Relaxed); }));
+        once.0.call_once(|| { once.1.fetch_add(1, Relaxed); });
+        handle.join().expect("failed to join thread");
+        assert_eq!(once.1.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn force_after_poison() {
+        let once = Arc::new((Once::new(), AtomicUsize::new(0)));
+
+        let poisoner = Arc::clone(&once);
+        std::thread::spawn(move || poisoner.0.call_once(|| panic!("poisoning on purpose")))
+            .join()
+            .expect_err("the closure didn't panic");
+
+        let threads = (0..4)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || {
+                    cloned.0.call_once_force(|state| {
+                        assert!(state.is_poisoned());
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                        cloned.1.fetch_add(1, Relaxed);
+                    });
+                    cloned.0.call_once(|| panic!("the Once should be completed already"));
+                    assert!(cloned.0.is_completed());
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().expect("failed to join thread");
+        }
+        assert_eq!(once.1.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn poisoned_observed_by_other_thread() {
+        let once = Arc::new(Once::new());
+        assert!(!once.is_poisoned());
+
+        let poisoner = Arc::clone(&once);
+        std::thread::spawn(move || poisoner.call_once(|| panic!("poisoning on purpose")))
+            .join()
+            .expect_err("the closure didn't panic");
+
+        let observer = Arc::clone(&once);
+        std::thread::spawn(move || {
+            assert!(observer.is_poisoned());
+            assert!(!observer.is_completed());
+        })
+        .join()
+        .expect("failed to join thread");
+
+        once.call_once_force(|_| ());
+        assert!(!once.is_poisoned());
+        assert!(once.is_completed());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wait_before_call_once() {
+        let once = Arc::new((Once::new(), AtomicUsize::new(0)));
+        let waiters = (0..4)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || {
+                    cloned.0.wait();
+                    assert_eq!(cloned.1.load(Relaxed), 1);
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        // give the waiters a chance to block on incomplete Once
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(waiters.iter().all(|waiter| !waiter.is_finished()));
+
+        once.0.call_once(|| { once.1.fetch_add(1, Relaxed); });
+        for waiter in waiters {
+            waiter.join().expect("failed to join thread");
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wait_while_running() {
+        let once = Arc::new((Once::new(), AtomicUsize::new(0)));
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+
+        let initializer = {
+            let once = Arc::clone(&once);
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || once.0.call_once(|| {
+                barrier.wait();
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                once.1.fetch_add(1, Relaxed);
+            }))
+        };
+
+        barrier.wait();
+        once.0.wait();
+        assert_eq!(once.1.load(Relaxed), 1);
+        initializer.join().expect("failed to join thread");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wait_poisoned() {
+        let once = Once::new();
+        std::panic::catch_unwind(|| once.call_once(|| panic!("poisoning on purpose"))).expect_err("the closure didn't panic");
+        std::panic::catch_unwind(|| once.wait()).expect_err("wait didn't panic");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wait_force_poisoned_while_waiting() {
+        let once = Arc::new(Once::new());
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+
+        let initializer = {
+            let once = Arc::clone(&once);
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || once.call_once(|| {
+                barrier.wait();
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                panic!("poisoning on purpose");
+            }))
+        };
+
+        barrier.wait();
+        let waiters = (0..4)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || {
+                    cloned.wait_force();
+                    assert!(cloned.is_poisoned());
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        initializer.join().expect_err("the closure didn't panic");
+        for waiter in waiters {
+            waiter.join().expect("wait_force panicked");
+        }
+        // doesn't block on already poisoned Once either
+        once.wait_force();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wait_timeout_slow_initializer() {
+        use std::time::{Duration, Instant};
+
+        let once = Arc::new(Once::new());
+        assert!(!once.wait_timeout(Duration::from_millis(10)));
+
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let initializer = {
+            let once = Arc::clone(&once);
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || once.call_once(|| {
+                barrier.wait();
+                std::thread::sleep(Duration::from_millis(200));
+            }))
+        };
+
+        barrier.wait();
+        let start = Instant::now();
+        assert!(!once.wait_timeout(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(once.wait_timeout(Duration::from_secs(10)));
+        assert!(once.is_completed());
+        initializer.join().expect("failed to join thread");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wait_timeout_zero_is_poll() {
+        use std::time::Duration;
+
+        let once = Once::new();
+        assert!(!once.wait_timeout(Duration::from_secs(0)));
+        once.call_once(|| ());
+        assert!(once.wait_timeout(Duration::from_secs(0)));
+        assert!(once.wait_timeout(Duration::MAX));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wait_deadline() {
+        use std::time::{Duration, Instant};
+
+        let once = Arc::new(Once::new());
+        let past = Instant::now();
+        assert!(!once.wait_deadline(past));
+
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let initializer = {
+            let once = Arc::clone(&once);
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || once.call_once(|| {
+                barrier.wait();
+                std::thread::sleep(Duration::from_millis(100));
+            }))
+        };
+
+        barrier.wait();
+        // expires mid-wait
+        let deadline = Instant::now() + Duration::from_millis(20);
+        assert!(!once.wait_deadline(deadline));
+        assert!(Instant::now() >= deadline);
+        // completes before the deadline
+        assert!(once.wait_deadline(Instant::now() + Duration::from_secs(10)));
+        initializer.join().expect("failed to join thread");
+
+        // completion is reported even if the deadline passed
+        assert!(once.wait_deadline(past));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn try_call_once_outcomes() {
+        use super::TryCallOnce;
+
+        let once = Arc::new(Once::new());
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let initializer = {
+            let once = Arc::clone(&once);
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || once.try_call_once(|| {
+                barrier.wait();
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }))
+        };
+
+        barrier.wait();
+        assert_eq!(once.try_call_once(|| panic!("the Once is busy")), TryCallOnce::Busy);
+        assert_eq!(initializer.join().expect("failed to join thread"), TryCallOnce::Ran);
+        assert_eq!(once.try_call_once(|| panic!("the Once is completed")), TryCallOnce::AlreadyComplete);
+
+        let once = Once::new();
+        std::panic::catch_unwind(|| once.try_call_once(|| panic!("poisoning on purpose"))).expect_err("the closure didn't panic");
+        assert_eq!(once.try_call_once(|| panic!("the Once is poisoned")), TryCallOnce::Poisoned);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn call_once_try_retries_after_error() {
+        let once = Arc::new((Once::new(), AtomicUsize::new(0)));
+        let barrier = Arc::new(std::sync::Barrier::new(8));
+        let threads = (0..8)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    cloned.0.call_once_try(|| {
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                        match cloned.1.fetch_add(1, Relaxed) {
+                            attempt @ 0..=1 => Err(attempt),
+                            _ => Ok(()),
+                        }
+                    })
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        let mut errors = threads
+            .into_iter()
+            .filter_map(|thread| thread.join().expect("failed to join thread").err())
+            .collect::<Vec<_>>();
+        errors.sort();
+        assert_eq!(errors, [0, 1]);
+        assert_eq!(once.1.load(Relaxed), 3);
+        assert!(once.0.is_completed());
+        assert_eq!(once.0.call_once_try(|| Err(42)), Ok(()));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn call_once_catch_releases_waiters() {
+        let once = Arc::new((Once::new(), AtomicUsize::new(0)));
+        let barrier = Arc::new(std::sync::Barrier::new(3));
+
+        let initializer = {
+            let once = Arc::clone(&once);
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || once.0.call_once_catch(|| {
+                barrier.wait();
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                panic!("caught on purpose");
+            }))
+        };
+
+        let waiters = (0..2)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    cloned.0.call_once_catch(|| { cloned.1.fetch_add(1, Relaxed); })
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        let payload = initializer
+            .join()
+            .expect("the panic wasn't caught")
+            .expect_err("the closure didn't panic");
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"caught on purpose"));
+        for waiter in waiters {
+            waiter.join().expect("failed to join thread").expect("the closure panicked");
+        }
+        assert_eq!(once.1.load(Relaxed), 1);
+        assert!(once.0.is_completed());
+        assert!(!once.0.is_poisoned());
+    }
+
+    #[test]
+    fn state_transitions() {
+        let once = Arc::new(Once::new());
+        assert_eq!(once.state(), OnceStatus::Incomplete);
+
+        let started = Arc::new(std::sync::Barrier::new(2));
+        let checked = Arc::new(std::sync::Barrier::new(2));
+        let initializer = {
+            let once = Arc::clone(&once);
+            let started = Arc::clone(&started);
+            let checked = Arc::clone(&checked);
+            std::thread::spawn(move || once.call_once(|| {
+                started.wait();
+                checked.wait();
+            }))
+        };
+
+        started.wait();
+        assert_eq!(once.state(), OnceStatus::Running);
+        checked.wait();
+        initializer.join().expect("failed to join thread");
+        assert_eq!(once.state(), OnceStatus::Complete);
+
+        let once = Once::new();
+        std::panic::catch_unwind(|| once.call_once(|| panic!("poisoning on purpose"))).expect_err("the closure didn't panic");
+        assert_eq!(once.state(), OnceStatus::Poisoned);
+    }
+
+    #[test]
+    fn is_running_during_slow_initializer() {
+        let once = Arc::new(Once::new());
+        assert!(!once.is_running());
+
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let initializer = {
+            let once = Arc::clone(&once);
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || once.call_once(|| {
+                barrier.wait();
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }))
+        };
+
+        barrier.wait();
+        let observer = Arc::clone(&once);
+        std::thread::spawn(move || assert!(observer.is_running()))
+            .join()
+            .expect("failed to join thread");
+        initializer.join().expect("failed to join thread");
+        assert!(!once.is_running());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn poison_wakes_waiters() {
+        let once = Arc::new(Once::new());
+        let waiters = (0..4)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || cloned.wait_force())
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        // give the waiters a chance to block on incomplete Once
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        once.poison();
+        for waiter in waiters {
+            waiter.join().expect("failed to join thread");
+        }
+        assert!(once.is_poisoned());
+        std::panic::catch_unwind(|| once.call_once(|| ())).expect_err("call_once didn't panic");
+        // no-op on poisoned and completed Once
+        once.poison();
+        once.call_once_force(|_| ());
+        once.poison();
+        assert!(once.is_completed());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn poison_races_initializer() {
+        use super::TryCallOnce;
+
+        for _ in 0..100 {
+            let once = Arc::new((Once::new(), AtomicUsize::new(0)));
+            let barrier = Arc::new(std::sync::Barrier::new(2));
+            let poisoner = {
+                let once = Arc::clone(&once);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    once.0.poison();
+                })
+            };
+
+            barrier.wait();
+            let result = once.0.try_call_once(|| { once.1.fetch_add(1, Relaxed); });
+            poisoner.join().expect("failed to join thread");
+            match result {
+                TryCallOnce::Ran => {
+                    assert_eq!(once.1.load(Relaxed), 1);
+                    assert!(once.0.is_completed());
+                },
+                TryCallOnce::Poisoned => {
+                    assert_eq!(once.1.load(Relaxed), 0);
+                    assert!(once.0.is_poisoned());
+                },
+                other => panic!("unexpected outcome {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn clear_poison() {
+        let once = Once::new();
+        once.poison();
+        once.clear_poison();
+        assert_eq!(once.state(), OnceStatus::Incomplete);
+
+        let mut ran = false;
+        once.call_once(|| ran = true);
+        assert!(ran);
+        // can't demote completed Once
+        once.clear_poison();
+        assert!(once.is_completed());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn clear_poison_races_call() {
+        use super::TryCallOnce;
+
+        for _ in 0..100 {
+            let once = Arc::new((Once::new(), AtomicUsize::new(0)));
+            once.0.poison();
+            let barrier = Arc::new(std::sync::Barrier::new(2));
+            let clearer = {
+                let once = Arc::clone(&once);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    once.0.clear_poison();
+                })
+            };
+
+            barrier.wait();
+            let result = once.0.try_call_once(|| { once.1.fetch_add(1, Relaxed); });
+            clearer.join().expect("failed to join thread");
+            match result {
+                TryCallOnce::Ran => {
+                    assert_eq!(once.1.load(Relaxed), 1);
+                    assert!(once.0.is_completed());
+                },
+                TryCallOnce::Poisoned => {
+                    assert_eq!(once.1.load(Relaxed), 0);
+                    assert_eq!(once.0.state(), OnceStatus::Incomplete);
+                },
+                other => panic!("unexpected outcome {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn mark_completed_races_call_once() {
+        for _ in 0..100 {
+            let once = Arc::new((Once::new(), AtomicUsize::new(0)));
+            let barrier = Arc::new(std::sync::Barrier::new(2));
+            let marker = {
+                let once = Arc::clone(&once);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    // the test doesn't guard anything
+                    unsafe { once.0.mark_completed(); }
+                    assert!(once.0.is_completed());
+                })
+            };
+
+            barrier.wait();
+            once.0.call_once(|| { once.1.fetch_add(1, Relaxed); });
+            assert!(once.0.is_completed());
+            marker.join().expect("failed to join thread");
+            assert!(once.1.load(Relaxed) <= 1);
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn mark_completed_releases_waiters() {
+        let once = Arc::new(Once::new());
+        let waiters = (0..4)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || {
+                    cloned.wait();
+                    cloned.call_once(|| panic!("the Once should be completed already"));
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        // give the waiters a chance to block on incomplete Once
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        // the test doesn't guard anything
+        unsafe { once.mark_completed(); }
+        for waiter in waiters {
+            waiter.join().expect("failed to join thread");
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn new_completed() {
+        static ONCE: Once = Once::new_completed();
+
+        assert!(ONCE.is_completed());
+        ONCE.call_once(|| panic!("the Once should be completed already"));
+        ONCE.call_once_force(|_| panic!("the Once should be completed already"));
+        ONCE.wait();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn call_once_and_report_contended() {
+        let once = Arc::new(Once::new());
+        let barrier = Arc::new(std::sync::Barrier::new(8));
+        let threads = (0..8)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    cloned.call_once_and_report(|| std::thread::sleep(std::time::Duration::from_millis(10)))
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        let ran = threads
+            .into_iter()
+            .map(|thread| thread.join().expect("failed to join thread"))
+            .filter(|ran| *ran)
+            .count();
+        assert_eq!(ran, 1);
+        assert!(!once.call_once_and_report(|| panic!("the Once should be completed already")));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn call_once_dyn_calls_at_most_once() {
+        let once = Once::new();
+        let mut calls = 0;
+        let mut f = || calls += 1;
+        once.call_once_dyn(&mut f);
+        once.call_once_dyn(&mut f);
+        once.call_once(|| panic!("the Once should be completed already"));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn begin_complete_from_other_thread() {
+        let once = Arc::new(Once::new());
+        let guard = once.begin().expect("the Once is incomplete");
+        let waiters = (0..4)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || cloned.call_once(|| panic!("the guard should complete the Once")))
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        // give the waiters a chance to block
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        std::thread::scope(|scope| { scope.spawn(move || guard.complete()); });
+        for waiter in waiters {
+            waiter.join().expect("failed to join thread");
+        }
+        assert!(once.begin().is_none());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn begin_abort_lets_waiter_initialize() {
+        let once = Arc::new((Once::new(), AtomicUsize::new(0)));
+        let guard = once.0.begin().expect("the Once is incomplete");
+        let waiters = (0..4)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || cloned.0.call_once(|| { cloned.1.fetch_add(1, Relaxed); }))
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        // give the waiters a chance to block
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        guard.abort();
+        for waiter in waiters {
+            waiter.join().expect("failed to join thread");
+        }
+        assert_eq!(once.1.load(Relaxed), 1);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn begin_drop_poisons_waiters() {
+        let once = Arc::new(Once::new());
+        let guard = once.begin().expect("the Once is incomplete");
+        let waiters = (0..4)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || cloned.wait_force())
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        // give the waiters a chance to block
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        drop(guard);
+        for waiter in waiters {
+            waiter.join().expect("failed to join thread");
+        }
+        assert!(once.is_poisoned());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn recursive_call_once_panics() {
+        let once = Arc::new(Once::new());
+        let payload = std::panic::catch_unwind(|| once.call_once(|| once.call_once(|| ())))
+            .expect_err("recursive call didn't panic");
+        let message = payload.downcast_ref::<&str>().expect("unexpected payload");
+        assert!(message.contains("recursively"));
+        assert!(once.is_poisoned());
+
+        let cloned = Arc::clone(&once);
+        std::thread::spawn(move || cloned.wait_force())
+            .join()
+            .expect("failed to join thread");
+
+        // nested initialization of a different instance is fine
+        let other = Once::new();
+        let mut ran = false;
+        once.call_once_force(|_| other.call_once(|| ran = true));
+        assert!(ran);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "poison-message"))]
+    fn poisoned_panic_includes_original_message() {
+        let once = Arc::new(Once::new());
+        let poisoner = Arc::clone(&once);
+        std::thread::spawn(move || poisoner.call_once(|| panic!("the original {} message", "formatted")))
+            .join()
+            .expect_err("the closure didn't panic");
+
+        let payload = std::panic::catch_unwind(|| once.call_once(|| ())).expect_err("call_once didn't panic");
+        let message = payload.downcast_ref::<String>().expect("unexpected payload");
+        assert!(message.contains("the original formatted message"), "unexpected message: {}", message);
+
+        once.clear_poison();
+        std::panic::catch_unwind(|| once.call_once(|| panic!("second"))).expect_err("the closure didn't panic");
+        let payload = std::panic::catch_unwind(|| once.wait()).expect_err("wait didn't panic");
+        let message = payload.downcast_ref::<String>().expect("unexpected payload");
+        assert!(message.ends_with("second"), "unexpected message: {}", message);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "poison-message"))]
+    fn poisoned_message_kept_after_other_poisonings() {
+        use crate::poison_message;
+
+        // addresses that can't belong to any Once while the vector is alive, so that the test
+        // doesn't take the slots of the poison location table from the other tests
+        let addresses = vec![0u32; 101];
+        let mut addresses = addresses.iter().map(|address| address as *const u32 as usize);
+        let root = addresses.next().expect("empty addresses");
+        poison_message::catch(&"root cause");
+        poison_message::publish(root);
+        for address in addresses.clone() {
+            poison_message::catch(&"unrelated");
+            poison_message::publish(address);
+        }
+
+        assert_eq!(poison_message::get(root).as_deref(), Some("root cause"));
+        for address in addresses.chain(Some(root)) {
+            poison_message::remove(address);
+        }
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "poison-message"))]
+    fn poisoned_message_not_inherited_at_same_address() {
+        let mut once = Once::new();
+        std::panic::catch_unwind(|| once.call_once(|| panic!("stale"))).expect_err("the closure didn't panic");
+        // the old Once never gets dropped so its message stays stored
+        std::mem::forget(std::mem::take(&mut once));
+
+        drop(once.begin().expect("fresh Once already initialized"));
+        let payload = std::panic::catch_unwind(|| once.wait()).expect_err("wait didn't panic");
+        let message = payload.downcast_ref::<String>().expect("unexpected payload");
+        assert!(!message.contains("stale"), "unexpected message: {}", message);
+
+        // the same goes for poisoning through a closure panicking with a non-string payload
+        std::mem::forget(std::mem::take(&mut once));
+        std::panic::catch_unwind(|| once.call_once(|| panic!("stale"))).expect_err("the closure didn't panic");
+        std::mem::forget(std::mem::take(&mut once));
+        std::panic::catch_unwind(|| once.call_once(|| std::panic::panic_any(42))).expect_err("the closure didn't panic");
+        let payload = std::panic::catch_unwind(|| once.wait()).expect_err("wait didn't panic");
+        let message = payload.downcast_ref::<String>().expect("unexpected payload");
+        assert!(!message.contains("stale"), "unexpected message: {}", message);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn poisoned_panic_includes_location() {
+        fn message_of(payload: Box<dyn std::any::Any + Send>) -> String {
+            payload.downcast::<String>().map(|message| *message).expect("unexpected payload")
+        }
+
+        let once = Once::new();
+        let line = line!() + 1;
+        std::panic::catch_unwind(|| once.call_once(|| panic!())).expect_err("the closure didn't panic");
+        let message = message_of(std::panic::catch_unwind(|| once.wait()).expect_err("wait didn't panic"));
+        let expected = format!(" at {}:{}:", file!(), line);
+        assert!(message.contains("previously been poisoned") && message.contains(&expected), "unexpected message: {}", message);
+
+        // poisoning again replaces the location
+        once.clear_poison();
+        let mut once = once;
+        let line = line!() + 1;
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| once.call_once_mut(|| panic!()))).expect_err("the closure didn't panic");
+        let message = message_of(std::panic::catch_unwind(|| once.call_once(|| ())).expect_err("call_once didn't panic"));
+        let expected = format!(" at {}:{}:", file!(), line);
+        assert!(message.contains(&expected), "unexpected message: {}", message);
+
+        // poisoning without a closure records the location too
+        let once = Once::new();
+        once.poison();
+        let message = message_of(std::panic::catch_unwind(|| once.call_once(|| ())).expect_err("call_once didn't panic"));
+        assert!(message.contains(file!()), "unexpected message: {}", message);
+
+        // the error of call_once_checked carries the same location
+        let line = line!() + 1;
+        std::panic::catch_unwind(|| once.call_once_force(|_| panic!())).expect_err("the closure didn't panic");
+        let error = once.call_once_checked(|| ()).expect_err("poisoning not reported");
+        let location = error.location().expect("location not recorded");
+        assert_eq!((location.file(), location.line()), (file!(), line));
+        assert!(error.to_string().contains(&location.to_string()), "unexpected message: {}", error);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn poisoned_cell_panic_names_initializer() {
+        use super::{OnceCell, OnceLock, PoisonPolicy};
+
+        fn message_of(payload: Box<dyn std::any::Any + Send>) -> String {
+            payload.downcast::<String>().map(|message| *message).expect("unexpected payload")
+        }
+
+        let cell = OnceCell::<u32>::new();
+        let line = line!() + 1;
+        std::panic::catch_unwind(|| cell.get_or_init(|| panic!())).expect_err("the closure didn't panic");
+        let message = message_of(std::panic::catch_unwind(|| cell.get_or_init(|| 42)).expect_err("get_or_init didn't panic"));
+        let expected = format!(" at {}:{}:", file!(), line);
+        assert!(message.contains(&expected), "unexpected message: {}", message);
+
+        let mut cell = OnceCell::<u32>::new();
+        let line = line!() + 1;
+        std::panic::catch_unwind(|| cell.get_or_try_init(|| -> Result<u32, ()> { panic!() })).expect_err("the closure didn't panic");
+        let message = message_of(std::panic::catch_unwind(|| cell.wait()).expect_err("wait didn't panic"));
+        let expected = format!(" at {}:{}:", file!(), line);
+        assert!(message.contains(&expected), "unexpected message: {}", message);
+
+        // the exclusive access reports the same as the shared one
+        let message = message_of(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *cell.get_mut_or_init(|| 42))).expect_err("get_mut_or_init didn't panic"));
+        assert!(message.contains(&expected), "unexpected message: {}", message);
+
+        let lock = OnceLock::<u32>::with_poison_policy(PoisonPolicy::Poison);
+        let line = line!() + 1;
+        std::panic::catch_unwind(|| lock.get_or_init(|| panic!())).expect_err("the closure didn't panic");
+        let message = message_of(std::panic::catch_unwind(|| lock.wait()).expect_err("wait didn't panic"));
+        let expected = format!(" at {}:{}:", file!(), line);
+        assert!(message.contains(&expected), "unexpected message: {}", message);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn retry_once_after_panics() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use super::RetryOnce;
+
+        let once = Arc::new(RetryOnce::new());
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(std::sync::Barrier::new(4));
+
+        let panicked = (0..4)
+            .map(|_| {
+                let once = Arc::clone(&once);
+                let attempts = Arc::clone(&attempts);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    std::panic::catch_unwind(|| once.call_once(|| {
+                        let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                        // give the other threads time to block
+                        std::thread::sleep(std::time::Duration::from_millis(50));
+                        if attempt < 2 {
+                            panic!("transient failure");
+                        }
+                    })).is_err()
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|panicked| *panicked)
+            .count();
+
+        assert_eq!(panicked, 2);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert!(once.is_completed());
+        once.call_once(|| panic!("called after completion"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn call_once_mut() {
+        let mut once = Once::new();
+        let mut calls = 0;
+        once.call_once_mut(|| calls += 1);
+        once.call_once_mut(|| calls += 1);
+        once.call_once(|| calls += 1);
+        assert_eq!(calls, 1);
+        assert!(once.is_completed());
+
+        let mut once = Once::new();
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| once.call_once_mut(|| panic!())))
+            .expect_err("the closure didn't panic");
+        assert!(once.is_poisoned());
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| once.call_once_mut(|| ())))
+            .expect_err("call_once_mut didn't panic on poisoned Once");
+    }
+
+    #[test]
+    fn debug_shows_state() {
+        let once = Once::default();
+        assert_eq!(format!("{:?}", once), "Once { state: Incomplete }");
+        once.call_once(|| ());
+        assert_eq!(format!("{:?}", once), "Once { state: Complete }");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn raw_round_trip() {
+        assert_eq!(Once::new().into_raw(), 0);
+        assert!(!Once::from_raw(0).is_completed());
+        assert!(Once::from_raw(1).is_completed());
+        assert!(Once::from_raw(2).is_poisoned());
+
+        let once = Once::new();
+        once.call_once(|| ());
+        let once = Once::from_raw(once.into_raw());
+        assert!(once.is_completed());
+
+        let once = Once::new();
+        std::panic::catch_unwind(|| once.call_once(|| panic!())).expect_err("the closure didn't panic");
+        let once = Once::from_raw(once.into_raw());
+        assert!(once.is_poisoned());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn as_ptr_observes_state() {
+        let once = Once::new();
+        let ptr = once.as_ptr();
+        // SAFETY: the pointer is valid while once is alive and it's accessed atomically
+        let load = || unsafe { (*(ptr as *const std::sync::atomic::AtomicI32)).load(std::sync::atomic::Ordering::Acquire) };
+        assert_eq!(load(), 0);
+        once.call_once(|| ());
+        assert_eq!(load(), 1);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn raw_state_values() {
+        // these are part of the public contract, changing them is a breaking change
+        assert_eq!(Once::INCOMPLETE, 0);
+        assert_eq!(Once::COMPLETE, 1);
+        assert_eq!(Once::POISONED, 2);
+        assert_eq!(Once::RUNNING_NO_WAIT, 3);
+        assert_eq!(Once::RUNNING_WAITING, 4);
+        assert_eq!(Once::INCOMPLETE_WAITING, 5);
+
+        let once = Once::new();
+        assert_eq!(once.load_state(), Once::INCOMPLETE);
+        once.call_once(|| assert_eq!(once.load_state(), Once::RUNNING_NO_WAIT));
+        assert_eq!(once.load_state(), Once::COMPLETE);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn waiters_count() {
+        const WAITERS: usize = 4;
+
+        let once = Arc::new(Once::new());
+        assert_eq!(once.waiters(), 0);
+        let guard = once.begin().expect("fresh Once already initialized");
+        let threads = (0..WAITERS)
+            .map(|i| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || if i % 2 == 0 {
+                    cloned.call_once(|| panic!("the initialization should've completed"))
+                } else {
+                    cloned.wait()
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        let start = std::time::Instant::now();
+        while once.waiters() < WAITERS {
+            assert!(start.elapsed() < std::time::Duration::from_secs(10), "waiters didn't block");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(once.waiters(), WAITERS);
+
+        guard.complete();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert_eq!(once.waiters(), 0);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn waiters_count_per_instance() {
+        // adjacent instances so that they start probing at neighboring slots
+        let onces = Arc::new([Once::new(), Once::new(), Once::new()]);
+        let guards = onces.iter().map(|once| once.begin().expect("fresh Once already initialized")).collect::<Vec<_>>();
+        let threads = (0..onces.len())
+            .flat_map(|i| (0..=i).map(move |_| i))
+            .map(|i| {
+                let onces = Arc::clone(&onces);
+                std::thread::spawn(move || onces[i].wait())
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        let start = std::time::Instant::now();
+        while onces.iter().enumerate().any(|(i, once)| once.waiters() < i + 1) {
+            assert!(start.elapsed() < std::time::Duration::from_secs(10), "waiters didn't block");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(onces.iter().map(Once::waiters).collect::<Vec<_>>(), [1, 2, 3]);
+
+        for guard in guards {
+            guard.complete();
+        }
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert!(onces.iter().all(|once| once.waiters() == 0));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn call_once_timeout_outcomes() {
+        use super::CallOnceOutcome;
+        use std::time::Duration;
+
+        let once = Arc::new(Once::new());
+        let guard = once.begin().expect("fresh Once already initialized");
+
+        let cloned = Arc::clone(&once);
+        let waiter = std::thread::spawn(move || cloned.call_once(|| panic!("the initialization should've completed")));
+
+        let result = once.call_once_timeout(|| panic!("ran while another initialization is running"), Duration::from_millis(100));
+        assert_eq!(result, CallOnceOutcome::TimedOut);
+        let cloned = Arc::clone(&once);
+        let timed_out = std::thread::spawn(move || cloned.call_once_timeout(|| (), Duration::from_millis(10)))
+            .join()
+            .expect("Failed to join");
+        assert_eq!(timed_out, CallOnceOutcome::TimedOut);
+
+        // the waiter that didn't time out is still woken up
+        guard.complete();
+        waiter.join().expect("Failed to join");
+        assert_eq!(once.call_once_timeout(|| panic!("ran after completion"), Duration::from_secs(0)), CallOnceOutcome::AlreadyComplete);
+
+        let once = Once::new();
+        assert_eq!(once.call_once_timeout(|| (), Duration::from_secs(0)), CallOnceOutcome::Ran);
+
+        let once = Once::new();
+        std::panic::catch_unwind(|| once.call_once(|| panic!())).expect_err("the closure didn't panic");
+        assert_eq!(once.call_once_timeout(|| (), Duration::from_secs(1)), CallOnceOutcome::Poisoned);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn call_once_watched_reports_stuck_initializer() {
+        use std::time::Duration;
+
+        let once = Arc::new(Once::new());
+        let (started_sender, started) = std::sync::mpsc::channel();
+        let cloned = Arc::clone(&once);
+        let initializer = std::thread::spawn(move || cloned.call_once(|| {
+            started_sender.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(300));
+        }));
+        started.recv().unwrap();
+
+        let mut reports = Vec::new();
+        once.call_once_watched(|| panic!("ran while another initialization is running"), Duration::from_millis(50), |info| reports.push(*info));
+        assert!(once.is_completed());
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].waited >= Duration::from_millis(50), "{:?}", reports[0]);
+        // the reporting thread itself is not blocked at the moment
+        assert_eq!(reports[0].waiters, 0);
+        initializer.join().expect("Failed to join");
+
+        // fast initialization doesn't trigger the handler
+        let once = Arc::new(Once::new());
+        let guard = once.begin().expect("fresh Once already initialized");
+        let cloned = Arc::clone(&once);
+        let waiter = std::thread::spawn(move || cloned.call_once_watched(|| panic!("the initialization should've completed"), Duration::from_secs(10), |info| panic!("handler called: {:?}", info)));
+        std::thread::sleep(Duration::from_millis(10));
+        guard.complete();
+        waiter.join().expect("Failed to join");
+
+        let mut ran = false;
+        Once::new().call_once_watched(|| ran = true, Duration::from_secs(0), |info| panic!("handler called: {:?}", info));
+        assert!(ran);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn once_pi_contended() {
+        use super::OncePi;
+
+        static ONCE: OncePi = OncePi::new();
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        assert_eq!(ONCE.state(), OnceStatus::Incomplete);
+        let threads = (0..4)
+            .map(|_| std::thread::spawn(|| ONCE.call_once(|| {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                CALLS.fetch_add(1, Relaxed);
+            })))
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert_eq!(CALLS.load(Relaxed), 1);
+        assert!(ONCE.is_completed());
+        assert_eq!(ONCE.state(), OnceStatus::Complete);
+        ONCE.call_once_force(|_| panic!("the initialization should've completed"));
+    }
+
+    /// Counts drops of the values stored in `OnceCell`
+    #[derive(Debug)]
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Relaxed);
+        }
+    }
+
+    #[test]
+    fn once_cell_drops_value_once() {
+        use super::OnceCell;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let cell = OnceCell::new();
+        assert!(cell.get().is_none());
+        cell.get_or_init(|| DropCounter(Arc::clone(&drops)));
+        cell.get_or_init(|| panic!("the initialization should've completed"));
+        assert!(cell.get().is_some());
+        assert_eq!(drops.load(Relaxed), 0);
+        drop(cell);
+        assert_eq!(drops.load(Relaxed), 1);
+
+        // moving the value out doesn't drop it
+        let cell = OnceCell::new();
+        cell.get_or_init(|| DropCounter(Arc::clone(&drops)));
+        let value = cell.into_inner().expect("cell not initialized");
+        assert_eq!(drops.load(Relaxed), 1);
+        drop(value);
+        assert_eq!(drops.load(Relaxed), 2);
+
+        // empty cells have nothing to drop
+        assert!(OnceCell::<DropCounter>::new().into_inner().is_none());
+        drop(OnceCell::<DropCounter>::default());
+        assert_eq!(drops.load(Relaxed), 2);
+    }
+
+    #[test]
+    fn once_cell_poisoning() {
+        use super::OnceCell;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let cell = OnceCell::new();
+        std::panic::catch_unwind(|| cell.get_or_init(|| -> DropCounter { panic!("poisoning on purpose") })).expect_err("the closure didn't panic");
+        assert!(cell.get().is_none());
+        std::panic::catch_unwind(|| cell.get_or_init(|| DropCounter(Arc::clone(&drops)))).expect_err("get_or_init didn't panic");
+        assert_eq!(format!("{:?}", cell), "OnceCell { state: Poisoned }");
+        assert!(cell.into_inner().is_none());
+        assert_eq!(drops.load(Relaxed), 0);
+    }
+
+    /// Runs 8 threads initializing the cell, the first initializer panics after creating a value
+    ///
+    /// Returns the number of threads that observed the value and the number of created values.
+    fn panicking_initializer_contended(cell: super::OnceCell<DropCounter>, drops: &Arc<AtomicUsize>) -> (usize, usize) {
+        use std::sync::Barrier;
+
+        let cell = Arc::new(cell);
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8)
+            .map(|_| {
+                let cell = Arc::clone(&cell);
+                let drops = Arc::clone(drops);
+                let attempts = Arc::clone(&attempts);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        cell.get_or_init(|| {
+                            let value = DropCounter(drops);
+                            // give the other threads time to block
+                            std::thread::sleep(std::time::Duration::from_millis(20));
+                            if attempts.fetch_add(1, Relaxed) == 0 {
+                                panic!("panicking on purpose");
+                            }
+                            value
+                        }) as *const DropCounter as usize
+                    }));
+                    result.ok()
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        let addresses = threads.into_iter().filter_map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+        assert!(addresses.iter().all(|address| *address == addresses[0]));
+        let created = attempts.load(Relaxed);
+        // only the published value is alive
+        assert_eq!(drops.load(Relaxed) + cell.get().map_or(0, |_| 1), created);
+        drop(Arc::try_unwrap(cell).expect("cell still shared"));
+        assert_eq!(drops.load(Relaxed), created);
+        (addresses.len(), created)
+    }
+
+    #[test]
+    fn once_cell_poison_policy_contended() {
+        use super::{OnceCell, PoisonPolicy};
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (succeeded, created) = panicking_initializer_contended(OnceCell::with_poison_policy(PoisonPolicy::Poison), &drops);
+        // everyone else observed the poisoning
+        assert_eq!(succeeded, 0);
+        assert_eq!(created, 1);
+    }
+
+    #[test]
+    fn once_cell_retry_policy_contended() {
+        use super::{OnceCell, PoisonPolicy};
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (succeeded, created) = panicking_initializer_contended(OnceCell::with_poison_policy(PoisonPolicy::Retry), &drops);
+        // a waiter retried and everyone except the panicking thread got its value
+        assert_eq!(succeeded, 7);
+        assert_eq!(created, 2);
+    }
+
+    #[test]
+    fn once_cell_retry_policy() {
+        use super::{OnceCell, PoisonPolicy};
+
+        let cell = OnceCell::with_poison_policy(PoisonPolicy::Retry);
+        std::panic::catch_unwind(|| cell.get_or_init(|| -> u32 { panic!("panicking on purpose") })).expect_err("the closure didn't panic");
+        assert_eq!(format!("{:?}", cell), "OnceCell { state: Incomplete }");
+        assert_eq!(cell.get_or_try_init(|| Err("failed")), Err("failed"));
+        assert_eq!(cell.set(42), Ok(()));
+        // the policy is preserved
+        let mut clone = cell.clone();
+        assert_eq!(clone.take(), Some(42));
+        std::panic::catch_unwind(|| clone.get_or_init(|| panic!("panicking on purpose"))).expect_err("the closure didn't panic");
+        assert_eq!(*clone.get_or_init(|| 47), 47);
+    }
+
+    #[test]
+    fn once_lock_poison_policy() {
+        use super::{OnceLock, PoisonPolicy};
+
+        let lock = OnceLock::new();
+        std::panic::catch_unwind(|| lock.get_or_init(|| -> u32 { panic!("panicking on purpose") })).expect_err("the closure didn't panic");
+        assert_eq!(*lock.get_or_init(|| 42), 42);
+
+        let mut lock = OnceLock::with_poison_policy(PoisonPolicy::Poison);
+        std::panic::catch_unwind(|| lock.get_or_init(|| -> u32 { panic!("poisoning on purpose") })).expect_err("the closure didn't panic");
+        std::panic::catch_unwind(|| lock.get_or_init(|| 42)).expect_err("poisoned lock didn't panic");
+        std::panic::catch_unwind(|| lock.set(42)).expect_err("poisoned lock didn't panic");
+        std::panic::catch_unwind(|| *lock.wait()).expect_err("poisoned lock didn't panic");
+        assert_eq!(lock.get(), None);
+        // taking the value clears the poisoning, same as in OnceCell
+        assert_eq!(lock.take(), None);
+        assert_eq!(*lock.get_or_init(|| 42), 42);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn once_lock_retry_recursion_panics() {
+        use super::{OnceCell, OnceLock, PoisonPolicy};
+
+        let lock = OnceLock::<u32>::new();
+        let payload = std::panic::catch_unwind(|| lock.get_or_init(|| *lock.get_or_init(|| 42)))
+            .expect_err("recursive initialization didn't panic");
+        let message = payload.downcast_ref::<String>().map(String::as_str).or_else(|| payload.downcast_ref::<&str>().copied());
+        assert!(message.expect("unexpected payload").contains("used recursively"), "unexpected message: {:?}", message);
+        // the Retry policy leaves the lock uninitialized
+        assert_eq!(lock.get(), None);
+        assert_eq!(*lock.get_or_init(|| 47), 47);
+
+        let cell = OnceCell::<u32>::with_poison_policy(PoisonPolicy::Retry);
+        std::panic::catch_unwind(|| cell.get_or_try_init(|| cell.get_or_try_init(|| Ok::<_, ()>(42)).copied()))
+            .expect_err("recursive initialization didn't panic");
+        assert_eq!(cell.get(), None);
+    }
+
+    #[test]
+    fn once_lock_poisoning_wakes_waiter() {
+        use super::{OnceLock, PoisonPolicy};
+        use std::time::Duration;
+
+        let lock = Arc::new(OnceLock::<u32>::with_poison_policy(PoisonPolicy::Poison));
+        let initializer = {
+            let lock = Arc::clone(&lock);
+            std::thread::spawn(move || lock.get_or_init(|| {
+                // give the waiter time to block
+                std::thread::sleep(Duration::from_millis(20));
+                panic!("poisoning on purpose")
+            }).to_owned())
+        };
+        // the waiter may block before or after the initializer started, either way it panics
+        let waiter = {
+            let lock = Arc::clone(&lock);
+            std::thread::spawn(move || *lock.wait())
+        };
+        initializer.join().expect_err("the closure didn't panic");
+        waiter.join().expect_err("waiter didn't observe the poisoning");
+    }
+
+    #[test]
+    fn once_cell_take_and_reinitialize() {
+        use super::OnceCell;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut cell = OnceCell::new();
+        assert!(cell.get_mut().is_none());
+        assert!(cell.take().is_none());
+
+        cell.get_or_init(|| DropCounter(Arc::clone(&drops)));
+        let value = cell.get_mut().expect("cell not initialized");
+        *value = DropCounter(Arc::clone(&drops));
+        assert_eq!(drops.load(Relaxed), 1);
+
+        let taken = cell.take().expect("cell not initialized");
+        assert!(cell.get().is_none());
+        assert!(cell.take().is_none());
+        assert_eq!(drops.load(Relaxed), 1);
+        drop(taken);
+        assert_eq!(drops.load(Relaxed), 2);
+
+        assert!(cell.set(DropCounter(Arc::clone(&drops))).is_ok());
+        assert!(cell.get().is_some());
+        let value = cell.into_inner().expect("cell not initialized");
+        assert_eq!(drops.load(Relaxed), 2);
+        drop(value);
+        assert_eq!(drops.load(Relaxed), 3);
+    }
+
+    #[test]
+    fn once_cell_take_clears_poison() {
+        use super::OnceCell;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut cell = OnceCell::new();
+        std::panic::catch_unwind(|| cell.get_or_init(|| -> DropCounter { panic!("poisoning on purpose") })).expect_err("the closure didn't panic");
+        assert!(cell.take().is_none());
+        assert_eq!(format!("{:?}", cell), "OnceCell { state: Incomplete }");
+        cell.get_or_init(|| DropCounter(Arc::clone(&drops)));
+        drop(cell);
+        assert_eq!(drops.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn once_cell_traits() {
+        use super::OnceCell;
+
+        let cell = OnceCell::from(String::from("verbose"));
+        assert_eq!(cell.get().map(String::as_str), Some("verbose"));
+        assert_eq!(cell.set(String::new()), Err(String::new()));
+        assert_eq!(cell.clone(), cell);
+        assert_eq!(OnceCell::<String>::default().clone(), OnceCell::new());
+        assert_ne!(cell, OnceCell::new());
+        assert_ne!(cell, OnceCell::from(String::from("quiet")));
+        assert_eq!(format!("{:?}", cell), "OnceCell(\"verbose\")");
+        assert_eq!(format!("{:?}", OnceCell::<String>::new()), "OnceCell { state: Incomplete }");
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        drop(OnceCell::from(DropCounter(Arc::clone(&drops))));
+        assert_eq!(drops.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn once_cell_clone_races_with_get_or_init() {
+        use super::OnceCell;
+
+        for _ in 0..100 {
+            let cell = Arc::new(OnceCell::<Vec<u32>>::new());
+            let initializer = {
+                let cell = Arc::clone(&cell);
+                std::thread::spawn(move || {
+                    cell.get_or_init(|| (0..64).collect());
+                })
+            };
+            loop {
+                let clone = (*cell).clone();
+                match clone.get() {
+                    Some(value) => {
+                        assert_eq!(*value, (0..64).collect::<Vec<_>>());
+                        break;
+                    },
+                    None => std::thread::yield_now(),
+                }
+            }
+            initializer.join().expect("Failed to join");
+        }
+    }
+
+    #[test]
+    fn lazy_lock_static_contended() {
+        use super::LazyLock;
+        use std::sync::Barrier;
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE: LazyLock<Vec<u32>> = LazyLock::new(|| {
+            CALLS.fetch_add(1, Relaxed);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            (0..64).collect()
+        });
+
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8)
+            .map(|_| {
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let value: &'static Vec<u32> = &VALUE;
+                    value
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            let value = thread.join().expect("Failed to join");
+            assert!(core::ptr::eq(value, &*VALUE));
+        }
+        assert_eq!(*VALUE, (0..64).collect::<Vec<_>>());
+        assert_eq!(CALLS.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn call_once_static_contended() {
+        use super::CallOnce;
+        use std::sync::Barrier;
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static INIT: CallOnce = CallOnce::new(|| {
+            CALLS.fetch_add(1, Relaxed);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        });
+
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8)
+            .map(|_| {
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    INIT.call();
+                    // the closure finished before any call returned
+                    assert!(INIT.is_completed());
+                    assert_eq!(CALLS.load(Relaxed), 1);
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        INIT.call();
+        assert_eq!(CALLS.load(Relaxed), 1);
+        assert_eq!(format!("{:?}", INIT), "CallOnce { completed: true }");
+    }
+
+    #[test]
+    fn call_once_poisoning_and_drops() {
+        use super::CallOnce;
+
+        let call = CallOnce::new(|| panic!("poisoning on purpose"));
+        std::panic::catch_unwind(|| call.call()).expect_err("the closure didn't panic");
+        std::panic::catch_unwind(|| call.call()).expect_err("poisoned CallOnce didn't panic");
+        assert!(!call.is_completed());
+
+        // the captures are dropped with the closure, whether it ran or not
+        let drops = Arc::new(AtomicUsize::new(0));
+        let counter = DropCounter(Arc::clone(&drops));
+        drop(CallOnce::new(move || drop(counter)));
+        assert_eq!(drops.load(Relaxed), 1);
+        let counter = DropCounter(Arc::clone(&drops));
+        let call = CallOnce::new(move || drop(counter));
+        call.call();
+        assert_eq!(drops.load(Relaxed), 2);
+        drop(call);
+        assert_eq!(drops.load(Relaxed), 2);
+    }
+
+    #[test]
+    fn once_drop_runs_exactly_once() {
+        use super::OnceDrop;
+        use std::sync::Barrier;
+
+        let teardown = Arc::new(OnceDrop::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cloned = Arc::clone(&calls);
+        teardown.register(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            cloned.fetch_add(1, Relaxed);
+        }).unwrap_or_else(|_| panic!("registration rejected"));
+        assert!(teardown.register(|| ()).is_err());
+        assert_eq!(format!("{:?}", teardown), "OnceDrop { registered: true, has_run: false }");
+
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8)
+            .map(|_| {
+                let teardown = Arc::clone(&teardown);
+                let calls = Arc::clone(&calls);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    teardown.run();
+                    // the teardown finished before any call returned
+                    assert_eq!(calls.load(Relaxed), 1);
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert!(teardown.has_run());
+        assert!(teardown.register(|| ()).is_err());
+        assert_eq!(format!("{:?}", teardown), "OnceDrop { registered: false, has_run: true }");
+    }
+
+    #[test]
+    fn once_drop_without_teardown_and_panicking() {
+        use super::OnceDrop;
+
+        let teardown = OnceDrop::new();
+        teardown.run();
+        assert!(teardown.has_run());
+        assert!(teardown.register(|| panic!("registered after running")).is_err());
+
+        let teardown = OnceDrop::new();
+        teardown.register(|| panic!("panicking on purpose")).unwrap_or_else(|_| panic!("registration rejected"));
+        std::panic::catch_unwind(|| teardown.run()).expect_err("the teardown didn't panic");
+        assert!(teardown.has_run());
+        teardown.run();
+
+        // the teardown is dropped without running
+        let drops = Arc::new(AtomicUsize::new(0));
+        let counter = DropCounter(Arc::clone(&drops));
+        OnceDrop::new().register(move || drop(counter)).unwrap_or_else(|_| panic!("registration rejected"));
+        assert_eq!(drops.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn init_barrier_exactly_n() {
+        use super::InitBarrier;
+
+        let barrier = Arc::new(InitBarrier::new(8));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let threads = (0..8)
+            .map(|_| {
+                let barrier = Arc::clone(&barrier);
+                let calls = Arc::clone(&calls);
+                std::thread::spawn(move || {
+                    let cloned = Arc::clone(&calls);
+                    barrier.arrive_and_call(|| {
+                        assert_eq!(barrier.arrived(), 8);
+                        cloned.fetch_add(1, Relaxed);
+                    });
+                    // nobody is released before the closure finished
+                    assert_eq!(calls.load(Relaxed), 1);
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert!(barrier.is_completed());
+        assert_eq!(format!("{:?}", barrier), "InitBarrier { arrived: 8, participants: 8, state: Complete }");
+        std::panic::catch_unwind(|| InitBarrier::new(0)).expect_err("zero participants didn't panic");
+    }
+
+    #[test]
+    fn init_barrier_more_than_n() {
+        use super::InitBarrier;
+
+        let barrier = Arc::new(InitBarrier::new(4));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let threads = (0..16)
+            .map(|_| {
+                let barrier = Arc::clone(&barrier);
+                let calls = Arc::clone(&calls);
+                std::thread::spawn(move || {
+                    barrier.arrive_and_call(|| { calls.fetch_add(1, Relaxed); });
+                    // arriving again after being released returns immediately
+                    barrier.arrive_and_call(|| panic!("the closure ran twice"));
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert_eq!(calls.load(Relaxed), 1);
+        assert_eq!(barrier.arrived(), 4);
+    }
+
+    #[test]
+    fn init_barrier_poisoning() {
+        use super::InitBarrier;
+
+        let barrier = Arc::new(InitBarrier::new(4));
+        let threads = (0..4)
+            .map(|_| {
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || barrier.arrive_and_call(|| panic!("poisoning on purpose")))
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            // the last participant panicked in the closure and the others because of the poison
+            thread.join().expect_err("the participant didn't panic");
+        }
+        assert!(barrier.is_poisoned());
+        std::panic::catch_unwind(|| barrier.arrive_and_call(|| ())).expect_err("poisoned barrier didn't panic");
+    }
+
+    #[test]
+    fn once_array_random_order() {
+        use super::OnceArray;
+
+        static SLOTS: OnceArray<16> = OnceArray::new();
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicUsize = AtomicUsize::new(0);
+        static CALLS: [AtomicUsize; 16] = [ZERO; 16];
+
+        let threads = (0..8u64)
+            .map(|seed| {
+                std::thread::spawn(move || {
+                    // each thread visits all slots in a different order
+                    let mut index = seed as usize;
+                    let step = [1, 3, 5, 7, 9, 11, 13, 15][seed as usize];
+                    for _ in 0..16 {
+                        SLOTS.call_once(index, || { CALLS[index].fetch_add(1, Relaxed); });
+                        assert!(SLOTS.is_completed(index));
+                        index = (index + step) % 16;
+                    }
+                    SLOTS.wait_all();
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert!(SLOTS.all_completed());
+        assert!(CALLS.iter().all(|calls| calls.load(Relaxed) == 1));
+        assert_eq!(format!("{:?}", SLOTS), "OnceArray { completed: 16, len: 16 }");
+        std::panic::catch_unwind(|| SLOTS.is_completed(16)).expect_err("index out of range didn't panic");
+    }
+
+    #[test]
+    fn once_array_wait_all_blocks_until_last_slot() {
+        use super::OnceArray;
+
+        let slots = Arc::new(OnceArray::<4>::new());
+        let finished = Arc::new(AtomicUsize::new(0));
+        let waiters = (0..4)
+            .map(|_| {
+                let slots = Arc::clone(&slots);
+                let finished = Arc::clone(&finished);
+                std::thread::spawn(move || {
+                    slots.wait_all();
+                    assert_eq!(finished.load(Relaxed), 4);
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for index in 0..4 {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            assert!(!slots.all_completed());
+            slots.call_once(index, || { finished.fetch_add(1, Relaxed); });
+        }
+        for waiter in waiters {
+            waiter.join().expect("Failed to join");
+        }
+    }
+
+    #[test]
+    fn once_array_poisoning() {
+        use super::OnceArray;
+
+        let slots = Arc::new(OnceArray::<2>::new());
+        let cloned = Arc::clone(&slots);
+        let waiter = std::thread::spawn(move || cloned.wait_all());
+        slots.call_once(0, || ());
+        std::panic::catch_unwind(|| slots.call_once(1, || panic!("poisoning on purpose"))).expect_err("the closure didn't panic");
+        waiter.join().expect_err("waiting for the poisoned slot didn't panic");
+        std::panic::catch_unwind(|| slots.call_once(1, || ())).expect_err("poisoned slot didn't panic");
+        assert!(slots.is_completed(0));
+        assert!(!slots.all_completed());
+    }
+
+    #[test]
+    fn padded_once_layout() {
+        use super::{CachePadded, PaddedOnce};
+
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "powerpc64"))]
+        const LINE: usize = 128;
+        #[cfg(any(target_arch = "arm", target_arch = "mips", target_arch = "mips64", target_arch = "sparc", target_arch = "hexagon"))]
+        const LINE: usize = 32;
+        #[cfg(target_arch = "s390x")]
+        const LINE: usize = 256;
+        #[cfg(not(any(
+            target_arch = "x86_64",
+            target_arch = "aarch64",
+            target_arch = "powerpc64",
+            target_arch = "arm",
+            target_arch = "mips",
+            target_arch = "mips64",
+            target_arch = "sparc",
+            target_arch = "hexagon",
+            target_arch = "s390x",
+        )))]
+        const LINE: usize = 64;
+
+        assert_eq!(core::mem::align_of::<PaddedOnce>(), LINE);
+        assert_eq!(core::mem::size_of::<PaddedOnce>(), LINE);
+        assert_eq!(core::mem::size_of::<[PaddedOnce; 2]>(), 2 * LINE);
+        assert_eq!(core::mem::size_of::<CachePadded<[u8; 129]>>() % LINE, 0);
+
+        let once = PaddedOnce::new(Once::new());
+        once.call_once(|| ());
+        assert!(once.is_completed());
+        assert_eq!(format!("{:?}", CachePadded::new(42)), "CachePadded(42)");
+        assert_eq!(CachePadded::from(42).into_inner(), 42);
+    }
+
+    #[test]
+    fn lazy_lock_poisoning() {
+        use super::LazyLock;
+
+        let lazy = LazyLock::new(|| -> u32 { panic!("poisoning on purpose") });
+        std::panic::catch_unwind(|| *lazy).expect_err("the closure didn't panic");
+        std::panic::catch_unwind(|| *lazy).expect_err("poisoned LazyLock didn't panic");
+        assert_eq!(format!("{:?}", lazy), "LazyLock { state: Poisoned }");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn poisoned_lazy_panic_names_caller() {
+        use super::{LazyLock, RetryLazy, TryLazy};
+
+        fn message_of(payload: Box<dyn std::any::Any + Send>) -> String {
+            payload.downcast::<String>().map(|message| *message).expect("unexpected payload")
+        }
+
+        let lazy = LazyLock::new(|| -> u32 { panic!("poisoning on purpose") });
+        let line = line!() + 1;
+        std::panic::catch_unwind(|| *lazy).expect_err("the closure didn't panic");
+        let message = message_of(std::panic::catch_unwind(|| LazyLock::force(&lazy)).expect_err("poisoned LazyLock didn't panic"));
+        let expected = format!(" at {}:{}:", file!(), line);
+        assert!(message.contains(&expected), "unexpected message: {}", message);
+
+        let mut lazy = LazyLock::new(|| -> u32 { panic!("poisoning on purpose") });
+        let line = line!() + 1;
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *LazyLock::force_mut(&mut lazy))).expect_err("the closure didn't panic");
+        let message = message_of(std::panic::catch_unwind(|| *lazy).expect_err("poisoned LazyLock didn't panic"));
+        let expected = format!(" at {}:{}:", file!(), line);
+        assert!(message.contains(&expected), "unexpected message: {}", message);
+
+        let lazy = TryLazy::new(|| -> Result<u32, ()> { panic!("poisoning on purpose") });
+        let line = line!() + 1;
+        std::panic::catch_unwind(|| TryLazy::force(&lazy).is_ok()).expect_err("the closure didn't panic");
+        let message = message_of(std::panic::catch_unwind(|| TryLazy::force(&lazy).is_ok()).expect_err("poisoned TryLazy didn't panic"));
+        let expected = format!(" at {}:{}:", file!(), line);
+        assert!(message.contains(&expected), "unexpected message: {}", message);
+
+        let lazy = RetryLazy::new(|| -> Result<u32, ()> { panic!("poisoning on purpose") });
+        let line = line!() + 1;
+        std::panic::catch_unwind(|| RetryLazy::force(&lazy).is_ok()).expect_err("the closure didn't panic");
+        let message = message_of(std::panic::catch_unwind(|| RetryLazy::force(&lazy).is_ok()).expect_err("poisoned RetryLazy didn't panic"));
+        let expected = format!(" at {}:{}:", file!(), line);
+        assert!(message.contains(&expected), "unexpected message: {}", message);
+    }
+
+    #[test]
+    fn lazy_lock_get_and_into_inner() {
+        use super::LazyLock;
+
+        let calls = AtomicUsize::new(0);
+        let init = || {
+            calls.fetch_add(1, Relaxed);
+            42
+        };
+
+        let lazy = LazyLock::new(init);
+        assert_eq!(LazyLock::get(&lazy), None);
+        assert_eq!(calls.load(Relaxed), 0);
+        let f = LazyLock::into_inner(lazy).expect_err("uninitialized LazyLock returned a value");
+        assert_eq!(calls.load(Relaxed), 0);
+        assert_eq!(f(), 42);
+        assert_eq!(calls.load(Relaxed), 1);
+
+        let lazy = LazyLock::new(init);
+        assert_eq!(*LazyLock::force(&lazy), 42);
+        assert_eq!(LazyLock::get(&lazy), Some(&42));
+        assert_eq!(LazyLock::into_inner(lazy).ok(), Some(42));
+        assert_eq!(calls.load(Relaxed), 2);
+
+        let lazy = LazyLock::new(|| -> u32 { panic!("poisoning on purpose") });
+        std::panic::catch_unwind(|| *lazy).expect_err("the closure didn't panic");
+        assert_eq!(LazyLock::get(&lazy), None);
+        std::panic::catch_unwind(move || LazyLock::into_inner(lazy).ok()).expect_err("into_inner of poisoned LazyLock didn't panic");
+    }
+
+    #[test]
+    fn lazy_lock_into_inner_drops() {
+        use super::LazyLock;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let captured = DropCounter(Arc::clone(&drops));
+        let lazy = LazyLock::new(move || captured);
+        let f = LazyLock::into_inner(lazy).expect_err("uninitialized LazyLock returned a value");
+        assert_eq!(drops.load(Relaxed), 0);
+        drop(f);
+        assert_eq!(drops.load(Relaxed), 1);
+
+        let captured = DropCounter(Arc::clone(&drops));
+        let lazy = LazyLock::new(move || captured);
+        LazyLock::force(&lazy);
+        let value = LazyLock::into_inner(lazy).ok().expect("initialized LazyLock returned the closure");
+        assert_eq!(drops.load(Relaxed), 1);
+        drop(value);
+        assert_eq!(drops.load(Relaxed), 2);
+    }
+
+    #[test]
+    fn try_lazy_caches_error_contended() {
+        use super::TryLazy;
+        use std::sync::Barrier;
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static DB: TryLazy<u32, String> = TryLazy::new(|| {
+            CALLS.fetch_add(1, Relaxed);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            Err(String::from("connection refused"))
+        });
+
+        assert_eq!(TryLazy::get(&DB), None);
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8)
+            .map(|_| {
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    TryLazy::force(&DB).expect_err("initialization succeeded") as *const String as usize
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            let error = thread.join().expect("Failed to join");
+            assert_eq!(error, TryLazy::force(&DB).expect_err("initialization succeeded") as *const String as usize);
+        }
+        assert_eq!(TryLazy::get(&DB), Some(Err(&String::from("connection refused"))));
+        assert_eq!(CALLS.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn try_lazy_value_and_poisoning() {
+        use super::TryLazy;
+
+        let lazy = TryLazy::<_, (), _>::new(|| Ok(42));
+        assert_eq!(format!("{:?}", lazy), "TryLazy { state: Incomplete }");
+        assert_eq!(TryLazy::force(&lazy), Ok(&42));
+        assert_eq!(format!("{:?}", lazy), "TryLazy(Ok(42))");
+
+        let lazy = TryLazy::<u32, (), _>::new(|| panic!("poisoning on purpose"));
+        std::panic::catch_unwind(|| TryLazy::force(&lazy).is_ok()).expect_err("the closure didn't panic");
+        std::panic::catch_unwind(|| TryLazy::force(&lazy).is_ok()).expect_err("poisoned TryLazy didn't panic");
+        assert_eq!(TryLazy::get(&lazy), None);
+    }
+
+    #[test]
+    fn retry_lazy_retries_contended() {
+        use super::RetryLazy;
+        use std::sync::Barrier;
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        // fails the first three attempts
+        static DB: RetryLazy<u32, usize> = RetryLazy::new(|| {
+            let attempt = CALLS.fetch_add(1, Relaxed);
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            if attempt < 3 {
+                Err(attempt)
+            } else {
+                Ok(42)
+            }
+        });
+
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8)
+            .map(|_| {
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    RetryLazy::force(&DB).copied()
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        let mut errors = threads
+            .into_iter()
+            .filter_map(|thread| thread.join().expect("Failed to join").err())
+            .collect::<Vec<_>>();
+        errors.sort_unstable();
+        // each failure is reported to exactly one caller
+        assert_eq!(errors, [0, 1, 2]);
+        assert_eq!(RetryLazy::get(&DB), Some(&42));
+        assert_eq!(RetryLazy::force(&DB), Ok(&42));
+        assert_eq!(CALLS.load(Relaxed), 4);
+    }
+
+    #[test]
+    fn retry_lazy_drops_function_after_success() {
+        use super::RetryLazy;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let captured = DropCounter(Arc::clone(&drops));
+        let mut fail = true;
+        let lazy = RetryLazy::new(move || {
+            let _ = &captured;
+            if core::mem::take(&mut fail) {
+                Err("try again")
+            } else {
+                Ok(42)
+            }
+        });
+        assert_eq!(RetryLazy::force(&lazy), Err("try again"));
+        assert_eq!(format!("{:?}", lazy), "RetryLazy { state: Incomplete }");
+        assert_eq!(drops.load(Relaxed), 0);
+        assert_eq!(RetryLazy::force(&lazy), Ok(&42));
+        assert_eq!(drops.load(Relaxed), 1);
+        assert_eq!(format!("{:?}", lazy), "RetryLazy(42)");
+    }
+
+    #[test]
+    fn lazy_lock_force_mut() {
+        use super::LazyLock;
+
+        let mut lazy = LazyLock::new(|| vec![1, 2]);
+        LazyLock::force_mut(&mut lazy).push(3);
+        assert_eq!(LazyLock::get(&lazy).map(Vec::as_slice), Some(&[1, 2, 3][..]));
+
+        let mut lazy = LazyLock::new(|| vec![1, 2]);
+        assert_eq!(lazy.len(), 2);
+        lazy.push(3);
+        assert_eq!(*LazyLock::force(&lazy), [1, 2, 3]);
+
+        let mut lazy = LazyLock::new(|| -> Vec<u32> { panic!("poisoning on purpose") });
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| lazy.push(0))).expect_err("the closure didn't panic");
+        assert_eq!(format!("{:?}", lazy), "LazyLock { state: Poisoned }");
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| lazy.push(0))).expect_err("poisoned LazyLock didn't panic");
+    }
+
+    #[test]
+    fn lazy_lock_nameable_in_statics() {
+        use super::LazyLock;
+
+        struct Limits;
+
+        // the constants are only used to initialize statics, which is the point
+        #[allow(clippy::declare_interior_mutable_const)]
+        impl Limits {
+            const DEFAULT: LazyLock<usize> = LazyLock::from_fn(|| 64);
+            const PREINIT: LazyLock<usize> = LazyLock::preinit(32);
+        }
+
+        static LIMITS: [LazyLock<usize>; 3] = [Limits::DEFAULT, Limits::PREINIT, Limits::DEFAULT];
+
+        assert_eq!(LazyLock::get(&LIMITS[0]), None);
+        assert_eq!(LazyLock::get(&LIMITS[1]), Some(&32));
+        assert_eq!(*LIMITS[0], 64);
+        assert_eq!(*LIMITS[1], 32);
+        assert_eq!(LazyLock::get(&LIMITS[2]), None);
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let lazy = LazyLock::<_, fn() -> DropCounter>::preinit(DropCounter(Arc::clone(&drops)));
+        assert_eq!(LazyLock::into_inner(lazy).ok().map(|counter| counter.0.load(Relaxed)), Some(0));
+        assert_eq!(drops.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn lazy_lock_drops() {
+        use super::LazyLock;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let captured = DropCounter(Arc::clone(&drops));
+        let lazy = LazyLock::new(move || {
+            drop(captured);
+            42
+        });
+        assert_eq!(format!("{:?}", lazy), "LazyLock { state: Incomplete }");
+        drop(lazy);
+        assert_eq!(drops.load(Relaxed), 1);
+
+        let captured = DropCounter(Arc::clone(&drops));
+        let lazy = LazyLock::new(move || captured);
+        LazyLock::force(&lazy);
+        assert_eq!(drops.load(Relaxed), 1);
+        drop(lazy);
+        assert_eq!(drops.load(Relaxed), 2);
+
+        let captured = DropCounter(Arc::clone(&drops));
+        let lazy = LazyLock::new(move || -> DropCounter {
+            let _captured = captured;
+            panic!("poisoning on purpose")
+        });
+        std::panic::catch_unwind(|| LazyLock::force(&lazy).0.load(Relaxed)).expect_err("the closure didn't panic");
+        assert_eq!(drops.load(Relaxed), 3);
+        drop(lazy);
+        assert_eq!(drops.load(Relaxed), 3);
+
+        let lazy = LazyLock::<String>::default();
+        assert_eq!(*lazy, "");
+        assert_eq!(format!("{:?}", lazy), "LazyLock(\"\")");
+    }
+
+    #[test]
+    fn once_cell_try_init_error_drops_partial_value() {
+        use super::OnceCell;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let cell = OnceCell::<(DropCounter, DropCounter)>::new();
+        let result = cell.get_or_try_init(|| {
+            let first = DropCounter(Arc::clone(&drops));
+            Err::<_, &str>("config file missing").map(|second| (first, second))
+        });
+        assert_eq!(result.expect_err("the error wasn't returned"), "config file missing");
+        assert_eq!(drops.load(Relaxed), 1);
+        assert!(cell.get().is_none());
+
+        let value = cell.get_or_try_init(|| Ok::<_, &str>((DropCounter(Arc::clone(&drops)), DropCounter(Arc::clone(&drops))))).expect("initialization failed");
+        assert_eq!(value as *const _, cell.get().expect("cell not initialized") as *const _);
+        cell.get_or_try_init(|| Err("ran after completion")).expect("initialized cell returned an error");
+        assert_eq!(drops.load(Relaxed), 1);
+        drop(cell);
+        assert_eq!(drops.load(Relaxed), 3);
+    }
+
+    #[test]
+    fn once_cell_try_init_contended() {
+        use super::OnceCell;
+        use std::sync::Barrier;
+
+        let cell = Arc::new(OnceCell::new());
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8)
+            .map(|_| {
+                let cell = Arc::clone(&cell);
+                let attempts = Arc::clone(&attempts);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    cell.get_or_try_init(|| {
+                        let attempt = attempts.fetch_add(1, Relaxed);
+                        // give the other threads time to block
+                        std::thread::sleep(std::time::Duration::from_millis(20));
+                        if attempt == 0 {
+                            Err("config file missing")
+                        } else {
+                            Ok(attempt)
+                        }
+                    }).copied()
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        let results = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+        assert!(results.iter().filter_map(|result| result.ok()).all(|value| value == 1));
+        assert_eq!(attempts.load(Relaxed), 2);
+        assert_eq!(cell.get(), Some(&1));
+    }
+
+    #[test]
+    fn once_cell_maybe_init_contended() {
+        use super::OnceCell;
+        use std::sync::Barrier;
+
+        let cell = Arc::new(OnceCell::new());
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8)
+            .map(|_| {
+                let cell = Arc::clone(&cell);
+                let attempts = Arc::clone(&attempts);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    cell.get_or_maybe_init(|| {
+                        let attempt = attempts.fetch_add(1, Relaxed);
+                        // give the other threads time to block
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                        // the flag is only on after a few reloads
+                        if attempt < 3 {
+                            None
+                        } else {
+                            Some(attempt)
+                        }
+                    }).copied()
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        let results = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+        assert_eq!(results.iter().filter(|result| result.is_none()).count(), 3);
+        assert!(results.iter().flatten().all(|value| *value == 3));
+        assert_eq!(attempts.load(Relaxed), 4);
+        assert_eq!(cell.get(), Some(&3));
+        assert_eq!(cell.get_or_maybe_init(|| unreachable!()), Some(&3));
+    }
+
+    #[test]
+    fn once_cell_maybe_init_panic_follows_policy() {
+        use super::{OnceCell, PoisonPolicy};
+
+        let cell = OnceCell::<u32>::new();
+        std::panic::catch_unwind(|| cell.get_or_maybe_init(|| panic!("poisoning on purpose"))).expect_err("the closure didn't panic");
+        std::panic::catch_unwind(|| cell.get_or_maybe_init(|| Some(42))).expect_err("poisoned cell didn't panic");
+
+        let cell = OnceCell::<u32>::with_poison_policy(PoisonPolicy::Retry);
+        std::panic::catch_unwind(|| cell.get_or_maybe_init(|| panic!("panicking on purpose"))).expect_err("the closure didn't panic");
+        assert_eq!(cell.get_or_maybe_init(|| None), None);
+        assert_eq!(cell.get_or_maybe_init(|| Some(42)), Some(&42));
+    }
+
+    /// Value storing its own address, as C structures pointing into themselves do
+    struct SelfRef {
+        this: *const SelfRef,
+        drops: Arc<AtomicUsize>,
+        _pinned: core::marker::PhantomPinned,
+    }
+
+    impl SelfRef {
+        fn init(slot: core::pin::Pin<&mut core::mem::MaybeUninit<Self>>, drops: Arc<AtomicUsize>) -> core::pin::Pin<&mut Self> {
+            // SAFETY: the slot is initialized before the reference to the value is created and
+            // nothing is moved
+            unsafe {
+                let slot = slot.get_unchecked_mut();
+                let this = slot.as_mut_ptr();
+                this.write(SelfRef { this, drops, _pinned: core::marker::PhantomPinned });
+                core::pin::Pin::new_unchecked(slot.assume_init_mut())
+            }
+        }
+
+        fn check(&self) {
+            assert_eq!(self.this, self as *const SelfRef, "the value moved");
+        }
+    }
+
+    impl Drop for SelfRef {
+        fn drop(&mut self) {
+            // dropped in place
+            self.check();
+            self.drops.fetch_add(1, Relaxed);
+        }
+    }
+
+    // SAFETY: the pointer is only compared
+    unsafe impl Send for SelfRef {}
+    // SAFETY: the pointer is only compared
+    unsafe impl Sync for SelfRef {}
+
+    #[test]
+    fn once_pin_address_sensitive_value() {
+        use super::OncePin;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let cell = Box::pin(OncePin::new());
+        assert!(cell.get().is_none());
+        let address = {
+            let value = cell.as_ref().get_or_init_pin(|slot| SelfRef::init(slot, Arc::clone(&drops)));
+            value.check();
+            value.this as usize
+        };
+        cell.as_ref().get_or_init_pin(|_| panic!("the initialization should've completed"));
+        // moving the box doesn't move the value
+        let moved = Some(cell).into_iter().collect::<Vec<_>>();
+        let value = moved[0].get().expect("cell not initialized");
+        value.check();
+        assert_eq!(value.this as usize, address);
+        assert_eq!(drops.load(Relaxed), 0);
+        drop(moved);
+        assert_eq!(drops.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn once_pin_contended() {
+        use super::OncePin;
+        use std::sync::Barrier;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let inits = Arc::new(AtomicUsize::new(0));
+        let cell = Arc::pin(OncePin::new());
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8)
+            .map(|_| {
+                let cell = cell.clone();
+                let drops = Arc::clone(&drops);
+                let inits = Arc::clone(&inits);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let value = cell.as_ref().get_or_init_pin(|slot| {
+                        inits.fetch_add(1, Relaxed);
+                        SelfRef::init(slot, drops)
+                    });
+                    value.check();
+                    value.this as usize
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        let addresses = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+        assert!(addresses.iter().all(|address| *address == addresses[0]));
+        assert_eq!(inits.load(Relaxed), 1);
+        drop(cell);
+        assert_eq!(drops.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn once_pin_rejects_foreign_reference() {
+        use super::OncePin;
+        use core::pin::Pin;
+
+        static CELL: OncePin<u32> = OncePin::new();
+
+        let cell = Pin::static_ref(&CELL);
+        let other_ptr = Box::into_raw(Box::new(42));
+        // SAFETY: the pointer comes from the box above
+        let other = unsafe { &mut *other_ptr };
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            cell.get_or_init_pin(move |_| Pin::new(other));
+        })).expect_err("foreign reference accepted");
+        // SAFETY: the reference was consumed by the panicking closure
+        drop(unsafe { Box::from_raw(other_ptr) });
+        assert!(CELL.get().is_none());
+        std::panic::catch_unwind(|| *cell.get_or_init(|| 42)).expect_err("poisoned cell didn't panic");
+        assert_eq!(format!("{:?}", CELL), "OncePin { state: Poisoned }");
+
+        let cell = Box::pin(OncePin::new());
+        assert_eq!(*cell.as_ref().get_or_init(|| 42), 42);
+        assert_eq!(format!("{:?}", cell), "OncePin(42)");
+    }
+
+    #[test]
+    fn once_cell_get_mut_or_init() {
+        use super::OnceCell;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut cell = OnceCell::new();
+        assert_eq!(cell.get_mut_or_try_init(|| Err("failed")).map(|value: &mut Vec<u32>| value.len()), Err("failed"));
+        assert!(cell.get().is_none());
+        cell.get_mut_or_init(Vec::new).push(1);
+        // already initialized
+        cell.get_mut_or_init(|| unreachable!()).push(2);
+        assert_eq!(cell.get_mut_or_try_init(|| Err("failed")).map(|value| value.len()), Ok(2));
+        assert_eq!(cell.get(), Some(&vec![1, 2]));
+        assert_eq!(*cell.get_or_init(|| unreachable!()), [1, 2]);
+
+        // panicking leaves the cell empty since nobody could observe it
+        let mut cell = OnceCell::new();
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| { cell.get_mut_or_init(|| -> DropCounter { panic!("panicking on purpose") }); }))
+            .expect_err("the closure didn't panic");
+        assert_eq!(format!("{:?}", cell), "OnceCell { state: Incomplete }");
+        cell.get_mut_or_init(|| DropCounter(Arc::clone(&drops)));
+        drop(cell);
+        assert_eq!(drops.load(Relaxed), 1);
+
+        let mut cell = OnceCell::<u32>::new();
+        std::panic::catch_unwind(|| cell.get_or_init(|| panic!("poisoning on purpose"))).expect_err("the closure didn't panic");
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *cell.get_mut_or_init(|| 42))).expect_err("poisoned cell didn't panic");
+    }
+
+    #[test]
+    fn once_lock_get_mut_or_init() {
+        use super::{OnceLock, PoisonPolicy};
+
+        let mut lock = OnceLock::new();
+        assert_eq!(lock.get_mut_or_try_init(|| Err("failed")).map(|value: &mut Vec<u32>| value.len()), Err("failed"));
+        assert!(lock.get().is_none());
+        lock.get_mut_or_init(Vec::new).push(1);
+        lock.get_mut_or_init(|| unreachable!()).push(2);
+        assert_eq!(lock.get(), Some(&vec![1, 2]));
+        assert_eq!(lock.set(Vec::new()), Err(Vec::new()));
+
+        let mut lock = OnceLock::<u32>::with_poison_policy(PoisonPolicy::Poison);
+        std::panic::catch_unwind(|| lock.get_or_init(|| panic!("poisoning on purpose"))).expect_err("the closure didn't panic");
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *lock.get_mut_or_init(|| 42))).expect_err("poisoned lock didn't panic");
+    }
+
+    #[test]
+    fn static_once_take_races() {
+        use super::StaticOnce;
+        use std::sync::Barrier;
+
+        static BUFFER: StaticOnce<[u8; 16]> = StaticOnce::new([0; 16]);
+        static DRIVER: StaticOnce<std::cell::Cell<u32>> = StaticOnce::uninit();
+
+        assert_eq!(format!("{:?}", BUFFER), "StaticOnce { state: ready }");
+        let barrier = Arc::new(Barrier::new(16));
+        let threads = (0..16)
+            .map(|i| {
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let buffer = BUFFER.take().map(|buffer| {
+                        buffer[0] = i;
+                        buffer[0]
+                    });
+                    // Cell is not Sync but only one thread gets it
+                    let driver = DRIVER.try_init(std::cell::Cell::new(u32::from(i))).ok().map(|driver| driver.get());
+                    (buffer, driver)
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        let results = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+        assert_eq!(results.iter().filter(|(buffer, _)| buffer.is_some()).count(), 1);
+        assert_eq!(results.iter().filter(|(_, driver)| driver.is_some()).count(), 1);
+        assert!(BUFFER.is_taken());
+        assert!(DRIVER.is_taken());
+        assert!(BUFFER.take().is_none());
+        assert!(DRIVER.take().is_none());
+        assert_eq!(format!("{:?}", DRIVER), "StaticOnce { state: taken }");
+        std::panic::catch_unwind(|| DRIVER.init(std::cell::Cell::new(0))).expect_err("second init didn't panic");
+    }
+
+    #[test]
+    fn static_once_uninit_and_drop() {
+        use super::StaticOnce;
+
+        static UNINIT: StaticOnce<u32> = StaticOnce::uninit();
+
+        assert!(UNINIT.take().is_none());
+        assert!(!UNINIT.is_taken());
+
+        // a value created but never taken is dropped
+        let drops = Arc::new(AtomicUsize::new(0));
+        drop(StaticOnce::new(DropCounter(Arc::clone(&drops))));
+        drop(StaticOnce::<DropCounter>::uninit());
+        assert_eq!(drops.load(Relaxed), 1);
+
+        static SLOT: StaticOnce<u32> = StaticOnce::uninit();
+        *SLOT.try_init(1).expect("SLOT initialized twice") += 1;
+        // the rejected value is returned
+        assert_eq!(SLOT.try_init(47), Err(47));
+    }
+
+    #[test]
+    fn once_cell_wait_timeout() {
+        use super::OnceCell;
+        use std::time::{Duration, Instant};
+
+        // set before the deadline
+        let cell = Arc::new(OnceCell::new());
+        let cloned = Arc::clone(&cell);
+        let producer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            cloned.set(42)
+        });
+        assert_eq!(cell.wait_timeout(Duration::from_secs(10)), Some(&42));
+        assert_eq!(producer.join().expect("Failed to join"), Ok(()));
+        // available immediately
+        assert_eq!(cell.wait_timeout(Duration::ZERO), Some(&42));
+        assert_eq!(cell.wait_deadline(Instant::now() - Duration::from_secs(1)), Some(&42));
+
+        // set after the deadline
+        let cell = Arc::new(OnceCell::new());
+        let cloned = Arc::clone(&cell);
+        let producer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            cloned.set(42)
+        });
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(20);
+        assert_eq!(cell.wait_deadline(deadline), None);
+        assert!(Instant::now() >= deadline);
+        assert_eq!(cell.wait_timeout(Duration::ZERO), None);
+        assert_eq!(producer.join().expect("Failed to join"), Ok(()));
+        assert_eq!(cell.wait_deadline(deadline), Some(&42));
+
+        let cell = OnceCell::<i32>::new();
+        std::panic::catch_unwind(|| cell.get_or_init(|| panic!("poisoning on purpose"))).expect_err("the closure didn't panic");
+        std::panic::catch_unwind(|| cell.wait_timeout(Duration::from_secs(1)).copied()).expect_err("wait_timeout didn't panic");
+    }
+
+    #[test]
+    fn once_cell_get_unchecked() {
+        use super::OnceCell;
+
+        let cell = Arc::new(OnceCell::<String>::new());
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let reader = {
+            let cell = Arc::clone(&cell);
+            std::thread::spawn(move || {
+                receiver.recv().expect("initializer exited");
+                // SAFETY: the initializing thread sent the message after initializing the cell
+                unsafe { cell.get_unchecked().clone() }
+            })
+        };
+        cell.get_or_init(|| String::from("verbose"));
+        sender.send(()).expect("reader exited");
+        assert_eq!(reader.join().expect("Failed to join"), "verbose");
+        // SAFETY: get_or_init returned above
+        assert_eq!(unsafe { cell.get_unchecked() }, "verbose");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn once_cell_get_unchecked_uninitialized_caught() {
+        use super::OnceCell;
+
+        let cell = OnceCell::<u32>::new();
+        // SAFETY: not actually safe but debug builds panic before accessing the value
+        std::panic::catch_unwind(|| unsafe { *cell.get_unchecked() }).expect_err("misuse not caught");
+    }
+
+    #[test]
+    fn guarded_cell_init_and_cleanup_once() {
+        use super::GuardedCell;
+        use std::sync::Barrier;
+
+        for _ in 0..20 {
+            let cell = Arc::new(GuardedCell::new());
+            let inits = Arc::new(AtomicUsize::new(0));
+            let cleanups = Arc::new(AtomicUsize::new(0));
+            let drops = Arc::new(AtomicUsize::new(0));
+            let barrier = Arc::new(Barrier::new(8));
+            let threads = (0..8)
+                .map(|i| {
+                    let cell = Arc::clone(&cell);
+                    let inits = Arc::clone(&inits);
+                    let cleanups = Arc::clone(&cleanups);
+                    let drops = Arc::clone(&drops);
+                    let barrier = Arc::clone(&barrier);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        if i % 2 == 0 {
+                            let result = std::panic::catch_unwind(|| {
+                                cell.get_or_init(|| {
+                                    inits.fetch_add(1, Relaxed);
+                                    DropCounter(drops)
+                                });
+                            });
+                            // initializing after the cleanup started panics
+                            assert_eq!(result.is_err(), cell.is_cleaned_up());
+                        } else {
+                            // SAFETY: the references are not used after the cleanup started
+                            unsafe {
+                                cell.cleanup(|value| {
+                                    cleanups.fetch_add(1, Relaxed);
+                                    drop(value);
+                                });
+                            }
+                        }
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            for thread in threads {
+                thread.join().expect("Failed to join");
+            }
+            assert!(inits.load(Relaxed) <= 1);
+            assert_eq!(cleanups.load(Relaxed), inits.load(Relaxed));
+            assert_eq!(drops.load(Relaxed), inits.load(Relaxed));
+            assert!(cell.get().is_none());
+            drop(cell);
+            assert_eq!(drops.load(Relaxed), inits.load(Relaxed));
+        }
+    }
+
+    #[test]
+    fn guarded_cell_states() {
+        use super::GuardedCell;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let cell = GuardedCell::new();
+        assert_eq!(format!("{:?}", cell), "GuardedCell { state: Incomplete }");
+        cell.get_or_init(|| DropCounter(Arc::clone(&drops)));
+        assert!(cell.get().is_some());
+        drop(cell);
+        assert_eq!(drops.load(Relaxed), 1);
+
+        let cell = GuardedCell::<u32>::new();
+        // SAFETY: the cell is never accessed
+        assert!(!unsafe { cell.cleanup(|_| panic!("cleaned up empty cell")) });
+        assert_eq!(cell.get(), None);
+        assert_eq!(format!("{:?}", cell), "GuardedCell(<cleaned up>)");
+        std::panic::catch_unwind(|| *cell.get_or_init(|| 42)).expect_err("initialized after cleanup");
+
+        let cell = GuardedCell::new();
+        assert_eq!(*cell.get_or_init(|| 42), 42);
+        // SAFETY: the reference isn't used anymore
+        assert!(unsafe { cell.cleanup(|value| assert_eq!(value, 42)) });
+        // SAFETY: same as above
+        assert!(!unsafe { cell.cleanup(|_| panic!("cleaned up twice")) });
+
+        let cell = GuardedCell::<u32>::new();
+        std::panic::catch_unwind(|| *cell.get_or_init(|| panic!("poisoning on purpose"))).expect_err("the closure didn't panic");
+        // SAFETY: the cell is never accessed
+        assert!(!unsafe { cell.cleanup(|_| panic!("cleaned up poisoned cell")) });
+        assert_eq!(cell.get(), None);
+    }
+
+    #[test]
+    fn unsync_once_cell() {
+        use super::unsync::OnceCell;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut cell = OnceCell::new();
+        assert!(cell.get().is_none());
+        assert_eq!(format!("{:?}", OnceCell::<u32>::new()), "OnceCell(<uninit>)");
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cell.get_or_init(|| -> DropCounter { panic!("failing on purpose") }))).expect_err("the closure didn't panic");
+        assert!(cell.get().is_none());
+        assert_eq!(cell.get_or_try_init(|| Err("failing on purpose")).map(drop), Err("failing on purpose"));
+        assert!(cell.get().is_none());
+
+        let value = cell.get_or_init(|| DropCounter(Arc::clone(&drops))) as *const DropCounter;
+        assert!(core::ptr::eq(cell.get_or_try_init(|| Err(())).expect("initialized cell returned an error"), value));
+        assert!(cell.set(DropCounter(Arc::clone(&drops))).is_err());
+        assert_eq!(drops.load(Relaxed), 1);
+        assert!(cell.get_mut().is_some());
+
+        let taken = cell.take().expect("cell not initialized");
+        assert!(cell.get().is_none());
+        drop(taken);
+        assert_eq!(drops.load(Relaxed), 2);
+
+        assert!(cell.set(DropCounter(Arc::clone(&drops))).is_ok());
+        drop(cell);
+        assert_eq!(drops.load(Relaxed), 3);
+
+        let cell = OnceCell::from(DropCounter(Arc::clone(&drops)));
+        let value = cell.into_inner().expect("cell not initialized");
+        assert_eq!(drops.load(Relaxed), 3);
+        drop(value);
+        assert_eq!(drops.load(Relaxed), 4);
+
+        let cell = OnceCell::from(42);
+        assert_eq!(cell.clone(), cell);
+        assert_ne!(cell, OnceCell::new());
+        assert_eq!(format!("{:?}", cell), "OnceCell(42)");
+    }
+
+    #[test]
+    fn unsync_once_cell_retries() {
+        use super::unsync::OnceCell;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let cell = OnceCell::<DropCounter>::default();
+
+        // error then retry, the partially built value is dropped
+        let result = cell.get_or_try_init(|| {
+            let _partial = DropCounter(Arc::clone(&drops));
+            Err("config file missing")
+        });
+        assert_eq!(result.map(drop), Err("config file missing"));
+        assert_eq!(drops.load(Relaxed), 1);
+        assert!(cell.get().is_none());
+
+        // panic then retry
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.get_or_try_init(|| -> Result<_, ()> {
+                let _partial = DropCounter(Arc::clone(&drops));
+                panic!("failing on purpose")
+            })
+            .map(drop)
+        }))
+        .expect_err("the closure didn't panic");
+        assert_eq!(drops.load(Relaxed), 2);
+        assert!(cell.get().is_none());
+
+        let value = cell.get_or_try_init(|| Ok::<_, ()>(DropCounter(Arc::clone(&drops)))).expect("initialization failed");
+        assert!(core::ptr::eq(value, cell.get().expect("cell not initialized")));
+        assert_eq!(drops.load(Relaxed), 2);
+        drop(cell);
+        assert_eq!(drops.load(Relaxed), 3);
+    }
+
+    #[test]
+    fn unsync_once_cell_reentrancy_panics() {
+        use super::unsync::OnceCell;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let cell = OnceCell::new();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.get_or_init(|| {
+                let inner = cell.get_or_init(|| DropCounter(Arc::clone(&drops)));
+                // the outer value must not overwrite the inner one while this reference is alive
+                let _ = &inner.0;
+                DropCounter(Arc::clone(&drops))
+            });
+        }));
+        result.expect_err("reentrant initialization didn't panic");
+        // the outer value was dropped, the inner one is kept
+        assert_eq!(drops.load(Relaxed), 1);
+        assert!(cell.get().is_some());
+        drop(cell);
+        assert_eq!(drops.load(Relaxed), 2);
+    }
+
+    #[test]
+    fn unsync_lazy_thread_local() {
+        use super::unsync::Lazy;
+        use core::cell::Cell;
+
+        thread_local! {
+            static CALLS: Cell<usize> = const { Cell::new(0) };
+            static SQUARES: Lazy<Vec<u64>> = Lazy::new(|| {
+                CALLS.with(|calls| calls.set(calls.get() + 1));
+                (0..16).map(|i| i * i).collect()
+            });
+        }
+
+        let check = || {
+            assert!(SQUARES.with(|squares| Lazy::get(squares).is_none()));
+            assert_eq!(SQUARES.with(|squares| squares[3]), 9);
+            assert_eq!(SQUARES.with(|squares| Lazy::force(squares).len()), 16);
+            assert_eq!(CALLS.with(Cell::get), 1);
+        };
+        check();
+        // each thread gets its own value
+        std::thread::spawn(check).join().expect("Failed to join");
+    }
+
+    #[test]
+    fn unsync_lazy_states() {
+        use super::unsync::Lazy;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let captured = DropCounter(Arc::clone(&drops));
+        let lazy = Lazy::new(move || captured);
+        assert_eq!(format!("{:?}", Lazy::<u32>::new(|| 42)), "Lazy(<uninit>)");
+        let f = Lazy::into_inner(lazy).expect_err("uninitialized Lazy returned a value");
+        assert_eq!(drops.load(Relaxed), 0);
+        drop(f);
+        assert_eq!(drops.load(Relaxed), 1);
+
+        let captured = DropCounter(Arc::clone(&drops));
+        let lazy = Lazy::new(move || captured);
+        Lazy::force(&lazy);
+        let value = Lazy::into_inner(lazy).ok().expect("initialized Lazy returned the function");
+        assert_eq!(drops.load(Relaxed), 1);
+        drop(value);
+        assert_eq!(drops.load(Relaxed), 2);
+
+        let lazy = Lazy::<u32>::new(|| panic!("poisoning on purpose"));
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *lazy)).expect_err("the closure didn't panic");
+        let message = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *lazy)).expect_err("poisoned Lazy didn't panic");
+        assert_eq!(message.downcast_ref::<&str>(), Some(&"unsync::Lazy instance has previously been poisoned"));
+        let message = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *lazy)).expect_err("poisoned Lazy didn't panic");
+        assert_eq!(message.downcast_ref::<&str>(), Some(&"unsync::Lazy instance has previously been poisoned"));
+        assert_eq!(Lazy::get(&lazy), None);
+
+        let lazy = Lazy::<String>::default();
+        assert_eq!(*lazy, "");
+    }
+
+    #[test]
+    fn unsync_lazy_reentrancy_panics() {
+        use super::unsync::Lazy;
+
+        thread_local! {
+            static REENTRANT: Lazy<u32> = Lazy::new(|| REENTRANT.with(|lazy| **lazy + 1));
+        }
+
+        let message = std::panic::catch_unwind(|| REENTRANT.with(|lazy| **lazy)).expect_err("reentrant forcing didn't panic");
+        assert_eq!(message.downcast_ref::<&str>(), Some(&"unsync::Lazy forced while being initialized"));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_once_cell_round_trip() {
+        use super::OnceCell;
+
+        let cell = OnceCell::from(vec![1, 2]);
+        let json = serde_json::to_string(&cell).expect("failed to serialize");
+        assert_eq!(json, "[1,2]");
+        assert_eq!(serde_json::from_str::<OnceCell<Vec<u32>>>(&json).expect("failed to deserialize"), cell);
+
+        let cell = OnceCell::<Vec<u32>>::new();
+        let json = serde_json::to_string(&cell).expect("failed to serialize");
+        assert_eq!(json, "null");
+        assert_eq!(serde_json::from_str::<OnceCell<Vec<u32>>>(&json).expect("failed to deserialize").get(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_once_lock_round_trip() {
+        use super::OnceLock;
+
+        let lock = OnceLock::from(String::from("verbose"));
+        let json = serde_json::to_string(&lock).expect("failed to serialize");
+        assert_eq!(json, "\"verbose\"");
+        assert_eq!(serde_json::from_str::<OnceLock<String>>(&json).expect("failed to deserialize"), lock);
+
+        let lock = OnceLock::<String>::new();
+        let json = serde_json::to_string(&lock).expect("failed to serialize");
+        assert_eq!(json, "null");
+        assert_eq!(serde_json::from_str::<OnceLock<String>>(&json).expect("failed to deserialize").get(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_lazy_lock_serialization_does_not_force() {
+        use super::LazyLock;
+
+        let lazy = LazyLock::new(|| -> u32 { panic!("serialization forced the LazyLock") });
+        assert_eq!(serde_json::to_string(&lazy).expect("failed to serialize"), "null");
+        assert_eq!(LazyLock::get(&lazy), None);
+
+        let lazy = LazyLock::new(|| 42);
+        LazyLock::force(&lazy);
+        assert_eq!(serde_json::to_string(&lazy).expect("failed to serialize"), "42");
+    }
+
+    #[test]
+    fn race_once_bool_contended() {
+        use super::race::OnceBool;
+        use std::sync::Barrier;
+
+        for _ in 0..20 {
+            let flag = Arc::new(OnceBool::new());
+            let barrier = Arc::new(Barrier::new(16));
+            let threads = (0..16)
+                .map(|i| {
+                    let flag = Arc::clone(&flag);
+                    let barrier = Arc::clone(&barrier);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        // threads disagree so that the winner matters
+                        (0..1000).map(|_| flag.get_or_init(|| i % 2 == 0)).collect::<Vec<_>>()
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            let observed = threads.into_iter().flat_map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+            let value = flag.get().expect("flag not initialized");
+            assert!(observed.iter().all(|observed| *observed == value));
+        }
+    }
+
+    #[test]
+    fn race_once_bool() {
+        use super::race::OnceBool;
+
+        let flag = OnceBool::default();
+        assert_eq!(flag.get(), None);
+        assert_eq!(format!("{:?}", flag), "OnceBool(None)");
+        assert!(!flag.get_or_init(|| false));
+        assert!(!flag.get_or_init(|| true));
+        assert_eq!(flag.set(true), Err(false));
+        assert_eq!(flag.get(), Some(false));
+
+        let flag = OnceBool::new();
+        assert_eq!(flag.set(true), Ok(()));
+        assert!(flag.get_or_init(|| false));
+        assert_eq!(format!("{:?}", flag), "OnceBool(Some(true))");
+    }
+
+    #[test]
+    fn race_once_non_zero_u32_contended() {
+        use super::race::OnceNonZeroU32;
+        use core::num::NonZeroU32;
+        use std::sync::Barrier;
+
+        assert_eq!(core::mem::size_of::<OnceNonZeroU32>(), 4);
+        for _ in 0..20 {
+            let cell = Arc::new(OnceNonZeroU32::new());
+            let barrier = Arc::new(Barrier::new(16));
+            let threads = (1..=16)
+                .map(|i| {
+                    let cell = Arc::clone(&cell);
+                    let barrier = Arc::clone(&barrier);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        (0..1000).map(|_| cell.get_or_init(|| NonZeroU32::new(i).expect("zero"))).collect::<Vec<_>>()
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            let observed = threads.into_iter().flat_map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+            let value = cell.get().expect("cell not initialized");
+            assert!(observed.iter().all(|observed| *observed == value));
+            assert_eq!(cell.set(NonZeroU32::new(100).expect("zero")), Err(value));
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn race_once_non_zero_u32_wait() {
+        use super::race::OnceNonZeroU32;
+        use core::num::NonZeroU32;
+
+        let cell = Arc::new(OnceNonZeroU32::default());
+        assert_eq!(format!("{:?}", cell), "OnceNonZeroU32(None)");
+        let waiters = (0..4)
+            .map(|_| {
+                let cell = Arc::clone(&cell);
+                std::thread::spawn(move || cell.wait())
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        // give the waiters time to block
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let value = NonZeroU32::new(42).expect("zero");
+        assert_eq!(cell.set(value), Ok(()));
+        for waiter in waiters {
+            assert_eq!(waiter.join().expect("Failed to join"), value);
+        }
+        assert_eq!(cell.wait(), value);
+        assert_eq!(format!("{:?}", cell), "OnceNonZeroU32(Some(42))");
+    }
+
+    #[test]
+    fn race_once_non_zero_usize() {
+        use super::race::OnceNonZeroUsize;
+        use core::num::NonZeroUsize;
+
+        let cell = OnceNonZeroUsize::default();
+        assert_eq!(cell.get(), None);
+        let value = NonZeroUsize::new(usize::MAX).expect("zero");
+        assert_eq!(cell.get_or_init(|| value), value);
+        assert_eq!(cell.get_or_init(|| NonZeroUsize::new(1).expect("zero")), value);
+        assert_eq!(cell.set(NonZeroUsize::new(1).expect("zero")), Err(value));
+        assert_eq!(format!("{:?}", cell), format!("OnceNonZeroUsize(Some({}))", usize::MAX));
+    }
+
+    #[test]
+    fn race_once_ptr_boxes_dont_leak() {
+        use super::race::OncePtr;
+        use core::ptr::NonNull;
+        use std::sync::Barrier;
+
+        struct Table {
+            entries: [u32; 16],
+            frees: Arc<AtomicUsize>,
+        }
+
+        impl Drop for Table {
+            fn drop(&mut self) {
+                self.frees.fetch_add(1, Relaxed);
+            }
+        }
+
+        let allocations = Arc::new(AtomicUsize::new(0));
+        let frees = Arc::new(AtomicUsize::new(0));
+        for _ in 0..20 {
+            let table = Arc::new(OncePtr::<Table>::new());
+            let barrier = Arc::new(Barrier::new(8));
+            let threads = (0..8u32)
+                .map(|i| {
+                    let table = Arc::clone(&table);
+                    let barrier = Arc::clone(&barrier);
+                    let allocations = Arc::clone(&allocations);
+                    let frees = Arc::clone(&frees);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        let published = table.get().unwrap_or_else(|| {
+                            allocations.fetch_add(1, Relaxed);
+                            let ours = NonNull::from(Box::leak(Box::new(Table { entries: [i; 16], frees })));
+                            table.set(ours).map(|()| ours).unwrap_or_else(|winner| {
+                                // SAFETY: the pointer came from Box::leak and was never published
+                                drop(unsafe { Box::from_raw(ours.as_ptr()) });
+                                winner
+                            })
+                        });
+                        // SAFETY: the published table is freed only after all threads are joined
+                        // and all writes to it happened before publishing it
+                        let entries = unsafe { published.as_ref() }.entries;
+                        assert!(entries.iter().all(|entry| *entry == entries[0]));
+                        entries[0]
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            let observed = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+            let published = table.get().expect("nothing published");
+            // SAFETY: the threads were joined
+            assert!(observed.iter().all(|observed| *observed == unsafe { published.as_ref() }.entries[0]));
+            assert_eq!(allocations.load(Relaxed), frees.load(Relaxed) + 1);
+            // SAFETY: the pointer came from Box::leak and nobody uses it anymore
+            drop(unsafe { Box::from_raw(published.as_ptr()) });
+            assert_eq!(allocations.load(Relaxed), frees.load(Relaxed));
+        }
+    }
+
+    #[test]
+    fn race_once_ref_contended() {
+        use super::race::OnceRef;
+        use std::sync::Barrier;
+
+        static VALUES: [u32; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+
+        for _ in 0..20 {
+            let cell = Arc::new(OnceRef::<'static, u32>::default());
+            let barrier = Arc::new(Barrier::new(8));
+            let threads = (0..8)
+                .map(|i| {
+                    let cell = Arc::clone(&cell);
+                    let barrier = Arc::clone(&barrier);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        cell.get_or_init(|| &VALUES[i])
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            let observed = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+            let stored = cell.get().expect("cell not initialized");
+            assert!(observed.iter().all(|observed| core::ptr::eq(*observed, stored)));
+            assert_eq!(cell.set(&VALUES[0]), Err(()));
+            assert_eq!(format!("{:?}", cell), format!("OnceRef(Some({}))", stored));
+        }
+
+        // works with non-static references too
+        let local = 42;
+        let cell = OnceRef::new();
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.set(&local), Ok(()));
+        assert_eq!(cell.get(), Some(&42));
+    }
+
+    #[test]
+    fn race_once_box_contended() {
+        use super::race::OnceBox;
+        use std::sync::Barrier;
+
+        let allocations = Arc::new(AtomicUsize::new(0));
+        let drops = Arc::new(AtomicUsize::new(0));
+        for _ in 0..20 {
+            let cell = Arc::new(OnceBox::new());
+            let barrier = Arc::new(Barrier::new(8));
+            let threads = (0..8)
+                .map(|_| {
+                    let cell = Arc::clone(&cell);
+                    let barrier = Arc::clone(&barrier);
+                    let allocations = Arc::clone(&allocations);
+                    let drops = Arc::clone(&drops);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        let value = cell.get_or_init(|| {
+                            allocations.fetch_add(1, Relaxed);
+                            Box::new(DropCounter(drops))
+                        });
+                        value as *const DropCounter as usize
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            let observed = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+            let stored = cell.get().expect("cell not initialized") as *const DropCounter as usize;
+            assert!(observed.iter().all(|observed| *observed == stored));
+            // only the winner is retained
+            assert_eq!(drops.load(Relaxed) + 1, allocations.load(Relaxed));
+            let rejected = cell.set(Box::new(DropCounter(Arc::clone(&drops)))).expect_err("set twice");
+            drop(rejected);
+            allocations.fetch_add(1, Relaxed);
+            assert_eq!(drops.load(Relaxed) + 1, allocations.load(Relaxed));
+            drop(Arc::try_unwrap(cell).expect("cell still shared"));
+            assert_eq!(drops.load(Relaxed), allocations.load(Relaxed));
+        }
+        assert_eq!(format!("{:?}", OnceBox::<u32>::default()), "OnceBox(None)");
+    }
+
+    #[test]
+    fn race_once_ptr_custom_allocator_balanced() {
+        use super::race::{OnceBoxIn, OncePtr};
+        use core::ptr::NonNull;
+        use std::alloc::Layout;
+        use std::sync::Barrier;
+
+        /// Allocator handed around explicitly, counting its operations
+        #[derive(Default)]
+        struct CountingAllocator {
+            allocations: AtomicUsize,
+            deallocations: AtomicUsize,
+        }
+
+        impl CountingAllocator {
+            fn alloc(&self, value: u64) -> NonNull<u64> {
+                self.allocations.fetch_add(1, Relaxed);
+                // SAFETY: the layout is not zero-sized
+                let ptr = NonNull::new(unsafe { std::alloc::alloc(Layout::new::<u64>()) }.cast::<u64>()).expect("out of memory");
+                // SAFETY: freshly allocated with the right layout
+                unsafe { ptr.as_ptr().write(value) };
+                ptr
+            }
+
+            /// # Safety
+            ///
+            /// The pointer must come from alloc and must not be used anymore.
+            unsafe fn dealloc(&self, ptr: NonNull<u64>) {
+                self.deallocations.fetch_add(1, Relaxed);
+                std::alloc::dealloc(ptr.as_ptr().cast(), Layout::new::<u64>());
+            }
+        }
+
+        let allocator = Arc::new(CountingAllocator::default());
+        for _ in 0..20 {
+            let cell = Arc::new(OncePtr::new());
+            let barrier = Arc::new(Barrier::new(8));
+            let threads = (0..8)
+                .map(|i| {
+                    let cell = Arc::clone(&cell);
+                    let barrier = Arc::clone(&barrier);
+                    let allocator = Arc::clone(&allocator);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        // SAFETY: the losing pointers are never published or used
+                        let ptr = cell.get_or_init_with_dealloc(|| allocator.alloc(i), |ptr| unsafe { allocator.dealloc(ptr) });
+                        // SAFETY: the published pointer is freed only after the threads are joined
+                        unsafe { *ptr.as_ref() }
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            let observed = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+            let published = cell.get().expect("nothing published");
+            // SAFETY: the threads were joined
+            assert!(observed.iter().all(|observed| *observed == unsafe { *published.as_ref() }));
+            assert_eq!(allocator.allocations.load(Relaxed), allocator.deallocations.load(Relaxed) + 1);
+            // SAFETY: nobody uses the pointer anymore
+            unsafe { allocator.dealloc(published) };
+            assert_eq!(allocator.allocations.load(Relaxed), allocator.deallocations.load(Relaxed));
+        }
+
+        // the owning variant frees the published pointer itself
+        for _ in 0..20 {
+            let dealloc_allocator = Arc::clone(&allocator);
+            // SAFETY: the cell passes only pointers allocated below
+            let cell = Arc::new(OnceBoxIn::new(move |ptr| unsafe { dealloc_allocator.dealloc(ptr) }));
+            let barrier = Arc::new(Barrier::new(8));
+            let threads = (0..8)
+                .map(|i| {
+                    let cell = Arc::clone(&cell);
+                    let barrier = Arc::clone(&barrier);
+                    let allocator = Arc::clone(&allocator);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        // SAFETY: the pointer is initialized and owned by the cell
+                        *unsafe { cell.get_or_init_in(|| allocator.alloc(i)) }
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            let observed = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+            let published = *cell.get().expect("nothing published");
+            assert!(observed.iter().all(|observed| *observed == published));
+            assert_eq!(allocator.allocations.load(Relaxed), allocator.deallocations.load(Relaxed) + 1);
+            drop(Arc::try_unwrap(cell).expect("cell still shared"));
+            assert_eq!(allocator.allocations.load(Relaxed), allocator.deallocations.load(Relaxed));
+        }
+    }
+
+    #[test]
+    fn once_cell_wait_for_set() {
+        use super::OnceCell;
+
+        let cell = Arc::new(OnceCell::new());
+        let consumers = (0..4)
+            .map(|_| {
+                let cell = Arc::clone(&cell);
+                std::thread::spawn(move || *cell.wait())
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        let cloned = Arc::clone(&cell);
+        let producer = std::thread::spawn(move || {
+            // give the consumers time to block
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            cloned.set(42)
+        });
+        assert_eq!(producer.join().expect("Failed to join"), Ok(()));
+        for consumer in consumers {
+            assert_eq!(consumer.join().expect("Failed to join"), 42);
+        }
+        assert_eq!(cell.set(0), Err(0));
+        assert_eq!(cell.try_insert(1), Err((&42, 1)));
+        assert_eq!(*cell.get_or_init(|| panic!("the cell should've been initialized")), 42);
+        assert_eq!(*cell.wait(), 42);
+
+        let cell = OnceCell::<i32>::new();
+        std::panic::catch_unwind(|| cell.get_or_init(|| panic!("poisoning on purpose"))).expect_err("the closure didn't panic");
+        std::panic::catch_unwind(|| *cell.wait()).expect_err("wait didn't panic");
+    }
+
+    #[test]
+    fn once_cell_set_races_with_get_or_init() {
+        use super::OnceCell;
+        use std::sync::Barrier;
+
+        let cell = Arc::new(OnceCell::new());
+        let drops = Arc::new(AtomicUsize::new(0));
+        let created = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8)
+            .map(|i| {
+                let cell = Arc::clone(&cell);
+                let drops = Arc::clone(&drops);
+                let created = Arc::clone(&created);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let won = if i % 2 == 0 {
+                        created.fetch_add(1, Relaxed);
+                        match cell.try_insert(DropCounter(drops)) {
+                            Ok(value) => {
+                                assert!(core::ptr::eq(value, cell.get().expect("cell not initialized")));
+                                true
+                            },
+                            Err((current, rejected)) => {
+                                assert!(!core::ptr::eq(current, &rejected));
+                                false
+                            },
+                        }
+                    } else {
+                        let mut won = false;
+                        cell.get_or_init(|| {
+                            won = true;
+                            created.fetch_add(1, Relaxed);
+                            DropCounter(drops)
+                        });
+                        won
+                    };
+                    won as usize
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        let winners = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).sum::<usize>();
+        assert_eq!(winners, 1);
+        // all rejected values were dropped by their callers
+        assert_eq!(drops.load(Relaxed), created.load(Relaxed) - 1);
+        drop(Arc::try_unwrap(cell).expect("cell still shared"));
+        assert_eq!(drops.load(Relaxed), created.load(Relaxed));
+    }
+
+    #[test]
+    fn once_cell_get_or_init_racy() {
+        use super::OnceCell;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let cell = OnceCell::new();
+        let first = cell.get_or_init_racy(|| DropCounter(Arc::clone(&drops)));
+        assert!(core::ptr::eq(first, cell.get().expect("cell not initialized")));
+        cell.get_or_init_racy(|| panic!("the initialization should've completed"));
+        assert_eq!(drops.load(Relaxed), 0);
+
+        // a panicking closure leaves the cell usable
+        let cell = OnceCell::<u32>::new();
+        std::panic::catch_unwind(|| cell.get_or_init_racy(|| panic!("panicking on purpose"))).expect_err("the closure didn't panic");
+        assert_eq!(cell.get(), None);
+        assert_eq!(*cell.get_or_init(|| 42), 42);
+
+        // the value computed while another one was published is dropped
+        let cell = OnceCell::new();
+        let value = cell.get_or_init_racy(|| {
+            cell.set(DropCounter(Arc::clone(&drops))).expect("cell already initialized");
+            DropCounter(Arc::clone(&drops))
+        });
+        assert!(core::ptr::eq(value, cell.get().expect("cell not initialized")));
+        assert_eq!(drops.load(Relaxed), 1);
+        drop(cell);
+        assert_eq!(drops.load(Relaxed), 2);
+    }
+
+    #[test]
+    fn once_cell_get_or_init_racy_mixed_with_blocking() {
+        use super::OnceCell;
+        use std::sync::Barrier;
+
+        for _ in 0..10 {
+            let cell = Arc::new(OnceCell::new());
+            let drops = Arc::new(AtomicUsize::new(0));
+            let created = Arc::new(AtomicUsize::new(0));
+            let blocking_inits = Arc::new(AtomicUsize::new(0));
+            let barrier = Arc::new(Barrier::new(8));
+            let threads = (0..8)
+                .map(|i| {
+                    let cell = Arc::clone(&cell);
+                    let drops = Arc::clone(&drops);
+                    let created = Arc::clone(&created);
+                    let blocking_inits = Arc::clone(&blocking_inits);
+                    let barrier = Arc::clone(&barrier);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        let value = if i % 2 == 0 {
+                            cell.get_or_init_racy(|| {
+                                created.fetch_add(1, Relaxed);
+                                DropCounter(drops)
+                            })
+                        } else {
+                            cell.get_or_init(|| {
+                                created.fetch_add(1, Relaxed);
+                                blocking_inits.fetch_add(1, Relaxed);
+                                // the racy initializers finish meanwhile
+                                std::thread::sleep(std::time::Duration::from_millis(1));
+                                DropCounter(drops)
+                            })
+                        };
+                        value as *const DropCounter as usize
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            let addresses = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+            assert!(addresses.iter().all(|address| *address == addresses[0]));
+            assert!(blocking_inits.load(Relaxed) <= 1);
+            // all values except the published one were dropped
+            assert_eq!(drops.load(Relaxed), created.load(Relaxed) - 1);
+            drop(Arc::try_unwrap(cell).expect("cell still shared"));
+            assert_eq!(drops.load(Relaxed), created.load(Relaxed));
+        }
+    }
+
+    #[test]
+    fn once_map_initializes_each_key_once() {
+        use super::OnceMap;
+        use std::sync::Barrier;
+
+        const KEYS: usize = 64;
+
+        let map = Arc::new(OnceMap::new());
+        let inits = Arc::new((0..KEYS).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>());
+        let barrier = Arc::new(Barrier::new(16));
+        let threads = (0..16)
+            .map(|i| {
+                let map = Arc::clone(&map);
+                let inits = Arc::clone(&inits);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    // every thread walks the keys from a different starting point
+                    (0..KEYS)
+                        .map(|key| (key + i * 4) % KEYS)
+                        .map(|key| {
+                            let value = map.get_or_init(key, |key| {
+                                inits[*key].fetch_add(1, Relaxed);
+                                *key * 2
+                            });
+                            assert_eq!(*value, key * 2);
+                            (key, value as *const usize as usize)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        let mut addresses = vec![None; KEYS];
+        for thread in threads {
+            for (key, address) in thread.join().expect("Failed to join") {
+                // the values never move
+                assert_eq!(*addresses[key].get_or_insert(address), address);
+            }
+        }
+        assert!(inits.iter().all(|inits| inits.load(Relaxed) == 1));
+        for (key, address) in addresses.into_iter().enumerate() {
+            assert!(map.contains_key(&key));
+            assert_eq!(map.get(&key).map(|value| value as *const usize as usize), address);
+        }
+        assert!(!map.contains_key(&KEYS));
+        assert_eq!(map.get(&KEYS), None);
+    }
+
+    #[test]
+    fn once_map_different_keys_in_parallel() {
+        use super::OnceMap;
+        use std::sync::mpsc;
+
+        let map = Arc::new(OnceMap::new());
+        let (started, started_rx) = mpsc::channel();
+        let (finish, finish_rx) = mpsc::channel::<()>();
+        let cloned = Arc::clone(&map);
+        let slow = std::thread::spawn(move || {
+            *cloned.get_or_init("slow", |_| {
+                started.send(()).expect("channel closed");
+                finish_rx.recv().expect("channel closed");
+                1
+            })
+        });
+        started_rx.recv().expect("channel closed");
+        // the slow initialization doesn't block the other keys
+        assert_eq!(*map.get_or_init("fast", |_| 2), 2);
+        assert!(!map.contains_key(&"slow"));
+        finish.send(()).expect("channel closed");
+        assert_eq!(slow.join().expect("Failed to join"), 1);
+        assert_eq!(map.get(&"slow"), Some(&1));
+    }
+
+    #[test]
+    fn once_map_poisoning_and_drop() {
+        use super::OnceMap;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let map = OnceMap::new();
+        std::panic::catch_unwind(|| {
+            map.get_or_init(0, |_| -> DropCounter { panic!("poisoning on purpose") });
+        }).expect_err("the closure didn't panic");
+        std::panic::catch_unwind(|| {
+            map.get_or_init(0, |_| DropCounter(Arc::clone(&drops)));
+        }).expect_err("poisoned entry didn't panic");
+        assert!(!map.contains_key(&0));
+        for key in 1..4 {
+            map.get_or_init(key, |_| DropCounter(Arc::clone(&drops)));
+        }
+        assert_eq!(format!("{:?}", OnceMap::<u8, u8>::new()), "{}");
+        assert_eq!(drops.load(Relaxed), 0);
+        drop(map);
+        assert_eq!(drops.load(Relaxed), 3);
+    }
+
+    #[test]
+    fn type_once_distinct_types_concurrently() {
+        use super::TypeOnce;
+        use std::sync::Barrier;
+
+        struct First;
+        struct Second;
+        struct Third;
+
+        static INITS: [AtomicUsize; 4] = [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+
+        fn init<T: 'static>(index: usize) -> &'static usize {
+            TypeOnce::get_or_init::<T, usize>(|| {
+                INITS[index].fetch_add(1, Relaxed);
+                // give the other threads time to block
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                index
+            })
+        }
+
+        assert!(!TypeOnce::is_completed::<First>());
+        assert_eq!(TypeOnce::get::<First, usize>(), None);
+        let barrier = Arc::new(Barrier::new(12));
+        let threads = (0..12)
+            .map(|i| {
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    match i % 4 {
+                        0 => assert_eq!(*init::<First>(0), 0),
+                        1 => assert_eq!(*init::<Second>(1), 1),
+                        2 => assert_eq!(*init::<Third>(2), 2),
+                        _ => TypeOnce::call_once::<First>(|| { INITS[3].fetch_add(1, Relaxed); }),
+                    }
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert!(INITS.iter().all(|inits| inits.load(Relaxed) == 1));
+        // the value type is part of the key
+        assert_eq!(TypeOnce::get::<First, usize>(), Some(&0));
+        assert_eq!(TypeOnce::get::<First, u32>(), None);
+        assert!(TypeOnce::is_completed::<First>());
+        assert!(!TypeOnce::is_completed::<Second>());
+        TypeOnce::call_once::<First>(|| panic!("the initialization should've completed"));
+    }
+
+    #[test]
+    fn type_once_recursion_panics() {
+        use super::TypeOnce;
+
+        struct Recursive;
+        struct Other;
+
+        let message = std::panic::catch_unwind(|| TypeOnce::call_once::<Recursive>(|| TypeOnce::call_once::<Recursive>(|| ())))
+            .expect_err("recursive call didn't panic");
+        let message = message.downcast_ref::<String>().expect("unexpected panic payload");
+        assert!(message.contains("recursively"), "unexpected message: {}", message);
+        std::panic::catch_unwind(|| TypeOnce::call_once::<Recursive>(|| ())).expect_err("poisoned type didn't panic");
+
+        // nesting different types is fine
+        let value = TypeOnce::get_or_init::<Other, u32>(|| u32::from(*TypeOnce::get_or_init::<Other, u8>(|| 21)) * 2);
+        assert_eq!(*value, 42);
+    }
+
+    #[test]
+    fn thread_local_once_runs_once_per_thread() {
+        use super::ThreadLocalOnce;
+
+        static ONCE: ThreadLocalOnce = ThreadLocalOnce::new();
+        static OTHER: ThreadLocalOnce = ThreadLocalOnce::new();
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+        fn run() -> usize {
+            let mut ran = 0;
+            for _ in 0..3 {
+                ONCE.call_once_per_thread(|| {
+                    ran += 1;
+                    RUNS.fetch_add(1, Relaxed);
+                    // re-entry doesn't run again
+                    ONCE.call_once_per_thread(|| panic!("ran on re-entry"));
+                    assert!(ONCE.has_run_on_this_thread());
+                });
+            }
+            assert!(ONCE.has_run_on_this_thread());
+            assert!(!OTHER.has_run_on_this_thread());
+            ran
+        }
+
+        let threads = (0..8)
+            .map(|_| std::thread::spawn(run))
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            assert_eq!(thread.join().expect("Failed to join"), 1);
+        }
+        assert_eq!(RUNS.load(Relaxed), 8);
+    }
+
+    #[test]
+    fn thread_local_once_panic_allows_retry() {
+        use super::ThreadLocalOnce;
+
+        static ONCE: ThreadLocalOnce = ThreadLocalOnce::new();
+
+        std::panic::catch_unwind(|| ONCE.call_once_per_thread(|| panic!("panicking on purpose"))).expect_err("the closure didn't panic");
+        assert!(!ONCE.has_run_on_this_thread());
+        let mut ran = false;
+        ONCE.call_once_per_thread(|| ran = true);
+        assert!(ran);
+        assert!(ONCE.has_run_on_this_thread());
+    }
+
+    #[test]
+    fn once_cell_contended() {
+        use super::OnceCell;
+        use std::sync::Barrier;
+
+        let cell = Arc::new(OnceCell::new());
+        let drops = Arc::new(AtomicUsize::new(0));
+        let inits = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(16));
+        let threads = (0..16)
+            .map(|_| {
+                let cell = Arc::clone(&cell);
+                let drops = Arc::clone(&drops);
+                let inits = Arc::clone(&inits);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let value = cell.get_or_init(|| {
+                        inits.fetch_add(1, Relaxed);
+                        // give the other threads time to block
+                        std::thread::sleep(std::time::Duration::from_millis(20));
+                        DropCounter(drops)
+                    });
+                    value as *const DropCounter as usize
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        let addresses = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+        assert!(addresses.iter().all(|address| *address == addresses[0]));
+        assert_eq!(inits.load(Relaxed), 1);
+        assert_eq!(drops.load(Relaxed), 0);
+        drop(Arc::try_unwrap(cell).expect("cell still shared"));
+        assert_eq!(drops.load(Relaxed), 1);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn fair_once_releases_in_arrival_order() {
+        use super::FairOnce;
+        use super::fair::test_hook;
+        use std::sync::mpsc;
+
+        let once = Arc::new(FairOnce::new());
+        let (finish, finish_rx) = mpsc::channel::<()>();
+        let cloned = Arc::clone(&once);
+        let initializer = std::thread::spawn(move || cloned.call_once(|| {
+            finish_rx.recv().expect("test dropped the sender");
+        }));
+        while once.state() != OnceStatus::Running {
+            std::thread::yield_now();
+        }
+
+        let waiters = (0..16)
+            .map(|i| {
+                let cloned = Arc::clone(&once);
+                let waiter = std::thread::spawn(move || {
+                    test_hook::set_waiter_id(i);
+                    cloned.call_once(|| panic!("the initialization should've completed"));
+                });
+                // the next waiter arrives only after this one queued
+                let start = std::time::Instant::now();
+                while once.waiters() <= i {
+                    assert!(start.elapsed() < std::time::Duration::from_secs(10), "waiter didn't block");
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+                waiter
+            })
+            .collect::<Vec<_>>();
+        finish.send(()).expect("initializer exited");
+        initializer.join().expect("Failed to join");
+        for waiter in waiters {
+            waiter.join().expect("Failed to join");
+        }
+        // the threads may return in a slightly different order if the scheduler preempts them
+        let released = test_hook::released().into_iter().filter(|id| *id < 16).collect::<Vec<_>>();
+        assert_eq!(released, (0..16).collect::<Vec<_>>());
+        assert_eq!(once.waiters(), 0);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn fair_once_forced_after_poisoning_keeps_order() {
+        use super::FairOnce;
+        use super::fair::test_hook;
+        use std::sync::mpsc;
+
+        // distinct from the ids in fair_once_releases_in_arrival_order
+        const IDS: core::ops::Range<usize> = 100..108;
+
+        let once = Arc::new(FairOnce::new());
+        let (finish, finish_rx) = mpsc::channel::<()>();
+        let cloned = Arc::clone(&once);
+        let initializer = std::thread::spawn(move || cloned.call_once(|| {
+            finish_rx.recv().expect("test dropped the sender");
+            panic!("poisoning on purpose");
+        }));
+        while once.state() != OnceStatus::Running {
+            std::thread::yield_now();
+        }
+
+        let waiters = IDS
+            .map(|id| {
+                let cloned = Arc::clone(&once);
+                let waiter = std::thread::spawn(move || {
+                    test_hook::set_waiter_id(id);
+                    let mut forced = None;
+                    cloned.call_once_force(|_| {
+                        // gives the other released waiters time to queue again
+                        std::thread::sleep(std::time::Duration::from_millis(50));
+                        forced = Some(id);
+                    });
+                    forced
+                });
+                // the next waiter arrives only after this one queued
+                let start = std::time::Instant::now();
+                while once.waiters() <= id - IDS.start {
+                    assert!(start.elapsed() < std::time::Duration::from_secs(10), "waiter didn't block");
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+                waiter
+            })
+            .collect::<Vec<_>>();
+        finish.send(()).expect("initializer exited");
+        initializer.join().expect_err("the closure didn't panic");
+        let forced = waiters.into_iter().filter_map(|waiter| waiter.join().expect("Failed to join")).collect::<Vec<_>>();
+        assert_eq!(forced.len(), 1);
+
+        // the waiters released before the forced initialization started may be released in any
+        // order but after it finishes they must get released in the arrival order
+        let released = test_hook::released().into_iter().filter(|id| IDS.contains(id)).collect::<Vec<_>>();
+        let expected = IDS.filter(|id| *id != forced[0]).collect::<Vec<_>>();
+        assert_eq!(released[(released.len() - expected.len())..], expected[..]);
+        assert!(once.is_completed());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn fair_once_poisoning() {
+        use super::FairOnce;
+
+        let once = Arc::new(FairOnce::new());
+        let cloned = Arc::clone(&once);
+        std::thread::spawn(move || cloned.call_once(|| panic!("poisoning on purpose")))
+            .join()
+            .expect_err("the closure didn't panic");
+        assert!(once.is_poisoned());
+        std::panic::catch_unwind(|| once.call_once(|| ())).expect_err("call_once didn't panic");
+        let mut was_poisoned = false;
+        once.call_once_force(|state| was_poisoned = state.is_poisoned());
+        assert!(was_poisoned);
+        assert_eq!(format!("{:?}", once), "FairOnce { state: Complete }");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn event_set_before_wait() {
+        use super::Event;
+
+        let event = Event::new();
+        assert!(!event.is_set());
+        assert_eq!(format!("{:?}", event), "Event { is_set: false }");
+        event.set();
+        event.set();
+        assert!(event.is_set());
+        event.wait();
+        assert_eq!(format!("{:?}", event), "Event { is_set: true }");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn event_set_while_many_wait() {
+        use super::Event;
+
+        let event = Arc::new(Event::new());
+        let written = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(std::sync::Barrier::new(9));
+        let waiters = (0..8)
+            .map(|_| {
+                let event = Arc::clone(&event);
+                let written = Arc::clone(&written);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    event.wait();
+                    assert_eq!(written.load(Relaxed), 42);
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        barrier.wait();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        written.store(42, Relaxed);
+        event.set();
+        for waiter in waiters {
+            waiter.join().expect("Failed to join");
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn event_never_loses_waiters() {
+        use super::Event;
+
+        for _ in 0..200 {
+            let event = Arc::new(Event::new());
+            let waiters = (0..4)
+                .map(|_| {
+                    let event = Arc::clone(&event);
+                    std::thread::spawn(move || event.wait())
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            event.set();
+            // a lost waiter would block forever
+            for waiter in waiters {
+                waiter.join().expect("Failed to join");
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn auto_reset_event_latches_one_signal() {
+        use super::AutoResetEvent;
+
+        let event = AutoResetEvent::new();
+        assert!(!event.is_set());
+        event.set();
+        event.set();
+        assert!(event.is_set());
+        assert_eq!(format!("{:?}", event), "AutoResetEvent { is_set: true }");
+        // the signals didn't accumulate
+        event.wait();
+        assert!(!event.is_set());
+        assert_eq!(format!("{:?}", event), "AutoResetEvent { is_set: false }");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn auto_reset_event_releases_one_waiter_per_set() {
+        use super::AutoResetEvent;
+
+        let event = Arc::new(AutoResetEvent::new());
+        let released = Arc::new(AtomicUsize::new(0));
+        let waiters = (0..8)
+            .map(|_| {
+                let event = Arc::clone(&event);
+                let released = Arc::clone(&released);
+                std::thread::spawn(move || {
+                    event.wait();
+                    released.fetch_add(1, Relaxed);
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for i in 1..=8 {
+            // some waiters may not have blocked yet, then the signal is latched for them
+            event.set();
+            let start = std::time::Instant::now();
+            while released.load(Relaxed) < i {
+                assert!(start.elapsed() < std::time::Duration::from_secs(10), "signal lost");
+                std::thread::yield_now();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            assert_eq!(released.load(Relaxed), i, "one signal released more waiters");
+            assert!(!event.is_set());
+        }
+        for waiter in waiters {
+            waiter.join().expect("Failed to join");
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn auto_reset_event_racing_set_and_wait() {
+        use super::AutoResetEvent;
+
+        for _ in 0..100 {
+            let event = Arc::new(AutoResetEvent::new());
+            let released = Arc::new(AtomicUsize::new(0));
+            let sets = Arc::new(AtomicUsize::new(0));
+            let waiters = (0..4)
+                .map(|_| {
+                    let event = Arc::clone(&event);
+                    let released = Arc::clone(&released);
+                    std::thread::spawn(move || {
+                        event.wait();
+                        released.fetch_add(1, Relaxed);
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            // Setting only an unset event makes every call latch a new signal
+            let setter = {
+                let event = Arc::clone(&event);
+                let released = Arc::clone(&released);
+                let sets = Arc::clone(&sets);
+                std::thread::spawn(move || while released.load(Relaxed) < 4 {
+                    if !event.is_set() {
+                        event.set();
+                        sets.fetch_add(1, Relaxed);
+                    }
+                    std::thread::yield_now();
+                })
+            };
+            // a lost signal would block a waiter forever
+            for waiter in waiters {
+                waiter.join().expect("Failed to join");
+            }
+            setter.join().expect("Failed to join");
+            // each signal released one waiter, except the one that may still be latched
+            assert_eq!(released.load(Relaxed), 4);
+            assert_eq!(sets.load(Relaxed), 4 + usize::from(event.is_set()));
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn latch_counts_down() {
+        use super::Latch;
+
+        let latch = Latch::new(2);
+        assert!(!latch.try_wait());
+        latch.count_down();
+        assert_eq!(format!("{:?}", latch), "Latch { count: 1 }");
+        latch.count_down();
+        assert!(latch.try_wait());
+        latch.wait();
+        std::panic::catch_unwind(|| latch.count_down()).expect_err("counting down below zero didn't panic");
+        assert!(latch.try_wait());
+        assert_eq!(latch.count(), 0);
+        Latch::new(0).wait();
+        std::panic::catch_unwind(|| Latch::new(u32::MAX)).expect_err("too large count didn't panic");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn latch_releases_all_waiters() {
+        use super::Latch;
+
+        for _ in 0..100 {
+            let latch = Arc::new(Latch::new(4));
+            let counted = Arc::new(AtomicUsize::new(0));
+            let barrier = Arc::new(std::sync::Barrier::new(7));
+            let waiters = (0..3)
+                .map(|_| {
+                    let latch = Arc::clone(&latch);
+                    let counted = Arc::clone(&counted);
+                    let barrier = Arc::clone(&barrier);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        latch.wait();
+                        assert_eq!(counted.load(Relaxed), 4);
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            let counters = (0..4)
+                .map(|_| {
+                    let latch = Arc::clone(&latch);
+                    let counted = Arc::clone(&counted);
+                    let barrier = Arc::clone(&barrier);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        counted.fetch_add(1, Relaxed);
+                        latch.count_down();
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            // a lost wakeup would block a waiter forever
+            for thread in waiters.into_iter().chain(counters) {
+                thread.join().expect("Failed to join");
+            }
+            assert!(latch.try_wait());
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wait_group_counts() {
+        use super::WaitGroup;
+
+        let group = WaitGroup::new();
+        group.wait();
+        group.add(2);
+        group.add(0);
+        group.done();
+        assert_eq!(format!("{:?}", group), "WaitGroup { count: 1 }");
+        group.done();
+        group.wait();
+        std::panic::catch_unwind(|| group.done()).expect_err("done() without a task didn't panic");
+        std::panic::catch_unwind(|| group.add(1 << 24)).expect_err("too large count didn't panic");
+        assert_eq!(group.count(), 0);
+        // wrapping the generation around doesn't break anything
+        for _ in 0..300 {
+            group.add(1);
+            group.done();
+        }
+        group.wait();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wait_group_releases_finished_generation() {
+        use super::WaitGroup;
+
+        let group = Arc::new(WaitGroup::new());
+        group.add(1);
+        let cloned = Arc::clone(&group);
+        let waiter = std::thread::spawn(move || cloned.wait());
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        // the next generation starts before the waiter could run
+        group.done();
+        group.add(1);
+        waiter.join().expect("Failed to join");
+
+        // waiters of the new generation wait for its tasks
+        let finished = Arc::new(AtomicUsize::new(0));
+        let cloned = Arc::clone(&group);
+        let cloned_finished = Arc::clone(&finished);
+        let waiter = std::thread::spawn(move || {
+            cloned.wait();
+            assert_eq!(cloned_finished.load(Relaxed), 1);
+        });
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        finished.store(1, Relaxed);
+        group.done();
+        waiter.join().expect("Failed to join");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wait_group_done_racing_add() {
+        use super::WaitGroup;
+
+        let group = Arc::new(WaitGroup::new());
+        for _ in 0..200 {
+            group.add(2);
+            let barrier = Arc::new(std::sync::Barrier::new(4));
+            let waiters = (0..2)
+                .map(|_| {
+                    let group = Arc::clone(&group);
+                    let barrier = Arc::clone(&barrier);
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        group.wait();
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+            let worker = {
+                let group = Arc::clone(&group);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    group.done();
+                    // the last task of the generation discovers more work
+                    group.add(1);
+                    group.done();
+                })
+            };
+            barrier.wait();
+            group.done();
+            // a waiter that missed the end of its generation would block forever
+            for thread in waiters.into_iter().chain(Some(worker)) {
+                thread.join().expect("Failed to join");
+            }
+            assert_eq!(group.count(), 0);
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn once_group_initializes_indices_in_parallel() {
+        use super::OnceGroup;
+
+        let group = Arc::new(OnceGroup::new());
+        let barrier = Arc::new(std::sync::Barrier::new(usize::from(OnceGroup::LEN)));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let threads = (0..OnceGroup::LEN * 2)
+            .map(|i| {
+                let group = Arc::clone(&group);
+                let barrier = Arc::clone(&barrier);
+                let calls = Arc::clone(&calls);
+                std::thread::spawn(move || group.call_once(i % OnceGroup::LEN, || {
+                    // deadlocks unless all initializers run at the same time
+                    barrier.wait();
+                    calls.fetch_add(1, Relaxed);
+                }))
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert_eq!(calls.load(Relaxed), usize::from(OnceGroup::LEN));
+        assert!((0..OnceGroup::LEN).all(|i| group.is_completed(i)));
+        assert_eq!(format!("{:?}", group), "OnceGroup { completed: 0xffffffff, poisoned: 0x00000000 }");
+        std::panic::catch_unwind(|| group.is_completed(OnceGroup::LEN)).expect_err("index out of range didn't panic");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn once_group_wakes_only_waiters_of_the_index() {
+        use super::OnceGroup;
+        use super::once_group::test_hook;
+        use std::sync::mpsc;
+
+        let group = Arc::new(OnceGroup::new());
+        let (finish, finish_rx) = mpsc::channel::<()>();
+        let cloned = Arc::clone(&group);
+        let initializer = std::thread::spawn(move || cloned.call_once(0, || {
+            finish_rx.recv().expect("test dropped the sender");
+        }));
+        let waiters = (0..4)
+            .map(|_| {
+                let cloned = Arc::clone(&group);
+                std::thread::spawn(move || {
+                    while !cloned.is_completed(1) {
+                        std::thread::yield_now();
+                    }
+                    cloned.call_once(0, || panic!("the initialization should've completed"));
+                    test_hook::waits()
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        group.call_once(1, || ());
+        // give the waiters time to block
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        for index in 2..OnceGroup::LEN {
+            group.call_once(index, || ());
+        }
+        finish.send(()).expect("initializer exited");
+        initializer.join().expect("Failed to join");
+        for waiter in waiters {
+            // completing the other indices didn't wake the waiters up so they blocked once
+            assert_eq!(waiter.join().expect("Failed to join"), 1);
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn once_group_poisons_one_index() {
+        use super::OnceGroup;
+
+        let group = Arc::new(OnceGroup::new());
+        let cloned = Arc::clone(&group);
+        std::thread::spawn(move || cloned.call_once(3, || panic!("poisoning on purpose")))
+            .join()
+            .expect_err("the closure didn't panic");
+        assert!(group.is_poisoned(3));
+        std::panic::catch_unwind(|| group.call_once(3, || ())).expect_err("call_once didn't panic");
+        group.call_once(4, || ());
+        assert!(group.is_completed(4));
+        assert!(!group.is_poisoned(4));
+        assert_eq!(format!("{:?}", group), "OnceGroup { completed: 0x00000010, poisoned: 0x00000008 }");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn resettable_once_runs_again_after_reset() {
+        use super::ResettableOnce;
+
+        let once = ResettableOnce::new();
+        let mut calls = 0;
+        once.reset().expect("resetting incomplete instance failed");
+        assert_eq!(once.generation(), 0);
+        once.call_once(|| calls += 1);
+        once.call_once(|| calls += 1);
+        assert_eq!(calls, 1);
+        once.reset().expect("resetting complete instance failed");
+        assert!(!once.is_completed());
+        once.call_once(|| calls += 1);
+        assert_eq!(calls, 2);
+        assert_eq!(format!("{:?}", once), "ResettableOnce { generation: 1, state: Complete }");
+
+        std::panic::catch_unwind(|| once.reset().map(|()| once.call_once(|| panic!("poisoning on purpose")))).expect_err("the closure didn't panic");
+        assert_eq!(once.state(), OnceStatus::Poisoned);
+        std::panic::catch_unwind(|| once.call_once(|| ())).expect_err("call_once didn't panic");
+        once.reset().expect("resetting poisoned instance failed");
+        once.call_once(|| calls += 1);
+        assert_eq!(calls, 3);
+        assert_eq!(once.generation(), 3);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn resettable_once_rejects_reset_while_running() {
+        use super::ResettableOnce;
+        use std::sync::mpsc;
+
+        let once = Arc::new(ResettableOnce::new());
+        let (finish, finish_rx) = mpsc::channel::<()>();
+        let cloned = Arc::clone(&once);
+        let initializer = std::thread::spawn(move || cloned.call_once(|| {
+            finish_rx.recv().expect("test dropped the sender");
+        }));
+        while once.state() != OnceStatus::Running {
+            std::thread::yield_now();
+        }
+        let cloned = Arc::clone(&once);
+        let waiter = std::thread::spawn(move || cloned.call_once(|| panic!("the initialization should've completed")));
+        assert!(once.reset().is_err());
+        assert_eq!(once.generation(), 0);
+        finish.send(()).expect("initializer exited");
+        initializer.join().expect("Failed to join");
+        waiter.join().expect("Failed to join");
+        once.reset().expect("resetting complete instance failed");
+        assert_eq!(once.generation(), 1);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn resettable_once_concurrent_resets() {
+        use super::ResettableOnce;
+
+        let once = Arc::new(ResettableOnce::new());
+        let running = Arc::new(AtomicUsize::new(0));
+        let threads = (0..4)
+            .map(|_| {
+                let once = Arc::clone(&once);
+                let running = Arc::clone(&running);
+                std::thread::spawn(move || for _ in 0..1000 {
+                    once.call_once(|| {
+                        // a reset during the initialization would let another one start
+                        assert_eq!(running.fetch_add(1, Relaxed), 0);
+                        std::thread::yield_now();
+                        running.fetch_sub(1, Relaxed);
+                    });
+                    let _ = once.reset();
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert!(once.generation() > 0);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn futex_wait_value_changed() {
+        use super::futex::{self, WaitResult};
+        use core::sync::atomic::AtomicI32;
+
+        let word = AtomicI32::new(1);
+        assert_eq!(futex::wait(&word, 0), WaitResult::ValueChanged);
+        assert_eq!(futex::wait_timeout(&word, 0, std::time::Duration::from_secs(60)), WaitResult::ValueChanged);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn futex_wait_timeout() {
+        use super::futex::{self, WaitResult};
+        use core::sync::atomic::AtomicI32;
+        use std::time::{Duration, Instant};
+
+        let word = AtomicI32::new(0);
+        let start = Instant::now();
+        assert_eq!(futex::wait_timeout(&word, 0, Duration::from_millis(10)), WaitResult::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn futex_wait_deadline() {
+        use super::futex::{self, WaitResult};
+        use core::sync::atomic::AtomicI32;
+        use std::time::{Duration, Instant};
+
+        let word = AtomicI32::new(0);
+        assert_eq!(futex::wait_deadline(&word, 0, Instant::now()), WaitResult::TimedOut);
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert_eq!(futex::wait_deadline(&word, 0, deadline), WaitResult::TimedOut);
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn futex_wake() {
+        use super::futex;
+        use core::sync::atomic::{AtomicI32, Ordering};
+
+        let word = Arc::new(AtomicI32::new(0));
+        let waiter = {
+            let word = Arc::clone(&word);
+            std::thread::spawn(move || while word.load(Ordering::Acquire) == 0 {
+                futex::wait(&word, 0);
+            })
+        };
+        word.store(1, Ordering::Release);
+        futex::wake(&word, 1);
+        waiter.join().expect("Failed to join");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn futex_wake_all() {
+        use super::futex;
+        use core::sync::atomic::{AtomicI32, Ordering};
+
+        let word = Arc::new(AtomicI32::new(0));
+        let waiters = (0..4)
+            .map(|_| {
+                let word = Arc::clone(&word);
+                std::thread::spawn(move || while word.load(Ordering::Acquire) == 0 {
+                    futex::wait(&word, 0);
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        word.store(1, Ordering::Release);
+        futex::wake_all(&word);
+        for waiter in waiters {
+            waiter.join().expect("Failed to join");
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn borrowed_once_in_vec() {
+        use super::BorrowedOnce;
+        use core::sync::atomic::AtomicI32;
+
+        let words = Arc::new((0..4).map(|_| AtomicI32::new(Once::INCOMPLETE)).collect::<Vec<_>>());
+        let counter = Arc::new(AtomicUsize::new(0));
+        let threads = (0..4)
+            .map(|_| {
+                let words = Arc::clone(&words);
+                let counter = Arc::clone(&counter);
+                std::thread::spawn(move || {
+                    // SAFETY: the words are initialized to INCOMPLETE and only accessed through
+                    // BorrowedOnce
+                    let once = unsafe { BorrowedOnce::from_atomic(&words[2]) };
+                    once.call_once(|| {
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                        counter.fetch_add(1, Relaxed);
+                    });
+                    assert_eq!(counter.load(Relaxed), 1);
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        let states = words.iter().map(|word| word.load(Relaxed)).collect::<Vec<_>>();
+        assert_eq!(states, [Once::INCOMPLETE, Once::INCOMPLETE, Once::COMPLETE, Once::INCOMPLETE]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn borrowed_once_static() {
+        use super::BorrowedOnce;
+        use core::sync::atomic::AtomicI32;
+
+        static WORD: AtomicI32 = AtomicI32::new(Once::INCOMPLETE);
+        // SAFETY: WORD is initialized to INCOMPLETE and only accessed through BorrowedOnce
+        static ONCE: BorrowedOnce<'static> = unsafe { BorrowedOnce::from_atomic(&WORD) };
+
+        let waiter = std::thread::spawn(|| ONCE.wait());
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        // SAFETY: same as above
+        let once = unsafe { BorrowedOnce::from_atomic(&WORD) };
+        once.call_once(|| ());
+        waiter.join().expect("Failed to join");
+        assert!(ONCE.is_completed());
+        assert_eq!(WORD.load(Relaxed), Once::COMPLETE);
+        assert_eq!(format!("{:?}", ONCE), "BorrowedOnce { state: Complete }");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn borrowed_once_poisoning() {
+        use super::BorrowedOnce;
+        use core::sync::atomic::AtomicI32;
+
+        let word = AtomicI32::new(Once::INCOMPLETE);
+        // SAFETY: the word is initialized to INCOMPLETE and only accessed through BorrowedOnce
+        let once = unsafe { BorrowedOnce::from_atomic(&word) };
+        std::panic::catch_unwind(|| once.call_once(|| panic!())).expect_err("the closure didn't panic");
+        assert_eq!(word.load(Relaxed), Once::POISONED);
+        std::panic::catch_unwind(|| once.wait()).expect_err("wait didn't panic");
+        once.clear_poison();
+        once.call_once(|| ());
+        assert!(once.as_once().is_completed());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn once_pi_poisoning() {
+        use super::OncePi;
+
+        let once = Arc::new(OncePi::new());
+        let cloned = Arc::clone(&once);
+        std::thread::spawn(move || cloned.call_once(|| panic!("poisoning on purpose")))
+            .join()
+            .expect_err("the closure didn't panic");
+        assert!(once.is_poisoned());
+        std::panic::catch_unwind(|| once.call_once(|| ())).expect_err("call_once didn't panic");
+        // the lock was released by the panicking threads
+        let mut was_poisoned = false;
+        once.call_once_force(|state| was_poisoned = state.is_poisoned());
+        assert!(was_poisoned);
+        assert!(once.is_completed());
+
+        let once = OncePi::new();
+        let payload = std::panic::catch_unwind(|| once.call_once(|| once.call_once(|| ()))).expect_err("recursion didn't panic");
+        let message = payload.downcast_ref::<&str>().expect("unexpected payload");
+        assert!(message.contains("recursively"), "unexpected message: {}", message);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn once_pi_denied_futex_spins() {
+        use super::OncePi;
+        use super::futex::test_hook;
+
+        let once = Arc::new(OncePi::new());
+        let (started_sender, started) = std::sync::mpsc::channel();
+        let cloned = Arc::clone(&once);
+        let initializer = std::thread::spawn(move || cloned.call_once(|| {
+            started_sender.send(()).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }));
+
+        test_hook::inject_error(Some(libc::ENOSYS));
+        started.recv().unwrap();
+        assert_eq!(once.state(), OnceStatus::Running);
+        once.call_once(|| panic!("the initialization should've completed"));
+        assert!(test_hook::is_pi_unavailable());
+        initializer.join().expect("Failed to join");
+
+        test_hook::inject_error(None);
+        test_hook::reset();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn deadline_uses_instant() {
+        use std::time::{Duration, Instant};
+
+        let once = Once::new();
+        let guard = once.begin().expect("fresh Once already initialized");
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let deadline = Instant::now() + Duration::from_millis(20);
+                assert!(!once.wait_deadline(deadline));
+                // the kernel used the same clock so it didn't return early
+                assert!(Instant::now() >= deadline);
+            });
+        });
+        guard.complete();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn timespec_conversion() {
+        use super::futex::add_timespec;
+        use std::time::Duration;
+
+        let time = libc::timespec { tv_sec: 10, tv_nsec: 999_999_999 };
+        // already passed deadlines don't move the time
+        let result = add_timespec(time, Duration::from_secs(0));
+        assert_eq!((result.tv_sec, result.tv_nsec), (10, 999_999_999));
+        // nanoseconds carry over into seconds
+        let result = add_timespec(time, Duration::from_nanos(1));
+        assert_eq!((result.tv_sec, result.tv_nsec), (11, 0));
+        let result = add_timespec(time, Duration::new(1, 999_999_999));
+        assert_eq!((result.tv_sec, result.tv_nsec), (12, 999_999_998));
+        // sub-second durations
+        let result = add_timespec(libc::timespec { tv_sec: 0, tv_nsec: 0 }, Duration::from_micros(1500));
+        assert_eq!((result.tv_sec, result.tv_nsec), (0, 1_500_000));
+        // saturates instead of overflowing
+        let result = add_timespec(time, Duration::from_secs(u64::MAX));
+        assert_eq!(result.tv_sec, libc::time_t::MAX);
+    }
 
-#[cfg(all(test, feature = "bench"))]
-extern crate test;
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn call_once_abort_on_panic_wakes_waiters() {
+        let once = Arc::new(Once::new());
+        let (started_sender, started) = std::sync::mpsc::channel();
+        let cloned = Arc::clone(&once);
+        let initializer = std::thread::spawn(move || cloned.call_once_abort_on_panic(|| {
+            started_sender.send(()).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }));
+        started.recv().unwrap();
 
-#[cfg(test)]
-mod tests;
+        let waiters = (0..2)
+            .map(|i| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || if i == 0 {
+                    cloned.call_once(|| panic!("the initialization should've completed"))
+                } else {
+                    cloned.call_once_abort_on_panic(|| panic!("the initialization should've completed"))
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        initializer.join().expect("Failed to join");
+        for waiter in waiters {
+            waiter.join().expect("Failed to join");
+        }
+        assert!(once.is_completed());
 
-#[cfg(target_os = "linux")]
-pub use linux::Once;
+        let once = Once::new();
+        let mut calls = 0;
+        once.call_once_abort_on_panic(|| calls += 1);
+        once.call_once_abort_on_panic(|| calls += 1);
+        assert_eq!(calls, 1);
+    }
 
-#[cfg(not(target_os = "linux"))]
-pub use std::sync::Once;
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn once_state_poison() {
+        let once = Once::new();
+        once.call_once_force(|state| {
+            assert!(!state.is_poisoned());
+            state.poison();
+        });
+        assert!(once.is_poisoned());
+        std::panic::catch_unwind(|| once.call_once(|| ())).expect_err("call_once didn't panic");
+        let mut was_poisoned = false;
+        once.call_once_force(|state| was_poisoned = state.is_poisoned());
+        assert!(was_poisoned);
+        assert!(once.is_completed());
 
-#[cfg(target_os = "linux")]
-mod linux {
-    use linux_futex::{Futex, Private};
-    use core::sync::atomic::Ordering;
-
-    /// A synchronization primitive which can be used to run a one-time global initialization. Useful
-    /// for one-time initialization for FFI or related functionality. This type can only be constructed
-    /// with [`Once::new()`].
-    pub struct Once(Futex<Private>);
-
-    /// The closure didn't run yet
-    const INCOMPLETE: i32 = 0;
-    /// The closure panicked
-    const POISONED: i32 = 2;
-    /// The closure finished without panicking
-    const COMPLETE: i32 = 1;
-    /// The closure is running and no thread is waiting yet
-    ///
-    /// Used to avoid expensive syscall
-    const RUNNING_NO_WAIT: i32 = 3;
-    /// The closure is running and at least on thread is waiting
-    const RUNNING_WAITING: i32 = 4;
-
-    impl Once {
-        /// Creates a new `Once` value.
-        pub const fn new() -> Self {
-            Once(Futex::new(INCOMPLETE))
-        }
-
-        /// Performs an initialization routine once and only once. The given closure will be executed if
-        /// this is the first time `call_once` has been called, and otherwise the routine will *not* be
-        /// invoked.
-        ///
-        /// This method will block the calling thread if another initialization routine is currently
-        /// running.
-        ///
-        /// When this function returns, it is guaranteed that some initialization has run and completed (it
-        /// may not be the closure specified). It is also guaranteed that any memory writes performed by the
-        /// executed closure can be reliably observed by other threads at this point (there is a
-        /// happens-before relation between the closure and code executing after the return).
-        ///
-        /// If the given closure recursively invokes call_once on the same [`Once`] instance the exact
-        /// behavior is not specified, allowed outcomes are a panic or a deadlock.
-        ///
-        /// Note specific to the Linux version: recursive calls currently cause deadlock. This
-        /// information is only intended to help debugging and must **not** be relied on.
-        pub fn call_once<F: FnOnce()>(&self, f: F) {
-            // Fast path
-            // std calls is_completed() at this point, we store the state instead to reuse later and
-            // avoid repeating atomic operation
-            let state = self.0.value.load(Ordering::Acquire);
-            if state == COMPLETE {
-                return;
-            }
+        let once = super::OncePi::new();
+        once.call_once_force(|state| state.poison());
+        assert!(once.is_poisoned());
+        std::panic::catch_unwind(|| once.call_once(|| ())).expect_err("call_once didn't panic");
+        once.call_once_force(|state| assert!(state.is_poisoned()));
+    }
 
-            let mut f = Some(f);
-            self.internal_call_once(state, &mut || f.take().expect("closure called more than once")())
-        }
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn named_once() {
+        use super::NamedOnce;
+        use std::time::Duration;
 
-        #[cold]
-        fn internal_call_once(&self, mut state: i32, f: &mut dyn FnMut()) {
-            // No need to over-complicate the checker as much as std does
-            struct PanicChecker<'a> {
-                futex: &'a Futex<Private>,
-                value_to_write: i32,
-            }
+        assert_eq!(core::mem::size_of::<Once>(), 4);
+        let once = Arc::new(NamedOnce::new("config"));
+        assert_eq!(once.name(), "config");
+        assert_eq!(format!("{:?}", once), "NamedOnce { name: \"config\", state: Incomplete }");
 
-            impl<'a> Drop for PanicChecker<'a> {
-                fn drop(&mut self) {
-                    // Only make expensive syscall if there are threads waiting
-                    if self.futex.value.swap(self.value_to_write, Ordering::AcqRel) == RUNNING_WAITING {
-                        self.futex.wake(i32::max_value());
-                    }
-                }
-            }
+        let guard = once.begin().expect("fresh Once already initialized");
+        let cloned = Arc::clone(&once);
+        let waiter = std::thread::spawn(move || {
+            let mut names = Vec::new();
+            cloned.call_once_watched(|| panic!("the initialization should've completed"), Duration::from_millis(10), |info| names.push(info.name));
+            names
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        guard.complete();
+        assert_eq!(waiter.join().expect("Failed to join"), [Some("config")]);
+        assert_eq!(format!("{:?}", once), "NamedOnce { name: \"config\", state: Complete }");
+    }
 
-            loop {
-                match state {
-                    INCOMPLETE => {
-                        // same thing std does
-                        // except we use weak, which seems a bit better
-                        if let Err(old) = self.0.value.compare_exchange_weak(INCOMPLETE, RUNNING_NO_WAIT, Ordering::Acquire, Ordering::Acquire) {
-                            state = old;
-                            continue;
-                        }
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn call_once_spin_with_normal_initializer() {
+        use std::sync::Barrier;
 
-                        {
-                            // we do it a bit simpler
-                            let mut panic_checker = PanicChecker { futex: &self.0, value_to_write: POISONED, };
-                            f();
-                            panic_checker.value_to_write = COMPLETE;
-                        }
-                        break;
-                    },
-                    COMPLETE => break,
-                    POISONED => panic!("Once instance has previously been poisoned"),
-                    // we have two versions of running to optimize a bit
-                    _running => {
-                        // Signal that there's at least one thread waiting
-                        if let Err(old) = self.0.value.compare_exchange(RUNNING_NO_WAIT, RUNNING_WAITING, Ordering::AcqRel, Ordering::Acquire) {
-                            // reuse expensive load
-                            state = old;
-                        } else {
-                            state = RUNNING_WAITING;
-                        }
+        let once = Arc::new(Once::new());
+        let barrier = Arc::new(Barrier::new(2));
+        let cloned = Arc::clone(&once);
+        let cloned_barrier = Arc::clone(&barrier);
+        let initializer = std::thread::spawn(move || cloned.call_once(|| {
+            cloned_barrier.wait();
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }));
+        barrier.wait();
+        once.call_once_spin(|| panic!("the initialization should've completed"));
+        initializer.join().expect("Failed to join");
+        assert!(once.is_completed());
+    }
 
-                        // TODO: is it worth spinning a bit?
-                        //       Probably not because the operation is supposed to be expensive but
-                        //       we don't know until we measure.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn call_once_spin_never_registers() {
+        let once = Arc::new(Once::new());
+        let guard = once.begin().expect("fresh Once already initialized");
+        let cloned = Arc::clone(&once);
+        let spinner = std::thread::spawn(move || cloned.call_once_spin(|| panic!("the initialization should've completed")));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        // the initializer doesn't have to wake anyone
+        assert_eq!(once.load_state(), Once::RUNNING_NO_WAIT);
+        assert_eq!(once.waiters(), 0);
+        guard.complete();
+        spinner.join().expect("Failed to join");
+    }
 
-                        // actual waiting logic
-                        while state >= RUNNING_NO_WAIT {
-                            // We need to check the value regardless, o we just ignore the error
-                            let _ = self.0.wait(state);
-                            state = self.0.value.load(Ordering::Acquire);
-                        }
-                        break;
-                    },
-                }
-            }
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn call_once_spin_mixed_with_futex_waiters() {
+        let once = Arc::new(Once::new());
+        let guard = once.begin().expect("fresh Once already initialized");
+        let threads = (0..6)
+            .map(|i| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || if i % 2 == 0 {
+                    cloned.call_once_spin(|| panic!("the initialization should've completed"))
+                } else {
+                    cloned.call_once(|| panic!("the initialization should've completed"))
+                })
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+        let start = std::time::Instant::now();
+        while once.waiters() < 3 {
+            assert!(start.elapsed() < std::time::Duration::from_secs(10), "waiters didn't block");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        // only the futex waiters are counted
+        assert_eq!(once.waiters(), 3);
+        guard.complete();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert_eq!(once.waiters(), 0);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    #[ignore = "installs a process-wide signal handler"]
+    fn call_once_from_real_signal_handler() {
+        use std::sync::atomic::AtomicBool;
+
+        static ONCE: Once = Once::new();
+        static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+        extern "C" fn handler(_: libc::c_int) {
+            ONCE.call_once_from_signal(|| INSTALLED.store(true, Relaxed));
         }
 
-        /// Returns `true` if some [`call_once()`](Self::call_once) call has completed successfully. Specifically, is_completed
-        /// will return false in the following situations:
-        ///
-        /// * [`call_once()`](Self::call_once) was not called at all,
-        /// * [`call_once()`](Self::call_once) was called, but has not yet completed,
-        /// * the [`Once`] instance is poisoned
-        ///
-        /// This function returning `false` does not mean that [`Once`] has not been executed. For example, it
-        /// may have been executed in the time between when `is_completed` starts executing and when it returns,
-        /// in which case the `false` return value would be stale (but still permissible).
-        pub fn is_completed(&self) -> bool {
-            self.0.value.load(Ordering::Acquire) == COMPLETE
+        // SAFETY: the handler only calls async-signal-safe functions
+        unsafe {
+            let mut action: libc::sigaction = core::mem::zeroed();
+            action.sa_sigaction = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            assert_eq!(libc::sigaction(libc::SIGUSR1, &action, core::ptr::null_mut()), 0);
+            assert_eq!(libc::raise(libc::SIGUSR1), 0);
         }
+        assert!(INSTALLED.load(Relaxed));
+        assert!(ONCE.is_completed());
     }
-}
 
-#[cfg(test)]
-mod our_tests {
-    use super::Once;
-    use std::sync::{Arc, atomic::{AtomicUsize, Ordering::Relaxed}};
-    #[cfg(feature = "bench")]
-    use test::Bencher;
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn spin_only_observes_completion() {
+        use super::policy::SpinOnly;
 
-    // Simulate 5 threads attempting to run `Once` at the same time
-    #[cfg(feature = "bench")]
-    const CONTENDED_THREADS: usize = 5;
-    // Simulate expensive operation that takes 1ms to complete
-    #[cfg(feature = "bench")]
-    const CONTENDED_WAIT: u64 = 1_000_000;
+        static ONCE: Once<SpinOnly> = Once::with_policy(SpinOnly);
+
+        let guard = ONCE.begin().expect("fresh Once already initialized");
+        let threads = (0..2)
+            .map(|i| std::thread::spawn(move || if i == 0 {
+                ONCE.call_once(|| panic!("the initialization should've completed"))
+            } else {
+                ONCE.wait()
+            }))
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        // spinning threads never register
+        assert_eq!(ONCE.load_state(), Once::RUNNING_NO_WAIT);
+        assert_eq!(ONCE.waiters(), 0);
+
+        guard.complete();
+        for thread in threads {
+            thread.join().expect("Failed to join");
+        }
+        assert!(ONCE.is_completed());
+    }
 
     #[test]
-    fn basic() {
-        let mut ran = false;
+    #[cfg(target_os = "linux")]
+    fn spin_only_timeout() {
+        use super::policy::SpinOnly;
+
+        let once = Once::with_policy(SpinOnly);
+        let guard = once.begin().expect("fresh Once already initialized");
+        std::thread::scope(|scope| {
+            scope.spawn(|| assert!(!once.wait_timeout(std::time::Duration::from_millis(10))));
+        });
+        guard.complete();
+        assert!(once.wait_timeout(std::time::Duration::from_millis(10)));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn relaxed_check() {
         let once = Once::new();
-        once.call_once(|| ran = true);
-        assert!(ran);
-        ran = false;
-        once.call_once(|| ran = true);
-        assert!(!ran);
+        assert!(!once.is_completed_relaxed());
+        let mut calls = 0;
+        once.call_once_relaxed_check(|| calls += 1);
+        once.call_once_relaxed_check(|| calls += 1);
+        assert_eq!(calls, 1);
+        assert!(once.is_completed_relaxed());
     }
 
     #[test]
-    fn multithreaded() {
-        let once = Arc::new((Once::new(), AtomicUsize::new(0)));
-        let once_cloned = Arc::clone(&once);
+    #[cfg(target_os = "linux")]
+    fn denied_futex_falls_back_to_polling() {
+        use super::futex::test_hook;
 
-        let handle = std::thread::spawn(move || once_cloned.0.call_once(|| { once_cloned.1.fetch_add(1, Relaxed); }));
-        once.0.call_once(|| { once.1.fetch_add(1, Relaxed); });
-        handle.join().expect("failed to join thread");
-        assert_eq!(once.1.load(Relaxed), 1);
+        for errno in [libc::EPERM, libc::ENOSYS] {
+            let once = Arc::new(Once::new());
+            let cloned = Arc::clone(&once);
+            let (started_sender, started) = std::sync::mpsc::channel();
+            let initializer = std::thread::spawn(move || {
+                test_hook::inject_error(Some(errno));
+                cloned.call_once(|| {
+                    started_sender.send(()).unwrap();
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                });
+            });
+
+            test_hook::inject_error(Some(errno));
+            started.recv().unwrap();
+            once.call_once(|| panic!("the initialization should've completed"));
+            assert!(test_hook::is_unavailable());
+            // timed waits work too
+            assert!(once.wait_timeout(std::time::Duration::from_millis(10)));
+            initializer.join().expect("Failed to join");
+
+            test_hook::inject_error(None);
+            test_hook::reset();
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn try_wait_raw_errors() {
+        use super::futex::test_hook;
+        use super::WaitError;
+
+        let once = Once::new();
+        let guard = once.begin().expect("fresh Once already initialized");
+        for (errno, expected) in [
+            (libc::EINTR, WaitError::Interrupted),
+            (libc::EAGAIN, WaitError::ValueChanged),
+            (libc::ETIMEDOUT, WaitError::TimedOut),
+            (libc::EPERM, WaitError::Os(libc::EPERM)),
+        ] {
+            test_hook::inject_error(Some(errno));
+            assert_eq!(once.try_wait_raw(None), Err(expected));
+        }
+        test_hook::inject_error(None);
+        // the raw API doesn't switch to polling
+        assert_eq!(once.try_wait_raw(Some(std::time::Instant::now())), Err(WaitError::TimedOut));
+        assert_eq!(once.waiters(), 0);
+
+        guard.complete();
+        assert_eq!(once.try_wait_raw(None), Ok(()));
+        assert!(WaitError::Os(libc::EPERM).to_string().contains("futex"));
+    }
+
+    // regression test: waiters used to return normally when the initializer panicked
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn blocked_waiters_panic_when_poisoned() {
+        const WAITERS: usize = 4;
+
+        let once = Arc::new(Once::new());
+        let (release_sender, release) = std::sync::mpsc::channel::<()>();
+        let (started_sender, started) = std::sync::mpsc::channel();
+        let cloned = Arc::clone(&once);
+        let initializer = std::thread::spawn(move || cloned.call_once(|| {
+            started_sender.send(()).unwrap();
+            release.recv().unwrap();
+            panic!("poisoning on purpose");
+        }));
+        started.recv().unwrap();
+
+        let waiters = (0..WAITERS)
+            .map(|_| {
+                let cloned = Arc::clone(&once);
+                std::thread::spawn(move || cloned.call_once(|| panic!("closure of a waiter ran")))
+            })
+            // required for true concurrency
+            .collect::<Vec<_>>();
+
+        let start = std::time::Instant::now();
+        while once.waiters() < WAITERS {
+            assert!(start.elapsed() < std::time::Duration::from_secs(10), "waiters didn't block");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        release_sender.send(()).unwrap();
+        initializer.join().expect_err("the closure didn't panic");
+
+        for waiter in waiters {
+            let payload = waiter.join().expect_err("waiter returned normally from poisoned Once");
+            let message = payload
+                .downcast_ref::<String>()
+                .map(String::as_str)
+                .or_else(|| payload.downcast_ref::<&str>().copied())
+                .expect("unexpected payload");
+            assert!(message.contains("previously been poisoned"), "unexpected message: {}", message);
+        }
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "tracing"))]
+    fn tracing_events() {
+        use std::sync::Mutex;
+        use tracing::field::{Field, Visit};
+
+        // message, names of other fields and the name of the span the event was emitted in
+        type Events = Arc<Mutex<Vec<(String, Vec<&'static str>, Option<String>)>>>;
+
+        // names of the spans, indexed by id - 1, and the currently entered span
+        #[derive(Default)]
+        struct Spans(Vec<String>, Option<usize>);
+
+        struct Recorder(Events, std::thread::ThreadId, Mutex<Spans>);
+
+        #[derive(Default)]
+        struct Visitor(String, Vec<&'static str>);
+
+        impl Visit for Visitor {
+            fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+                if field.name() == "message" || field.name() == "name" {
+                    self.0 = format!("{:?}", value);
+                } else {
+                    self.1.push(field.name());
+                }
+            }
+        }
+
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, metadata: &tracing::Metadata<'_>) -> bool {
+                metadata.target() == "linux_once"
+            }
+            fn new_span(&self, attributes: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+                let mut visitor = Visitor::default();
+                attributes.record(&mut visitor);
+                let mut spans = self.2.lock().unwrap();
+                spans.0.push(visitor.0);
+                tracing::span::Id::from_u64(spans.0.len() as u64)
+            }
+            fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+            fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+            fn event(&self, event: &tracing::Event<'_>) {
+                // other tests may be running at the same time
+                if std::thread::current().id() != self.1 {
+                    return;
+                }
+                let mut visitor = Visitor::default();
+                event.record(&mut visitor);
+                let spans = self.2.lock().unwrap();
+                let span = spans.1.map(|index| spans.0[index].clone());
+                self.0.lock().unwrap().push((visitor.0, visitor.1, span));
+            }
+            fn enter(&self, id: &tracing::span::Id) {
+                if std::thread::current().id() == self.1 {
+                    self.2.lock().unwrap().1 = Some(id.into_u64() as usize - 1);
+                }
+            }
+            fn exit(&self, _: &tracing::span::Id) {
+                if std::thread::current().id() == self.1 {
+                    self.2.lock().unwrap().1 = None;
+                }
+            }
+        }
+
+        let events = Events::default();
+        // A scoped subscriber wouldn't work reliably because tracing caches the interest of the
+        // thread that hits the callsite first, which may be another test.
+        tracing::subscriber::set_global_default(Recorder(Arc::clone(&events), std::thread::current().id(), Default::default()))
+            .expect("global subscriber already set");
+        {
+            let once = Once::new();
+            std::panic::catch_unwind(|| once.call_once(|| panic!())).expect_err("the closure didn't panic");
+            Once::new().call_once(|| ());
+
+            let once = Arc::new(Once::new());
+            let (started_sender, started) = std::sync::mpsc::channel();
+            let cloned = Arc::clone(&once);
+            let initializer = std::thread::spawn(move || cloned.call_once(|| {
+                started_sender.send(()).unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }));
+            started.recv().unwrap();
+            once.call_once(|| panic!("the initialization should've completed"));
+            initializer.join().expect("Failed to join");
+
+            super::NamedOnce::new("config").call_once(|| ());
+        }
+
+        let events = events.lock().unwrap();
+        let fields_of = |message: &str| events
+            .iter()
+            .find(|(event_message, _, _)| event_message == message)
+            .map(|(_, fields, _)| fields.clone())
+            .unwrap_or_else(|| panic!("missing event {}, got {:?}", message, events));
+        assert_eq!(fields_of("entered slow path"), ["once", "state"]);
+        assert_eq!(fields_of("blocking"), ["once", "state"]);
+        assert_eq!(fields_of("woken"), ["once", "state"]);
+        assert_eq!(fields_of("initialization poisoned"), ["once", "elapsed_ns", "waiters"]);
+        assert_eq!(fields_of("initialization completed"), ["once", "elapsed_ns", "waiters"]);
+        let named = events
+            .iter()
+            .filter(|(_, _, span)| span.as_deref() == Some("\"config\""))
+            .map(|(message, _, _)| &**message)
+            .collect::<Vec<_>>();
+        assert_eq!(named, ["entered slow path", "initialization completed"]);
     }
 
     #[bench]
@@ -244,6 +5180,81 @@ mod our_tests {
         })
     }
 
+    #[bench]
+    #[cfg(feature = "bench")]
+    #[cfg_attr(miri, ignore)]
+    #[cfg(target_os = "linux")]
+    fn measure_linux_trivial_mut(bencher: &mut Bencher) {
+        bencher.iter(|| {
+            let mut ran = false;
+            let mut once = Once::new();
+            once.call_once_mut(|| ran = true);
+            assert!(ran);
+        })
+    }
+
+    #[bench]
+    #[cfg(feature = "bench")]
+    #[cfg_attr(miri, ignore)]
+    fn measure_linux_is_completed(bencher: &mut Bencher) {
+        let once = Once::new();
+        once.call_once(|| ());
+        bencher.iter(|| assert!(test::black_box(&once).is_completed()))
+    }
+
+    #[bench]
+    #[cfg(feature = "bench")]
+    #[cfg_attr(miri, ignore)]
+    fn measure_linux_is_completed_relaxed(bencher: &mut Bencher) {
+        let once = Once::new();
+        once.call_once(|| ());
+        bencher.iter(|| assert!(test::black_box(&once).is_completed_relaxed()))
+    }
+
+    // A thread keeps writing right next to the Once while its fast path is measured
+    #[cfg(feature = "bench")]
+    fn measure_fast_path_next_to_writes<O: Send + Sync + 'static>(bencher: &mut Bencher, once: O, as_once: fn(&O) -> &Once) {
+        use core::sync::atomic::AtomicBool;
+
+        #[repr(C)]
+        struct Shard<O> {
+            once: O,
+            writes: AtomicUsize,
+        }
+
+        let shard = Arc::new(Shard { once, writes: AtomicUsize::new(0) });
+        let stop = Arc::new(AtomicBool::new(false));
+        as_once(&shard.once).call_once(|| ());
+        let writer = {
+            let shard = Arc::clone(&shard);
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || while !stop.load(Relaxed) {
+                shard.writes.fetch_add(1, Relaxed);
+            })
+        };
+        bencher.iter(|| {
+            for _ in 0..100 {
+                as_once(test::black_box(&shard.once)).call_once(|| unreachable!());
+            }
+        });
+        stop.store(true, Relaxed);
+        writer.join().expect("Failed to join");
+    }
+
+    #[bench]
+    #[cfg(feature = "bench")]
+    #[cfg_attr(miri, ignore)]
+    fn measure_adjacent_once_false_sharing(bencher: &mut Bencher) {
+        measure_fast_path_next_to_writes(bencher, Once::new(), |once| once);
+    }
+
+    #[bench]
+    #[cfg(feature = "bench")]
+    #[cfg_attr(miri, ignore)]
+    fn measure_padded_once_false_sharing(bencher: &mut Bencher) {
+        measure_fast_path_next_to_writes(bencher, super::PaddedOnce::new(Once::new()), |once| once);
+    }
+
     #[bench]
     #[cfg(feature = "bench")]
     #[cfg_attr(miri, ignore)]
@@ -299,4 +5310,97 @@ mod our_tests {
                 .expect("Failed to join");
         })
     }
+
+    #[bench]
+    #[cfg(feature = "bench")]
+    #[cfg_attr(miri, ignore)]
+    fn measure_std_contended_short(bencher: &mut Bencher) {
+        let barrier = Arc::new(std::sync::Barrier::new(CONTENDED_THREADS));
+        bencher.iter(|| {
+            let once = Arc::new(std::sync::Once::new());
+            let threads = (0..CONTENDED_THREADS)
+                .map(|_| {
+                    let cloned_once = Arc::clone(&once);
+                    let cloned_barrier = Arc::clone(&barrier);
+                    std::thread::spawn(move || {
+                        cloned_barrier.wait();
+                        cloned_once.call_once(short_initializer)
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .map(|thread| thread.join().map(drop))
+                .collect::<Result<(), _>>()
+                .expect("Failed to join");
+        })
+    }
+
+    #[bench]
+    #[cfg(feature = "bench")]
+    #[cfg_attr(miri, ignore)]
+    fn measure_linux_contended_short(bencher: &mut Bencher) {
+        let barrier = Arc::new(std::sync::Barrier::new(CONTENDED_THREADS));
+        bencher.iter(|| {
+            let once = Arc::new(Once::new());
+            let threads = (0..CONTENDED_THREADS)
+                .map(|_| {
+                    let cloned = Arc::clone(&once);
+                    let cloned_barrier = Arc::clone(&barrier);
+                    std::thread::spawn(move || {
+                        cloned_barrier.wait();
+                        cloned.call_once(short_initializer)
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .map(|thread| thread.join().map(drop))
+                .collect::<Result<(), _>>()
+                .expect("Failed to join");
+        })
+    }
+
+    #[bench]
+    #[cfg(feature = "bench")]
+    #[cfg_attr(miri, ignore)]
+    fn measure_linux_contended_short_block_immediately(bencher: &mut Bencher) {
+        measure_linux_contended_short_with::<super::policy::BlockImmediately>(bencher)
+    }
+
+    #[bench]
+    #[cfg(feature = "bench")]
+    #[cfg_attr(miri, ignore)]
+    fn measure_linux_contended_short_spin_only(bencher: &mut Bencher) {
+        measure_linux_contended_short_with::<super::policy::SpinOnly>(bencher)
+    }
+
+    #[cfg(feature = "bench")]
+    fn measure_linux_contended_short_with<P: super::policy::WaitPolicy + Default + 'static>(bencher: &mut Bencher) {
+        let barrier = Arc::new(std::sync::Barrier::new(CONTENDED_THREADS));
+        bencher.iter(|| {
+            let once = Arc::new(Once::with_policy(P::default()));
+            let threads = (0..CONTENDED_THREADS)
+                .map(|_| {
+                    let cloned = Arc::clone(&once);
+                    let cloned_barrier = Arc::clone(&barrier);
+                    std::thread::spawn(move || {
+                        cloned_barrier.wait();
+                        cloned.call_once(short_initializer)
+                    })
+                })
+                // required for true concurrency
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .map(|thread| thread.join().map(drop))
+                .collect::<Result<(), _>>()
+                .expect("Failed to join");
+        })
+    }
 }
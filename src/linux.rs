@@ -0,0 +1,1710 @@
+use linux_futex::{Futex, Private};
+use core::any::Any;
+use core::cell::{RefCell, UnsafeCell};
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::panic::Location;
+use core::sync::atomic::{AtomicU32, Ordering};
+use std::panic::{self, RefUnwindSafe, UnwindSafe};
+use std::time::{Duration, Instant};
+use crate::{CallOnceOutcome, OnceState, OnceStatus, PoisonedError, SlowInit, TryCallOnce};
+use crate::futex::{self, WaitError};
+use crate::once_fn::OnceFn;
+use crate::waiter_count;
+use crate::poison_location;
+use crate::poison_hook::{self, PoisonInfo};
+use crate::policy::{DefaultWait, SpinPolicy, WaitPolicy};
+#[cfg(feature = "poison-message")]
+use crate::poison_message;
+
+/// A synchronization primitive which can be used to run a one-time global initialization. Useful
+/// for one-time initialization for FFI or related functionality. This type can only be constructed
+/// with [`Once::new()`] or [`Once::with_policy()`].
+///
+/// # Layout
+///
+/// `Once` has the same layout as `i32` (or `AtomicI32`) so it can be placed into storage dictated
+/// by foreign code and accessed via [`as_ptr()`](Self::as_ptr). The state is encoded as follows
+/// and the encoding is stable within a major version of this crate:
+///
+/// * [`Once::INCOMPLETE`] (`0`) - incomplete, nothing is running
+/// * [`Once::COMPLETE`] (`1`) - complete
+/// * [`Once::POISONED`] (`2`) - poisoned
+/// * [`Once::RUNNING_NO_WAIT`] (`3`) - running, no thread is waiting
+/// * [`Once::RUNNING_WAITING`] (`4`) - running, some threads are waiting
+/// * [`Once::INCOMPLETE_WAITING`] (`5`) - incomplete, some threads are waiting for someone to
+///   run the initialization
+///
+/// Foreign code may initialize the storage to `0` and check for completion by an acquire load
+/// comparing with `1`. Any other modification must go through this type. Rust code can do the
+/// same check using [`load_state()`](Once::load_state).
+///
+/// # Waiting policy
+///
+/// The type parameter chooses how the threads wait for a running initialization, see the
+/// [`policy`](crate::policy) module. It doesn't affect the layout.
+#[repr(transparent)]
+pub struct Once<P = DefaultWait>(Futex<Private>, PhantomData<fn() -> P>);
+
+const _: () = assert!(core::mem::size_of::<Once>() == core::mem::size_of::<i32>());
+const _: () = assert!(core::mem::align_of::<Once>() == core::mem::align_of::<i32>());
+
+// The slow paths must be instantiated once per wait policy, not once per closure, so the generic
+// methods only wrap the closures in `OnceFn`. Type parameters are early-bound, so if a slow path
+// was generic over the closure it couldn't be coerced to a function pointer that is higher-ranked
+// over the lifetime of the closure.
+#[allow(clippy::type_complexity)]
+const _: () = {
+    fn _slow_paths_not_generic<P: WaitPolicy>() {
+        let _: fn(&Once<P>, i32, &mut dyn FnMut()) = Once::<P>::call_once_slow;
+        let _: fn(&Once<P>, i32, &mut dyn FnMut()) = Once::<P>::call_once_spin_slow;
+        let _: fn(&Once<P>, i32, Option<Instant>, &mut dyn FnMut()) -> CallOnceOutcome = Once::<P>::call_once_until;
+        let _: fn(&Once<P>, i32, Duration, &mut dyn FnMut(), &mut dyn FnMut(&SlowInit)) = Once::<P>::call_once_watched_slow;
+        let _: fn(&Once<P>, i32, bool, &mut dyn FnMut(&OnceState) -> bool) -> bool = Once::<P>::internal_call_once;
+        let _: fn(&Once<P>, i32, &AtomicU32, &mut dyn FnMut()) = Once::<P>::call_once_retry_slow;
+    }
+};
+
+/// The closure didn't run yet
+const INCOMPLETE: i32 = 0;
+/// The closure panicked
+const POISONED: i32 = 2;
+/// The closure finished without panicking
+const COMPLETE: i32 = 1;
+/// The closure is running and no thread is waiting yet
+///
+/// Used to avoid expensive syscall
+const RUNNING_NO_WAIT: i32 = 3;
+/// The closure is running and at least on thread is waiting
+const RUNNING_WAITING: i32 = 4;
+/// The closure didn't run yet and at least one thread is waiting for someone to run it
+///
+/// Only [`Once::wait()`] can get us into this state. The initializer turns it into
+/// [`RUNNING_WAITING`] so that the waiting threads get woken up once it finishes.
+const INCOMPLETE_WAITING: i32 = 5;
+
+// Not generic so that `Once::default()` doesn't need type annotations, just like `Once::new()`
+impl Default for Once {
+    fn default() -> Self {
+        Once::new()
+    }
+}
+
+impl<P: WaitPolicy> fmt::Debug for Once<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Once").field("state", &self.state()).finish()
+    }
+}
+
+// Same as in std: a panicking closure poisons the Once so other threads can't observe broken
+// state without explicitly asking for it (`call_once_force()`).
+impl<P> UnwindSafe for Once<P> {}
+impl<P> RefUnwindSafe for Once<P> {}
+
+/// A variant of [`Once`] that retries the initialization after panics.
+///
+/// If the initialization closure panics the `RetryOnce` is reset to incomplete instead and the
+/// threads waiting for it are woken up. The next caller (possibly one of the woken threads) then
+/// runs its own closure. Each retry is thus a fresh [`call_once()`](Self::call_once) attempt and
+/// no closure is ever run more than once.
+///
+/// By default the number of retries is unlimited so the `RetryOnce` is never poisoned. A
+/// `RetryOnce` created by [`with_retry_budget()`](Self::with_retry_budget) only allows the given
+/// number of retries and the closure panicking after that poisons it just like [`Once`].
+///
+/// This is useful when the initialization may fail transiently, e.g. when creating a network
+/// client. Just like with [`Once::call_once_catch()`], other threads may observe whatever state
+/// the panicking closure left behind.
+pub struct RetryOnce {
+    once: Once,
+    /// Number of remaining retries, only accessed by the thread running the initialization
+    budget: AtomicU32,
+}
+
+/// Retry budget of a `RetryOnce` that is never poisoned
+const UNLIMITED_RETRIES: u32 = u32::MAX;
+
+impl RetryOnce {
+    /// Creates a new `RetryOnce` value.
+    pub const fn new() -> Self {
+        RetryOnce::with_retry_budget(UNLIMITED_RETRIES)
+    }
+
+    /// Creates a new `RetryOnce` value that allows at most `retries` retries.
+    ///
+    /// After the closure panics `retries + 1` times in total the `RetryOnce` is permanently
+    /// poisoned and further calls panic. `u32::MAX` is treated as unlimited.
+    pub const fn with_retry_budget(retries: u32) -> Self {
+        RetryOnce { once: Once::new(), budget: AtomicU32::new(retries) }
+    }
+
+    /// Performs an initialization routine once and only once.
+    ///
+    /// This behaves like [`Once::call_once()`] except a panic in the closure resets the
+    /// `RetryOnce` to incomplete instead of poisoning it, unless the retry budget is exhausted.
+    /// A panicking closure doesn't count as an initialization so if this call was blocked waiting
+    /// for it, it runs its own closure.
+    ///
+    /// # Panics
+    ///
+    /// If the retry budget was exhausted and this `RetryOnce` is poisoned, this method will also
+    /// panic.
+    #[track_caller]
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        // Fast path, same as in Once::call_once
+        let state = self.once.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_retry_slow calls the closure at most once
+        self.once.call_once_retry_slow(state, &self.budget, &mut || unsafe { f.take()() });
+    }
+
+    /// Returns `true` if some [`call_once()`](Self::call_once) call has completed successfully.
+    pub fn is_completed(&self) -> bool {
+        self.once.is_completed()
+    }
+
+    /// Returns `true` if an initialization routine is running right now.
+    ///
+    /// The routine may finish right after this returns so the returned value is inherently racy.
+    pub fn is_running(&self) -> bool {
+        self.once.is_running()
+    }
+
+    /// Returns `true` if the retry budget was exhausted and the last closure panicked.
+    pub fn is_poisoned(&self) -> bool {
+        self.once.is_poisoned()
+    }
+}
+
+impl fmt::Debug for RetryOnce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryOnce").field("state", &self.once.state()).finish()
+    }
+}
+
+impl Default for RetryOnce {
+    fn default() -> Self {
+        RetryOnce::new()
+    }
+}
+
+impl<P> Drop for Once<P> {
+    fn drop(&mut self) {
+        // Only poisoned instances can have a message or a location
+        if *self.0.value.get_mut() == POISONED {
+            let address = self as *const Self as usize;
+            #[cfg(feature = "poison-message")]
+            poison_message::remove(address);
+            poison_location::remove(address);
+        }
+    }
+}
+
+/// Guard representing running two-phase initialization started by [`Once::begin()`]
+///
+/// The initialization must be finished by calling [`complete()`](Self::complete) or
+/// [`abort()`](Self::abort). Dropping the guard poisons the [`Once`], just like panicking in
+/// [`Once::call_once()`] does. Either way, the waiting threads are woken up.
+#[must_use = "dropping the guard poisons the Once"]
+pub struct OnceInitGuard<'a>(PanicChecker<'a>);
+
+impl<'a> OnceInitGuard<'a> {
+    /// Marks the initialization as completed successfully.
+    pub fn complete(mut self) {
+        self.0.value_to_write = COMPLETE;
+    }
+
+    /// Gives up on the initialization leaving the [`Once`] incomplete.
+    ///
+    /// One of the waiting threads or a later caller gets to perform the initialization instead.
+    pub fn abort(mut self) {
+        self.0.value_to_write = INCOMPLETE;
+    }
+}
+
+mod spinning {
+    use core::sync::atomic::{AtomicU8, Ordering};
+    use crate::policy::SpinPolicy;
+
+    const UNKNOWN: u8 = 0;
+    const USEFUL: u8 = 1;
+    const USELESS: u8 = 2;
+
+    static SPINNING: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+    /// Returns `false` on single-CPU systems where spinning would just delay the initializer
+    pub(super) fn is_useful() -> bool {
+        match SPINNING.load(Ordering::Relaxed) {
+            USEFUL => true,
+            USELESS => false,
+            _ => {
+                // this is a syscall so it's cached; if it fails we don't know and spinning is cheap
+                let useful = std::thread::available_parallelism().map_or(true, |count| count.get() > 1);
+                SPINNING.store(if useful { USEFUL } else { USELESS }, Ordering::Relaxed);
+                useful
+            },
+        }
+    }
+
+    /// Waits a little bit before checking the state again
+    pub(super) fn relax(policy: &SpinPolicy) {
+        if policy.yield_between_spins {
+            std::thread::yield_now();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+thread_local! {
+    /// Addresses of `Once` instances whose closures are running on the current thread
+    ///
+    /// Closures may initialize other `Once` instances so this is a stack.
+    static RUNNING_HERE: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Keeps the address of the `Once` in `RUNNING_HERE` while the closure is running
+///
+/// Two-phase initialization is not tracked since it may finish on another thread.
+struct RecursionGuard(usize);
+
+impl RecursionGuard {
+    fn new<P: WaitPolicy>(once: &Once<P>) -> Self {
+        let address = once.address();
+        // Detection is best-effort, so if the thread is being destroyed we just skip it
+        let _ = RUNNING_HERE.try_with(|running| running.borrow_mut().push(address));
+        RecursionGuard(address)
+    }
+
+    fn is_running_here<P: WaitPolicy>(once: &Once<P>) -> bool {
+        let address = once.address();
+        RUNNING_HERE
+            .try_with(|running| running.borrow().contains(&address))
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for RecursionGuard {
+    fn drop(&mut self) {
+        let _ = RUNNING_HERE.try_with(|running| {
+            let mut running = running.borrow_mut();
+            if let Some(pos) = running.iter().rposition(|address| *address == self.0) {
+                running.remove(pos);
+            }
+        });
+    }
+}
+
+/// Aborts the process if dropped, used to turn panics into aborts without `catch_unwind`
+struct AbortOnPanic;
+
+impl Drop for AbortOnPanic {
+    fn drop(&mut self) {
+        std::process::abort();
+    }
+}
+
+// No need to over-complicate the checker as much as std does
+struct PanicChecker<'a> {
+    futex: &'a Futex<Private>,
+    value_to_write: i32,
+    /// Call that started the initialization, recorded when poisoning
+    location: &'static Location<'static>,
+    #[cfg(feature = "tracing")]
+    started: Instant,
+}
+
+impl<'a> Drop for PanicChecker<'a> {
+    fn drop(&mut self) {
+        // has to be visible to the threads that observe the poisoned state
+        if self.value_to_write == POISONED {
+            #[cfg(feature = "poison-message")]
+            poison_message::publish(self.futex as *const Futex<Private> as usize);
+            poison_location::record(self.futex as *const Futex<Private> as usize, self.location);
+        } else {
+            // a caught panic that resets the state instead of poisoning it
+            #[cfg(feature = "poison-message")]
+            if std::thread::panicking() {
+                poison_message::discard();
+            }
+        }
+        // Only make expensive syscall if there are threads waiting
+        let previous = self.futex.value.swap(self.value_to_write, Ordering::AcqRel);
+        #[cfg(feature = "tracing")]
+        {
+            let once = self.futex as *const Futex<Private> as usize;
+            let elapsed_ns = self.started.elapsed().as_nanos() as u64;
+            let waiters = previous == RUNNING_WAITING;
+            match self.value_to_write {
+                COMPLETE => trace_event!(once, elapsed_ns, waiters, "initialization completed"),
+                POISONED => trace_event!(once, elapsed_ns, waiters, "initialization poisoned"),
+                _ => trace_event!(once, elapsed_ns, waiters, "initialization aborted"),
+            }
+        }
+        if previous == RUNNING_WAITING {
+            futex::wake(&self.futex.value, i32::MAX);
+        }
+        // the state is published already so the hook can't prevent poisoning
+        if self.value_to_write == POISONED {
+            notify_poisoned(self.futex as *const Futex<Private> as usize, self.location);
+        }
+    }
+}
+
+/// Calls the poison hook for `Once` at `address` if it's set
+#[cold]
+fn notify_poisoned(address: usize, location: &'static Location<'static>) {
+    if let Some(hook) = poison_hook::get() {
+        #[cfg(feature = "poison-message")]
+        let message = poison_message::get(address);
+        #[cfg(not(feature = "poison-message"))]
+        let message = None::<String>;
+        hook(&PoisonInfo { name: crate::named::name_of(address), location: Some(location), message: message.as_deref() });
+    }
+}
+
+/// Records the location and calls the poison hook if dropped, used where the state is written
+/// without `PanicChecker`
+///
+/// Only dropped when unwinding so that the successful path doesn't touch the global table.
+struct NotifyPoisoned {
+    address: usize,
+    location: &'static Location<'static>,
+}
+
+impl Drop for NotifyPoisoned {
+    fn drop(&mut self) {
+        #[cfg(feature = "poison-message")]
+        poison_message::publish(self.address);
+        poison_location::record(self.address, self.location);
+        notify_poisoned(self.address, self.location);
+    }
+}
+
+impl Once {
+    /// Raw state of a `Once` that didn't run any initialization yet
+    ///
+    /// The value of this constant is stable within a major version, see
+    /// [the layout section](Self#layout).
+    pub const INCOMPLETE: i32 = INCOMPLETE;
+    /// Raw state of a `Once` that completed successfully
+    ///
+    /// The value of this constant is stable within a major version.
+    pub const COMPLETE: i32 = COMPLETE;
+    /// Raw state of a `Once` whose initialization panicked
+    ///
+    /// The value of this constant is stable within a major version.
+    pub const POISONED: i32 = POISONED;
+    /// Raw state of a `Once` whose initialization is running with no thread waiting
+    ///
+    /// The value of this constant is stable within a major version.
+    pub const RUNNING_NO_WAIT: i32 = RUNNING_NO_WAIT;
+    /// Raw state of a `Once` whose initialization is running with some threads waiting
+    ///
+    /// The value of this constant is stable within a major version.
+    pub const RUNNING_WAITING: i32 = RUNNING_WAITING;
+    /// Raw state of an incomplete `Once` with some threads waiting in [`wait()`](Self::wait)
+    ///
+    /// The value of this constant is stable within a major version.
+    pub const INCOMPLETE_WAITING: i32 = INCOMPLETE_WAITING;
+
+    /// Creates a new `Once` value.
+    pub const fn new() -> Self {
+        Once::from_state(INCOMPLETE)
+    }
+
+    /// Creates a new `Once` value that is already completed.
+    ///
+    /// This is useful when the initialization is known to have happened already, e.g. when
+    /// restoring state from a snapshot. No closure passed to this `Once` will ever run.
+    pub const fn new_completed() -> Self {
+        Once::from_state(COMPLETE)
+    }
+
+    /// Creates a `Once` from its raw state.
+    ///
+    /// See [the layout section](Self#layout) for the encoding. This is mainly useful to restore a
+    /// value obtained from [`into_raw()`](Self::into_raw). Passing a running state while no
+    /// initialization is running will make all calls block forever, which is safe but not useful.
+    pub const fn from_raw(state: i32) -> Self {
+        Once::from_state(state)
+    }
+}
+
+impl<P: WaitPolicy> Once<P> {
+    /// Creates a new `Once` value waiting according to the given policy.
+    ///
+    /// ```
+    /// use linux_once::{Once, policy::SpinOnly};
+    ///
+    /// static INIT: Once<SpinOnly> = Once::with_policy(SpinOnly);
+    /// # INIT.call_once(|| ());
+    /// ```
+    pub const fn with_policy(policy: P) -> Self {
+        // policies are just markers
+        core::mem::forget(policy);
+        Once::from_state(INCOMPLETE)
+    }
+
+    const fn from_state(state: i32) -> Self {
+        Once(Futex::new(state), PhantomData)
+    }
+
+    /// Returns a raw pointer to the underlying state.
+    ///
+    /// See [the layout section](Self#layout) for the encoding. The pointer is valid for as long as
+    /// the `Once` is and it must only be accessed atomically.
+    pub fn as_ptr(&self) -> *mut i32 {
+        self.0.value.as_ptr()
+    }
+
+    /// Loads the raw state with `Acquire` ordering.
+    ///
+    /// Comparing the result with [`Once::COMPLETE`] is equivalent to
+    /// [`is_completed()`](Self::is_completed) and the same synchronization guarantees apply. There
+    /// is intentionally no way to get the underlying `AtomicI32` since storing
+    /// [`Once::COMPLETE`] would bypass the contract of [`mark_completed()`](Self::mark_completed).
+    pub fn load_state(&self) -> i32 {
+        self.0.value.load(Ordering::Acquire)
+    }
+
+    /// Consumes the `Once` returning its raw state.
+    ///
+    /// See [the layout section](Self#layout) for the encoding.
+    pub fn into_raw(self) -> i32 {
+        let mut this = core::mem::ManuallyDrop::new(self);
+        // same thing Drop would do
+        if *this.0.value.get_mut() == POISONED {
+            #[cfg(feature = "poison-message")]
+            poison_message::remove(this.address());
+            poison_location::remove(this.address());
+        }
+        *this.0.value.get_mut()
+    }
+
+    /// Performs an initialization routine once and only once. The given closure will be executed if
+    /// this is the first time `call_once` has been called, and otherwise the routine will *not* be
+    /// invoked.
+    ///
+    /// This method will block the calling thread if another initialization routine is currently
+    /// running.
+    ///
+    /// When this function returns, it is guaranteed that some initialization has run and completed (it
+    /// may not be the closure specified). It is also guaranteed that any memory writes performed by the
+    /// executed closure can be reliably observed by other threads at this point (there is a
+    /// happens-before relation between the closure and code executing after the return).
+    ///
+    /// If the given closure recursively invokes call_once on the same [`Once`] instance the exact
+    /// behavior is not specified, allowed outcomes are a panic or a deadlock.
+    ///
+    /// Note specific to the Linux version: recursive calls currently panic, poisoning the `Once`.
+    /// This information is only intended to help debugging and must **not** be relied on.
+    #[track_caller]
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        // Fast path
+        // std calls is_completed() at this point, we store the state instead to reuse later and
+        // avoid repeating atomic operation
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_slow calls the closure at most once
+        self.call_once_slow(state, &mut || unsafe { f.take()() });
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) using exclusive access.
+    ///
+    /// Since no other thread can access the `Once` at the same time this doesn't need any atomic
+    /// operations or syscalls. This is useful during single-threaded setup, before the `Once` is
+    /// shared. Poisoning works the same way as with [`call_once()`](Self::call_once).
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned because an initialization closure has panicked, this
+    /// method will also panic.
+    #[track_caller]
+    pub fn call_once_mut<F: FnOnce()>(&mut self, f: F) {
+        let value = self.0.value.get_mut();
+        match *value {
+            COMPLETE => (),
+            // a waiter that timed out may leave INCOMPLETE_WAITING behind but it's gone already
+            INCOMPLETE | INCOMPLETE_WAITING => {
+                // nobody can observe the value before we finish, so setting it up front is enough
+                // to poison the Once if the closure panics
+                *value = POISONED;
+                let notify_poisoned = NotifyPoisoned { address: self.address(), location: Location::caller() };
+                #[cfg(feature = "poison-message")]
+                self.run_closure(f);
+                // recursion is impossible thanks to &mut so no need to track it
+                #[cfg(not(feature = "poison-message"))]
+                f();
+                core::mem::forget(notify_poisoned);
+                *self.0.value.get_mut() = COMPLETE;
+            },
+            // poisoned or a leaked OnceInitGuard, the shared path handles these
+            _ => self.call_once(f),
+        }
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) without being generic.
+    ///
+    /// `f` is called at most once even though it's `FnMut`. Since all calls of this method share
+    /// a single instance of the slow path this can reduce code size when there are many call sites
+    /// with different closures. The check for completion is still inlined so the common case
+    /// doesn't pay for dynamic dispatch.
+    #[inline]
+    #[track_caller]
+    pub fn call_once_dyn(&self, f: &mut dyn FnMut()) {
+        // Fast path, same as in call_once
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return;
+        }
+
+        self.call_once_slow(state, f);
+    }
+
+    /// Shared by `call_once` and `call_once_dyn` so that there's only one instance of the wrapper
+    #[cold]
+    #[track_caller]
+    fn call_once_slow(&self, state: i32, f: &mut dyn FnMut()) {
+        self.internal_call_once(state, false, &mut |_| {
+            f();
+            true
+        });
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) except ignores poisoning.
+    ///
+    /// Unlike [`call_once()`](Self::call_once), if this [`Once`] has been poisoned (i.e., a previous
+    /// call to [`call_once()`](Self::call_once) or [`call_once_force()`](Self::call_once_force)
+    /// caused a panic), calling [`call_once_force()`](Self::call_once_force) will still invoke the
+    /// closure `f` and will _not_ result in an immediate panic. If `f` panics, the [`Once`] will
+    /// remain in a poison state. If `f` does _not_ panic, the [`Once`] will no longer be in a poison
+    /// state and all future calls to [`call_once()`](Self::call_once) or
+    /// [`call_once_force()`](Self::call_once_force) will be no-ops.
+    ///
+    /// The closure `f` is yielded a [`OnceState`] structure which can be used to query the poison
+    /// status of the [`Once`].
+    #[track_caller]
+    pub fn call_once_force<F: FnOnce(&OnceState)>(&self, f: F) {
+        // Fast path, same as in call_once
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: internal_call_once calls the closure at most once
+        self.internal_call_once(state, true, &mut |once_state| {
+            unsafe { f.take()(once_state) };
+            true
+        });
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) but aborts the process
+    /// instead of unwinding.
+    ///
+    /// This is intended for contexts where unwinding is undefined behavior, such as callbacks
+    /// called from C code. If the closure panics the process is aborted right away, so the
+    /// [`Once`] never gets poisoned. The same applies to panics caused by misuse, such as calling
+    /// this on a poisoned [`Once`] or recursively. Other than that this behaves exactly like
+    /// [`call_once()`](Self::call_once), including waking up the waiting threads.
+    ///
+    /// The abort is triggered by a drop guard which is forgotten after the closure returns, so no
+    /// panic handling happens when the closure succeeds.
+    ///
+    /// # Signal safety
+    ///
+    /// The fast path is a single atomic load so checking an already completed [`Once`] from a
+    /// signal handler is fine. The slow path is not async-signal-safe though: blocking and the
+    /// diagnostics take locks and may allocate. Thus calling this from a signal handler is only
+    /// sound if the handler can't interrupt a thread inside some method of a [`Once`] and the
+    /// closure itself is async-signal-safe.
+    #[track_caller]
+    pub fn call_once_abort_on_panic<F: FnOnce()>(&self, f: F) {
+        // Fast path, same as in call_once
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return;
+        }
+
+        let abort_on_panic = AbortOnPanic;
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_slow calls the closure at most once
+        self.call_once_slow(state, &mut || unsafe { f.take()() });
+        core::mem::forget(abort_on_panic);
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) but never blocks in the
+    /// kernel.
+    ///
+    /// If another thread is running the initialization this spins until it finishes instead of
+    /// using the futex, regardless of the policy of this [`Once`]. The thread doesn't register
+    /// as a waiter either, so if no thread blocks the initializer doesn't issue the wake syscall.
+    /// The policy still decides whether the thread yields between checks, which is a syscall.
+    /// Other threads may wait for the same [`Once`] in any way.
+    ///
+    /// This is intended for threads that must never enter the kernel, such as realtime audio
+    /// threads. If the closure has to run it runs on the calling thread as usual, so it is up to
+    /// the caller to make sure it doesn't perform syscalls.
+    ///
+    /// Beware of priority inversion: if the spinning thread has higher priority than the
+    /// initializing thread and they share a CPU, the initializer may never get to run and the
+    /// spinning thread spins forever. This is especially likely with realtime scheduling
+    /// policies. Make sure the initialization happens before such threads start or that they
+    /// can't preempt the initializer.
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned because an initialization closure has panicked, this
+    /// method will also panic.
+    #[track_caller]
+    pub fn call_once_spin<F: FnOnce()>(&self, f: F) {
+        // Fast path, same as in call_once
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_spin_slow calls the closure at most once
+        self.call_once_spin_slow(state, &mut || unsafe { f.take()() });
+    }
+
+    #[cold]
+    #[track_caller]
+    fn call_once_spin_slow(&self, mut state: i32, f: &mut dyn FnMut()) {
+        trace_event!(once = self.address(), state, "entered slow path");
+        #[cfg(feature = "stats")]
+        crate::stats::record_slow_path();
+        let policy = P::POLICY.block(false);
+        loop {
+            match state {
+                COMPLETE => break,
+                POISONED => self.panic_poisoned(),
+                INCOMPLETE | INCOMPLETE_WAITING => match self.try_start(state) {
+                    Ok(mut panic_checker) => {
+                        self.run_closure(f);
+                        panic_checker.value_to_write = COMPLETE;
+                        break;
+                    },
+                    Err(old) => state = old,
+                },
+                _running => {
+                    self.check_recursion(state);
+                    state = self.spin_for_change(state, None, &policy);
+                },
+            }
+        }
+    }
+
+    /// Attempts to perform an initialization routine from a signal handler.
+    ///
+    /// Returns `true` if the [`Once`] is complete, either because the closure ran or because some
+    /// initialization has completed before. Returns `false` without waiting if another
+    /// initialization is running (possibly on the very thread the signal interrupted) or the
+    /// [`Once`] is poisoned.
+    ///
+    /// This never blocks nor unwinds: if the closure panics the process is aborted. Only atomic
+    /// operations and the futex wake syscall (to release the threads waiting in other methods)
+    /// are performed, all of which are async-signal-safe. None of the diagnostics (recursion
+    /// detection, poison messages and locations, `tracing` events) are involved.
+    ///
+    /// The closure has to be async-signal-safe too, so it must not allocate, take locks (including
+    /// the ones inside `println!` or the global allocator) or call non-reentrant functions. Storing
+    /// into atomics, pre-allocated buffers and calling functions listed in `signal-safety(7)` is
+    /// fine.
+    pub fn call_once_from_signal<F: FnOnce()>(&self, f: F) -> bool {
+        let mut state = self.0.value.load(Ordering::Acquire);
+        loop {
+            match state {
+                COMPLETE => break true,
+                INCOMPLETE | INCOMPLETE_WAITING => {
+                    // same as try_start but without the guard, which may record diagnostics
+                    let running = if state == INCOMPLETE_WAITING { RUNNING_WAITING } else { RUNNING_NO_WAIT };
+                    if let Err(old) = self.0.value.compare_exchange_weak(state, running, Ordering::Acquire, Ordering::Acquire) {
+                        state = old;
+                        continue;
+                    }
+                    let abort_on_panic = AbortOnPanic;
+                    f();
+                    core::mem::forget(abort_on_panic);
+                    if self.0.value.swap(COMPLETE, Ordering::AcqRel) == RUNNING_WAITING {
+                        futex::wake_from_signal(&self.0.value, i32::MAX);
+                    }
+                    break true;
+                },
+                // running or poisoned
+                _ => break false,
+            }
+        }
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) and reports whether the
+    /// closure was executed.
+    ///
+    /// Returns `true` only in the thread whose closure ran. This is useful to decide which thread
+    /// should perform actions tied to the initialization, such as logging or registering cleanup.
+    #[track_caller]
+    pub fn call_once_and_report<F: FnOnce()>(&self, f: F) -> bool {
+        // Fast path, same as in call_once
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return false;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: internal_call_once calls the closure at most once
+        self.internal_call_once(state, false, &mut |_| {
+            unsafe { f.take()() };
+            true
+        })
+    }
+
+    /// Performs a fallible initialization routine once and only once.
+    ///
+    /// This behaves like [`call_once()`](Self::call_once) except the closure may fail. If it
+    /// returns `Ok` the [`Once`] is completed. If it returns `Err` the [`Once`] stays incomplete,
+    /// the error is returned to the caller and a later call (possibly one that is waiting right
+    /// now) will run its own closure. If it panics the [`Once`] is poisoned as usual.
+    ///
+    /// Returns `Ok(())` without running the closure if some initialization has completed already.
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned because an initialization closure has panicked, this
+    /// method will also panic.
+    #[track_caller]
+    pub fn call_once_try<E, F: FnOnce() -> Result<(), E>>(&self, f: F) -> Result<(), E> {
+        // Fast path, same as in call_once
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return Ok(());
+        }
+
+        let mut f = OnceFn::new(f);
+        let mut result = Ok(());
+        // SAFETY: internal_call_once calls the closure at most once
+        self.internal_call_once(state, false, &mut |_| match unsafe { f.take()() } {
+            Ok(()) => true,
+            Err(error) => {
+                result = Err(error);
+                false
+            },
+        });
+        result
+    }
+
+    /// Same as [`call_once_try()`](Self::call_once_try) except a panic in `f` leaves the `Once`
+    /// incomplete instead of poisoning it, used by the `Retry` poisoning policy
+    #[track_caller]
+    pub(crate) fn call_once_try_retry<E, F: FnOnce() -> Result<(), E>>(&self, f: F) -> Result<(), E> {
+        // Fast path, same as in call_once
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return Ok(());
+        }
+
+        match self.start_or_wait(state, false) {
+            Some((mut panic_checker, _)) => {
+                // resetting to incomplete wakes the waiters so one of them can retry
+                panic_checker.value_to_write = INCOMPLETE;
+                let _recursion_guard = RecursionGuard::new(self);
+                let result = f();
+                if result.is_ok() {
+                    panic_checker.value_to_write = COMPLETE;
+                }
+                result
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Performs an initialization routine once and only once, returning an error if the [`Once`]
+    /// is poisoned.
+    ///
+    /// This behaves exactly like [`call_once()`](Self::call_once) except it returns
+    /// [`PoisonedError`] instead of panicking when it finds the [`Once`] poisoned, either right
+    /// away or after waiting for another thread whose closure panicked. The error contains the
+    /// location of the call that poisoned it if it was recorded. If the closure itself panics the
+    /// panic is propagated and the [`Once`] gets poisoned as usual.
+    #[track_caller]
+    pub fn call_once_checked<F: FnOnce()>(&self, f: F) -> Result<(), PoisonedError> {
+        // Fast path, same as in call_once
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return Ok(());
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_until calls the closure at most once
+        match self.call_once_until(state, None, &mut || unsafe { f.take()() }) {
+            CallOnceOutcome::Poisoned => Err(PoisonedError::new(poison_location::get(self.address()))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Attempts to perform an initialization routine without blocking.
+    ///
+    /// If the [`Once`] is incomplete the closure is executed just like with
+    /// [`call_once()`](Self::call_once). If another thread is running an initialization routine
+    /// at the moment this returns [`TryCallOnce::Busy`] immediately instead of waiting for it.
+    ///
+    /// Unlike [`call_once()`](Self::call_once) this doesn't panic if the [`Once`] is poisoned, it
+    /// returns [`TryCallOnce::Poisoned`] instead. However if the closure itself panics the panic
+    /// is propagated and the [`Once`] gets poisoned as usual.
+    #[track_caller]
+    pub fn try_call_once<F: FnOnce()>(&self, f: F) -> TryCallOnce {
+        let mut state = self.0.value.load(Ordering::Acquire);
+        loop {
+            match state {
+                COMPLETE => break TryCallOnce::AlreadyComplete,
+                POISONED => break TryCallOnce::Poisoned,
+                INCOMPLETE | INCOMPLETE_WAITING => {
+                    let mut panic_checker = match self.try_start(state) {
+                        Ok(panic_checker) => panic_checker,
+                        Err(old) => {
+                            state = old;
+                            continue;
+                        },
+                    };
+                    self.run_closure(f);
+                    panic_checker.value_to_write = COMPLETE;
+                    break TryCallOnce::Ran;
+                },
+                // Not even registering as a waiter, so no syscall
+                _running => break TryCallOnce::Busy,
+            }
+        }
+    }
+
+    /// Attempts to perform an initialization routine waiting at most `timeout` for other threads.
+    ///
+    /// If the [`Once`] is incomplete the closure is executed just like with
+    /// [`call_once()`](Self::call_once), without any timeout applied - the closure itself is never
+    /// interrupted. If another thread is running an initialization routine this waits for it for
+    /// at most `timeout` and returns [`CallOnceOutcome::TimedOut`] if it didn't finish in time. If
+    /// the other routine fails or panics within the timeout (and the [`Once`] is reset to
+    /// incomplete) this call may still get to run its closure.
+    ///
+    /// Unlike [`call_once()`](Self::call_once) this doesn't panic if the [`Once`] is poisoned, it
+    /// returns [`CallOnceOutcome::Poisoned`] instead. However if the closure itself panics the
+    /// panic is propagated and the [`Once`] gets poisoned as usual.
+    #[track_caller]
+    pub fn call_once_timeout<F: FnOnce()>(&self, f: F, timeout: Duration) -> CallOnceOutcome {
+        // Fast path, same as in call_once
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return CallOnceOutcome::AlreadyComplete;
+        }
+
+        let mut f = OnceFn::new(f);
+        // None is practically infinite
+        let deadline = Instant::now().checked_add(timeout);
+        // SAFETY: call_once_until calls the closure at most once
+        self.call_once_until(state, deadline, &mut || unsafe { f.take()() })
+    }
+
+    #[cold]
+    #[track_caller]
+    fn call_once_until(&self, mut state: i32, deadline: Option<Instant>, f: &mut dyn FnMut()) -> CallOnceOutcome {
+        trace_event!(once = self.address(), state, "entered slow path");
+        #[cfg(feature = "stats")]
+        crate::stats::record_slow_path();
+        loop {
+            match state {
+                COMPLETE => break CallOnceOutcome::AlreadyComplete,
+                POISONED => break CallOnceOutcome::Poisoned,
+                INCOMPLETE | INCOMPLETE_WAITING => match self.try_start(state) {
+                    Ok(mut panic_checker) => {
+                        self.run_closure(f);
+                        panic_checker.value_to_write = COMPLETE;
+                        break CallOnceOutcome::Ran;
+                    },
+                    Err(old) => state = old,
+                },
+                // Giving up leaves RUNNING_WAITING behind even if nobody else is waiting. That's
+                // fine since the initializer overwrites it when it finishes, it just costs it one
+                // unnecessary wake syscall. The remaining waiters are woken up as usual.
+                _running => match deadline {
+                    Some(deadline) if Instant::now() >= deadline => break CallOnceOutcome::TimedOut,
+                    _ => state = self.wait_for_change(state, deadline),
+                },
+            }
+        }
+    }
+
+    /// Performs an initialization routine once and only once, reporting stuck initializations.
+    ///
+    /// This behaves like [`call_once()`](Self::call_once) except that if another thread is
+    /// running an initialization routine and it didn't finish within `timeout`, `on_timeout` is
+    /// called once with diagnostic information and this keeps waiting. The handler can log the
+    /// problem or abort the process. The closure itself is never interrupted.
+    ///
+    /// The timeout is implemented by the timed futex wait so no threads are spawned. The handler
+    /// runs on the waiting thread and only if the initialization was observed still running after
+    /// the timeout, so it never runs after this thread noticed the completion.
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned because an initialization closure has panicked, this
+    /// method will also panic.
+    #[track_caller]
+    pub fn call_once_watched<F: FnOnce(), H: FnOnce(&SlowInit)>(&self, f: F, timeout: Duration, on_timeout: H) {
+        // Fast path, same as in call_once
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        let mut on_timeout = Some(on_timeout);
+        // SAFETY: call_once_watched_slow calls the closure at most once
+        self.call_once_watched_slow(
+            state,
+            timeout,
+            &mut || unsafe { f.take()() },
+            &mut |info| if let Some(on_timeout) = on_timeout.take() {
+                on_timeout(info)
+            },
+        )
+    }
+
+    #[cold]
+    #[track_caller]
+    fn call_once_watched_slow(&self, mut state: i32, timeout: Duration, f: &mut dyn FnMut(), on_timeout: &mut dyn FnMut(&SlowInit)) {
+        let start = Instant::now();
+        // None is practically infinite and after the handler was called we wait indefinitely
+        let mut deadline = start.checked_add(timeout);
+        loop {
+            match state {
+                COMPLETE => break,
+                POISONED => self.panic_poisoned(),
+                INCOMPLETE | INCOMPLETE_WAITING => match self.try_start(state) {
+                    Ok(mut panic_checker) => {
+                        self.run_closure(f);
+                        panic_checker.value_to_write = COMPLETE;
+                        break;
+                    },
+                    Err(old) => state = old,
+                },
+                // state is the value loaded after the deadline passed so the initialization is
+                // still running
+                _running => match deadline {
+                    Some(limit) if Instant::now() >= limit => {
+                        deadline = None;
+                        on_timeout(&SlowInit {
+                            waited: start.elapsed(),
+                            waiters: waiter_count::get(self.address()),
+                            name: None,
+                        });
+                        state = self.0.value.load(Ordering::Acquire);
+                    },
+                    _ => state = self.wait_for_change(state, deadline),
+                },
+            }
+        }
+    }
+
+    /// Attempts to become the thread running the initialization routine
+    ///
+    /// `state` is the last observed state and must be either incomplete or poisoned. The returned
+    /// guard writes `POISONED` unless told otherwise.
+    #[track_caller]
+    fn try_start(&self, state: i32) -> Result<PanicChecker<'_>, i32> {
+        // threads blocked in wait() must get woken up when we finish
+        let running = if state == INCOMPLETE_WAITING { RUNNING_WAITING } else { RUNNING_NO_WAIT };
+        // same thing std does
+        // except we use weak, which seems a bit better
+        self.0.value.compare_exchange_weak(state, running, Ordering::Acquire, Ordering::Acquire)?;
+        // we do it a bit simpler
+        Ok(PanicChecker {
+            futex: &self.0,
+            value_to_write: POISONED,
+            location: Location::caller(),
+            #[cfg(feature = "tracing")]
+            started: Instant::now(),
+        })
+    }
+
+    /// Performs an initialization routine once and only once, catching panics.
+    ///
+    /// This behaves like [`call_once()`](Self::call_once) except that if the closure panics the
+    /// panic payload is returned to the caller instead of poisoning the [`Once`]. The [`Once`]
+    /// stays incomplete in such case so a later call (possibly one that is waiting right now) will
+    /// run its own closure.
+    ///
+    /// Since the [`Once`] isn't poisoned other threads may observe whatever state the closure left
+    /// behind when it panicked. It's up to the caller to make sure this is not a problem, which is
+    /// why the closure is not required to be [`UnwindSafe`].
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned because an initialization closure passed to another
+    /// method has panicked, this method will also panic.
+    #[track_caller]
+    pub fn call_once_catch<F: FnOnce()>(&self, f: F) -> Result<(), Box<dyn Any + Send>> {
+        // unwinding stops before reaching PanicChecker so the Once is just reset to incomplete
+        self.call_once_try(|| panic::catch_unwind(panic::AssertUnwindSafe(f)))
+    }
+
+    /// `f` returns `false` if the initialization failed and the `Once` should stay incomplete.
+    ///
+    /// Returns `true` if `f` was called.
+    #[cold]
+    #[track_caller]
+    fn internal_call_once(&self, state: i32, ignore_poisoning: bool, f: &mut dyn FnMut(&OnceState) -> bool) -> bool {
+        match self.start_or_wait(state, ignore_poisoning) {
+            Some((mut panic_checker, poisoned)) => {
+                // waking the waiters when resetting to incomplete allows them to try
+                // running their own closures
+                let once_state = OnceState::new(poisoned);
+                let succeeded = self.run_closure(|| f(&once_state));
+                panic_checker.value_to_write = if once_state.is_poison_requested() {
+                    POISONED
+                } else if succeeded {
+                    COMPLETE
+                } else {
+                    INCOMPLETE
+                };
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Slow path of `RetryOnce::call_once`
+    #[cold]
+    #[track_caller]
+    fn call_once_retry_slow(&self, state: i32, budget: &AtomicU32, f: &mut dyn FnMut()) {
+        if let Some((mut panic_checker, _)) = self.start_or_wait(state, false) {
+            // ordered by the state transitions since only the initializing thread accesses it
+            match budget.load(Ordering::Relaxed) {
+                // the last attempt, panicking poisons the Once just like in call_once
+                0 => self.run_closure(f),
+                remaining => {
+                    // the budget doesn't matter after a success so it can be consumed up front
+                    if remaining != UNLIMITED_RETRIES {
+                        budget.store(remaining - 1, Ordering::Relaxed);
+                    }
+                    // resetting to incomplete wakes the waiters so one of them can retry
+                    panic_checker.value_to_write = INCOMPLETE;
+                    // not using run_closure because the panic message is not needed
+                    let _recursion_guard = RecursionGuard::new(self);
+                    f();
+                },
+            }
+            panic_checker.value_to_write = COMPLETE;
+        }
+    }
+
+    /// Runs the initialization closure while tracking it for diagnostic purposes
+    fn run_closure<R>(&self, f: impl FnOnce() -> R) -> R {
+        let _recursion_guard = RecursionGuard::new(self);
+        #[cfg(feature = "poison-message")]
+        {
+            // the payload is not available during unwinding so we have to catch it
+            match panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+                Ok(result) => result,
+                Err(payload) => {
+                    poison_message::catch(&*payload);
+                    // the panic checker in the caller writes POISONED and publishes the message
+                    panic::resume_unwind(payload)
+                },
+            }
+        }
+        #[cfg(not(feature = "poison-message"))]
+        f()
+    }
+
+    /// Panics because the `Once` is poisoned, including the location of the call that poisoned it
+    /// and the original message if available
+    #[cold]
+    pub(crate) fn panic_poisoned(&self) -> ! {
+        let location = poison_location::get(self.address());
+        #[cfg(feature = "poison-message")]
+        {
+            if let Some(message) = poison_message::get(self.address()) {
+                match location {
+                    Some(location) => panic!("Once instance has previously been poisoned by a panic in call at {}: {}", location, message),
+                    None => panic!("Once instance has previously been poisoned by a panic: {}", message),
+                }
+            }
+        }
+        match location {
+            Some(location) => panic!("Once instance has previously been poisoned by call at {}", location),
+            None => panic!("Once instance has previously been poisoned"),
+        }
+    }
+
+    fn address(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Waits until the current thread either becomes the initializer or the `Once` completes.
+    ///
+    /// Returns the guard along with the information whether the `Once` was poisoned before, or
+    /// `None` if it completed.
+    ///
+    /// This is the whole handoff between the initializer and the waiters. It relies on these
+    /// invariants (modeled in `tests/loom.rs`):
+    ///
+    /// * Every decision is made on the value returned by the last atomic operation, there are no
+    ///   assumptions about what the state might be.
+    /// * A waiter blocks only on the exact value it last observed (or has just written), which
+    ///   is `*_WAITING`. The kernel checks the value atomically with going to sleep, so if the
+    ///   initializer writes its final state in between the syscall returns immediately.
+    /// * The initializer swaps in the final state and issues the wake if the previous value was
+    ///   `RUNNING_WAITING`. Since registering as a waiter is a CAS on the same atomic, either the
+    ///   registration comes first and the initializer sees it, or the CAS fails and the waiter
+    ///   sees the final state without blocking.
+    /// * After every wake up, spurious or not, the state is dispatched through the whole match
+    ///   again, so poisoning, aborts and forced calls are all handled.
+    #[cold]
+    #[track_caller]
+    fn start_or_wait(&self, mut state: i32, ignore_poisoning: bool) -> Option<(PanicChecker<'_>, bool)> {
+        trace_event!(once = self.address(), state, "entered slow path");
+        #[cfg(feature = "stats")]
+        crate::stats::record_slow_path();
+        loop {
+            match state {
+                POISONED if !ignore_poisoning => self.panic_poisoned(),
+                INCOMPLETE | INCOMPLETE_WAITING | POISONED => match self.try_start(state) {
+                    Ok(panic_checker) => {
+                        // the message and location are going to be either stale or replaced
+                        if state == POISONED {
+                            #[cfg(feature = "poison-message")]
+                            poison_message::remove(self.address());
+                            poison_location::remove(self.address());
+                        }
+                        break Some((panic_checker, state == POISONED))
+                    },
+                    Err(old) => state = old,
+                },
+                COMPLETE => break None,
+                // The closure could've panicked or a forced call could've started running
+                // in the meantime so we have to go through the whole state machine again.
+                _running => state = self.wait_for_change(state, None),
+            }
+        }
+    }
+
+    /// Starts a two-phase initialization.
+    ///
+    /// Returns `Some` if the calling thread won the right to perform the initialization. The
+    /// initialization is then considered running until the returned guard is consumed by
+    /// [`complete()`](OnceInitGuard::complete) or [`abort()`](OnceInitGuard::abort), or dropped,
+    /// which poisons the `Once`. This allows initialization to finish in a different stack frame
+    /// or even a different thread, e.g. from an FFI callback.
+    ///
+    /// Returns `None` if some initialization has completed already. If another thread is
+    /// initializing the `Once` this blocks just like [`call_once()`](Self::call_once) and then
+    /// returns `None` if it completed or tries again if it was aborted.
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned, this method will also panic.
+    #[track_caller]
+    pub fn begin(&self) -> Option<OnceInitGuard<'_>> {
+        // Fast path, same as in call_once
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return None;
+        }
+
+        self.start_or_wait(state, false).map(|(panic_checker, _)| OnceInitGuard(panic_checker))
+    }
+
+    /// Blocks the current thread until initialization has completed.
+    ///
+    /// Unlike [`call_once()`](Self::call_once) this never runs any initialization routine, it only
+    /// waits for another thread to run it. So if no thread ever calls
+    /// [`call_once()`](Self::call_once) (or [`call_once_force()`](Self::call_once_force)) this
+    /// blocks forever.
+    ///
+    /// When this function returns, the same happens-before guarantees as with
+    /// [`call_once()`](Self::call_once) apply.
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned because an initialization closure has panicked, this
+    /// method will also panic.
+    pub fn wait(&self) {
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return;
+        }
+
+        self.internal_wait(state, false)
+    }
+
+    /// Blocks the current thread until initialization has completed, ignoring poisoning.
+    ///
+    /// Unlike [`wait()`](Self::wait) this returns normally even if the initialization closure
+    /// panicked. Use [`is_completed()`](Self::is_completed) or [`is_poisoned()`](Self::is_poisoned)
+    /// to find out which one happened. Note that a forced call may be running already by the time
+    /// this returns.
+    pub fn wait_force(&self) {
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE || state == POISONED {
+            return;
+        }
+
+        self.internal_wait(state, true)
+    }
+
+    /// Blocks the current thread until initialization has completed or the timeout expires.
+    ///
+    /// Returns `true` if the initialization has completed and `false` if the timeout expired
+    /// first. Zero timeout just checks the state without blocking. Other than that this behaves
+    /// the same as [`wait()`](Self::wait).
+    ///
+    /// The timeout is measured from the moment of the call, so spurious wake ups (e.g. caused by
+    /// signals) don't prolong it. See [`wait_deadline()`](Self::wait_deadline) too.
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned because an initialization closure has panicked, this
+    /// method will also panic.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return true;
+        }
+
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.internal_wait_until(state, deadline),
+            // practically infinite
+            None => {
+                self.internal_wait(state, false);
+                true
+            },
+        }
+    }
+
+    /// Blocks the current thread until initialization has completed or the deadline passes.
+    ///
+    /// Returns `true` if the initialization has completed and `false` if the deadline passed
+    /// first. A deadline in the past just checks the state without blocking. Other than that this
+    /// behaves the same as [`wait()`](Self::wait).
+    ///
+    /// The deadline is handed over to the kernel directly so repeated waiting (e.g. caused by
+    /// signals) doesn't lose precision.
+    ///
+    /// # Clock
+    ///
+    /// The deadline is measured using `CLOCK_MONOTONIC`, the same clock [`Instant`] uses, and the
+    /// kernel interprets it as an absolute time of that clock (`FUTEX_WAIT_BITSET`). So changes of
+    /// the wall clock don't shorten nor prolong waiting. All timed methods of this crate, including
+    /// the ones taking a timeout, behave this way.
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned because an initialization closure has panicked, this
+    /// method will also panic.
+    pub fn wait_deadline(&self, deadline: Instant) -> bool {
+        let state = self.0.value.load(Ordering::Acquire);
+        if state == COMPLETE {
+            return true;
+        }
+
+        self.internal_wait_until(state, deadline)
+    }
+
+    #[cold]
+    fn internal_wait(&self, mut state: i32, ignore_poisoning: bool) {
+        loop {
+            match state {
+                COMPLETE => break,
+                POISONED if ignore_poisoning => break,
+                POISONED => self.panic_poisoned(),
+                _ => state = self.wait_for_change(state, None),
+            }
+        }
+    }
+
+    #[cold]
+    fn internal_wait_until(&self, mut state: i32, deadline: Instant) -> bool {
+        loop {
+            match state {
+                COMPLETE => break true,
+                POISONED => self.panic_poisoned(),
+                // the state is checked before the deadline so that completion racing with the
+                // deadline is reported as completion
+                _ if Instant::now() >= deadline => break false,
+                _ => state = self.wait_for_change(state, Some(deadline)),
+            }
+        }
+    }
+
+    /// Registers the current thread as a waiter and blocks until the state changes.
+    ///
+    /// `state` is the last observed state and must be neither complete nor poisoned. Returns the
+    /// newly observed state which may be the same one if the wake up was spurious or the deadline
+    /// passed.
+    fn wait_for_change(&self, state: i32, deadline: Option<Instant>) -> i32 {
+        let policy = P::POLICY;
+
+        self.check_recursion(state);
+
+        if !policy.block {
+            return self.spin_for_change(state, deadline, &policy);
+        }
+
+        // Short initializers finish sooner than the syscalls take so spin a bit first. If some
+        // thread already decided to block the initializer is likely long-running, so don't.
+        if state == RUNNING_NO_WAIT && policy.max_spins > 0 && spinning::is_useful() {
+            for _ in 0..policy.max_spins {
+                spinning::relax(&policy);
+                let current = self.0.value.load(Ordering::Acquire);
+                if current != state {
+                    return current;
+                }
+            }
+        }
+
+        let waiting = match self.register_waiter(state) {
+            Ok(waiting) => waiting,
+            // reuse expensive load
+            Err(old) => return old,
+        };
+
+        // actual waiting logic
+        let _waiter = waiter_count::Waiter::new(self.address());
+        trace_event!(once = self.address(), state = waiting, "blocking");
+        // We need to check the value regardless, so the result is not interesting
+        futex::wait_until(&self.0.value, waiting, deadline);
+        let state = self.0.value.load(Ordering::Acquire);
+        trace_event!(once = self.address(), state, "woken");
+        state
+    }
+
+    /// Panics if `state` is running and the initialization routine runs on this thread
+    fn check_recursion(&self, state: i32) {
+        // Waiting for ourselves would deadlock
+        if (state == RUNNING_NO_WAIT || state == RUNNING_WAITING) && RecursionGuard::is_running_here(self) {
+            panic!("Once instance has been used recursively from its own initialization routine");
+        }
+    }
+
+    /// Signals that there's at least one thread waiting
+    ///
+    /// Returns the state to wait on or the current state if it changed in the meantime.
+    fn register_waiter(&self, state: i32) -> Result<i32, i32> {
+        // we have two versions of running (and incomplete) to optimize a bit
+        let waiting = match state {
+            INCOMPLETE => INCOMPLETE_WAITING,
+            RUNNING_NO_WAIT => RUNNING_WAITING,
+            already_waiting => already_waiting,
+        };
+
+        if waiting != state {
+            self.0.value.compare_exchange(state, waiting, Ordering::AcqRel, Ordering::Acquire)?;
+        }
+        Ok(waiting)
+    }
+
+    /// Performs a single attempt to wait for the initialization to complete, reporting errors.
+    ///
+    /// Unlike [`wait_deadline()`](Self::wait_deadline) this doesn't retry. It blocks at most once,
+    /// without spinning, and reports why it returned if the initialization didn't complete. This
+    /// is intended for building custom waiting loops, e.g. ones that react to signals or collect
+    /// metrics. Returns `Ok(())` if the initialization has completed.
+    ///
+    /// The waiting policy is ignored and the syscall is always attempted so errors such as `EPERM`
+    /// are reported as [`WaitError::Os`] rather than handled by polling.
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned because an initialization closure has panicked, this
+    /// method will also panic. It also panics if called from the initialization routine of the
+    /// same `Once`.
+    pub fn try_wait_raw(&self, deadline: Option<Instant>) -> Result<(), WaitError> {
+        let state = self.0.value.load(Ordering::Acquire);
+        match state {
+            COMPLETE => return Ok(()),
+            POISONED => self.panic_poisoned(),
+            _ => (),
+        }
+
+        self.check_recursion(state);
+        let waiting = self.register_waiter(state).map_err(|_| WaitError::ValueChanged)?;
+        {
+            let _waiter = waiter_count::Waiter::new(self.address());
+            futex::wait_raw(&self.0.value, waiting, deadline)?;
+        }
+        match self.0.value.load(Ordering::Acquire) {
+            COMPLETE => Ok(()),
+            POISONED => self.panic_poisoned(),
+            // woken up by a failed initialization or spuriously
+            _ => Err(WaitError::ValueChanged),
+        }
+    }
+
+    /// Spins until the state changes or the deadline passes, never blocking
+    ///
+    /// Doesn't register as a waiter so the initializer doesn't have to issue a wake syscall.
+    fn spin_for_change(&self, state: i32, deadline: Option<Instant>, policy: &SpinPolicy) -> i32 {
+        loop {
+            spinning::relax(policy);
+            let current = self.0.value.load(Ordering::Acquire);
+            if current != state {
+                break current;
+            }
+            // Instant::now() is implemented using vDSO so this doesn't enter the kernel
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break current;
+            }
+        }
+    }
+
+    /// Returns the number of threads blocked waiting for this `Once`.
+    ///
+    /// This includes threads blocked in [`call_once()`](Self::call_once) as well as in
+    /// [`wait()`](Self::wait) and similar methods. The count is approximate: threads are counted
+    /// shortly before they block and shortly after they wake up. It drops to zero once the
+    /// initialization completes and all waiters are released. If threads are blocked on more than
+    /// 64 instances at the same time some of them may not be counted. This is intended for
+    /// diagnostics, e.g. reporting stalled initialization from a watchdog.
+    pub fn waiters(&self) -> usize {
+        waiter_count::get(self.address())
+    }
+
+    /// Returns `true` if some [`call_once()`](Self::call_once) call has completed successfully. Specifically, is_completed
+    /// will return false in the following situations:
+    ///
+    /// * [`call_once()`](Self::call_once) was not called at all,
+    /// * [`call_once()`](Self::call_once) was called, but has not yet completed,
+    /// * the [`Once`] instance is poisoned
+    ///
+    /// This function returning `false` does not mean that [`Once`] has not been executed. For example, it
+    /// may have been executed in the time between when `is_completed` starts executing and when it returns,
+    /// in which case the `false` return value would be stale (but still permissible).
+    pub fn is_completed(&self) -> bool {
+        self.0.value.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Performs the same function as [`is_completed()`](Self::is_completed) without
+    /// synchronizing with the initialization.
+    ///
+    /// The load uses `Relaxed` ordering so there's no happens-before relation between the
+    /// initialization routine and the code after this returns `true`. That's fine if the `Once`
+    /// guards a side effect (e.g. registering a callback in a C library) rather than data. To
+    /// access the data issue an `Acquire` fence after observing `true`, which establishes the same
+    /// relation as [`is_completed()`](Self::is_completed) would:
+    ///
+    /// ```
+    /// use core::sync::atomic::{fence, Ordering};
+    /// # let once = linux_once::Once::new();
+    /// # once.call_once(|| ());
+    ///
+    /// if once.is_completed_relaxed() {
+    ///     fence(Ordering::Acquire);
+    ///     // data written by the initialization routine can be accessed here
+    /// }
+    /// ```
+    ///
+    /// This is only useful on weakly-ordered architectures such as aarch64, on x86 the loads are
+    /// the same.
+    pub fn is_completed_relaxed(&self) -> bool {
+        self.0.value.load(Ordering::Relaxed) == COMPLETE
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) except the check for
+    /// completion is `Relaxed`.
+    ///
+    /// If the `Once` is observed complete right away this returns without establishing a
+    /// happens-before relation with the initialization routine, see
+    /// [`is_completed_relaxed()`](Self::is_completed_relaxed). Otherwise it behaves exactly like
+    /// [`call_once()`](Self::call_once). Use this only if the `Once` guards a side effect, or
+    /// issue an `Acquire` fence after it returns.
+    #[track_caller]
+    pub fn call_once_relaxed_check<F: FnOnce()>(&self, f: F) {
+        let state = self.0.value.load(Ordering::Relaxed);
+        if state == COMPLETE {
+            return;
+        }
+
+        // the slow path doesn't rely on the ordering of the first load, it only uses it as a hint
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_slow calls the closure at most once
+        self.call_once_slow(state, &mut || unsafe { f.take()() });
+    }
+
+    /// Poisons an incomplete [`Once`] without running any closure.
+    ///
+    /// This is intended for testing recovery paths, which would otherwise require panicking in a
+    /// closure. Threads blocked in [`wait()`](Self::wait) are woken up and observe the poisoned
+    /// state.
+    ///
+    /// If the [`Once`] is not incomplete (some closure is running, has completed or the `Once` is
+    /// already poisoned) this does nothing. Thus if this races with [`call_once()`](Self::call_once)
+    /// either the closure doesn't run and `call_once` panics or the closure runs and the `Once`
+    /// doesn't get poisoned.
+    ///
+    /// Only available with the `test-util` feature.
+    #[cfg(any(test, feature = "test-util"))]
+    #[track_caller]
+    pub fn poison(&self) {
+        let mut state = self.0.value.load(Ordering::Acquire);
+        while state == INCOMPLETE || state == INCOMPLETE_WAITING {
+            match self.0.value.compare_exchange_weak(state, POISONED, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => {
+                    // there's no panic, so no message, but a stale one could be stored
+                    #[cfg(feature = "poison-message")]
+                    poison_message::remove(self.address());
+                    poison_location::record(self.address(), Location::caller());
+                    if state == INCOMPLETE_WAITING {
+                        futex::wake(&self.0.value, i32::MAX);
+                    }
+                    notify_poisoned(self.address(), Location::caller());
+                    break;
+                },
+                Err(old) => state = old,
+            }
+        }
+    }
+
+    /// Clears the poisoned state so that the next call runs its closure again.
+    ///
+    /// This is useful when the cause of the panic was fixed and the initialization can be retried.
+    /// If the [`Once`] is not poisoned this does nothing, in particular it never makes a completed
+    /// `Once` incomplete again.
+    ///
+    /// Calls that observed the poisoned state before it was cleared still panic (or report it)
+    /// even if they didn't return yet. Calls that observe the state afterwards behave as if the
+    /// `Once` was never poisoned.
+    pub fn clear_poison(&self) {
+        // nobody can be waiting on a poisoned Once, so no need to wake anyone
+        if self.0.value.compare_exchange(POISONED, INCOMPLETE, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+            #[cfg(feature = "poison-message")]
+            poison_message::remove(self.address());
+            poison_location::remove(self.address());
+        }
+    }
+
+    /// Marks the [`Once`] as completed without running any closure.
+    ///
+    /// This is useful when the initialization was performed externally, e.g. by C code calling
+    /// the initialization function of a library before Rust code started. Waiting threads are
+    /// woken up and all subsequent calls behave as if a closure has completed successfully.
+    ///
+    /// If an initialization routine is running this blocks until it finishes (in which case it's
+    /// the one that counts). If the [`Once`] is poisoned it gets completed, just like with
+    /// [`call_once_force()`](Self::call_once_force). Either way the `Once` is completed when this
+    /// returns.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the initialization guarded by this `Once` has really been
+    /// performed and that all its memory effects happen-before this call. Code relying on the
+    /// `Once` (possibly including unsafe code) would observe uninitialized state otherwise.
+    pub unsafe fn mark_completed(&self) {
+        // empty forced call gives us all the properties we need, including waking the waiters
+        self.call_once_force(|_| ())
+    }
+
+    /// Initializes `slot` with the value returned by `init` once and only once, returning the
+    /// reference to the stored value.
+    ///
+    /// This is the pattern [`OnceCell`](crate::OnceCell) is built on, exposed for containers that
+    /// keep their storage elsewhere, e.g. in an arena. `init` runs under the same rules as
+    /// the closure passed to [`call_once()`](Self::call_once): only the first caller runs it, the
+    /// concurrent callers block until it finishes and the write to `slot` happens-before the
+    /// returned reference is obtained by any thread. If `init` panics the `Once` is poisoned and
+    /// `slot` stays uninitialized.
+    ///
+    /// ```
+    /// use linux_once::Once;
+    /// use core::cell::UnsafeCell;
+    /// use core::mem::MaybeUninit;
+    ///
+    /// struct Slot {
+    ///     once: Once,
+    ///     value: UnsafeCell<MaybeUninit<String>>,
+    /// }
+    ///
+    /// // SAFETY: the value is only accessed through the Once
+    /// unsafe impl Sync for Slot {}
+    ///
+    /// static NAME: Slot = Slot { once: Once::new(), value: UnsafeCell::new(MaybeUninit::uninit()) };
+    ///
+    /// // SAFETY: NAME.value is only ever initialized through NAME.once and never written otherwise
+    /// let name = unsafe { NAME.once.call_once_init(&NAME.value, || String::from("linux_once")) };
+    /// assert_eq!(name, "linux_once");
+    /// ```
+    ///
+    /// The value is never dropped by the `Once`, the owner of `slot` has to drop it if the `Once`
+    /// is complete.
+    ///
+    /// # Panics
+    ///
+    /// If this [`Once`] has been poisoned because an initialization closure has panicked, this
+    /// method will also panic.
+    ///
+    /// # Safety
+    ///
+    /// The `Once` must guard exactly this `slot`:
+    ///
+    /// * `slot` must be initialized whenever the `Once` is complete. Thus the `Once` must not be
+    ///   completed in any other way than by this method with this `slot`, e.g. by
+    ///   [`call_once()`](Self::call_once), [`mark_completed()`](Self::mark_completed), a forced call
+    ///   recovering it from poisoning or by being created completed, unless `slot` was initialized
+    ///   before.
+    /// * Nobody may access `slot` while the `Once` is not complete, except this method.
+    /// * Once the `Once` is complete `slot` may only be read until the lifetime `'a` ends. In
+    ///   particular it must not be written, moved out of or dropped while any returned reference
+    ///   is alive.
+    #[track_caller]
+    pub unsafe fn call_once_init<'a, T, F: FnOnce() -> T>(&self, slot: &'a UnsafeCell<MaybeUninit<T>>, init: F) -> &'a T {
+        self.call_once(|| {
+            let value = init();
+            // SAFETY: only the thread running the initialization gets here and the caller
+            // guarantees nobody else accesses the slot before the Once completes
+            unsafe { (*slot.get()).write(value); }
+        });
+        // SAFETY: call_once returns only after the Once completed and the caller guarantees the
+        // slot is initialized in that case and not written anymore
+        unsafe { (*slot.get()).assume_init_ref() }
+    }
+
+    /// Returns a snapshot of the current state.
+    ///
+    /// The state may change right after it was observed so this is mainly useful for debugging.
+    pub fn state(&self) -> OnceStatus {
+        match self.0.value.load(Ordering::Acquire) {
+            INCOMPLETE | INCOMPLETE_WAITING => OnceStatus::Incomplete,
+            COMPLETE => OnceStatus::Complete,
+            POISONED => OnceStatus::Poisoned,
+            _running => OnceStatus::Running,
+        }
+    }
+
+    /// Returns `true` if an initialization routine is running right now.
+    ///
+    /// This is just a single load without any syscall, so it's cheap enough to e.g. display
+    /// progress. However the routine may finish right after this returns so the returned value is
+    /// inherently racy.
+    pub fn is_running(&self) -> bool {
+        let state = self.0.value.load(Ordering::Acquire);
+        state == RUNNING_NO_WAIT || state == RUNNING_WAITING
+    }
+
+    /// Returns `true` if a closure passed to [`call_once()`](Self::call_once) or
+    /// [`call_once_force()`](Self::call_once_force) panicked and no subsequent
+    /// [`call_once_force()`](Self::call_once_force) call has completed successfully.
+    ///
+    /// Just like with [`is_completed()`](Self::is_completed), the returned value may be stale by
+    /// the time it's observed, e.g. because a forced call recovered the [`Once`] in the meantime.
+    pub fn is_poisoned(&self) -> bool {
+        self.0.value.load(Ordering::Acquire) == POISONED
+    }
+}
@@ -0,0 +1,170 @@
+//! `Once` with a name attached for diagnostics
+//!
+//! The name is not stored inside `Once` so that it stays as small as an `i32`. The wrapper passes
+//! it to the diagnostics at the points where they are produced instead.
+
+use core::cell::Cell;
+use core::fmt;
+use core::ops::Deref;
+use std::time::Duration;
+use crate::{Once, OnceState, SlowInit};
+use crate::policy::{DefaultWait, WaitPolicy};
+
+/// A [`Once`] with a static name used in diagnostics.
+///
+/// The name is included in the [`Debug`](fmt::Debug) output, in the [`SlowInit`] passed to the
+/// handler of [`call_once_watched()`](Self::call_once_watched) and, with the `tracing` feature, as
+/// the `name` field of a `once` span entered around the slow paths of the methods of this type. So
+/// the events emitted while waiting for or running the initialization identify the instance.
+///
+/// The name is also passed to the [poison hook](crate::set_poison_hook) if the `Once` gets
+/// poisoned by one of the methods of this type.
+///
+/// All other methods of [`Once`] are available through [`Deref`], they just don't enter the span
+/// nor report the name to the poison hook.
+///
+/// ```
+/// use linux_once::NamedOnce;
+///
+/// static CONFIG: NamedOnce = NamedOnce::new("config");
+///
+/// CONFIG.call_once(|| ());
+/// assert_eq!(format!("{:?}", CONFIG), "NamedOnce { name: \"config\", state: Complete }");
+/// ```
+pub struct NamedOnce<P = DefaultWait> {
+    once: Once<P>,
+    name: &'static str,
+}
+
+impl NamedOnce {
+    /// Creates a new `NamedOnce` value.
+    pub const fn new(name: &'static str) -> Self {
+        NamedOnce { once: Once::new(), name }
+    }
+}
+
+impl<P: WaitPolicy> NamedOnce<P> {
+    /// Creates a new `NamedOnce` value waiting according to the given policy.
+    pub const fn with_policy(name: &'static str, policy: P) -> Self {
+        NamedOnce { once: Once::with_policy(policy), name }
+    }
+
+    /// Returns the name of this `NamedOnce`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Performs an initialization routine once and only once.
+    ///
+    /// See [`Once::call_once()`].
+    #[track_caller]
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        // the span is only entered on the slow path
+        if self.once.is_completed() {
+            return;
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+        let _name = NameGuard::new(self.address(), self.name);
+        self.once.call_once(f)
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) except ignores poisoning.
+    ///
+    /// See [`Once::call_once_force()`].
+    #[track_caller]
+    pub fn call_once_force<F: FnOnce(&OnceState)>(&self, f: F) {
+        if self.once.is_completed() {
+            return;
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+        let _name = NameGuard::new(self.address(), self.name);
+        self.once.call_once_force(f)
+    }
+
+    /// Performs an initialization routine once and only once, reporting stuck initializations.
+    ///
+    /// See [`Once::call_once_watched()`]. The [`SlowInit`] passed to `on_timeout` contains the
+    /// name.
+    #[track_caller]
+    pub fn call_once_watched<F: FnOnce(), H: FnOnce(&SlowInit)>(&self, f: F, timeout: Duration, on_timeout: H) {
+        if self.once.is_completed() {
+            return;
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+        let _name = NameGuard::new(self.address(), self.name);
+        self.once.call_once_watched(f, timeout, |info| on_timeout(&SlowInit { name: Some(self.name), ..*info }))
+    }
+
+    /// Blocks the current thread until initialization has completed.
+    ///
+    /// See [`Once::wait()`].
+    pub fn wait(&self) {
+        if self.once.is_completed() {
+            return;
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+        self.once.wait()
+    }
+
+    fn address(&self) -> usize {
+        &self.once as *const Once<P> as usize
+    }
+
+    #[cfg(feature = "tracing")]
+    fn enter_span(&self) -> tracing::span::EnteredSpan {
+        tracing::debug_span!(target: "linux_once", "once", name = self.name).entered()
+    }
+}
+
+thread_local! {
+    /// Address and name of the `NamedOnce` whose slow path is running on this thread
+    static CURRENT: Cell<Option<(usize, &'static str)>> = const { Cell::new(None) };
+}
+
+/// Makes the name available to the poison hook while the slow path runs
+struct NameGuard(Option<(usize, &'static str)>);
+
+impl NameGuard {
+    fn new(address: usize, name: &'static str) -> Self {
+        // the closure may use another NamedOnce so the previous value is restored afterwards
+        NameGuard(CURRENT.with(|current| current.replace(Some((address, name)))))
+    }
+}
+
+impl Drop for NameGuard {
+    fn drop(&mut self) {
+        let _ = CURRENT.try_with(|current| current.set(self.0));
+    }
+}
+
+/// Returns the name of the `NamedOnce` at `address` if its slow path is running on this thread
+///
+/// Other `Once` instances used by the closure are not affected since the address doesn't match.
+pub(crate) fn name_of(address: usize) -> Option<&'static str> {
+    match CURRENT.try_with(Cell::get) {
+        Ok(Some((current, name))) if current == address => Some(name),
+        _ => None,
+    }
+}
+
+impl<P> Deref for NamedOnce<P> {
+    type Target = Once<P>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.once
+    }
+}
+
+impl<P: WaitPolicy> fmt::Debug for NamedOnce<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NamedOnce").field("name", &self.name).field("state", &self.once.state()).finish()
+    }
+}
@@ -0,0 +1,180 @@
+//! Fixed number of once-flags with a shared completion counter
+//!
+//! Each slot is a plain `Once`. The thread that ran the closure of a slot increments the counter
+//! after the slot completed, so by the time the counter reaches `N` all slots are observably
+//! complete. On Linux the counter is a futex word using the same waiting bit as `Latch`, so
+//! `wait_all()` blocks on a single word no matter how many slots are missing. A panicking closure
+//! sets the poisoned bit of the counter since the counter would never reach `N`. Elsewhere
+//! `wait_all()` simply waits for each slot.
+
+use core::fmt;
+use core::sync::atomic::{AtomicI32, Ordering};
+#[cfg(target_os = "linux")]
+use crate::futex;
+use crate::Once;
+
+/// Set while some threads may be blocked in `wait_all()`
+#[cfg(target_os = "linux")]
+const WAITING: i32 = i32::MIN;
+/// Set once the closure of any slot panicked
+const POISONED: i32 = 1 << 30;
+const COUNT_MASK: i32 = POISONED - 1;
+
+/// An array of `N` once-flags indexed by a dense id.
+///
+/// This is like `[Once; N]` except it can also tell whether all slots completed and wait for
+/// that using [`wait_all()`](Self::wait_all), which blocks on a single futex no matter how many
+/// slots are still missing.
+///
+/// ```
+/// use linux_once::OnceArray;
+///
+/// static SUBSYSTEMS: OnceArray<3> = OnceArray::new();
+///
+/// for id in 0..3 {
+///     SUBSYSTEMS.call_once(id, || println!("initializing subsystem {}", id));
+/// }
+/// assert!(SUBSYSTEMS.all_completed());
+/// SUBSYSTEMS.wait_all();
+/// ```
+///
+/// All methods taking an index panic if it's not less than `N`.
+pub struct OnceArray<const N: usize> {
+    slots: [Once; N],
+    completed: AtomicI32,
+}
+
+impl<const N: usize> OnceArray<N> {
+    /// Creates a new array with all slots incomplete.
+    ///
+    /// # Panics
+    ///
+    /// The completed slots are counted in 30 bits so this panics if `N` doesn't fit into them.
+    pub const fn new() -> Self {
+        // only used to initialize the array
+        #[allow(clippy::declare_interior_mutable_const)]
+        const INCOMPLETE: Once = Once::new();
+
+        assert!(N <= COUNT_MASK as usize, "OnceArray is too large");
+        OnceArray { slots: [INCOMPLETE; N], completed: AtomicI32::new(0) }
+    }
+
+    /// Performs the initialization routine of slot `i` once and only once.
+    ///
+    /// See [`Once::call_once()`].
+    ///
+    /// # Panics
+    ///
+    /// If the slot has been poisoned because an initialization closure has panicked, this method
+    /// will also panic.
+    pub fn call_once<F: FnOnce()>(&self, i: usize, f: F) {
+        let slot = &self.slots[i];
+        if slot.is_completed() {
+            return;
+        }
+
+        let mut ran = false;
+        let poison_guard = PoisonOnUnwind(self);
+        slot.call_once(|| {
+            f();
+            ran = true;
+        });
+        core::mem::forget(poison_guard);
+        if ran {
+            let previous = self.completed.fetch_add(1, Ordering::Release);
+            // Waiters only need to be woken up once all slots completed
+            if previous & COUNT_MASK == N as i32 - 1 {
+                self.wake_all(previous);
+            }
+        }
+    }
+
+    /// Returns `true` if the slot `i` has completed successfully.
+    pub fn is_completed(&self, i: usize) -> bool {
+        self.slots[i].is_completed()
+    }
+
+    /// Returns `true` if all slots have completed successfully.
+    pub fn all_completed(&self) -> bool {
+        self.completed.load(Ordering::Acquire) & COUNT_MASK == N as i32
+    }
+
+    /// Blocks the current thread until all slots have completed.
+    ///
+    /// # Panics
+    ///
+    /// If any slot has been poisoned because an initialization closure has panicked, this method
+    /// will also panic, possibly only after the other slots completed.
+    pub fn wait_all(&self) {
+        if !self.all_completed() {
+            self.wait_all_slow();
+        }
+    }
+
+    /// Wakes up the threads blocked in `wait_all()`, `previous` is the state before the last change
+    #[cfg(target_os = "linux")]
+    fn wake_all(&self, previous: i32) {
+        // Only make expensive syscall if there are threads waiting
+        if previous & WAITING != 0 {
+            futex::wake(&self.completed, i32::MAX);
+        }
+    }
+
+    /// The waiters wait for the slots themselves
+    #[cfg(not(target_os = "linux"))]
+    fn wake_all(&self, _previous: i32) {}
+
+    #[cfg(target_os = "linux")]
+    #[cold]
+    fn wait_all_slow(&self) {
+        let mut state = self.completed.load(Ordering::Acquire);
+        while state & COUNT_MASK != N as i32 {
+            if state & POISONED != 0 {
+                panic!("OnceArray instance has previously been poisoned");
+            }
+            if state & WAITING == 0 {
+                if let Err(old) = self.completed.compare_exchange_weak(state, state | WAITING, Ordering::Acquire, Ordering::Acquire) {
+                    state = old;
+                    continue;
+                }
+                state |= WAITING;
+            }
+            // Returns immediately if another slot completed in the meantime
+            futex::wait(&self.completed, state);
+            state = self.completed.load(Ordering::Acquire);
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[cold]
+    fn wait_all_slow(&self) {
+        for slot in &self.slots {
+            slot.wait();
+        }
+    }
+}
+
+/// Wakes up the threads waiting for all slots if the closure panics
+struct PoisonOnUnwind<'a, const N: usize>(&'a OnceArray<N>);
+
+impl<'a, const N: usize> Drop for PoisonOnUnwind<'a, N> {
+    fn drop(&mut self) {
+        let previous = self.0.completed.fetch_or(POISONED, Ordering::Release);
+        self.0.wake_all(previous);
+    }
+}
+
+impl<const N: usize> Default for OnceArray<N> {
+    fn default() -> Self {
+        OnceArray::new()
+    }
+}
+
+impl<const N: usize> fmt::Debug for OnceArray<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnceArray")
+            .field("completed", &(self.completed.load(Ordering::Acquire) & COUNT_MASK))
+            .field("len", &N)
+            .finish()
+    }
+}
@@ -0,0 +1,110 @@
+//! Teardown closure registered at runtime and run at most once
+//!
+//! The closure is stored behind a mutex which also closes the slot when the teardown starts, so a
+//! registration racing with `run()` either gets in before and runs or is rejected, it's never
+//! silently lost. The `Once` makes the other callers of `run()` wait until the teardown finished.
+
+use core::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use crate::Once;
+
+enum Slot {
+    Empty,
+    Registered(Box<dyn FnOnce() + Send>),
+    Closed,
+}
+
+/// A teardown closure which runs exactly once, no matter how many paths request it.
+///
+/// This is useful when several shutdown paths, e.g. the `Drop` of a guard in `main` and an
+/// explicit shutdown request, may all ask for the cleanup. The teardown is registered using
+/// [`register()`](Self::register) and [`run()`](Self::run) may be called any number of times from
+/// any thread.
+///
+/// ```
+/// use linux_once::OnceDrop;
+///
+/// static SHUTDOWN: OnceDrop = OnceDrop::new();
+///
+/// SHUTDOWN.register(|| println!("flushing the logs")).unwrap_or_else(|_| panic!("registered twice"));
+/// SHUTDOWN.run();
+/// SHUTDOWN.run();
+/// assert!(SHUTDOWN.has_run());
+/// ```
+///
+/// Only one teardown can be registered and only before [`run()`](Self::run) is called for the
+/// first time. Calling [`run()`](Self::run) without a registered teardown still counts as running
+/// it, so the later registrations are rejected. If the `OnceDrop` is dropped without running, the
+/// registered closure is dropped without being called.
+pub struct OnceDrop {
+    once: Once,
+    slot: Mutex<Slot>,
+}
+
+impl OnceDrop {
+    /// Creates a new `OnceDrop` without a teardown.
+    pub const fn new() -> Self {
+        OnceDrop { once: Once::new(), slot: Mutex::new(Slot::Empty) }
+    }
+
+    /// Registers the teardown.
+    ///
+    /// # Errors
+    ///
+    /// If a teardown was registered already or [`run()`](Self::run) was called `f` is returned
+    /// back.
+    pub fn register<F: FnOnce() + Send + 'static>(&self, f: F) -> Result<(), F> {
+        let mut slot = self.lock();
+        match *slot {
+            Slot::Empty => {
+                *slot = Slot::Registered(Box::new(f));
+                Ok(())
+            },
+            Slot::Registered(_) | Slot::Closed => Err(f),
+        }
+    }
+
+    /// Runs the registered teardown if it's the first call.
+    ///
+    /// If another thread is running the teardown this blocks until it finishes, so once this
+    /// returns the teardown is done.
+    ///
+    /// # Panics
+    ///
+    /// If the teardown panics the panic is propagated. It's still considered run so the later
+    /// calls return without panicking, which makes it safe to call from `Drop` during unwinding.
+    pub fn run(&self) {
+        // A panicking teardown poisons the Once, the next call then finds the slot closed
+        self.once.call_once_force(|_| {
+            let slot = core::mem::replace(&mut *self.lock(), Slot::Closed);
+            if let Slot::Registered(f) = slot {
+                f();
+            }
+        });
+    }
+
+    /// Returns `true` if the teardown has run or panicked.
+    ///
+    /// A teardown that is running right now is not considered run yet.
+    pub fn has_run(&self) -> bool {
+        self.once.is_completed() || self.once.is_poisoned()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Slot> {
+        // nothing panics while holding the lock
+        self.slot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for OnceDrop {
+    fn default() -> Self {
+        OnceDrop::new()
+    }
+}
+
+impl fmt::Debug for OnceDrop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let registered = matches!(*self.lock(), Slot::Registered(_));
+        f.debug_struct("OnceDrop").field("registered", &registered).field("has_run", &self.has_run()).finish()
+    }
+}
@@ -0,0 +1,39 @@
+//! Passing `FnOnce` closures to the non-generic slow paths
+//!
+//! The slow paths take `&mut dyn FnMut` so that they are instantiated only once. Wrapping the
+//! closure in `Option` and calling `take().expect()` would work but puts a branch and a panic on
+//! every call site. The state machine already guarantees that the closure runs at most once so
+//! this wrapper relies on it instead.
+
+use core::mem::ManuallyDrop;
+
+/// `FnOnce` closure that can be called through a `FnMut` shim
+pub(crate) struct OnceFn<F> {
+    f: ManuallyDrop<F>,
+    taken: bool,
+}
+
+impl<F> OnceFn<F> {
+    pub(crate) fn new(f: F) -> Self {
+        OnceFn { f: ManuallyDrop::new(f), taken: false }
+    }
+
+    /// Moves the closure out so that it can be called
+    ///
+    /// # Safety
+    ///
+    /// This must be called at most once.
+    pub(crate) unsafe fn take(&mut self) -> F {
+        self.taken = true;
+        ManuallyDrop::take(&mut self.f)
+    }
+}
+
+impl<F> Drop for OnceFn<F> {
+    fn drop(&mut self) {
+        if !self.taken {
+            // SAFETY: the closure wasn't moved out and it's never accessed again
+            unsafe { ManuallyDrop::drop(&mut self.f) }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Up to 32 independent once-flags sharing a single futex
+//!
+//! The per-index state doesn't fit into one word: completion, running, waiting and poisoning
+//! need at least three bits per index. So each of them gets its own word with one bit per index:
+//!
+//! * `complete` is only set by the initializer and read by the fast path.
+//! * `running` is the futex word, the initializer owns the index while its bit is set. Clearing
+//!   the bit changes the value so a waiter about to block notices the change just like with
+//!   `Once`. The waiters block with the bitset of their index and the initializer wakes only
+//!   that bitset, so finishing one index doesn't wake the threads waiting for other indices.
+//! * `waiting` avoids the wake syscall if nobody waits for the index. Since it's a different
+//!   word than the one the waiters block on, registering and finishing are a store followed by a
+//!   load on each side, which needs `SeqCst`: either the initializer sees the waiting bit and
+//!   wakes or the waiter sees the running bit cleared and doesn't block.
+//! * `poisoned` is set before clearing the running bit so a thread acquiring the index observes
+//!   it.
+
+use core::fmt;
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use crate::futex;
+use crate::once_fn::OnceFn;
+use crate::poison_hook;
+
+/// Group of 32 once-flags, each running its own initialization at most once.
+///
+/// This is like an array of 32 [`Once`](crate::Once) values except it's four words in total and
+/// completing an index only wakes up the threads waiting for that index. The indices are
+/// independent: their initializations may run in parallel and poisoning one index doesn't affect
+/// the others.
+///
+/// ```
+/// use linux_once::OnceGroup;
+///
+/// const NETWORKING: u8 = 0;
+/// const STORAGE: u8 = 1;
+///
+/// static FEATURES: OnceGroup = OnceGroup::new();
+///
+/// FEATURES.call_once(NETWORKING, || println!("registering networking"));
+/// FEATURES.call_once(NETWORKING, || unreachable!());
+/// assert!(FEATURES.is_completed(NETWORKING));
+/// assert!(!FEATURES.is_completed(STORAGE));
+/// ```
+///
+/// If the closure recursively calls [`call_once()`](Self::call_once) with the same index it
+/// deadlocks.
+pub struct OnceGroup {
+    complete: AtomicU32,
+    running: AtomicI32,
+    waiting: AtomicU32,
+    poisoned: AtomicU32,
+}
+
+impl OnceGroup {
+    /// The number of indices in a group.
+    pub const LEN: u8 = 32;
+
+    /// Creates a new group with all indices incomplete.
+    pub const fn new() -> Self {
+        OnceGroup {
+            complete: AtomicU32::new(0),
+            running: AtomicI32::new(0),
+            waiting: AtomicU32::new(0),
+            poisoned: AtomicU32::new(0),
+        }
+    }
+
+    /// Performs the initialization routine of `index` once and only once.
+    ///
+    /// This behaves like [`Once::call_once()`](crate::Once::call_once) for the given index. If
+    /// another thread is running the initialization of the same index this blocks until it
+    /// finishes, initializations of other indices don't block it.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is not less than [`LEN`](Self::LEN) or if the index has been poisoned because
+    /// its initialization closure has panicked, this method panics.
+    pub fn call_once<F: FnOnce()>(&self, index: u8, f: F) {
+        let bit = Self::bit(index);
+        // Fast path, same as in Once
+        if self.complete.load(Ordering::Acquire) & bit != 0 {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_slow calls the closure at most once
+        self.call_once_slow(bit, &mut || unsafe { f.take()() });
+    }
+
+    #[cold]
+    fn call_once_slow(&self, bit: u32, f: &mut dyn FnMut()) {
+        loop {
+            let running = self.running.fetch_or(bit as i32, Ordering::Acquire);
+            if running & bit as i32 == 0 {
+                // The previous owner updated the other words before clearing the running bit
+                let mut release = Release { group: self, bit, poison: true };
+                if self.complete.load(Ordering::Relaxed) & bit == 0 {
+                    if self.poisoned.load(Ordering::Relaxed) & bit != 0 {
+                        release.poison = false;
+                        panic!("OnceGroup index {} has previously been poisoned", bit.trailing_zeros());
+                    }
+                    f();
+                    self.complete.fetch_or(bit, Ordering::Release);
+                }
+                release.poison = false;
+                return;
+            }
+
+            self.wait(bit);
+            if self.complete.load(Ordering::Acquire) & bit != 0 {
+                return;
+            }
+        }
+    }
+
+    /// Blocks until the running bit of the index is cleared, may return spuriously
+    fn wait(&self, bit: u32) {
+        self.waiting.fetch_or(bit, Ordering::SeqCst);
+        let running = self.running.load(Ordering::SeqCst);
+        if running & bit as i32 != 0 {
+            #[cfg(test)]
+            test_hook::record_wait();
+            // Returns immediately if any index started or finished in the meantime
+            futex::wait_bitset(&self.running, running, bit);
+        }
+    }
+
+    /// Returns `true` if the initialization of `index` has completed successfully.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is not less than [`LEN`](Self::LEN) this method panics.
+    pub fn is_completed(&self, index: u8) -> bool {
+        self.complete.load(Ordering::Acquire) & Self::bit(index) != 0
+    }
+
+    /// Returns `true` if the initialization closure of `index` has panicked.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is not less than [`LEN`](Self::LEN) this method panics.
+    pub fn is_poisoned(&self, index: u8) -> bool {
+        self.poisoned.load(Ordering::Acquire) & Self::bit(index) != 0
+    }
+
+    fn bit(index: u8) -> u32 {
+        assert!(index < Self::LEN, "OnceGroup index {} out of range", index);
+        1 << index
+    }
+}
+
+/// Releases the ownership of an index, poisoning it unless told otherwise
+struct Release<'a> {
+    group: &'a OnceGroup,
+    bit: u32,
+    poison: bool,
+}
+
+impl<'a> Drop for Release<'a> {
+    fn drop(&mut self) {
+        if self.poison {
+            self.group.poisoned.fetch_or(self.bit, Ordering::Release);
+        }
+        self.group.running.fetch_and(!self.bit as i32, Ordering::SeqCst);
+        // Only make expensive syscall if there are threads waiting
+        if self.group.waiting.fetch_and(!self.bit, Ordering::SeqCst) & self.bit != 0 {
+            futex::wake_bitset(&self.group.running, i32::MAX, self.bit);
+        }
+        if self.poison {
+            poison_hook::notify_without_details();
+        }
+    }
+}
+
+impl Default for OnceGroup {
+    fn default() -> Self {
+        OnceGroup::new()
+    }
+}
+
+impl fmt::Debug for OnceGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnceGroup")
+            .field("completed", &format_args!("{:#010x}", self.complete.load(Ordering::Acquire)))
+            .field("poisoned", &format_args!("{:#010x}", self.poisoned.load(Ordering::Acquire)))
+            .finish()
+    }
+}
+
+// Same as Once
+impl UnwindSafe for OnceGroup {}
+impl RefUnwindSafe for OnceGroup {}
+
+/// Counts how many times the current thread blocked, which tests can't observe otherwise
+#[cfg(test)]
+pub(crate) mod test_hook {
+    use core::cell::Cell;
+
+    thread_local! {
+        static WAITS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    pub(super) fn record_wait() {
+        WAITS.with(|waits| waits.set(waits.get() + 1));
+    }
+
+    /// Returns the number of times the current thread blocked on any group
+    pub(crate) fn waits() -> usize {
+        WAITS.with(Cell::get)
+    }
+}
@@ -0,0 +1,152 @@
+//! Drop-in replacement for `std::sync::OnceLock`
+//!
+//! This is a thin wrapper around `OnceCell` using the `Retry` policy by default since `std`
+//! doesn't poison. Only the API and the `Debug` format follow `std`. Using our own cell on all
+//! platforms rather than wrapping the one from `std` means the waiters are woken up when the
+//! initializer poisons the lock too.
+
+use core::fmt;
+use crate::{OnceCell, PoisonPolicy};
+
+/// A synchronization primitive which can be written to only once.
+///
+/// This has the same API and behavior as [`std::sync::OnceLock`] so code using it can switch by
+/// changing the import. In particular, if the initialization closure panics the lock is left
+/// uninitialized and another call may initialize it, there's no poisoning. Use
+/// [`with_poison_policy()`](Self::with_poison_policy) if you want poisoning or
+/// [`OnceCell`] for the additional methods of this crate.
+///
+/// The threads waiting for the initialization block using the same mechanism as
+/// [`Once`](crate::Once).
+///
+/// ```
+/// use linux_once::OnceLock;
+///
+/// static CONFIG: OnceLock<String> = OnceLock::new();
+///
+/// assert_eq!(CONFIG.get(), None);
+/// assert_eq!(CONFIG.get_or_init(|| String::from("verbose")), "verbose");
+/// assert_eq!(CONFIG.set(String::from("quiet")), Err(String::from("quiet")));
+/// ```
+///
+/// The value is dropped exactly once, the same way as in [`OnceCell`](crate::OnceCell#dropping).
+#[derive(Clone, PartialEq, Eq)]
+pub struct OnceLock<T> {
+    cell: OnceCell<T>,
+}
+
+impl<T> OnceLock<T> {
+    /// Creates a new uninitialized lock.
+    pub const fn new() -> Self {
+        OnceLock::with_poison_policy(PoisonPolicy::Retry)
+    }
+
+    /// Creates a new uninitialized lock handling panicking initializers according to `policy`.
+    ///
+    /// With [`PoisonPolicy::Poison`] the lock behaves like [`OnceCell`]: a panic in the
+    /// initializer poisons it and all further attempts to initialize it or wait for it panic.
+    pub const fn with_poison_policy(policy: PoisonPolicy) -> Self {
+        OnceLock { cell: OnceCell::with_poison_policy(policy) }
+    }
+
+    /// Gets the reference to the underlying value.
+    ///
+    /// Returns `None` if the lock is uninitialized or being initialized. This never blocks.
+    pub fn get(&self) -> Option<&T> {
+        self.cell.get()
+    }
+
+    /// Gets the mutable reference to the underlying value.
+    ///
+    /// Returns `None` if the lock is uninitialized.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.cell.get_mut()
+    }
+
+    /// Blocks the current thread until the lock is initialized.
+    ///
+    /// # Panics
+    ///
+    /// If the lock uses [`PoisonPolicy::Poison`] and has been poisoned this method panics, even
+    /// if it was already blocked when the initializer panicked.
+    pub fn wait(&self) -> &T {
+        self.cell.wait()
+    }
+
+    /// Initializes the contents of the lock to `value`.
+    ///
+    /// May block if another thread is currently attempting to initialize the lock. Returns
+    /// `Ok(())` if the lock was uninitialized and `Err(value)` if it was initialized already.
+    #[track_caller]
+    pub fn set(&self, value: T) -> Result<(), T> {
+        self.cell.set(value)
+    }
+
+    /// Gets the contents of the lock, initializing it to `f()` if the lock was uninitialized.
+    ///
+    /// Many threads may call this concurrently with different closures but only one of them is
+    /// executed. If `f` panics the panic is propagated to the caller and the lock remains
+    /// uninitialized, unless it uses [`PoisonPolicy::Poison`].
+    #[track_caller]
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        self.cell.get_or_init(f)
+    }
+
+    /// Gets the mutable reference to the contents of the lock, initializing it to `f()` if the
+    /// lock was uninitialized.
+    ///
+    /// See [`OnceCell::get_mut_or_init()`].
+    #[track_caller]
+    pub fn get_mut_or_init<F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
+        self.cell.get_mut_or_init(f)
+    }
+
+    /// Gets the mutable reference to the contents of the lock, initializing it to `f()` if the
+    /// lock was uninitialized.
+    ///
+    /// See [`OnceCell::get_mut_or_try_init()`].
+    #[track_caller]
+    pub fn get_mut_or_try_init<E, F: FnOnce() -> Result<T, E>>(&mut self, f: F) -> Result<&mut T, E> {
+        self.cell.get_mut_or_try_init(f)
+    }
+
+    /// Consumes the lock, returning the wrapped value.
+    ///
+    /// Returns `None` if the lock was uninitialized.
+    pub fn into_inner(self) -> Option<T> {
+        self.cell.into_inner()
+    }
+
+    /// Takes the value out of the lock, moving it back to an uninitialized state.
+    ///
+    /// Returns `None` if the lock was uninitialized. This clears the poisoning too.
+    pub fn take(&mut self) -> Option<T> {
+        self.cell.take()
+    }
+}
+
+impl<T> Default for OnceLock<T> {
+    fn default() -> Self {
+        OnceLock::new()
+    }
+}
+
+impl<T> From<T> for OnceLock<T> {
+    fn from(value: T) -> Self {
+        let mut lock = OnceLock::new();
+        lock.get_mut_or_init(|| value);
+        lock
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // same format as std
+        let mut tuple = f.debug_tuple("OnceLock");
+        match self.get() {
+            Some(value) => tuple.field(value),
+            None => tuple.field(&format_args!("<uninit>")),
+        };
+        tuple.finish()
+    }
+}
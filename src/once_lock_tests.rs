@@ -0,0 +1,179 @@
+// Conformance tests of `OnceLock`, included both for `std::sync::OnceLock` and for ours
+//
+// Only the behavior promised by the documentation of `std` is tested here so that anything
+// passing against `std` must pass against our type too.
+
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use std::sync::{mpsc, Arc, Barrier};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::time::Duration;
+
+struct DropCounter(Arc<AtomicUsize>);
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Relaxed);
+    }
+}
+
+#[allow(dead_code)]
+fn assert_traits() {
+    fn assert_lock_traits<T: core::fmt::Debug + Default + Clone + Eq + From<u32> + Send + Sync + Unpin + UnwindSafe + RefUnwindSafe>() {}
+
+    assert_lock_traits::<OnceLock<u32>>();
+}
+
+#[test]
+fn new_is_uninitialized() {
+    static LOCK: OnceLock<u32> = OnceLock::new();
+
+    assert_eq!(LOCK.get(), None);
+    assert_eq!(OnceLock::<u32>::default().get(), None);
+}
+
+#[test]
+fn set() {
+    let lock = OnceLock::new();
+    assert_eq!(lock.set(42), Ok(()));
+    assert_eq!(lock.get(), Some(&42));
+    assert_eq!(lock.set(47), Err(47));
+    assert_eq!(lock.get(), Some(&42));
+}
+
+#[test]
+fn get_or_init_runs_once() {
+    let lock = OnceLock::new();
+    let value = lock.get_or_init(|| 42);
+    assert_eq!(*value, 42);
+    assert!(core::ptr::eq(lock.get_or_init(|| panic!("initialized twice")), value));
+    assert_eq!(lock.set(47), Err(47));
+}
+
+#[test]
+fn get_or_init_panic_leaves_uninitialized() {
+    let lock = OnceLock::new();
+    std::panic::catch_unwind(|| lock.get_or_init(|| -> u32 { panic!("failing on purpose") })).expect_err("the closure didn't panic");
+    assert_eq!(lock.get(), None);
+    assert_eq!(*lock.get_or_init(|| 42), 42);
+}
+
+#[test]
+fn panic_lets_waiting_thread_initialize() {
+    let lock = Arc::new(OnceLock::new());
+    let (started_sender, started) = mpsc::channel();
+    let panicking = {
+        let lock = Arc::clone(&lock);
+        std::thread::spawn(move || {
+            std::panic::catch_unwind(|| lock.get_or_init(|| -> u32 {
+                started_sender.send(()).expect("main thread exited");
+                std::thread::sleep(Duration::from_millis(50));
+                panic!("failing on purpose");
+            })).expect_err("the closure didn't panic");
+        })
+    };
+    started.recv().expect("initializer thread exited");
+    // blocks until the first closure panics and then initializes the lock itself
+    assert_eq!(*lock.get_or_init(|| 42), 42);
+    panicking.join().expect("Failed to join");
+}
+
+#[test]
+fn concurrent_get_or_init() {
+    let lock = Arc::new(OnceLock::new());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let barrier = Arc::new(Barrier::new(8));
+    let threads = (0..8)
+        .map(|i| {
+            let lock = Arc::clone(&lock);
+            let calls = Arc::clone(&calls);
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || {
+                barrier.wait();
+                *lock.get_or_init(|| {
+                    calls.fetch_add(1, Relaxed);
+                    i
+                })
+            })
+        })
+        // required for true concurrency
+        .collect::<Vec<_>>();
+    let values = threads.into_iter().map(|thread| thread.join().expect("Failed to join")).collect::<Vec<_>>();
+    assert_eq!(calls.load(Relaxed), 1);
+    assert!(values.iter().all(|value| Some(value) == lock.get()));
+}
+
+#[test]
+fn wait_for_set() {
+    let lock = Arc::new(OnceLock::new());
+    let waiters = (0..4)
+        .map(|_| {
+            let lock = Arc::clone(&lock);
+            std::thread::spawn(move || *lock.wait())
+        })
+        // required for true concurrency
+        .collect::<Vec<_>>();
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(lock.set(42), Ok(()));
+    for waiter in waiters {
+        assert_eq!(waiter.join().expect("Failed to join"), 42);
+    }
+    assert_eq!(*lock.wait(), 42);
+}
+
+#[test]
+fn get_mut() {
+    let mut lock = OnceLock::new();
+    assert_eq!(lock.get_mut(), None);
+    lock.set(42).expect("lock initialized");
+    *lock.get_mut().expect("lock not initialized") += 1;
+    assert_eq!(lock.get(), Some(&43));
+}
+
+#[test]
+fn take_resets() {
+    let mut lock = OnceLock::new();
+    assert_eq!(lock.take(), None);
+    lock.set(42).expect("lock initialized");
+    assert_eq!(lock.take(), Some(42));
+    assert_eq!(lock.get(), None);
+    assert_eq!(lock.set(47), Ok(()));
+    assert_eq!(lock.get(), Some(&47));
+}
+
+#[test]
+fn into_inner() {
+    assert_eq!(OnceLock::<u32>::new().into_inner(), None);
+    assert_eq!(OnceLock::from(42).into_inner(), Some(42));
+}
+
+#[test]
+fn from_clone_and_eq() {
+    let lock = OnceLock::from(42);
+    assert_eq!(lock.get(), Some(&42));
+    assert_eq!(lock.clone(), lock);
+    assert_eq!(OnceLock::<u32>::new().clone(), OnceLock::new());
+    assert_ne!(lock, OnceLock::new());
+    assert_ne!(lock, OnceLock::from(47));
+}
+
+#[test]
+fn drops_value_once() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let lock = OnceLock::new();
+    lock.get_or_init(|| DropCounter(Arc::clone(&drops)));
+    assert!(lock.set(DropCounter(Arc::clone(&drops))).is_err());
+    assert_eq!(drops.load(Relaxed), 1);
+    drop(lock);
+    assert_eq!(drops.load(Relaxed), 2);
+
+    let mut lock = OnceLock::from(DropCounter(Arc::clone(&drops)));
+    drop(lock.take());
+    assert_eq!(drops.load(Relaxed), 3);
+    drop(lock);
+    assert_eq!(drops.load(Relaxed), 3);
+
+    let value = OnceLock::from(DropCounter(Arc::clone(&drops))).into_inner();
+    assert_eq!(drops.load(Relaxed), 3);
+    drop(value);
+    assert_eq!(drops.load(Relaxed), 4);
+}
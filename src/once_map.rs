@@ -0,0 +1,170 @@
+//! Map of values initialized at most once per key
+//!
+//! The entries are allocated separately and the map only holds pointers to them, so neither the
+//! keys nor the values move when the map grows. The mutex protects only the lookup and insertion
+//! of the entries, the initialization itself is guarded by the `OnceCell` of each entry. Entries
+//! are never removed while the map is shared, so the references to them stay valid until the map
+//! is dropped.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ptr::NonNull;
+use std::collections::HashSet;
+use std::sync::{Mutex, PoisonError};
+use crate::OnceCell;
+
+struct Entry<K, V> {
+    key: K,
+    value: OnceCell<V>,
+}
+
+/// Owning pointer to an entry, hashed and compared by the key
+struct EntryPtr<K, V>(NonNull<Entry<K, V>>);
+
+impl<K, V> EntryPtr<K, V> {
+    fn entry(&self) -> &Entry<K, V> {
+        // SAFETY: the entry is allocated when the pointer is created and freed only when the map
+        // is dropped
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl<K: Hash, V> Hash for EntryPtr<K, V> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.entry().key.hash(state)
+    }
+}
+
+impl<K: PartialEq, V> PartialEq for EntryPtr<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.entry().key == other.entry().key
+    }
+}
+
+impl<K: Eq, V> Eq for EntryPtr<K, V> {}
+
+// Allows looking up the entries by the key, the hash and equality agree with the ones above
+impl<K, V> Borrow<K> for EntryPtr<K, V> {
+    fn borrow(&self) -> &K {
+        &self.entry().key
+    }
+}
+
+/// A map whose values are initialized at most once per key.
+///
+/// This is like `Mutex<HashMap<K, Arc<OnceCell<V>>>>` except the mutex is held only while looking
+/// up the entry and the returned references live as long as the map. Initializations of
+/// different keys run in parallel, the threads initializing the same key block on the
+/// [`OnceCell`] of the entry.
+///
+/// ```
+/// use linux_once::OnceMap;
+///
+/// let connections = OnceMap::new();
+///
+/// let first = connections.get_or_init("tenant-1", |tenant| format!("connection to {}", tenant));
+/// let again = connections.get_or_init("tenant-1", |_| unreachable!());
+/// assert!(std::ptr::eq(first, again));
+/// assert_eq!(connections.get(&"tenant-2"), None);
+/// ```
+///
+/// If an initializing closure panics the entry of its key is poisoned and accessing it with
+/// [`get_or_init()`](Self::get_or_init) panics, the other keys are not affected.
+pub struct OnceMap<K, V> {
+    entries: Mutex<HashSet<EntryPtr<K, V>>>,
+}
+
+impl<K: Hash + Eq, V> OnceMap<K, V> {
+    /// Creates a new empty map.
+    pub fn new() -> Self {
+        OnceMap { entries: Mutex::new(HashSet::new()) }
+    }
+
+    /// Returns the value of `key`, initializing it with `f` if it wasn't initialized yet.
+    ///
+    /// If another thread is initializing the same key this blocks until it finishes. Only one
+    /// closure is ever executed successfully for each key. If the key is already present `key`
+    /// is dropped and the stored one is passed to `f`.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics the panic is propagated and the entry of `key` is poisoned. If the entry has
+    /// been poisoned this method panics too.
+    pub fn get_or_init<F: FnOnce(&K) -> V>(&self, key: K, f: F) -> &V {
+        let entry = self.entry(key);
+        entry.value.get_or_init(|| f(&entry.key))
+    }
+
+    /// Returns the value of `key` if it was initialized.
+    ///
+    /// This never blocks on the initialization: if it's running on another thread `None` is
+    /// returned.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let entry = entries.get(key)?.0;
+        drop(entries);
+        // SAFETY: the entry is freed only when the map is dropped
+        unsafe { entry.as_ref() }.value.get()
+    }
+
+    /// Returns `true` if the value of `key` was initialized.
+    ///
+    /// Same as `get(key).is_some()`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the entry of `key`, inserting an empty one if it's missing
+    fn entry(&self, key: K) -> &Entry<K, V> {
+        // The hash and equality are user code which may panic but neither modifies the set, so
+        // the poisoning can be ignored.
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let entry = match entries.get(&key) {
+            Some(entry) => entry.0,
+            None => {
+                let entry = Box::new(Entry { key, value: OnceCell::new() });
+                let entry = NonNull::from(Box::leak(entry));
+                entries.insert(EntryPtr(entry));
+                entry
+            },
+        };
+        drop(entries);
+        // SAFETY: the entry is freed only when the map is dropped
+        unsafe { entry.as_ref() }
+    }
+}
+
+impl<K, V> Drop for OnceMap<K, V> {
+    fn drop(&mut self) {
+        let entries = self.entries.get_mut().unwrap_or_else(PoisonError::into_inner);
+        for entry in entries.drain() {
+            // SAFETY: the entry was allocated by Box and nobody refers to it anymore
+            drop(unsafe { Box::from_raw(entry.0.as_ptr()) });
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> Default for OnceMap<K, V> {
+    fn default() -> Self {
+        OnceMap::new()
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for OnceMap<K, V> {
+    /// Formats the initialized entries, the ones being initialized are skipped.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let initialized = entries
+            .iter()
+            .map(EntryPtr::entry)
+            .filter_map(|entry| Some((&entry.key, entry.value.get()?)));
+        f.debug_map().entries(initialized).finish()
+    }
+}
+
+// SAFETY: the keys are shared between the threads looking up the entries and passed to the
+// closures, the values are the same as in OnceCell
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for OnceMap<K, V> {}
+// SAFETY: sending the map sends the keys and the values
+unsafe impl<K: Send, V: Send> Send for OnceMap<K, V> {}
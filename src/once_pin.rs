@@ -0,0 +1,176 @@
+//! Value initialized at most once and pinned in the cell
+//!
+//! The storage is the same as in `OnceCell`. The initializing methods require the cell to be
+//! pinned, so once the value exists the cell doesn't move until it's dropped and `get()` can hand
+//! out pinned references without requiring the pin itself. There's no way to get a mutable
+//! reference or move the value out, `Drop` drops it in place.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::pin::Pin;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use crate::Once;
+
+/// A thread-safe cell which can be written to only once and never moves the value.
+///
+/// This is like [`OnceCell`](crate::OnceCell) except the value is pinned: references to it are
+/// handed out as `Pin<&T>` and it can't be moved out of the cell, so it may contain pointers to
+/// itself, e.g. a C structure holding pointers into its own buffer. To initialize the cell it
+/// has to be pinned too, statics can be pinned using [`Pin::static_ref()`].
+///
+/// ```
+/// use linux_once::OncePin;
+/// use std::pin::Pin;
+///
+/// static BUFFER: OncePin<[u8; 4096]> = OncePin::new();
+///
+/// let buffer = Pin::static_ref(&BUFFER).get_or_init(|| [0; 4096]);
+/// assert_eq!(buffer[42], 0);
+/// ```
+///
+/// If the initialization closure panics the cell is poisoned, just like [`Once`].
+pub struct OncePin<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> OncePin<T> {
+    /// Creates a new empty cell.
+    pub const fn new() -> Self {
+        OncePin { once: Once::new(), value: UnsafeCell::new(MaybeUninit::uninit()) }
+    }
+
+    /// Returns the pinned value if the cell was initialized.
+    ///
+    /// This never blocks: if the initialization is running on another thread `None` is returned.
+    pub fn get(&self) -> Option<Pin<&T>> {
+        if self.once.is_completed() {
+            // SAFETY: the Once is complete
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the pinned value, initializing it with `f` if the cell is empty.
+    ///
+    /// If another thread is initializing the cell this blocks until it finishes. The value is
+    /// moved into the cell once `f` returns, use [`get_or_init_pin()`](Self::get_or_init_pin) to
+    /// initialize it in place.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics the panic is propagated and the cell is poisoned. If the cell has been
+    /// poisoned this method panics too.
+    pub fn get_or_init<F: FnOnce() -> T>(self: Pin<&Self>, f: F) -> Pin<&T> {
+        self.get_or_init_pin(|slot| {
+            // SAFETY: MaybeUninit is not structurally pinned, the slot is only written and then
+            // pinned again
+            let slot = unsafe { slot.get_unchecked_mut() };
+            // SAFETY: the value is never moved out of the slot
+            unsafe { Pin::new_unchecked(slot.write(f())) }
+        })
+    }
+
+    /// Returns the pinned value, initializing it in place with `f` if the cell is empty.
+    ///
+    /// `f` receives the pinned uninitialized storage of the cell and has to initialize it,
+    /// returning the pinned reference to the initialized value, so the value can refer to its
+    /// own address:
+    ///
+    /// ```
+    /// use linux_once::OncePin;
+    /// use std::marker::PhantomPinned;
+    /// use std::pin::Pin;
+    ///
+    /// struct SelfRef {
+    ///     this: *const SelfRef,
+    ///     _pinned: PhantomPinned,
+    /// }
+    ///
+    /// let cell = Box::pin(OncePin::<SelfRef>::new());
+    /// let value = cell.as_ref().get_or_init_pin(|mut slot| {
+    ///     // SAFETY: the slot is initialized before the reference to the value is created and
+    ///     // nothing is moved
+    ///     unsafe {
+    ///         let this = slot.as_mut().get_unchecked_mut().as_mut_ptr();
+    ///         this.write(SelfRef { this, _pinned: PhantomPinned });
+    ///         slot.map_unchecked_mut(|slot| slot.assume_init_mut())
+    ///     }
+    /// });
+    /// assert_eq!(value.this, &*value as *const SelfRef);
+    /// ```
+    ///
+    /// If another thread is initializing the cell this blocks until it finishes.
+    ///
+    /// # Panics
+    ///
+    /// If `f` returns a reference to anything but the storage it received this panics. If `f`
+    /// panics the panic is propagated. Either way the cell is poisoned and if the cell has been
+    /// poisoned this method panics too.
+    pub fn get_or_init_pin<F>(self: Pin<&Self>, f: F) -> Pin<&T>
+    where
+        F: FnOnce(Pin<&mut MaybeUninit<T>>) -> Pin<&mut T>,
+    {
+        let this = self.get_ref();
+        if let Some(value) = this.get() {
+            return value;
+        }
+
+        this.once.call_once(|| {
+            // SAFETY: only the closure of the Once accesses the storage and nobody reads it before
+            // the Once completes. The cell is pinned so its storage is pinned as well.
+            let slot = unsafe { Pin::new_unchecked(&mut *this.value.get()) };
+            let expected = &*slot as *const MaybeUninit<T> as *const T;
+            let value = f(slot);
+            // A reference to the storage can only be created after it's initialized
+            assert_eq!(&*value as *const T, expected, "the initializer of OncePin returned a foreign reference");
+        });
+        // SAFETY: call_once returns only after the Once completed
+        unsafe { this.get_unchecked() }
+    }
+
+    /// # Safety
+    ///
+    /// The `Once` must be complete.
+    unsafe fn get_unchecked(&self) -> Pin<&T> {
+        // The value was initialized through a pinned reference to the cell so the cell doesn't
+        // move until it's dropped.
+        Pin::new_unchecked((*self.value.get()).assume_init_ref())
+    }
+}
+
+impl<T> Drop for OncePin<T> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            // SAFETY: the Once is complete so the value is initialized, it's dropped in place as
+            // required by the pinning and never accessed again
+            unsafe { self.value.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+impl<T> Default for OncePin<T> {
+    fn default() -> Self {
+        OncePin::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OncePin<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OncePin").field(&*value).finish(),
+            None => f.debug_struct("OncePin").field("state", &self.once.state()).finish(),
+        }
+    }
+}
+
+// SAFETY: same as OnceCell
+unsafe impl<T: Send + Sync> Sync for OncePin<T> {}
+// SAFETY: sending the cell sends the value
+unsafe impl<T: Send> Send for OncePin<T> {}
+
+// Same as OnceCell, the poisoning protects from observing a broken value
+impl<T: RefUnwindSafe + UnwindSafe> RefUnwindSafe for OncePin<T> {}
+impl<T: UnwindSafe> UnwindSafe for OncePin<T> {}
@@ -0,0 +1,106 @@
+//! Padding to the size of a cache line
+//!
+//! The alignments follow `crossbeam_utils::CachePadded`: 128 bytes on the architectures that
+//! prefetch cache lines in pairs (x86_64) or have 128-byte lines (big aarch64 and powerpc64
+//! cores), the known line sizes of the smaller architectures and 64 bytes elsewhere.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use crate::Once;
+
+/// Pads and aligns a value to the size of a cache line.
+///
+/// Values placed next to each other share a cache line, so writing one of them slows down
+/// reading the other one on a different core even though they are unrelated (false sharing). In
+/// this wrapper each value occupies its own cache line.
+///
+/// ```
+/// use linux_once::CachePadded;
+/// use std::sync::atomic::AtomicUsize;
+///
+/// let counters = [CachePadded::new(AtomicUsize::new(0)), CachePadded::new(AtomicUsize::new(0))];
+/// assert!(core::mem::size_of_val(&counters[0]) >= 64);
+/// ```
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "powerpc64"), repr(align(128)))]
+#[cfg_attr(
+    any(target_arch = "arm", target_arch = "mips", target_arch = "mips64", target_arch = "sparc", target_arch = "hexagon"),
+    repr(align(32))
+)]
+#[cfg_attr(target_arch = "s390x", repr(align(256)))]
+#[cfg_attr(
+    not(any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "powerpc64",
+        target_arch = "arm",
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "sparc",
+        target_arch = "hexagon",
+        target_arch = "s390x",
+    )),
+    repr(align(64))
+)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CachePadded<T> {
+    value: T,
+}
+
+/// A [`Once`] occupying its own cache line.
+///
+/// This is useful for a `Once` per shard of a structure accessed from many threads, where the
+/// fast path of one shard would otherwise be slowed down by writes to the neighboring shards.
+/// The methods of [`Once`] are available through `Deref`:
+///
+/// ```
+/// use linux_once::{CachePadded, Once, PaddedOnce};
+///
+/// static SHARDS: [PaddedOnce; 4] = [
+///     CachePadded::new(Once::new()),
+///     CachePadded::new(Once::new()),
+///     CachePadded::new(Once::new()),
+///     CachePadded::new(Once::new()),
+/// ];
+///
+/// SHARDS[2].call_once(|| println!("initializing shard 2"));
+/// assert!(SHARDS[2].is_completed());
+/// ```
+pub type PaddedOnce = CachePadded<Once>;
+
+impl<T> CachePadded<T> {
+    /// Pads and aligns `value` to the size of a cache line.
+    pub const fn new(value: T) -> Self {
+        CachePadded { value }
+    }
+
+    /// Returns the inner value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T> From<T> for CachePadded<T> {
+    fn from(value: T) -> Self {
+        CachePadded::new(value)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for CachePadded<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CachePadded").field(&self.value).finish()
+    }
+}
@@ -0,0 +1,190 @@
+//! Priority-inheriting variant of `Once`
+//!
+//! The initialization is guarded by a PI futex whose value is the id of the owning thread, as the
+//! kernel requires. Waiters block in `FUTEX_LOCK_PI` so the kernel boosts the initializer to the
+//! highest priority among them. The completion state is kept in a separate atomic so that the
+//! fast path is still a single load.
+
+use core::fmt;
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use crate::{OnceState, OnceStatus};
+use crate::futex;
+use crate::poison_hook;
+use crate::once_fn::OnceFn;
+
+const INCOMPLETE: i32 = 0;
+const COMPLETE: i32 = 1;
+const POISONED: i32 = 2;
+
+/// A variant of [`Once`](crate::Once) that avoids priority inversion.
+///
+/// With [`Once`](crate::Once) a low-priority thread may start the initialization, get preempted
+/// and a high-priority thread then waits for it without the kernel knowing about the dependency.
+/// `OncePi` uses priority-inheriting futex operations (`FUTEX_LOCK_PI`/`FUTEX_UNLOCK_PI`) so the
+/// initializing thread runs with the priority of the highest-priority waiter. This matters with
+/// real-time scheduling policies such as `SCHED_FIFO`, with normal scheduling it behaves like
+/// [`Once`](crate::Once), just a bit slower when contended.
+///
+/// The API is a subset of [`Once`](crate::Once) and poisoning works the same way. The PI protocol
+/// imposes some extra constraints:
+///
+/// * The initialization must finish on the thread that started it, so there's no two-phase
+///   initialization like [`Once::begin()`](crate::Once::begin).
+/// * Waiters are released one by one since the kernel hands the futex over to a single thread.
+///   Each of them just checks the state and releases it right away.
+/// * `OncePi` is twice as large as [`Once`](crate::Once) and doesn't support waiting policies.
+///
+/// If the PI syscalls are denied (e.g. by seccomp) or not supported the threads spin instead,
+/// without any priority inheritance.
+pub struct OncePi {
+    state: AtomicI32,
+    /// PI futex holding the id of the thread running the initialization, or zero
+    lock: AtomicU32,
+}
+
+// Same as in `Once`, the slow path is not generic over the closure.
+#[allow(clippy::type_complexity)]
+const _: () = {
+    let _: fn(&OncePi, bool, &mut dyn FnMut(&OnceState)) = OncePi::call_once_slow;
+};
+
+impl OncePi {
+    /// Creates a new `OncePi` value.
+    pub const fn new() -> Self {
+        OncePi {
+            state: AtomicI32::new(INCOMPLETE),
+            lock: AtomicU32::new(0),
+        }
+    }
+
+    /// Performs an initialization routine once and only once.
+    ///
+    /// This behaves like [`Once::call_once()`](crate::Once::call_once) except the thread running
+    /// the closure inherits the priority of the threads waiting for it.
+    ///
+    /// # Panics
+    ///
+    /// If this `OncePi` has been poisoned because an initialization closure has panicked, this
+    /// method will also panic. Recursive calls from the closure panic too.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        // Fast path, same as in Once
+        if self.state.load(Ordering::Acquire) == COMPLETE {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_slow calls the closure at most once
+        self.call_once_slow(false, &mut |_| unsafe { f.take()() });
+    }
+
+    /// Performs the same function as [`call_once()`](Self::call_once) except ignores poisoning.
+    ///
+    /// See [`Once::call_once_force()`](crate::Once::call_once_force).
+    pub fn call_once_force<F: FnOnce(&OnceState)>(&self, f: F) {
+        if self.state.load(Ordering::Acquire) == COMPLETE {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_slow calls the closure at most once
+        self.call_once_slow(true, &mut |once_state| unsafe { f.take()(once_state) });
+    }
+
+    #[cold]
+    fn call_once_slow(&self, ignore_poisoning: bool, f: &mut dyn FnMut(&OnceState)) {
+        let tid = futex::gettid();
+        // the kernel would report EDEADLK, this gives a better message
+        if futex::pi_owner(&self.lock) == tid {
+            panic!("Once instance has been used recursively from its own initialization routine");
+        }
+
+        let _lock = PiGuard::lock(&self.lock, tid);
+        // Only the owner of the lock changes the state so it's stable now
+        match self.state.load(Ordering::Acquire) {
+            COMPLETE => (),
+            POISONED if !ignore_poisoning => panic!("Once instance has previously been poisoned"),
+            state => {
+                // dropped before the lock so the waiters observe the final state
+                let mut panic_checker = PanicChecker { state: &self.state, value_to_write: POISONED };
+                let once_state = OnceState::new(state == POISONED);
+                f(&once_state);
+                panic_checker.value_to_write = if once_state.is_poison_requested() { POISONED } else { COMPLETE };
+            },
+        }
+    }
+
+    /// Returns `true` if some initialization has completed successfully.
+    ///
+    /// See [`Once::is_completed()`](crate::Once::is_completed).
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Returns `true` if an initialization closure has panicked.
+    pub fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::Acquire) == POISONED
+    }
+
+    /// Returns a snapshot of the current state.
+    ///
+    /// The state may change right after it was observed so this is mainly useful for debugging.
+    pub fn state(&self) -> OnceStatus {
+        match self.state.load(Ordering::Acquire) {
+            COMPLETE => OnceStatus::Complete,
+            POISONED => OnceStatus::Poisoned,
+            _ if futex::pi_owner(&self.lock) != 0 => OnceStatus::Running,
+            _ => OnceStatus::Incomplete,
+        }
+    }
+}
+
+impl Default for OncePi {
+    fn default() -> Self {
+        OncePi::new()
+    }
+}
+
+impl fmt::Debug for OncePi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OncePi").field("state", &self.state()).finish()
+    }
+}
+
+// Same as Once
+impl UnwindSafe for OncePi {}
+impl RefUnwindSafe for OncePi {}
+
+/// Holds the PI futex, unlocking it on the same thread when dropped
+struct PiGuard<'a> {
+    lock: &'a AtomicU32,
+    tid: u32,
+}
+
+impl<'a> PiGuard<'a> {
+    fn lock(lock: &'a AtomicU32, tid: u32) -> Self {
+        futex::lock_pi(lock, tid);
+        PiGuard { lock, tid }
+    }
+}
+
+impl<'a> Drop for PiGuard<'a> {
+    fn drop(&mut self) {
+        futex::unlock_pi(self.lock, self.tid);
+    }
+}
+
+/// Writes the final state, poisoning unless told otherwise
+struct PanicChecker<'a> {
+    state: &'a AtomicI32,
+    value_to_write: i32,
+}
+
+impl<'a> Drop for PanicChecker<'a> {
+    fn drop(&mut self) {
+        self.state.store(self.value_to_write, Ordering::Release);
+        if self.value_to_write == POISONED {
+            poison_hook::notify_without_details();
+        }
+    }
+}
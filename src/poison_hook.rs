@@ -0,0 +1,56 @@
+//! Process-global hook called when a `Once` gets poisoned
+//!
+//! Poisoning is usually noticed only when another thread panics on the poisoned `Once`, possibly
+//! much later and far from the original problem. The hook allows reporting it right away.
+
+use core::panic::Location;
+use std::sync::OnceLock;
+
+static HOOK: OnceLock<fn(&PoisonInfo<'_>)> = OnceLock::new();
+
+/// Information about a poisoned [`Once`](crate::Once) passed to the hook set by
+/// [`set_poison_hook()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PoisonInfo<'a> {
+    /// Name of the `Once` if it's a `NamedOnce` and it was poisoned by its own methods
+    pub name: Option<&'static str>,
+    /// Location of the call that poisoned the `Once`, only recorded on Linux and with the same
+    /// limits as [`PoisonedError::location()`](crate::PoisonedError::location)
+    pub location: Option<&'static Location<'static>>,
+    /// Message of the panic that poisoned the `Once`, requires the `poison-message` feature
+    pub message: Option<&'a str>,
+}
+
+/// Sets the process-global hook called whenever a [`Once`](crate::Once) gets poisoned.
+///
+/// The hook is called exactly once for each transition to the poisoned state: when an
+/// initialization closure panics, a guard returned by [`Once::begin()`](crate::Once::begin) is
+/// dropped or poisoning is requested explicitly. It's not called for panics caused by observing
+/// an already poisoned `Once`.
+///
+/// The hook can be set only once, like [`std::sync::OnceLock::set()`]. If a hook was set already
+/// the given one is returned back in `Err`.
+///
+/// # Constraints
+///
+/// The hook runs on the thread that poisoned the `Once`, usually while it's unwinding from the
+/// panic of the closure. Thus it must not panic, otherwise the process is aborted. The `Once` is
+/// already poisoned and the waiting threads are woken up at that point so the hook can't prevent
+/// the poisoning, it can only report it. The hook should be quick and should avoid touching the
+/// state the panicking closure may have left broken.
+pub fn set_poison_hook(hook: fn(&PoisonInfo<'_>)) -> Result<(), fn(&PoisonInfo<'_>)> {
+    HOOK.set(hook)
+}
+
+/// Returns the hook if it was set, so that the information is only gathered when needed
+pub(crate) fn get() -> Option<fn(&PoisonInfo<'_>)> {
+    HOOK.get().copied()
+}
+
+/// Calls the hook for a `Once` implementation that doesn't keep any details about poisoning
+pub(crate) fn notify_without_details() {
+    if let Some(hook) = get() {
+        hook(&PoisonInfo { name: None, location: None, message: None });
+    }
+}
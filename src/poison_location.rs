@@ -0,0 +1,63 @@
+//! Locations of the calls whose closures poisoned `Once` instances
+//!
+//! Like the poison messages these are kept in a global table keyed by the address. Locations are
+//! `'static` so the table is a fixed array of atomics and recording doesn't allocate. If the table
+//! is full the location is not recorded and the panic message just doesn't include it.
+//!
+//! The entry is recorded before the poisoned state is published and removed when the poisoning
+//! is cleared or the `Once` is dropped, so a new `Once` placed at the same address doesn't
+//! inherit a stale location. A poisoned `Once` that is leaked or moved by value never frees its
+//! slot and whether the `Once` is still poisoned can't be checked from the table, so the slots
+//! aren't evicted either. This limitation is documented in `PoisonedError::location()`.
+
+use core::panic::Location;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+const SLOTS: usize = 64;
+
+struct Slot {
+    /// Address of the `Once`, zero if the slot is free
+    address: AtomicUsize,
+    location: AtomicPtr<Location<'static>>,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: Slot = Slot { address: AtomicUsize::new(0), location: AtomicPtr::new(ptr::null_mut()) };
+
+static TABLE: [Slot; SLOTS] = [EMPTY; SLOTS];
+
+/// Remembers the location of the call that is about to poison `Once` at `address`
+///
+/// Only the thread running the initialization poisons the `Once` so there are no concurrent
+/// calls for the same address.
+pub(crate) fn record(address: usize, location: &'static Location<'static>) {
+    let location = location as *const Location<'static> as *mut Location<'static>;
+    if let Some(slot) = TABLE.iter().find(|slot| slot.address.load(Ordering::Acquire) == address) {
+        slot.location.store(location, Ordering::Release);
+        return;
+    }
+    if let Some(slot) = TABLE.iter().find(|slot| slot.address.compare_exchange(0, address, Ordering::AcqRel, Ordering::Relaxed).is_ok()) {
+        slot.location.store(location, Ordering::Release);
+    }
+}
+
+/// Returns the location of the call that poisoned `Once` at `address`, if known
+pub(crate) fn get(address: usize) -> Option<&'static Location<'static>> {
+    let slot = TABLE.iter().find(|slot| slot.address.load(Ordering::Acquire) == address)?;
+    let location = slot.location.load(Ordering::Acquire);
+    // the slot could've been reused for a different address in the meantime
+    if location.is_null() || slot.address.load(Ordering::Acquire) != address {
+        return None;
+    }
+    // SAFETY: only references to `'static` locations are stored in the table
+    Some(unsafe { &*location })
+}
+
+/// Forgets the location for the `Once` at `address`
+pub(crate) fn remove(address: usize) {
+    if let Some(slot) = TABLE.iter().find(|slot| slot.address.load(Ordering::Acquire) == address) {
+        slot.location.store(ptr::null_mut(), Ordering::Release);
+        slot.address.store(0, Ordering::Release);
+    }
+}
@@ -0,0 +1,75 @@
+//! Messages of panics that poisoned `Once` instances
+//!
+//! Storing the message inside `Once` would make it bigger for everyone so we keep a global table
+//! keyed by the address instead. It's only accessed on the already-slow poisoning paths.
+//!
+//! The message is caught while unwinding out of the closure but the state is written later by the
+//! caller, so it's kept in a thread-local until then. Every poisoning replaces the entry for the
+//! address or removes it if there's no message, so a `Once` placed at the address of an old one
+//! never reports a stale message. The entry is also removed when the poisoning is cleared or the
+//! `Once` is dropped.
+//!
+//! Entries are never evicted, so the message of a `Once` that stays poisoned, e.g. a `static`
+//! one, is kept for the whole life of the process. A poisoned `Once` moved by value leaves its
+//! entry at the old address behind though, so the message is lost and the entry stays in the
+//! table until a `Once` at that address gets poisoned again or is dropped while poisoned.
+
+use core::any::Any;
+use core::cell::RefCell;
+use std::sync::{Mutex, PoisonError};
+
+static MESSAGES: Mutex<Vec<(usize, String)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// Message of the panic unwinding towards the code writing the poisoned state
+    static PENDING: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Remembers the message of the panic that is about to poison a `Once` on this thread
+pub(crate) fn catch(payload: &(dyn Any + Send)) {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        Some(String::from(*message))
+    } else {
+        payload.downcast_ref::<String>().cloned()
+    };
+    PENDING.with(|pending| *pending.borrow_mut() = message);
+}
+
+/// Forgets the caught message because the `Once` didn't get poisoned after all
+pub(crate) fn discard() {
+    PENDING.with(|pending| pending.borrow_mut().take());
+}
+
+/// Assigns the caught message, if any, to the `Once` at `address` that is about to be poisoned
+pub(crate) fn publish(address: usize) {
+    let message = match PENDING.with(|pending| pending.borrow_mut().take()) {
+        Some(message) => message,
+        // whatever is stored belongs to a previous `Once` at the same address
+        None => return remove(address),
+    };
+
+    // this module doesn't panic while holding the lock but it's just a diagnostic anyway
+    let mut messages = MESSAGES.lock().unwrap_or_else(PoisonError::into_inner);
+    match messages.iter_mut().find(|(entry_address, _)| *entry_address == address) {
+        Some(entry) => entry.1 = message,
+        None => messages.push((address, message)),
+    }
+}
+
+/// Returns the message of the panic that poisoned `Once` at `address`
+pub(crate) fn get(address: usize) -> Option<String> {
+    MESSAGES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .find(|(entry_address, _)| *entry_address == address)
+        .map(|(_, message)| message.clone())
+}
+
+/// Forgets the message because the `Once` at `address` is no longer poisoned (or no longer exists)
+pub(crate) fn remove(address: usize) {
+    let mut messages = MESSAGES.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(pos) = messages.iter().position(|(entry_address, _)| *entry_address == address) {
+        messages.swap_remove(pos);
+    }
+}
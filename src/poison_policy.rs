@@ -0,0 +1,37 @@
+//! What the cells do when their initializer panics
+//!
+//! Both policies run the initializer through the same `Once` state machine, including the
+//! detection of recursive initialization on Linux. They differ only in the transition taken on panic:
+//! `call_once_try()` poisons while `call_once_try_retry()` moves the `Once` back to incomplete and
+//! wakes the waiters.
+
+use crate::Once;
+
+/// The behavior of a cell whose initialization closure panicked.
+///
+/// Used by [`OnceCell::with_poison_policy()`](crate::OnceCell::with_poison_policy) and
+/// [`OnceLock::with_poison_policy()`](crate::OnceLock::with_poison_policy).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PoisonPolicy {
+    /// The cell is poisoned, accessing it panics.
+    ///
+    /// This is what [`std::sync::Once`] does and the default of [`OnceCell`](crate::OnceCell).
+    Poison,
+    /// The cell stays empty and the next caller, possibly one that's waiting right now, runs its
+    /// own closure.
+    ///
+    /// This is what [`std::sync::OnceLock`] does and the default of
+    /// [`OnceLock`](crate::OnceLock).
+    Retry,
+}
+
+/// Runs `f` unless `once` completed, handling panics according to `policy`
+///
+/// If `f` fails the `Once` stays incomplete with both policies.
+#[track_caller]
+pub(crate) fn call_once_with_policy<E, F: FnOnce() -> Result<(), E>>(once: &Once, policy: PoisonPolicy, f: F) -> Result<(), E> {
+    match policy {
+        PoisonPolicy::Poison => once.call_once_try(f),
+        PoisonPolicy::Retry => once.call_once_try_retry(f),
+    }
+}
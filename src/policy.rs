@@ -0,0 +1,112 @@
+//! Policies controlling how threads wait for a running initialization
+//!
+//! The policy is a type parameter of [`Once`](crate::Once) so it doesn't take any space and can
+//! be chosen in statics. The default one spins briefly and then blocks, which works well for
+//! both short and long initializers. Custom policies can be defined by implementing
+//! [`WaitPolicy`]:
+//!
+//! ```
+//! use linux_once::policy::{SpinPolicy, WaitPolicy};
+//!
+//! struct Patient;
+//!
+//! impl WaitPolicy for Patient {
+//!     const POLICY: SpinPolicy = SpinPolicy::new().max_spins(10_000).yield_between_spins(true);
+//! }
+//! ```
+//!
+//! The policies only affect waiting, the initializing thread behaves the same regardless.
+
+/// Parameters of waiting
+///
+/// Constructed using const builder methods so that it can be used in [`WaitPolicy::POLICY`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpinPolicy {
+    pub(crate) max_spins: u32,
+    pub(crate) yield_between_spins: bool,
+    pub(crate) block: bool,
+}
+
+impl SpinPolicy {
+    /// Creates the default policy: spin for a few microseconds and then block.
+    pub const fn new() -> Self {
+        SpinPolicy {
+            max_spins: 100,
+            yield_between_spins: false,
+            block: true,
+        }
+    }
+
+    /// Sets how many times the state is checked before blocking.
+    ///
+    /// Zero means blocking immediately. This only applies when blocking is enabled, with
+    /// blocking disabled the thread spins for as long as needed. Spinning before blocking is
+    /// skipped on single-CPU systems since it would only delay the initializer.
+    pub const fn max_spins(mut self, max_spins: u32) -> Self {
+        self.max_spins = max_spins;
+        self
+    }
+
+    /// Sets whether the thread yields to the scheduler between checks instead of just hinting
+    /// the CPU.
+    ///
+    /// Note that yielding is a syscall.
+    pub const fn yield_between_spins(mut self, yield_between_spins: bool) -> Self {
+        self.yield_between_spins = yield_between_spins;
+        self
+    }
+
+    /// Sets whether the thread may block using the futex syscall.
+    ///
+    /// If blocking is disabled the waiting threads spin until the state changes. Such threads
+    /// never register as waiters so the initializer doesn't have to wake them up. This is useful
+    /// for threads that must not enter the kernel but it wastes CPU if the initializer is slow,
+    /// or never finishes at all if the initializer got preempted by a spinning thread with higher
+    /// priority on the same CPU.
+    pub const fn block(mut self, block: bool) -> Self {
+        self.block = block;
+        self
+    }
+}
+
+impl Default for SpinPolicy {
+    fn default() -> Self {
+        SpinPolicy::new()
+    }
+}
+
+/// Chooses the [`SpinPolicy`] of a [`Once`](crate::Once)
+pub trait WaitPolicy {
+    /// The policy used when waiting
+    const POLICY: SpinPolicy;
+}
+
+/// Spins for a few microseconds and then blocks
+///
+/// This is the default policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DefaultWait;
+
+impl WaitPolicy for DefaultWait {
+    const POLICY: SpinPolicy = SpinPolicy::new();
+}
+
+/// Blocks immediately without spinning
+///
+/// Good for initializers that are known to be slow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BlockImmediately;
+
+impl WaitPolicy for BlockImmediately {
+    const POLICY: SpinPolicy = SpinPolicy::new().max_spins(0);
+}
+
+/// Never blocks, spinning until the state changes
+///
+/// See [`SpinPolicy::block()`] for the hazards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SpinOnly;
+
+impl WaitPolicy for SpinOnly {
+    const POLICY: SpinPolicy = SpinPolicy::new().block(false);
+}
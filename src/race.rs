@@ -0,0 +1,649 @@
+//! Cells initialized by racing threads
+//!
+//! Unlike the other types of this crate these never block: if several threads find the cell
+//! uninitialized all of them compute the value and the first one to store it wins, the others
+//! get the winning value. This is a good fit for values that are cheap to compute but read very
+//! often, such as CPU feature detection. The initialization never blocks, only
+//! `OnceNonZeroU32::wait()` does.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::num::{NonZeroU32, NonZeroUsize};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicI32, AtomicPtr, AtomicU8, AtomicUsize, Ordering};
+
+const UNINIT: u8 = 0;
+const FALSE: u8 = 1;
+const TRUE: u8 = 2;
+
+/// A boolean which can be set only once, initialized by racing threads.
+///
+/// ```
+/// use linux_once::race::OnceBool;
+///
+/// static HAS_AVX2: OnceBool = OnceBool::new();
+///
+/// let has_avx2 = HAS_AVX2.get_or_init(|| cfg!(target_feature = "avx2"));
+/// assert_eq!(HAS_AVX2.get(), Some(has_avx2));
+/// ```
+pub struct OnceBool {
+    state: AtomicU8,
+}
+
+impl OnceBool {
+    /// Creates a new uninitialized `OnceBool`.
+    pub const fn new() -> Self {
+        OnceBool { state: AtomicU8::new(UNINIT) }
+    }
+
+    /// Returns the value if it was initialized.
+    pub fn get(&self) -> Option<bool> {
+        Self::decode(self.state.load(Ordering::Acquire))
+    }
+
+    /// Sets the value if it's uninitialized.
+    ///
+    /// Returns `Err` with the current value if it was initialized already.
+    pub fn set(&self, value: bool) -> Result<(), bool> {
+        match self.state.compare_exchange(UNINIT, Self::encode(value), Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => Ok(()),
+            Err(current) => Err(current == TRUE),
+        }
+    }
+
+    /// Returns the value, initializing it with `f` if it's uninitialized.
+    ///
+    /// If several threads call this concurrently `f` may run on each of them, the value stored
+    /// first is returned to all of them.
+    pub fn get_or_init<F: FnOnce() -> bool>(&self, f: F) -> bool {
+        match self.get() {
+            Some(value) => value,
+            None => {
+                let value = f();
+                match self.set(value) {
+                    Ok(()) => value,
+                    Err(current) => current,
+                }
+            },
+        }
+    }
+
+    fn encode(value: bool) -> u8 {
+        if value { TRUE } else { FALSE }
+    }
+
+    fn decode(state: u8) -> Option<bool> {
+        match state {
+            UNINIT => None,
+            state => Some(state == TRUE),
+        }
+    }
+}
+
+impl Default for OnceBool {
+    fn default() -> Self {
+        OnceBool::new()
+    }
+}
+
+impl fmt::Debug for OnceBool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnceBool").field(&self.get()).finish()
+    }
+}
+
+/// A non-zero 32-bit integer which can be set only once, initialized by racing threads.
+///
+/// The value is stored in a single 32-bit word with zero meaning uninitialized, so the whole cell
+/// takes 4 bytes. Besides the racy initialization a thread can block until another thread
+/// publishes the value using [`wait()`](Self::wait), which uses the word as a futex on Linux.
+///
+/// ```
+/// use linux_once::race::OnceNonZeroU32;
+/// use std::num::NonZeroU32;
+///
+/// static ID: OnceNonZeroU32 = OnceNonZeroU32::new();
+///
+/// let id = ID.get_or_init(|| NonZeroU32::new(42).unwrap());
+/// assert_eq!(ID.get(), Some(id));
+/// ```
+pub struct OnceNonZeroU32 {
+    // signed because that's what the futex helpers take, the bits are reinterpreted
+    value: AtomicI32,
+}
+
+impl OnceNonZeroU32 {
+    /// Creates a new uninitialized `OnceNonZeroU32`.
+    pub const fn new() -> Self {
+        OnceNonZeroU32 { value: AtomicI32::new(0) }
+    }
+
+    /// Returns the value if it was initialized.
+    pub fn get(&self) -> Option<NonZeroU32> {
+        NonZeroU32::new(self.value.load(Ordering::Acquire) as u32)
+    }
+
+    /// Sets the value if it's uninitialized.
+    ///
+    /// Returns `Err` with the current value if it was initialized already. On Linux the
+    /// successful call also wakes up the threads blocked in [`wait()`](Self::wait), which costs
+    /// one syscall during the lifetime of the cell.
+    pub fn set(&self, value: NonZeroU32) -> Result<(), NonZeroU32> {
+        match self.value.compare_exchange(0, value.get() as i32, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => {
+                #[cfg(target_os = "linux")]
+                crate::futex::wake(&self.value, i32::MAX);
+                Ok(())
+            },
+            Err(current) => Err(NonZeroU32::new(current as u32).expect("the value is initialized")),
+        }
+    }
+
+    /// Returns the value, initializing it with `f` if it's uninitialized.
+    ///
+    /// If several threads call this concurrently `f` may run on each of them, the value stored
+    /// first is returned to all of them.
+    pub fn get_or_init<F: FnOnce() -> NonZeroU32>(&self, f: F) -> NonZeroU32 {
+        match self.get() {
+            Some(value) => value,
+            None => {
+                let value = f();
+                match self.set(value) {
+                    Ok(()) => value,
+                    Err(current) => current,
+                }
+            },
+        }
+    }
+
+    /// Blocks the current thread until another thread sets the value.
+    ///
+    /// This never initializes the value itself so if no other thread ever does this blocks
+    /// forever.
+    ///
+    /// Only available on Linux.
+    #[cfg(target_os = "linux")]
+    pub fn wait(&self) -> NonZeroU32 {
+        loop {
+            if let Some(value) = self.get() {
+                break value;
+            }
+            crate::futex::wait(&self.value, 0);
+        }
+    }
+}
+
+impl Default for OnceNonZeroU32 {
+    fn default() -> Self {
+        OnceNonZeroU32::new()
+    }
+}
+
+impl fmt::Debug for OnceNonZeroU32 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnceNonZeroU32").field(&self.get()).finish()
+    }
+}
+
+/// A non-zero `usize` which can be set only once, initialized by racing threads.
+///
+/// This is the same as [`OnceNonZeroU32`] except it's pointer-sized and there's no blocking
+/// [`wait()`](OnceNonZeroU32::wait) since futexes are always 32-bit.
+pub struct OnceNonZeroUsize {
+    value: AtomicUsize,
+}
+
+impl OnceNonZeroUsize {
+    /// Creates a new uninitialized `OnceNonZeroUsize`.
+    pub const fn new() -> Self {
+        OnceNonZeroUsize { value: AtomicUsize::new(0) }
+    }
+
+    /// Returns the value if it was initialized.
+    pub fn get(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.value.load(Ordering::Acquire))
+    }
+
+    /// Sets the value if it's uninitialized.
+    ///
+    /// Returns `Err` with the current value if it was initialized already.
+    pub fn set(&self, value: NonZeroUsize) -> Result<(), NonZeroUsize> {
+        match self.value.compare_exchange(0, value.get(), Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => Ok(()),
+            Err(current) => Err(NonZeroUsize::new(current).expect("the value is initialized")),
+        }
+    }
+
+    /// Returns the value, initializing it with `f` if it's uninitialized.
+    ///
+    /// If several threads call this concurrently `f` may run on each of them, the value stored
+    /// first is returned to all of them.
+    pub fn get_or_init<F: FnOnce() -> NonZeroUsize>(&self, f: F) -> NonZeroUsize {
+        match self.get() {
+            Some(value) => value,
+            None => {
+                let value = f();
+                match self.set(value) {
+                    Ok(()) => value,
+                    Err(current) => current,
+                }
+            },
+        }
+    }
+}
+
+impl Default for OnceNonZeroUsize {
+    fn default() -> Self {
+        OnceNonZeroUsize::new()
+    }
+}
+
+impl fmt::Debug for OnceNonZeroUsize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnceNonZeroUsize").field(&self.get()).finish()
+    }
+}
+
+/// A pointer which can be published only once, initialized by racing threads.
+///
+/// The pointer that is stored first wins. Storing it has release semantics and loading it
+/// acquire semantics so everything written to the pointee before publishing it is visible to the
+/// threads that obtained it from [`get()`](Self::get) or [`set()`](Self::set).
+///
+/// This type never frees anything. A thread that loses the race still owns its pointer and is
+/// responsible for freeing it:
+///
+/// ```
+/// use linux_once::race::OncePtr;
+/// use std::ptr::NonNull;
+///
+/// static TABLE: OncePtr<[u8; 256]> = OncePtr::new();
+///
+/// fn table() -> &'static [u8; 256] {
+///     let table = TABLE.get().unwrap_or_else(|| {
+///         let ours = NonNull::from(Box::leak(Box::new([0; 256])));
+///         TABLE.set(ours).map(|()| ours).unwrap_or_else(|winner| {
+///             // SAFETY: the pointer came from Box::leak above and was never published
+///             drop(unsafe { Box::from_raw(ours.as_ptr()) });
+///             winner
+///         })
+///     });
+///     // SAFETY: the published table is never freed or modified
+///     unsafe { table.as_ref() }
+/// }
+///
+/// assert_eq!(table()[42], 0);
+/// ```
+pub struct OncePtr<T> {
+    ptr: AtomicPtr<T>,
+}
+
+impl<T> OncePtr<T> {
+    /// Creates a new uninitialized `OncePtr`.
+    pub const fn new() -> Self {
+        OncePtr { ptr: AtomicPtr::new(ptr::null_mut()) }
+    }
+
+    /// Returns the pointer if it was published.
+    pub fn get(&self) -> Option<NonNull<T>> {
+        NonNull::new(self.ptr.load(Ordering::Acquire))
+    }
+
+    /// Publishes the pointer if no pointer was published yet.
+    ///
+    /// Returns `Err` with the published pointer if some was published already, the given pointer
+    /// is then left to the caller.
+    pub fn set(&self, ptr: NonNull<T>) -> Result<(), NonNull<T>> {
+        match self.ptr.compare_exchange(ptr::null_mut(), ptr.as_ptr(), Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => Ok(()),
+            Err(current) => Err(NonNull::new(current).expect("the pointer is published")),
+        }
+    }
+
+    /// Returns the published pointer, publishing the one returned by `alloc` if none was.
+    ///
+    /// If several threads call this concurrently `alloc` may run on each of them, the pointer
+    /// published first is returned to all of them and the other pointers are passed to
+    /// `dealloc`. This allows using any allocator, including ones handed around explicitly
+    /// instead of the global one:
+    ///
+    /// ```
+    /// use linux_once::race::OncePtr;
+    /// use std::alloc::{alloc, dealloc, Layout};
+    /// use std::ptr::NonNull;
+    ///
+    /// static TABLE: OncePtr<[u8; 4096]> = OncePtr::new();
+    ///
+    /// let layout = Layout::new::<[u8; 4096]>();
+    /// let table = TABLE.get_or_init_with_dealloc(
+    ///     // SAFETY: the layout is not zero-sized and the memory is initialized before use
+    ///     || unsafe {
+    ///         let table = NonNull::new(alloc(layout).cast::<[u8; 4096]>()).expect("out of memory");
+    ///         table.as_ptr().write([0; 4096]);
+    ///         table
+    ///     },
+    ///     // SAFETY: the pointer was allocated above with the same layout
+    ///     |table| unsafe { dealloc(table.as_ptr().cast(), layout) },
+    /// );
+    /// // SAFETY: the published table is never freed or modified
+    /// assert_eq!(unsafe { table.as_ref() }[42], 0);
+    /// ```
+    ///
+    /// The published pointer is never passed to `dealloc`, freeing it is up to the caller. See
+    /// [`OnceBoxIn`] for a cell that owns it.
+    pub fn get_or_init_with_dealloc<A, D>(&self, alloc: A, dealloc: D) -> NonNull<T>
+    where
+        A: FnOnce() -> NonNull<T>,
+        D: FnOnce(NonNull<T>),
+    {
+        match self.get() {
+            Some(ptr) => ptr,
+            None => {
+                let ours = alloc();
+                match self.set(ours) {
+                    Ok(()) => ours,
+                    Err(winner) => {
+                        dealloc(ours);
+                        winner
+                    },
+                }
+            },
+        }
+    }
+}
+
+impl<T> Default for OncePtr<T> {
+    fn default() -> Self {
+        OncePtr::new()
+    }
+}
+
+impl<T> fmt::Debug for OncePtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OncePtr").field(&self.get()).finish()
+    }
+}
+
+/// A reference which can be set only once, initialized by racing threads.
+///
+/// This is useful for interning, e.g. with leaked `&'static` references:
+///
+/// ```
+/// use linux_once::race::OnceRef;
+///
+/// static CONFIG: OnceRef<'static, String> = OnceRef::new();
+///
+/// let config = CONFIG.get_or_init(|| Box::leak(Box::new(String::from("verbose"))));
+/// assert_eq!(config, "verbose");
+/// assert_eq!(CONFIG.set(Box::leak(Box::new(String::from("quiet")))), Err(()));
+/// ```
+///
+/// `OnceRef` is invariant in `'a`. If it was covariant a `&OnceRef<'static, T>` could be turned
+/// into a `&OnceRef<'short, T>`, which would allow storing a short-lived reference and then
+/// reading it back as `'static`:
+///
+/// ```compile_fail
+/// use linux_once::race::OnceRef;
+///
+/// fn shorten<'a>(cell: &'a OnceRef<'static, u32>) -> &'a OnceRef<'a, u32> {
+///     cell
+/// }
+/// ```
+///
+/// Making it longer-lived is unsound for the same reason:
+///
+/// ```compile_fail
+/// use linux_once::race::OnceRef;
+///
+/// fn lengthen<'a>(cell: OnceRef<'a, u32>) -> OnceRef<'static, u32> {
+///     cell
+/// }
+/// ```
+pub struct OnceRef<'a, T> {
+    ptr: AtomicPtr<T>,
+    // invariant in 'a, see the type documentation
+    _phantom: PhantomData<UnsafeCell<&'a T>>,
+}
+
+impl<'a, T> OnceRef<'a, T> {
+    /// Creates a new uninitialized `OnceRef`.
+    pub const fn new() -> Self {
+        OnceRef { ptr: AtomicPtr::new(ptr::null_mut()), _phantom: PhantomData }
+    }
+
+    /// Returns the reference if it was set.
+    pub fn get(&self) -> Option<&'a T> {
+        let ptr = self.ptr.load(Ordering::Acquire);
+        // SAFETY: only pointers obtained from `&'a T` are stored, see set
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Sets the reference if it's uninitialized.
+    ///
+    /// Returns `Err` if it was initialized already.
+    // same signature as once_cell::race::OnceRef
+    #[allow(clippy::result_unit_err)]
+    pub fn set(&self, value: &'a T) -> Result<(), ()> {
+        // the pointer is only ever turned back into a shared reference
+        let ptr = value as *const T as *mut T;
+        match self.ptr.compare_exchange(ptr::null_mut(), ptr, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(()),
+        }
+    }
+
+    /// Returns the reference, initializing it with `f` if it's uninitialized.
+    ///
+    /// If several threads call this concurrently `f` may run on each of them, the reference
+    /// stored first is returned to all of them.
+    pub fn get_or_init<F: FnOnce() -> &'a T>(&self, f: F) -> &'a T {
+        match self.get() {
+            Some(value) => value,
+            None => {
+                let value = f();
+                match self.set(value) {
+                    Ok(()) => value,
+                    Err(()) => self.get().expect("the reference is set"),
+                }
+            },
+        }
+    }
+}
+
+impl<'a, T> Default for OnceRef<'a, T> {
+    fn default() -> Self {
+        OnceRef::new()
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for OnceRef<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnceRef").field(&self.get()).finish()
+    }
+}
+
+// SAFETY: the cell only hands out shared references
+unsafe impl<'a, T: Sync> Sync for OnceRef<'a, T> {}
+// SAFETY: same as above
+unsafe impl<'a, T: Sync> Send for OnceRef<'a, T> {}
+
+/// A box which can be set only once, initialized by racing threads.
+///
+/// This is useful for lazily allocated tables where allocating more than once is acceptable: if
+/// several threads find the cell empty each of them allocates, the box stored first wins and the
+/// other boxes are dropped. The boxes always use the global allocator, see [`OnceBoxIn`] for
+/// other allocators.
+///
+/// ```
+/// use linux_once::race::OnceBox;
+///
+/// static TABLE: OnceBox<[u8; 256]> = OnceBox::new();
+///
+/// let table = TABLE.get_or_init(|| Box::new([7; 256]));
+/// assert_eq!(table[42], 7);
+/// ```
+pub struct OnceBox<T> {
+    ptr: AtomicPtr<T>,
+    // owns the box, so it's Send and Sync the same way
+    _phantom: PhantomData<Option<Box<T>>>,
+}
+
+impl<T> OnceBox<T> {
+    /// Creates a new empty `OnceBox`.
+    pub const fn new() -> Self {
+        OnceBox { ptr: AtomicPtr::new(ptr::null_mut()), _phantom: PhantomData }
+    }
+
+    /// Returns the value if it was set.
+    pub fn get(&self) -> Option<&T> {
+        let ptr = self.ptr.load(Ordering::Acquire);
+        // SAFETY: only pointers from Box::into_raw are stored, the box is freed only in Drop and
+        // the acquire load synchronizes with the release store so the contents are visible
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Sets the value if it's empty.
+    ///
+    /// Returns the box back in `Err` if the cell was set already.
+    pub fn set(&self, value: Box<T>) -> Result<(), Box<T>> {
+        let ptr = Box::into_raw(value);
+        match self.ptr.compare_exchange(ptr::null_mut(), ptr, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => Ok(()),
+            // SAFETY: the pointer came from Box::into_raw and was never published
+            Err(_) => Err(unsafe { Box::from_raw(ptr) }),
+        }
+    }
+
+    /// Returns the value, initializing it with `f` if it's empty.
+    ///
+    /// If several threads call this concurrently `f` may run on each of them, the box stored
+    /// first is returned to all of them and the other boxes are dropped.
+    pub fn get_or_init<F: FnOnce() -> Box<T>>(&self, f: F) -> &T {
+        match self.get() {
+            Some(value) => value,
+            None => {
+                // the losing box is dropped right away
+                let _ = self.set(f());
+                self.get().expect("the box is set")
+            },
+        }
+    }
+}
+
+impl<T> Drop for OnceBox<T> {
+    fn drop(&mut self) {
+        let ptr = *self.ptr.get_mut();
+        if !ptr.is_null() {
+            // SAFETY: the pointer came from Box::into_raw in set and nobody can access it anymore
+            drop(unsafe { Box::from_raw(ptr) });
+        }
+    }
+}
+
+impl<T> Default for OnceBox<T> {
+    fn default() -> Self {
+        OnceBox::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnceBox").field(&self.get()).finish()
+    }
+}
+
+// SAFETY: the value is shared between threads and may be dropped by any of them, same as in
+// OnceCell
+unsafe impl<T: Send + Sync> Sync for OnceBox<T> {}
+
+/// A [`OnceBox`] allocating through a custom allocator, initialized by racing threads.
+///
+/// The value is allocated by the closure passed to [`get_or_init_in()`](Self::get_or_init_in)
+/// and freed by `dealloc` given to [`new()`](Self::new): right away if another thread stored its
+/// value first or when the cell is dropped. `dealloc` may capture the allocator, which is useful
+/// when it's handed around explicitly instead of being global.
+///
+/// ```
+/// use linux_once::race::OnceBoxIn;
+/// use std::alloc::{alloc, dealloc, Layout};
+/// use std::ptr::NonNull;
+///
+/// let layout = Layout::new::<[u8; 4096]>();
+/// // SAFETY: the pointers come from alloc with the same layout and [u8; 4096] doesn't need drop
+/// let table = OnceBoxIn::new(|table: NonNull<[u8; 4096]>| unsafe { dealloc(table.as_ptr().cast(), layout) });
+/// // SAFETY: the layout is not zero-sized and the memory is initialized before it's returned
+/// let value = unsafe { table.get_or_init_in(|| {
+///     let table = NonNull::new(alloc(layout).cast::<[u8; 4096]>()).expect("out of memory");
+///     table.as_ptr().write([0; 4096]);
+///     table
+/// }) };
+/// assert_eq!(value[42], 0);
+/// ```
+pub struct OnceBoxIn<T, D: Fn(NonNull<T>)> {
+    ptr: AtomicPtr<T>,
+    dealloc: D,
+    // owns the value, so it's Send and Sync the same way
+    _phantom: PhantomData<Option<Box<T>>>,
+}
+
+impl<T, D: Fn(NonNull<T>)> OnceBoxIn<T, D> {
+    /// Creates a new empty cell freeing its values using `dealloc`.
+    ///
+    /// `dealloc` is responsible for dropping the value as well as for freeing the memory.
+    pub const fn new(dealloc: D) -> Self {
+        OnceBoxIn { ptr: AtomicPtr::new(ptr::null_mut()), dealloc, _phantom: PhantomData }
+    }
+
+    /// Returns the value if it was set.
+    pub fn get(&self) -> Option<&T> {
+        let ptr = self.ptr.load(Ordering::Acquire);
+        // SAFETY: the caller of get_or_init_in guaranteed the pointer stays valid until it's
+        // passed to dealloc, which happens only in Drop, and the acquire load synchronizes with
+        // the release store so the contents are visible
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Returns the value, initializing it with the pointer returned by `alloc` if it's empty.
+    ///
+    /// If several threads call this concurrently `alloc` may run on each of them, the pointer
+    /// stored first is returned to all of them and the other pointers are passed to `dealloc`
+    /// right away.
+    ///
+    /// # Safety
+    ///
+    /// The pointer returned by `alloc` must point to a valid value of `T` that stays valid and
+    /// isn't accessed by anything else until it's passed to `dealloc` of this cell. `dealloc`
+    /// must accept it.
+    pub unsafe fn get_or_init_in<A: FnOnce() -> NonNull<T>>(&self, alloc: A) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+        let ours = alloc();
+        if let Err(winner) = self.ptr.compare_exchange(ptr::null_mut(), ours.as_ptr(), Ordering::AcqRel, Ordering::Acquire) {
+            // the pointer was never published so nobody else can see it
+            (self.dealloc)(ours);
+            // SAFETY: same as in get
+            return unsafe { &*winner };
+        }
+        // SAFETY: the caller guarantees the pointer is valid until it's passed to dealloc
+        unsafe { ours.as_ref() }
+    }
+}
+
+impl<T, D: Fn(NonNull<T>)> Drop for OnceBoxIn<T, D> {
+    fn drop(&mut self) {
+        if let Some(ptr) = NonNull::new(*self.ptr.get_mut()) {
+            (self.dealloc)(ptr);
+        }
+    }
+}
+
+impl<T: fmt::Debug, D: Fn(NonNull<T>)> fmt::Debug for OnceBoxIn<T, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnceBoxIn").field(&self.get()).finish()
+    }
+}
+
+// SAFETY: same as OnceBox, dealloc may be called by any thread sharing the cell
+unsafe impl<T: Send + Sync, D: Fn(NonNull<T>) + Sync> Sync for OnceBoxIn<T, D> {}
@@ -0,0 +1,211 @@
+//! `Once` that can be reset to run the initialization again
+//!
+//! The futex word holds the state in the low three bits, using the same states as `Once`, and a
+//! generation number in the rest. A reset moves a finished generation to the incomplete state of
+//! the next one in a single compare-exchange, so the state can never be observed half-reset.
+//! Resetting is rejected while an initialization is running, which is the only state threads
+//! wait in, so a reset never has anyone to wake up.
+
+use core::fmt;
+use core::sync::atomic::{AtomicI32, Ordering};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use crate::futex;
+use crate::once_fn::OnceFn;
+use crate::poison_hook;
+use crate::OnceStatus;
+
+const INCOMPLETE: i32 = 0;
+const COMPLETE: i32 = 1;
+const POISONED: i32 = 2;
+/// Used to avoid expensive syscall
+const RUNNING_NO_WAIT: i32 = 3;
+const RUNNING_WAITING: i32 = 4;
+
+const STATE_BITS: u32 = 3;
+const STATE_MASK: i32 = (1 << STATE_BITS) - 1;
+
+/// A variant of [`Once`](crate::Once) which can be reset to run the initialization again.
+///
+/// Within a generation this behaves like [`Once`](crate::Once): the closure passed to
+/// [`call_once()`](Self::call_once) runs at most once and the other callers wait for it. Calling
+/// [`reset()`](Self::reset) after the initialization finished starts a new generation in which
+/// the next caller runs its closure again. This is useful for tests and for reloading
+/// configuration.
+///
+/// ```
+/// use linux_once::ResettableOnce;
+///
+/// static CONFIG: ResettableOnce = ResettableOnce::new();
+///
+/// CONFIG.call_once(|| println!("loading the configuration"));
+/// CONFIG.reset().unwrap();
+/// CONFIG.call_once(|| println!("reloading the configuration"));
+/// assert_eq!(CONFIG.generation(), 1);
+/// ```
+///
+/// A thread that raced with the reset, e.g. one that was woken up after the initialization
+/// completed, simply observes the state of the new generation. Note that this can't protect
+/// data initialized by the closure: callers that need to read it while it may be reset have to
+/// synchronize with the resetting thread in some other way. If the closure recursively calls
+/// [`call_once()`](Self::call_once) on the same instance it deadlocks.
+pub struct ResettableOnce {
+    state: AtomicI32,
+}
+
+impl ResettableOnce {
+    /// Creates a new `ResettableOnce` in generation zero.
+    pub const fn new() -> Self {
+        ResettableOnce { state: AtomicI32::new(INCOMPLETE) }
+    }
+
+    /// Performs an initialization routine once and only once in the current generation.
+    ///
+    /// See [`Once::call_once()`](crate::Once::call_once).
+    ///
+    /// # Panics
+    ///
+    /// If the current generation has been poisoned because an initialization closure has
+    /// panicked, this method will also panic.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        // Fast path, same as in Once
+        if self.state.load(Ordering::Acquire) & STATE_MASK == COMPLETE {
+            return;
+        }
+
+        let mut f = OnceFn::new(f);
+        // SAFETY: call_once_slow calls the closure at most once
+        self.call_once_slow(&mut || unsafe { f.take()() });
+    }
+
+    #[cold]
+    fn call_once_slow(&self, f: &mut dyn FnMut()) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let generation = state & !STATE_MASK;
+            match state & STATE_MASK {
+                COMPLETE => break,
+                POISONED => panic!("Once instance has previously been poisoned"),
+                INCOMPLETE => match self.state.compare_exchange(state, generation | RUNNING_NO_WAIT, Ordering::Acquire, Ordering::Acquire) {
+                    Ok(_) => {
+                        // The generation can't change while running since reset() is rejected
+                        let mut panic_checker = PanicChecker { once: self, value_to_write: generation | POISONED };
+                        f();
+                        panic_checker.value_to_write = generation | COMPLETE;
+                        break;
+                    },
+                    Err(old) => state = old,
+                },
+                RUNNING_NO_WAIT => match self.state.compare_exchange_weak(state, generation | RUNNING_WAITING, Ordering::Acquire, Ordering::Acquire) {
+                    Ok(_) => state = generation | RUNNING_WAITING,
+                    Err(old) => state = old,
+                },
+                _waiting => {
+                    futex::wait(&self.state, state);
+                    state = self.state.load(Ordering::Acquire);
+                },
+            }
+        }
+    }
+
+    /// Starts a new generation in which the initialization runs again.
+    ///
+    /// Resetting a poisoned instance clears the poison. Resetting an instance on which no
+    /// initialization ran in the current generation does nothing.
+    ///
+    /// # Errors
+    ///
+    /// If an initialization is running the instance is not reset since it would allow another
+    /// initialization to run concurrently.
+    pub fn reset(&self) -> Result<(), ResetError> {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            let next = match state & STATE_MASK {
+                INCOMPLETE => return Ok(()),
+                COMPLETE | POISONED => (state & !STATE_MASK).wrapping_add(1 << STATE_BITS) | INCOMPLETE,
+                _running => return Err(ResetError(())),
+            };
+            // Acquire so that the resetting thread sees the data the initialization produced and
+            // Release so that its earlier accesses to the data can't move after the reset, the
+            // initializer of the next generation acquires the state and synchronizes with them
+            match self.state.compare_exchange_weak(state, next, Ordering::AcqRel, Ordering::Relaxed) {
+                Ok(_) => return Ok(()),
+                Err(old) => state = old,
+            }
+        }
+    }
+
+    /// Returns the current generation, which is incremented by each successful reset.
+    ///
+    /// The generation is stored in 29 bits so it wraps around to zero after 2^29 resets. It may
+    /// change right after it was observed so this is mainly useful for debugging.
+    pub fn generation(&self) -> u32 {
+        self.state.load(Ordering::Relaxed) as u32 >> STATE_BITS
+    }
+
+    /// Returns `true` if an initialization has completed successfully in the current
+    /// generation.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) & STATE_MASK == COMPLETE
+    }
+
+    /// Returns a snapshot of the state of the current generation.
+    ///
+    /// The state may change right after it was observed so this is mainly useful for debugging.
+    pub fn state(&self) -> OnceStatus {
+        match self.state.load(Ordering::Acquire) & STATE_MASK {
+            COMPLETE => OnceStatus::Complete,
+            POISONED => OnceStatus::Poisoned,
+            RUNNING_NO_WAIT | RUNNING_WAITING => OnceStatus::Running,
+            _ => OnceStatus::Incomplete,
+        }
+    }
+}
+
+impl Default for ResettableOnce {
+    fn default() -> Self {
+        ResettableOnce::new()
+    }
+}
+
+impl fmt::Debug for ResettableOnce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResettableOnce")
+            .field("generation", &self.generation())
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+// Same as Once
+impl UnwindSafe for ResettableOnce {}
+impl RefUnwindSafe for ResettableOnce {}
+
+/// Error returned by [`ResettableOnce::reset()`] while an initialization is running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResetError(());
+
+impl fmt::Display for ResetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("can't reset the Once while an initialization is running")
+    }
+}
+
+impl std::error::Error for ResetError {}
+
+/// Writes the final state and wakes up the waiters, poisoning unless told otherwise
+struct PanicChecker<'a> {
+    once: &'a ResettableOnce,
+    value_to_write: i32,
+}
+
+impl<'a> Drop for PanicChecker<'a> {
+    fn drop(&mut self) {
+        // Only make expensive syscall if there are threads waiting
+        if self.once.state.swap(self.value_to_write, Ordering::Release) & STATE_MASK == RUNNING_WAITING {
+            futex::wake(&self.once.state, i32::MAX);
+        }
+        if self.value_to_write & STATE_MASK == POISONED {
+            poison_hook::notify_without_details();
+        }
+    }
+}
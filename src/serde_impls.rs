@@ -0,0 +1,40 @@
+//! Serialization of the cell types
+//!
+//! The cells are (de)serialized as `Option<T>`: an initialized cell as the value and an empty one
+//! as none. Serialization never initializes anything, so an uninitialized `LazyLock` is
+//! serialized as none without running its function.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::{LazyLock, OnceCell, OnceLock};
+
+impl<T: Serialize> Serialize for OnceCell<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.get().serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for OnceCell<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Option::<T>::deserialize(deserializer)?.map_or_else(OnceCell::new, OnceCell::from))
+    }
+}
+
+impl<T: Serialize> Serialize for OnceLock<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.get().serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for OnceLock<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Option::<T>::deserialize(deserializer)?.map_or_else(OnceLock::new, OnceLock::from))
+    }
+}
+
+// The function can't be deserialized so only serialization is supported
+impl<T: Serialize, F> Serialize for LazyLock<T, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        LazyLock::get(self).serialize(serializer)
+    }
+}
+
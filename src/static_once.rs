@@ -0,0 +1,122 @@
+//! Value handed out as `&'static mut` at most once
+//!
+//! Nobody ever waits for the value so a plain atomic state suffices. The thread that moves the
+//! state to `TAKEN` owns the value exclusively from then on, which is what makes handing out the
+//! mutable reference sound.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+const UNINIT: u8 = 0;
+const READY: u8 = 1;
+const TAKEN: u8 = 2;
+
+/// A static value from which a mutable reference can be obtained exactly once.
+///
+/// This is useful for static buffers and drivers in low-level code: the first caller of
+/// [`take()`](Self::take) gets `&'static mut T` and all later callers get `None`, even when they
+/// race. There's no allocation and the value doesn't have to be `Sync` since only one thread can
+/// ever access it.
+///
+/// ```
+/// use linux_once::StaticOnce;
+///
+/// static BUFFER: StaticOnce<[u8; 4096]> = StaticOnce::new([0; 4096]);
+///
+/// let buffer: &'static mut [u8; 4096] = BUFFER.take().unwrap();
+/// buffer[0] = 42;
+/// assert!(BUFFER.take().is_none());
+/// ```
+///
+/// The value may also be provided at runtime using [`init()`](Self::init) if the cell was
+/// created by [`uninit()`](Self::uninit).
+pub struct StaticOnce<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> StaticOnce<T> {
+    /// Creates a cell holding `value`, ready to be taken.
+    pub const fn new(value: T) -> Self {
+        StaticOnce { state: AtomicU8::new(READY), value: UnsafeCell::new(MaybeUninit::new(value)) }
+    }
+
+    /// Creates an empty cell to be initialized using [`init()`](Self::init).
+    pub const fn uninit() -> Self {
+        StaticOnce { state: AtomicU8::new(UNINIT), value: UnsafeCell::new(MaybeUninit::uninit()) }
+    }
+
+    /// Returns the mutable reference to the value if it's the first call that can take it.
+    ///
+    /// Returns `None` if the value was taken or initialized already or if the cell is empty.
+    // the state makes sure only one mutable reference is handed out
+    #[allow(clippy::mut_from_ref)]
+    pub fn take(&'static self) -> Option<&'static mut T> {
+        match self.state.compare_exchange(READY, TAKEN, Ordering::Acquire, Ordering::Relaxed) {
+            // SAFETY: the value is initialized and this thread is the only one that moved the
+            // state to TAKEN so nobody else accesses it
+            Ok(_) => Some(unsafe { (*self.value.get()).assume_init_mut() }),
+            Err(_) => None,
+        }
+    }
+
+    /// Stores `value` in the empty cell and returns the mutable reference to it.
+    ///
+    /// Returns `value` back if the cell was not empty, either because it was created using
+    /// [`new()`](Self::new) or because it was already initialized.
+    // the state makes sure only one mutable reference is handed out
+    #[allow(clippy::mut_from_ref)]
+    pub fn try_init(&'static self, value: T) -> Result<&'static mut T, T> {
+        // Nobody reads the value written here, the reference is returned to this thread
+        match self.state.compare_exchange(UNINIT, TAKEN, Ordering::Relaxed, Ordering::Relaxed) {
+            // SAFETY: this thread is the only one that moved the state from UNINIT so nobody else
+            // accesses the value
+            Ok(_) => Ok(unsafe { (*self.value.get()).write(value) }),
+            Err(_) => Err(value),
+        }
+    }
+
+    /// Stores `value` in the empty cell and returns the mutable reference to it.
+    ///
+    /// # Panics
+    ///
+    /// If the cell was not empty this method panics, see [`try_init()`](Self::try_init).
+    pub fn init(&'static self, value: T) -> &'static mut T {
+        match self.try_init(value) {
+            Ok(value) => value,
+            Err(_) => panic!("StaticOnce initialized twice"),
+        }
+    }
+
+    /// Returns `true` if the mutable reference to the value has been handed out.
+    pub fn is_taken(&self) -> bool {
+        self.state.load(Ordering::Relaxed) == TAKEN
+    }
+}
+
+impl<T> Drop for StaticOnce<T> {
+    fn drop(&mut self) {
+        // The value can't be borrowed anymore, even if it was taken, since the borrow is 'static
+        if *self.state.get_mut() != UNINIT {
+            // SAFETY: the value is initialized in any other state and it's never accessed again
+            unsafe { self.value.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+impl<T> fmt::Debug for StaticOnce<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.state.load(Ordering::Relaxed) {
+            UNINIT => "uninit",
+            READY => "ready",
+            _ => "taken",
+        };
+        f.debug_struct("StaticOnce").field("state", &format_args!("{}", state)).finish()
+    }
+}
+
+// SAFETY: the value is accessed by exactly one thread which might be different from the one that
+// created it, so it's sent but never shared
+unsafe impl<T: Send> Sync for StaticOnce<T> {}
@@ -0,0 +1,53 @@
+//! Process-global counters of contention on `Once` instances
+//!
+//! Only available with the `stats` feature. The counters are shared by all `Once` instances and
+//! updated with relaxed atomics at the points where the thread is already slow anyway, so they
+//! are cheap but the snapshot is not consistent across counters when taken concurrently.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+static SLOW_PATH: AtomicU64 = AtomicU64::new(0);
+static BLOCKED: AtomicU64 = AtomicU64::new(0);
+static WAKES: AtomicU64 = AtomicU64::new(0);
+
+/// Values of the counters at the time [`snapshot()`] was called
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OnceStats {
+    /// Number of calls that didn't find the `Once` complete and had to go through the slow path
+    pub slow_path: u64,
+    /// Number of times a thread blocked in the `futex` wait syscall
+    pub blocked: u64,
+    /// Number of `futex` wake syscalls issued
+    pub wakes: u64,
+}
+
+/// Returns the current values of the counters
+pub fn snapshot() -> OnceStats {
+    OnceStats {
+        slow_path: SLOW_PATH.load(Ordering::Relaxed),
+        blocked: BLOCKED.load(Ordering::Relaxed),
+        wakes: WAKES.load(Ordering::Relaxed),
+    }
+}
+
+/// Resets all counters to zero
+///
+/// Intended for tests. Operations running concurrently may be counted either before or after the
+/// reset.
+pub fn reset() {
+    SLOW_PATH.store(0, Ordering::Relaxed);
+    BLOCKED.store(0, Ordering::Relaxed);
+    WAKES.store(0, Ordering::Relaxed);
+}
+
+pub(crate) fn record_slow_path() {
+    SLOW_PATH.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn record_blocked() {
+    BLOCKED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn record_wake() {
+    WAKES.fetch_add(1, Ordering::Relaxed);
+}
@@ -67,23 +67,18 @@ fn poison_bad() {
     });
     assert!(t.is_err());
 
-    // TODO: re-enable force
-    /*
     // we can subvert poisoning, however
     let mut called = false;
     O.call_once_force(|p| {
         called = true;
-        assert!(p.poisoned())
+        assert!(p.is_poisoned())
     });
     assert!(called);
 
     // once any success happens, we stop propagating the poison
     O.call_once(|| {});
-    */
 }
 
-// TODO: re-enable force
-/*
 #[test]
 fn wait_for_force_to_finish() {
     static O: Once = Once::new();
@@ -99,7 +94,7 @@ fn wait_for_force_to_finish() {
     let (tx2, rx2) = channel();
     let t1 = thread::spawn(move || {
         O.call_once_force(|p| {
-            assert!(p.poisoned());
+            assert!(p.is_poisoned());
             tx1.send(()).unwrap();
             rx2.recv().unwrap();
         });
@@ -121,4 +116,3 @@ fn wait_for_force_to_finish() {
     assert!(t1.join().is_ok());
     assert!(t2.join().is_ok());
 }
-*/
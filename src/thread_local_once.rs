@@ -0,0 +1,119 @@
+//! Closure running once per thread
+//!
+//! The instances are identified by their addresses, which are unique and stable since the methods
+//! require `&'static self`. Each thread keeps a sorted list of the addresses of the instances
+//! that ran on it, so the list only grows when an instance is used on the thread for the first
+//! time.
+
+use core::cell::RefCell;
+use core::fmt;
+use core::sync::atomic::AtomicU8;
+
+thread_local! {
+    static HAS_RUN: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs a closure at most once on each thread.
+///
+/// This replaces the `thread_local!` + `Cell<bool>` combination, e.g. for initializing
+/// per-thread state of a C library the first time a thread uses it. Since each thread only
+/// observes its own state this never blocks.
+///
+/// ```
+/// use linux_once::ThreadLocalOnce;
+///
+/// static INIT: ThreadLocalOnce = ThreadLocalOnce::new();
+///
+/// INIT.call_once_per_thread(|| println!("initializing the main thread"));
+/// INIT.call_once_per_thread(|| unreachable!());
+/// std::thread::spawn(|| {
+///     assert!(!INIT.has_run_on_this_thread());
+///     INIT.call_once_per_thread(|| println!("initializing another thread"));
+/// }).join().unwrap();
+/// ```
+///
+/// The instance must be a `static`, a constant would create a new temporary on every use:
+///
+/// ```compile_fail
+/// use linux_once::ThreadLocalOnce;
+///
+/// const INIT: ThreadLocalOnce = ThreadLocalOnce::new();
+///
+/// INIT.call_once_per_thread(|| ());
+/// ```
+pub struct ThreadLocalOnce {
+    // Zero-sized statics may share an address and constants without interior mutability get
+    // promoted to statics that may be merged with others, the atomic prevents both
+    _unique: AtomicU8,
+}
+
+impl ThreadLocalOnce {
+    /// Creates a new `ThreadLocalOnce` that didn't run on any thread.
+    pub const fn new() -> Self {
+        ThreadLocalOnce { _unique: AtomicU8::new(0) }
+    }
+
+    /// Runs `f` if it's the first call on the current thread.
+    ///
+    /// The closure is considered run as soon as it starts, so if it calls this method again the
+    /// inner call returns immediately without running its closure.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics the panic is propagated and the closure is not considered run, so the next
+    /// call on the same thread runs its closure.
+    pub fn call_once_per_thread<F: FnOnce()>(&'static self, f: F) {
+        let address = self.address();
+        let inserted = HAS_RUN.with(|has_run| {
+            let mut has_run = has_run.borrow_mut();
+            match has_run.binary_search(&address) {
+                Ok(_) => false,
+                Err(position) => {
+                    has_run.insert(position, address);
+                    true
+                },
+            }
+        });
+        if inserted {
+            let guard = RemoveOnUnwind(address);
+            f();
+            core::mem::forget(guard);
+        }
+    }
+
+    /// Returns `true` if the closure ran or is running on the current thread.
+    pub fn has_run_on_this_thread(&'static self) -> bool {
+        let address = self.address();
+        HAS_RUN.with(|has_run| has_run.borrow().binary_search(&address).is_ok())
+    }
+
+    fn address(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+/// Unmarks the instance if the closure panics
+struct RemoveOnUnwind(usize);
+
+impl Drop for RemoveOnUnwind {
+    fn drop(&mut self) {
+        HAS_RUN.with(|has_run| {
+            let mut has_run = has_run.borrow_mut();
+            if let Ok(position) = has_run.binary_search(&self.0) {
+                has_run.remove(position);
+            }
+        });
+    }
+}
+
+impl Default for ThreadLocalOnce {
+    fn default() -> Self {
+        ThreadLocalOnce::new()
+    }
+}
+
+impl fmt::Debug for ThreadLocalOnce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThreadLocalOnce").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,207 @@
+//! Lazily initialized values with fallible initializers, built on `Once`
+//!
+//! The storage is an enum describing what's initialized, guarded by the `Once` the same way as in
+//! `LazyLock`: only the closure of the `Once` modifies it and it's read only after the `Once`
+//! completed. The two types differ in what a failed initialization does to the `Once`: `TryLazy`
+//! completes it with the error stored, `RetryLazy` leaves it incomplete using `call_once_try()`.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use crate::Once;
+
+enum Slot<T, E, F> {
+    Init(F),
+    Value(T),
+    Error(E),
+    /// The function was moved out and panicked, the `Once` is poisoned
+    Empty,
+}
+
+/// A lazily initialized value with a fallible initializer that caches the error.
+///
+/// The first access runs the initializing function. Whatever it returns is stored and handed out
+/// to all callers: a reference to the value or a reference to the error. The function runs at
+/// most once, so a failed initialization is never retried. Use [`RetryLazy`] if the next caller
+/// should try again instead.
+///
+/// If the function panics the `TryLazy` is poisoned, just like [`Once`], and all further accesses
+/// panic.
+///
+/// ```
+/// use linux_once::TryLazy;
+///
+/// static PORT: TryLazy<u16, std::num::ParseIntError> = TryLazy::new(|| "80a".parse());
+///
+/// assert!(TryLazy::force(&PORT).is_err());
+/// // the function doesn't run again, the error is returned to everyone
+/// assert!(TryLazy::force(&PORT).is_err());
+/// ```
+pub struct TryLazy<T, E, F = fn() -> Result<T, E>> {
+    once: Once,
+    slot: UnsafeCell<Slot<T, E, F>>,
+}
+
+impl<T, E, F: FnOnce() -> Result<T, E>> TryLazy<T, E, F> {
+    /// Creates a new lazy value with the given initializing function.
+    pub const fn new(f: F) -> Self {
+        TryLazy { once: Once::new(), slot: UnsafeCell::new(Slot::Init(f)) }
+    }
+
+    /// Forces the evaluation of the lazy value and returns the result.
+    ///
+    /// If another thread is initializing the value this blocks until it finishes.
+    ///
+    /// # Panics
+    ///
+    /// If the initializing function panics the panic is propagated and the `TryLazy` is
+    /// poisoned. If the `TryLazy` has been poisoned this method panics too.
+    #[track_caller]
+    pub fn force(this: &TryLazy<T, E, F>) -> Result<&T, &E> {
+        this.once.call_once(|| {
+            // SAFETY: the closure of the Once is the only one accessing the slot until it
+            // completes
+            let slot = unsafe { &mut *this.slot.get() };
+            if let Slot::Init(f) = mem::replace(slot, Slot::Empty) {
+                *slot = match f() {
+                    Ok(value) => Slot::Value(value),
+                    Err(error) => Slot::Error(error),
+                };
+            }
+        });
+        // SAFETY: call_once returns only after the Once completed
+        unsafe { this.get_unchecked() }
+    }
+}
+
+impl<T, E, F> TryLazy<T, E, F> {
+    /// Returns the result if the initialization has finished, without initializing it.
+    ///
+    /// This never runs the initializing function and never blocks.
+    pub fn get(this: &TryLazy<T, E, F>) -> Option<Result<&T, &E>> {
+        if this.once.is_completed() {
+            // SAFETY: the Once is complete
+            Some(unsafe { this.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The `Once` must be complete.
+    unsafe fn get_unchecked(&self) -> Result<&T, &E> {
+        match &*self.slot.get() {
+            Slot::Value(value) => Ok(value),
+            Slot::Error(error) => Err(error),
+            Slot::Init(_) | Slot::Empty => unreachable!("completed TryLazy without a result"),
+        }
+    }
+}
+
+impl<T: fmt::Debug, E: fmt::Debug, F> fmt::Debug for TryLazy<T, E, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match TryLazy::get(self) {
+            Some(result) => f.debug_tuple("TryLazy").field(&result).finish(),
+            None => f.debug_struct("TryLazy").field("state", &self.once.state()).finish(),
+        }
+    }
+}
+
+// SAFETY: the function is called by one of the threads and both the value and the error are
+// shared between them
+unsafe impl<T: Send + Sync, E: Send + Sync, F: Send> Sync for TryLazy<T, E, F> {}
+
+// Same as LazyLock, the poisoning protects from observing a broken value
+impl<T: RefUnwindSafe + UnwindSafe, E: RefUnwindSafe + UnwindSafe, F: UnwindSafe> RefUnwindSafe for TryLazy<T, E, F> {}
+impl<T: UnwindSafe, E: UnwindSafe, F: UnwindSafe> UnwindSafe for TryLazy<T, E, F> {}
+
+/// A lazily initialized value with a fallible initializer that's retried after failure.
+///
+/// The first access runs the initializing function. If it succeeds the value is stored and handed
+/// out to all callers. If it fails the error is returned only to the caller that ran the function
+/// and the value stays uninitialized, so the next caller (possibly one that's waiting right now)
+/// runs the function again. Thus the function has to be callable multiple times but it's never
+/// called concurrently. Use [`TryLazy`] if the error should be cached instead.
+///
+/// If the function panics the `RetryLazy` is poisoned, just like [`Once`], and all further
+/// accesses panic.
+pub struct RetryLazy<T, E, F = fn() -> Result<T, E>> {
+    once: Once,
+    slot: UnsafeCell<Slot<T, E, F>>,
+}
+
+impl<T, E, F: FnMut() -> Result<T, E>> RetryLazy<T, E, F> {
+    /// Creates a new lazy value with the given initializing function.
+    pub const fn new(f: F) -> Self {
+        RetryLazy { once: Once::new(), slot: UnsafeCell::new(Slot::Init(f)) }
+    }
+
+    /// Forces the evaluation of the lazy value and returns the reference to it.
+    ///
+    /// If the initializing function fails the error is returned and the value stays
+    /// uninitialized. If another thread is initializing the value this blocks until it finishes
+    /// and runs the function again if the other thread failed.
+    ///
+    /// # Panics
+    ///
+    /// If the initializing function panics the panic is propagated and the `RetryLazy` is
+    /// poisoned. If the `RetryLazy` has been poisoned this method panics too.
+    #[track_caller]
+    pub fn force(this: &RetryLazy<T, E, F>) -> Result<&T, E> {
+        this.once.call_once_try(|| {
+            // SAFETY: the closure of the Once is the only one accessing the slot until it
+            // completes and the slot holds the function until then
+            let slot = unsafe { &mut *this.slot.get() };
+            if let Slot::Init(f) = slot {
+                *slot = Slot::Value(f()?);
+            }
+            Ok(())
+        })?;
+        // SAFETY: call_once_try returns Ok only after the Once completed
+        Ok(unsafe { this.get_unchecked() })
+    }
+}
+
+impl<T, E, F> RetryLazy<T, E, F> {
+    /// Returns the value if it was initialized, without initializing it.
+    ///
+    /// This never runs the initializing function and never blocks.
+    pub fn get(this: &RetryLazy<T, E, F>) -> Option<&T> {
+        if this.once.is_completed() {
+            // SAFETY: the Once is complete
+            Some(unsafe { this.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The `Once` must be complete.
+    unsafe fn get_unchecked(&self) -> &T {
+        match &*self.slot.get() {
+            Slot::Value(value) => value,
+            Slot::Init(_) | Slot::Error(_) | Slot::Empty => unreachable!("completed RetryLazy without a value"),
+        }
+    }
+}
+
+impl<T: fmt::Debug, E, F> fmt::Debug for RetryLazy<T, E, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match RetryLazy::get(self) {
+            Some(value) => f.debug_tuple("RetryLazy").field(value).finish(),
+            None => f.debug_struct("RetryLazy").field("state", &self.once.state()).finish(),
+        }
+    }
+}
+
+// SAFETY: the function is called by one thread at a time and the value is shared between them,
+// the errors are returned to the thread that called the function
+unsafe impl<T: Send + Sync, E, F: Send> Sync for RetryLazy<T, E, F> {}
+
+// Same as LazyLock, the poisoning protects from observing a broken value
+impl<T: RefUnwindSafe + UnwindSafe, E, F: UnwindSafe> RefUnwindSafe for RetryLazy<T, E, F> {}
+impl<T: UnwindSafe, E, F: UnwindSafe> UnwindSafe for RetryLazy<T, E, F> {}
+
@@ -0,0 +1,146 @@
+//! One-time initialization keyed by type
+//!
+//! The cells are leaked and looked up in a global `OnceMap` keyed by the `TypeId` of the key type
+//! and of the value type, so different value types stored for the same key type don't collide.
+//! The map is protected by a single mutex, so each thread caches the cells it looked up and only
+//! its first access of each type locks it. After that each type only touches its own `OnceCell`
+//! and the thread-local cache. Recursion is detected using a
+//! thread-local list of the keys being initialized on the current thread since the portable
+//! `Once` would deadlock instead of panicking.
+
+use core::any::{Any, TypeId};
+use core::cell::RefCell;
+use std::collections::HashMap;
+use crate::{LazyLock, OnceCell, OnceMap};
+
+type Key = (TypeId, TypeId);
+type Cell = &'static (dyn Any + Send + Sync);
+
+static CELLS: LazyLock<OnceMap<Key, Cell>> = LazyLock::new(OnceMap::new);
+
+thread_local! {
+    static RUNNING: RefCell<Vec<Key>> = const { RefCell::new(Vec::new()) };
+    /// Cells already looked up in `CELLS` by this thread
+    static CACHE: RefCell<HashMap<Key, Cell>> = RefCell::new(HashMap::new());
+}
+
+/// One-time initialization keyed by type, for the whole process.
+///
+/// This is like having a `static` [`Once`](crate::Once) or [`OnceCell`] for every type, even
+/// generic ones, where statics can't be declared. E.g. a plugin registry can register each plugin
+/// type exactly once:
+///
+/// ```
+/// use linux_once::TypeOnce;
+///
+/// struct Plugin<T>(T);
+///
+/// fn register<T: 'static>() -> usize {
+///     *TypeOnce::get_or_init::<Plugin<T>, usize>(|| {
+///         println!("registering {}", std::any::type_name::<T>());
+///         std::mem::size_of::<T>()
+///     })
+/// }
+///
+/// assert_eq!(register::<u32>(), 4);
+/// assert_eq!(register::<u64>(), 8);
+/// // initialized already
+/// assert_eq!(register::<u32>(), 4);
+/// ```
+///
+/// The initializations of different types run in parallel, the threads initializing the same
+/// type block until it finishes. The values live until the process exits. If an initialization
+/// closure panics the type is poisoned, just like [`Once`](crate::Once). If the closure
+/// initializes the same type again it panics instead of deadlocking.
+pub enum TypeOnce {}
+
+impl TypeOnce {
+    /// Runs `f` if it's the first call for `T`, blocking if it's running on another thread.
+    ///
+    /// This is the same as `get_or_init::<T, ()>(f)`.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics the panic is propagated and `T` is poisoned. If `T` has been poisoned or
+    /// `f` initializes `T` itself this panics too.
+    pub fn call_once<T: 'static>(f: impl FnOnce()) {
+        Self::get_or_init::<T, ()>(f);
+    }
+
+    /// Returns `true` if [`call_once()`](Self::call_once) for `T` has completed.
+    pub fn is_completed<T: 'static>() -> bool {
+        Self::get::<T, ()>().is_some()
+    }
+
+    /// Returns the value of type `V` stored for `T`, initializing it with `f` if there's none.
+    ///
+    /// If another thread is initializing the value this blocks until it finishes.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics the panic is propagated and the value is poisoned. If the value has been
+    /// poisoned or `f` initializes the same value itself this panics too.
+    pub fn get_or_init<T: 'static, V: Send + Sync + 'static>(f: impl FnOnce() -> V) -> &'static V {
+        let cell = Self::cell::<T, V>();
+        if let Some(value) = cell.get() {
+            return value;
+        }
+
+        let key = key::<T, V>();
+        RUNNING.with(|running| {
+            if running.borrow().contains(&key) {
+                panic!("TypeOnce for {} used recursively from its own initialization", core::any::type_name::<T>());
+            }
+        });
+        cell.get_or_init(|| {
+            RUNNING.with(|running| running.borrow_mut().push(key));
+            let _guard = RunningGuard(key);
+            f()
+        })
+    }
+
+    /// Returns the value of type `V` stored for `T` if it was initialized.
+    ///
+    /// This never blocks.
+    pub fn get<T: 'static, V: Send + Sync + 'static>() -> Option<&'static V> {
+        let cell = cached(key::<T, V>(), |key| CELLS.get(key).copied())?;
+        cell.downcast_ref::<OnceCell<V>>().expect("the key contains the TypeId of the value").get()
+    }
+
+    fn cell<T: 'static, V: Send + Sync + 'static>() -> &'static OnceCell<V> {
+        let cell = cached(key::<T, V>(), |key| {
+            Some(*CELLS.get_or_init(*key, |_| Box::leak(Box::new(OnceCell::<V>::new()))))
+        });
+        cell.expect("the lookup always returns a cell").downcast_ref().expect("the key contains the TypeId of the value")
+    }
+}
+
+/// Returns the cell of `key` from the thread-local cache, calling `lookup` on a miss
+///
+/// The cache is skipped if it's not available anymore because the thread is exiting.
+fn cached(key: Key, lookup: impl FnOnce(&Key) -> Option<Cell>) -> Option<Cell> {
+    if let Ok(Some(cell)) = CACHE.try_with(|cache| cache.borrow().get(&key).copied()) {
+        return Some(cell);
+    }
+    let cell = lookup(&key)?;
+    // the cells are never freed so caching them is always fine
+    let _ = CACHE.try_with(|cache| cache.borrow_mut().insert(key, cell));
+    Some(cell)
+}
+
+fn key<T: 'static, V: 'static>() -> Key {
+    (TypeId::of::<T>(), TypeId::of::<V>())
+}
+
+/// Removes the key from the running list even if the initialization panics
+struct RunningGuard(Key);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.with(|running| {
+            let mut running = running.borrow_mut();
+            let position = running.iter().rposition(|key| *key == self.0).expect("missing running key");
+            running.remove(position);
+        });
+    }
+}
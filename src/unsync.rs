@@ -0,0 +1,306 @@
+//! Single-threaded variants of the cell types
+//!
+//! These don't use any atomics or futexes and are not `Sync`, so they are suitable for state that
+//! never leaves a thread anyway.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem;
+use core::ops::Deref;
+
+/// A cell which can be written to only once, not thread-safe.
+///
+/// This is the single-threaded counterpart of [`crate::OnceCell`]. Since only one thread can
+/// access it there's no blocking and no poisoning: if the initialization closure panics the cell
+/// stays empty.
+///
+/// ```
+/// use linux_once::unsync::OnceCell;
+///
+/// let cell = OnceCell::new();
+/// assert_eq!(cell.get(), None);
+/// assert_eq!(cell.get_or_init(|| 42), &42);
+/// assert_eq!(cell.set(47), Err(47));
+/// ```
+///
+/// # Soundness without a state flag
+///
+/// The cell is either empty or holds the value, there's no "initializing" state. The closures
+/// passed to [`get_or_init()`](Self::get_or_init) and [`get_or_try_init()`](Self::get_or_try_init)
+/// run before the cell is touched, so if they panic or fail the cell simply stays empty and the
+/// next call runs its own closure. If a closure initializes the cell itself the outer call
+/// panics instead of overwriting the value the inner call handed out.
+///
+/// The value is never modified or moved through a shared reference once it's stored. The methods
+/// that do modify it, [`get_mut()`](Self::get_mut), [`take()`](Self::take) and
+/// [`into_inner()`](Self::into_inner), take `&mut self` or `self` so the borrow checker rejects
+/// calling them while a reference returned by [`get()`](Self::get) is alive:
+///
+/// ```compile_fail
+/// use linux_once::unsync::OnceCell;
+///
+/// let mut cell = OnceCell::from(42);
+/// let value = cell.get().unwrap();
+/// cell.take();
+/// assert_eq!(*value, 42);
+/// ```
+pub struct OnceCell<T> {
+    // Once it's `Some` it's never modified through a shared reference so the references handed
+    // out stay valid.
+    value: UnsafeCell<Option<T>>,
+}
+
+impl<T> OnceCell<T> {
+    /// Creates a new empty cell.
+    pub const fn new() -> Self {
+        OnceCell { value: UnsafeCell::new(None) }
+    }
+
+    /// Returns the value if the cell was initialized.
+    pub fn get(&self) -> Option<&T> {
+        // SAFETY: the value is only written while it's `None`, so there are no references to it
+        // then, and the write happens on this thread so it can't be concurrent with this read
+        unsafe { (*self.value.get()).as_ref() }
+    }
+
+    /// Returns the mutable reference to the value if the cell was initialized.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.value.get_mut().as_mut()
+    }
+
+    /// Initializes the cell with `value` if it's empty.
+    ///
+    /// Returns the value back in `Err` if the cell was initialized already.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        if self.get().is_some() {
+            return Err(value);
+        }
+        self.insert(value);
+        Ok(())
+    }
+
+    /// Returns the value, initializing it with `f` if the cell is empty.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics the panic is propagated and the cell stays empty. If `f` initializes the cell
+    /// itself (e.g. by calling this method recursively) this panics once `f` returns because the
+    /// reference handed out by the inner call must stay valid.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        match self.get_or_try_init(|| Ok::<_, core::convert::Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Returns the value, initializing it with the fallible `f` if the cell is empty.
+    ///
+    /// If `f` returns an error the cell stays empty and the error is returned.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`get_or_init()`](Self::get_or_init).
+    pub fn get_or_try_init<E, F: FnOnce() -> Result<T, E>>(&self, f: F) -> Result<&T, E> {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        let value = f()?;
+        if self.get().is_some() {
+            panic!("reentrant initialization of unsync::OnceCell");
+        }
+        Ok(self.insert(value))
+    }
+
+    /// Moves the value out of the cell, leaving it empty.
+    pub fn take(&mut self) -> Option<T> {
+        self.value.get_mut().take()
+    }
+
+    /// Consumes the cell returning the value if it was initialized.
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+
+    /// Stores the value in an empty cell
+    fn insert(&self, value: T) -> &T {
+        // SAFETY: the cell is empty so there are no references to the value and nothing else runs
+        // while this executes since the type is not Sync
+        let slot = unsafe { &mut *self.value.get() };
+        debug_assert!(slot.is_none(), "overwriting the value of unsync::OnceCell");
+        slot.get_or_insert(value)
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        OnceCell::new()
+    }
+}
+
+impl<T> From<T> for OnceCell<T> {
+    /// Creates an initialized cell.
+    fn from(value: T) -> Self {
+        OnceCell { value: UnsafeCell::new(Some(value)) }
+    }
+}
+
+impl<T: Clone> Clone for OnceCell<T> {
+    fn clone(&self) -> Self {
+        OnceCell { value: UnsafeCell::new(self.get().cloned()) }
+    }
+}
+
+impl<T: PartialEq> PartialEq for OnceCell<T> {
+    /// Compares the values, empty cells are equal to each other.
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl<T: Eq> Eq for OnceCell<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceCell").field(value).finish(),
+            None => f.write_str("OnceCell(<uninit>)"),
+        }
+    }
+}
+
+enum State<T, F> {
+    Init(F),
+    /// The function is running right now
+    Running,
+    Value(T),
+    /// The function panicked
+    Poisoned,
+}
+
+/// A value which is initialized on the first access, not thread-safe.
+///
+/// This is the single-threaded counterpart of [`crate::LazyLock`], e.g. for per-request caches or
+/// thread-local statics:
+///
+/// ```
+/// use linux_once::unsync::Lazy;
+///
+/// thread_local! {
+///     static SQUARES: Lazy<Vec<u64>> = Lazy::new(|| (0..16).map(|i| i * i).collect());
+/// }
+///
+/// assert_eq!(SQUARES.with(|squares| squares[3]), 9);
+/// ```
+///
+/// If the initializing function panics the `Lazy` is poisoned and all further accesses panic.
+pub struct Lazy<T, F = fn() -> T> {
+    // Once it's `Value` it's never modified through a shared reference so the references handed
+    // out stay valid.
+    state: UnsafeCell<State<T, F>>,
+}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    /// Creates a new lazy value with the given initializing function.
+    pub const fn new(f: F) -> Self {
+        Lazy { state: UnsafeCell::new(State::Init(f)) }
+    }
+
+    /// Forces the evaluation of the lazy value and returns the reference to the result.
+    ///
+    /// This is equivalent to the `Deref` impl but it's explicit.
+    ///
+    /// # Panics
+    ///
+    /// If the initializing function panics the panic is propagated and the `Lazy` is poisoned.
+    /// If the `Lazy` has been poisoned or the initializing function forces the same `Lazy` this
+    /// method panics too.
+    pub fn force(this: &Lazy<T, F>) -> &T {
+        if let Some(value) = Lazy::get(this) {
+            return value;
+        }
+
+        // SAFETY: the state is not `Value` so there are no references to it and the mutable
+        // reference is not held while the function runs
+        let state = unsafe { &mut *this.state.get() };
+        match state {
+            State::Running => panic!("unsync::Lazy forced while being initialized"),
+            State::Poisoned => panic!("unsync::Lazy instance has previously been poisoned"),
+            State::Init(_) | State::Value(_) => (),
+        }
+        let f = match mem::replace(state, State::Running) {
+            State::Init(f) => f,
+            _ => unreachable!("value not returned by get"),
+        };
+        let poison = PoisonOnUnwind(&this.state);
+        let value = f();
+        mem::forget(poison);
+        // SAFETY: same as above, the state is `Running` now
+        let state = unsafe { &mut *this.state.get() };
+        *state = State::Value(value);
+        match state {
+            State::Value(value) => value,
+            _ => unreachable!("value just stored"),
+        }
+    }
+}
+
+impl<T, F> Lazy<T, F> {
+    /// Returns the value if it was initialized, without initializing it.
+    pub fn get(this: &Lazy<T, F>) -> Option<&T> {
+        // SAFETY: the state is only modified when it's not `Value`, so there are no references
+        // then, and the modification happens on this thread so it can't be concurrent with this
+        match unsafe { &*this.state.get() } {
+            State::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Consumes the `Lazy` returning the value if it was initialized or the initializing function
+    /// if it wasn't.
+    ///
+    /// # Panics
+    ///
+    /// If the `Lazy` has been poisoned this panics because it holds neither the value nor the
+    /// function anymore.
+    pub fn into_inner(this: Lazy<T, F>) -> Result<T, F> {
+        match this.state.into_inner() {
+            State::Value(value) => Ok(value),
+            State::Init(f) => Err(f),
+            State::Running | State::Poisoned => panic!("unsync::Lazy instance has previously been poisoned"),
+        }
+    }
+}
+
+/// Marks the `Lazy` as poisoned if the initializing function panics
+struct PoisonOnUnwind<'a, T, F>(&'a UnsafeCell<State<T, F>>);
+
+impl<'a, T, F> Drop for PoisonOnUnwind<'a, T, F> {
+    fn drop(&mut self) {
+        // SAFETY: the state is `Running` so there are no references to it
+        unsafe { *self.0.get() = State::Poisoned; }
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
+
+impl<T: Default> Default for Lazy<T> {
+    /// Creates a new lazy value using `Default` as the initializing function.
+    fn default() -> Self {
+        Lazy::new(T::default)
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match Lazy::get(self) {
+            Some(value) => f.debug_tuple("Lazy").field(value).finish(),
+            None => f.write_str("Lazy(<uninit>)"),
+        }
+    }
+}
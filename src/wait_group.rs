@@ -0,0 +1,156 @@
+//! Reusable counter of pending tasks on a single futex word
+//!
+//! The word packs the count into the low 24 bits, a generation number into the next 7 bits and
+//! the waiting bit, same as in `Latch`, into the high bit. Each time the count reaches zero the
+//! generation is incremented, which is what releases the waiters: a waiter returns once the
+//! generation it observed ended, even if `add()` started a new one before the waiter got to run.
+//! Without the generation such a waiter would see a non-zero count and block for work it never
+//! waited for.
+//!
+//! The generation wraps around after 128 generations. A woken waiter that doesn't run until
+//! exactly 128 more generations ended would block again until the next one ends.
+
+use core::fmt;
+use core::sync::atomic::{AtomicI32, Ordering};
+use crate::futex;
+
+/// Set while some threads may be blocked
+const WAITING: i32 = i32::MIN;
+const COUNT_MASK: i32 = 0x00ff_ffff;
+const GENERATION_MASK: i32 = 0x7f00_0000;
+const GENERATION_ONE: i32 = 0x0100_0000;
+
+/// A counter of pending tasks allowing threads to wait until all of them are done.
+///
+/// Unlike [`Latch`](crate::Latch) the tasks don't have to be known upfront: each task is
+/// registered using [`add()`](Self::add) as it's discovered and calls [`done()`](Self::done)
+/// when finished. [`wait()`](Self::wait) blocks until the count drops to zero, like `WaitGroup`
+/// in Go.
+///
+/// ```
+/// use linux_once::WaitGroup;
+/// use std::sync::Arc;
+///
+/// let tasks = Arc::new(WaitGroup::new());
+/// for _ in 0..3 {
+///     tasks.add(1);
+///     let tasks = Arc::clone(&tasks);
+///     std::thread::spawn(move || {
+///         // run the task
+///         tasks.done();
+///     });
+/// }
+/// tasks.wait();
+/// assert_eq!(tasks.count(), 0);
+/// ```
+///
+/// The group can be reused: calling [`add()`](Self::add) after the count dropped to zero starts
+/// a new generation. Each call to [`wait()`](Self::wait) waits for the generation that is running
+/// when it's called, so if the last [`done()`](Self::done) races with an [`add()`](Self::add)
+/// starting the next generation the threads waiting for the finished one are still released.
+pub struct WaitGroup {
+    state: AtomicI32,
+}
+
+impl WaitGroup {
+    /// Creates a new group with no pending tasks.
+    pub const fn new() -> Self {
+        WaitGroup { state: AtomicI32::new(0) }
+    }
+
+    /// Registers `n` pending tasks.
+    ///
+    /// If there were no pending tasks this starts a new generation.
+    ///
+    /// # Panics
+    ///
+    /// The count is stored in 24 bits so this panics if it would exceed 16 777 215.
+    pub fn add(&self, n: u32) {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            let count = (state & COUNT_MASK) as u32;
+            let new_count = count.checked_add(n).filter(|count| *count <= COUNT_MASK as u32).expect("the count of WaitGroup doesn't fit into 24 bits");
+            let new = (state & !COUNT_MASK) | new_count as i32;
+            match self.state.compare_exchange_weak(state, new, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(old) => state = old,
+            }
+        }
+    }
+
+    /// Marks one pending task done, ending the generation if it was the last one.
+    ///
+    /// Ending the generation wakes up all threads waiting for it. Memory operations performed
+    /// before this call are visible to them.
+    ///
+    /// # Panics
+    ///
+    /// Calling this more times than the tasks were added is a bug so this panics if there's no
+    /// pending task.
+    pub fn done(&self) {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            let count = state & COUNT_MASK;
+            assert!(count != 0, "WaitGroup::done() called without a pending task");
+            // The last task ends the generation and clears the waiting bit since all waiters are
+            // released
+            let new = if count == 1 { state.wrapping_add(GENERATION_ONE) & GENERATION_MASK } else { state - 1 };
+            match self.state.compare_exchange_weak(state, new, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => {
+                    // Only make expensive syscall if there are threads waiting
+                    if count == 1 && state & WAITING != 0 {
+                        futex::wake(&self.state, i32::MAX);
+                    }
+                    break;
+                },
+                Err(old) => state = old,
+            }
+        }
+    }
+
+    /// Blocks the current thread until the current generation ends.
+    ///
+    /// Returns immediately if there are no pending tasks.
+    pub fn wait(&self) {
+        let state = self.state.load(Ordering::Acquire);
+        if state & COUNT_MASK != 0 {
+            self.wait_slow(state);
+        }
+    }
+
+    #[cold]
+    fn wait_slow(&self, mut state: i32) {
+        let generation = state & GENERATION_MASK;
+        while state & COUNT_MASK != 0 && state & GENERATION_MASK == generation {
+            if state & WAITING == 0 {
+                if let Err(old) = self.state.compare_exchange_weak(state, state | WAITING, Ordering::Acquire, Ordering::Acquire) {
+                    state = old;
+                    continue;
+                }
+                state |= WAITING;
+            }
+            // Returns immediately if the state changed in the meantime
+            futex::wait(&self.state, state);
+            state = self.state.load(Ordering::Acquire);
+        }
+    }
+
+    /// Returns the number of pending tasks.
+    ///
+    /// The value may change right after it was observed so this is mainly useful for debugging.
+    pub fn count(&self) -> u32 {
+        (self.state.load(Ordering::Relaxed) & COUNT_MASK) as u32
+    }
+}
+
+impl Default for WaitGroup {
+    fn default() -> Self {
+        WaitGroup::new()
+    }
+}
+
+impl fmt::Debug for WaitGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WaitGroup").field("count", &self.count()).finish()
+    }
+}
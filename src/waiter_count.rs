@@ -0,0 +1,85 @@
+//! Counts of threads blocked on `Once` instances
+//!
+//! The count doesn't fit into the state of `Once` without complicating the fast path so we keep a
+//! global table keyed by the address instead. Like the poison locations the table is a fixed array
+//! of atomics, so unrelated instances don't contend on a lock. Waiters start probing at a slot
+//! picked by the address so instances usually don't share a slot either. If the table is full the
+//! waiter is simply not counted.
+//!
+//! A slot is owned by an address while its count is non-zero and the waiter that decrements it to
+//! zero frees it, so a new `Once` placed at the same address starts at zero. A waiter joins an
+//! existing slot only by incrementing a non-zero count and then checks the slot still belongs to
+//! its address, leaving it again if it was reused in the meantime. Thus an address may end up in
+//! several slots and the count is their sum.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const SLOTS: usize = 64;
+
+struct Slot {
+    /// Address of the `Once`, zero if the slot is free
+    address: AtomicUsize,
+    count: AtomicUsize,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: Slot = Slot { address: AtomicUsize::new(0), count: AtomicUsize::new(0) };
+
+static TABLE: [Slot; SLOTS] = [EMPTY; SLOTS];
+
+/// Registers a waiting thread for the `Once` at the given address until dropped
+pub(crate) struct Waiter(Option<&'static Slot>);
+
+impl Waiter {
+    pub(crate) fn new(address: usize) -> Self {
+        // `Once` is aligned to 4 bytes so the low bits are always zero
+        let start = (address >> 2) % SLOTS;
+        let mut slots = TABLE[start..].iter().chain(&TABLE[..start]);
+        Waiter(slots.find(|slot| join(slot, address)))
+    }
+}
+
+/// Tries to count a waiter for `address` in `slot`
+fn join(slot: &'static Slot, address: usize) -> bool {
+    if slot.address.compare_exchange(0, address, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+        // nobody else touches the count of a free slot
+        slot.count.store(1, Ordering::Release);
+        return true;
+    }
+    if slot.address.load(Ordering::Acquire) != address {
+        return false;
+    }
+    // a zero count means the slot is being freed
+    if slot.count.fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| count.checked_sub(1).map(|_| count + 1)).is_err() {
+        return false;
+    }
+    // the slot could've been freed and reused by another address before we incremented the count
+    if slot.address.load(Ordering::Acquire) != address {
+        leave(slot);
+        return false;
+    }
+    true
+}
+
+fn leave(slot: &Slot) {
+    if slot.count.fetch_sub(1, Ordering::AcqRel) == 1 {
+        slot.address.store(0, Ordering::Release);
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0 {
+            leave(slot);
+        }
+    }
+}
+
+/// Returns the number of threads waiting for the `Once` at the given address
+pub(crate) fn get(address: usize) -> usize {
+    TABLE
+        .iter()
+        .filter(|slot| slot.address.load(Ordering::Acquire) == address)
+        .map(|slot| slot.count.load(Ordering::Acquire))
+        .sum()
+}
@@ -0,0 +1,215 @@
+//! Models of the memory orderings used by `Once`
+//!
+//! `Once` is built on top of `linux_futex` which loom can't instrument so these tests replicate
+//! the relevant parts of the protocol using loom's atomics. Keep them in sync with `src/linux.rs`.
+//!
+//! Run with `RUSTFLAGS="--cfg loom" cargo test --release --test loom`.
+
+#![cfg(all(loom, target_os = "linux"))]
+
+use linux_once::Once;
+use loom::cell::UnsafeCell;
+use loom::sync::atomic::{fence, AtomicI32, AtomicUsize, Ordering};
+use loom::sync::{Arc, Condvar, Mutex};
+use loom::thread;
+
+/// Initializer side of the protocol: the same orderings `try_start` and `PanicChecker` use
+fn initialize(state: &AtomicI32, data: &UnsafeCell<u32>) {
+    if state.compare_exchange(Once::INCOMPLETE, Once::RUNNING_NO_WAIT, Ordering::Acquire, Ordering::Acquire).is_ok() {
+        data.with_mut(|data| unsafe { *data = 42 });
+        state.swap(Once::COMPLETE, Ordering::AcqRel);
+    }
+}
+
+#[test]
+fn relaxed_check_with_fence_publishes_data() {
+    loom::model(|| {
+        let state = Arc::new(AtomicI32::new(Once::INCOMPLETE));
+        let data = Arc::new(UnsafeCell::new(0u32));
+
+        let initializer = {
+            let state = Arc::clone(&state);
+            let data = Arc::clone(&data);
+            thread::spawn(move || initialize(&state, &data))
+        };
+
+        // is_completed_relaxed() followed by the documented fence
+        if state.load(Ordering::Relaxed) == Once::COMPLETE {
+            fence(Ordering::Acquire);
+            // loom reports a data race here if the fence wasn't sufficient
+            assert_eq!(data.with(|data| unsafe { *data }), 42);
+        }
+
+        initializer.join().unwrap();
+    });
+}
+
+#[test]
+fn acquire_check_publishes_data() {
+    loom::model(|| {
+        let state = Arc::new(AtomicI32::new(Once::INCOMPLETE));
+        let data = Arc::new(UnsafeCell::new(0u32));
+
+        let initializer = {
+            let state = Arc::clone(&state);
+            let data = Arc::clone(&data);
+            thread::spawn(move || initialize(&state, &data))
+        };
+
+        // the default fast path of call_once() and is_completed()
+        if state.load(Ordering::Acquire) == Once::COMPLETE {
+            assert_eq!(data.with(|data| unsafe { *data }), 42);
+        }
+
+        initializer.join().unwrap();
+    });
+}
+
+/// Model of a private futex
+///
+/// The kernel checks the value and goes to sleep atomically with respect to wakes, which is
+/// modeled by doing both under a mutex that the waker takes after changing the value.
+struct ModelFutex {
+    value: AtomicI32,
+    lock: Mutex<()>,
+    condvar: Condvar,
+}
+
+impl ModelFutex {
+    fn new(value: i32) -> Self {
+        ModelFutex { value: AtomicI32::new(value), lock: Mutex::new(()), condvar: Condvar::new() }
+    }
+
+    fn wait(&self, expected: i32) {
+        let guard = self.lock.lock().unwrap();
+        if self.value.load(Ordering::Relaxed) == expected {
+            drop(self.condvar.wait(guard).unwrap());
+        }
+    }
+
+    fn wake(&self) {
+        drop(self.lock.lock().unwrap());
+        self.condvar.notify_all();
+    }
+}
+
+/// Replica of `Once::start_or_wait()` and the parts of `wait_for_change()` that matter
+///
+/// Returns `true` if the calling thread won the right to run the initialization.
+fn start_or_wait(futex: &ModelFutex) -> bool {
+    let mut state = futex.value.load(Ordering::Acquire);
+    loop {
+        match state {
+            Once::INCOMPLETE | Once::INCOMPLETE_WAITING => {
+                let running = if state == Once::INCOMPLETE_WAITING { Once::RUNNING_WAITING } else { Once::RUNNING_NO_WAIT };
+                match futex.value.compare_exchange(state, running, Ordering::Acquire, Ordering::Acquire) {
+                    Ok(_) => break true,
+                    Err(old) => state = old,
+                }
+            },
+            Once::COMPLETE => break false,
+            _running => state = wait_for_change(futex, state),
+        }
+    }
+}
+
+/// Replica of `Once::wait_for_change()` without spinning
+fn wait_for_change(futex: &ModelFutex, state: i32) -> i32 {
+    let waiting = match state {
+        Once::INCOMPLETE => Once::INCOMPLETE_WAITING,
+        Once::RUNNING_NO_WAIT => Once::RUNNING_WAITING,
+        already_waiting => already_waiting,
+    };
+    if waiting != state {
+        if let Err(old) = futex.value.compare_exchange(state, waiting, Ordering::AcqRel, Ordering::Acquire) {
+            return old;
+        }
+    }
+    futex.wait(waiting);
+    futex.value.load(Ordering::Acquire)
+}
+
+/// Replica of `PanicChecker::drop()`
+fn finish(futex: &ModelFutex, value: i32) {
+    if futex.value.swap(value, Ordering::AcqRel) == Once::RUNNING_WAITING {
+        futex.wake();
+    }
+}
+
+/// Replica of `Once::call_once()`
+fn call_once(futex: &ModelFutex, data: &UnsafeCell<u32>, runs: &AtomicUsize) {
+    if futex.value.load(Ordering::Acquire) == Once::COMPLETE || !start_or_wait(futex) {
+        return;
+    }
+    runs.fetch_add(1, Ordering::Relaxed);
+    data.with_mut(|data| unsafe { *data = 42 });
+    finish(futex, Once::COMPLETE);
+}
+
+fn contended_call_once(threads: usize) {
+    let shared = Arc::new((ModelFutex::new(Once::INCOMPLETE), UnsafeCell::new(0u32), AtomicUsize::new(0)));
+
+    let threads = (1..threads)
+        .map(|_| {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                call_once(&shared.0, &shared.1, &shared.2);
+                assert_eq!(shared.1.with(|data| unsafe { *data }), 42);
+            })
+        })
+        .collect::<Vec<_>>();
+
+    call_once(&shared.0, &shared.1, &shared.2);
+    assert_eq!(shared.1.with(|data| unsafe { *data }), 42);
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(shared.2.load(Ordering::Relaxed), 1);
+}
+
+// loom reports a deadlock if any interleaving lets a waiter sleep through the wake
+#[test]
+fn handoff_never_loses_wake() {
+    loom::model(|| contended_call_once(2));
+}
+
+// With three threads a waiter can observe RUNNING_WAITING set by another waiter. Exhaustive search
+// takes too long so the number of preemptions is bounded, which still covers the interleavings
+// where the second waiter registers between the first one's CAS and its wait.
+#[test]
+fn handoff_never_loses_wake_three_threads() {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound = Some(2);
+    builder.check(|| contended_call_once(3));
+}
+
+// exercises the path through INCOMPLETE_WAITING used by Once::wait() and aborted initialization
+#[test]
+fn handoff_with_wait_and_abort() {
+    loom::model(|| {
+        let shared = Arc::new((ModelFutex::new(Once::INCOMPLETE), UnsafeCell::new(0u32)));
+
+        let waiter = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                // replica of Once::wait()
+                let mut state = shared.0.value.load(Ordering::Acquire);
+                while state != Once::COMPLETE {
+                    state = wait_for_change(&shared.0, state);
+                }
+                assert_eq!(shared.1.with(|data| unsafe { *data }), 42);
+            })
+        };
+
+        // the first attempt gives up, which resets the state and wakes the waiter
+        assert!(start_or_wait(&shared.0));
+        finish(&shared.0, Once::INCOMPLETE);
+
+        assert!(start_or_wait(&shared.0));
+        shared.1.with_mut(|data| unsafe { *data = 42 });
+        finish(&shared.0, Once::COMPLETE);
+
+        waiter.join().unwrap();
+    });
+}
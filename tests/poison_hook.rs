@@ -0,0 +1,76 @@
+//! Tests of the poison hook
+//!
+//! The hook is process-global and can be set only once so these live in their own test binary.
+
+#![cfg(target_os = "linux")]
+
+use linux_once::{set_poison_hook, NamedOnce, Once, PoisonInfo};
+use std::sync::{Arc, Barrier, Mutex, PoisonError};
+
+const NAMES: [&str; 8] = ["once-0", "once-1", "once-2", "once-3", "once-4", "once-5", "once-6", "once-7"];
+
+struct Call {
+    name: Option<&'static str>,
+    file: Option<&'static str>,
+    message: Option<String>,
+}
+
+static CALLS: Mutex<Vec<Call>> = Mutex::new(Vec::new());
+
+fn record(info: &PoisonInfo<'_>) {
+    CALLS.lock().unwrap_or_else(PoisonError::into_inner).push(Call {
+        name: info.name,
+        file: info.location.map(|location| location.file()),
+        message: info.message.map(String::from),
+    });
+}
+
+fn install_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| set_poison_hook(record).expect("hook already set"));
+}
+
+#[test]
+fn hook_is_set_once() {
+    install_hook();
+    set_poison_hook(|_| ()).expect_err("hook set twice");
+}
+
+#[test]
+fn fires_once_per_poisoned_once() {
+    install_hook();
+
+    let onces = NAMES.iter().map(|name| &*Box::leak(Box::new(NamedOnce::new(name)))).collect::<Vec<_>>();
+    let barrier = Arc::new(Barrier::new(onces.len() * 3));
+    let threads = onces
+        .iter()
+        .flat_map(|once| std::iter::repeat_n(*once, 3))
+        .map(|once| {
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || {
+                barrier.wait();
+                // only the first thread runs the closure, the others observe the poisoning
+                std::panic::catch_unwind(|| once.call_once(|| {
+                    std::thread::sleep(std::time::Duration::from_millis(20));
+                    panic!("boom");
+                })).expect_err("call_once didn't panic");
+            })
+        })
+        // required for true concurrency
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().expect("Failed to join");
+    }
+
+    let calls = CALLS.lock().unwrap_or_else(PoisonError::into_inner);
+    for name in &NAMES {
+        let mut calls = calls.iter().filter(|call| call.name == Some(*name));
+        let call = calls.next().expect("hook not called");
+        assert!(calls.next().is_none(), "hook called more than once for {}", name);
+        assert_eq!(call.file, Some(file!()));
+        #[cfg(feature = "poison-message")]
+        assert_eq!(call.message.as_deref(), Some("boom"));
+        #[cfg(not(feature = "poison-message"))]
+        assert_eq!(call.message, None);
+    }
+}
@@ -0,0 +1,82 @@
+//! Tests of the contention counters
+//!
+//! The counters are process-global so these live in their own test binary where nothing else
+//! touches `Once`. The tests are serialized using a lock for the same reason.
+//!
+//! Run with `cargo test --features stats --test stats`.
+
+#![cfg(all(target_os = "linux", feature = "stats"))]
+
+use linux_once::{stats, Once};
+use std::sync::{Arc, Barrier, Mutex, PoisonError};
+use std::time::Duration;
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+#[test]
+fn uncontended_initialization() {
+    let _lock = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+    stats::reset();
+
+    let once = Once::new();
+    once.call_once(|| ());
+    once.call_once(|| panic!("the initialization should've completed"));
+
+    let stats = stats::snapshot();
+    assert_eq!(stats.slow_path, 1);
+    assert_eq!(stats.blocked, 0);
+    assert_eq!(stats.wakes, 0);
+}
+
+#[test]
+fn contended_initialization() {
+    const THREADS: usize = 4;
+
+    let _lock = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+    stats::reset();
+
+    let once = Arc::new(Once::new());
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let threads = (0..THREADS)
+        .map(|_| {
+            let once = Arc::clone(&once);
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || {
+                barrier.wait();
+                once.call_once(|| std::thread::sleep(Duration::from_millis(100)));
+            })
+        })
+        // required for true concurrency
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().expect("Failed to join");
+    }
+
+    let stats = stats::snapshot();
+    assert!(stats.slow_path >= 1 && stats.slow_path <= THREADS as u64, "{:?}", stats);
+    // the other threads surely didn't spin for 100 ms
+    assert!(stats.blocked >= 1, "{:?}", stats);
+    assert!(stats.blocked <= stats.slow_path, "{:?}", stats);
+    // all waiters are woken up at once
+    assert_eq!(stats.wakes, 1, "{:?}", stats);
+
+    stats::reset();
+    assert_eq!(stats::snapshot(), stats::OnceStats::default());
+}
+
+#[test]
+fn mutable_initialization_is_unsynchronized() {
+    let _lock = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+    stats::reset();
+
+    let mut cell = linux_once::OnceCell::new();
+    *cell.get_mut_or_init(|| 42) += 1;
+    let mut lock = linux_once::OnceLock::new();
+    assert_eq!(lock.get_mut_or_try_init(|| Err(())), Err(()));
+    *lock.get_mut_or_init(|| 42) += 1;
+    assert_eq!(cell.get(), Some(&43));
+    assert_eq!(lock.get(), Some(&43));
+
+    // the state machine wasn't entered at all
+    assert_eq!(stats::snapshot(), stats::OnceStats::default());
+}